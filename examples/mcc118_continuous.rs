@@ -1,6 +1,6 @@
 use anyhow;
 use std::panic;
-use daqhats::core::{hat_list, HatId, Mcc118};
+use daqhats::core::{hat_list, Hat, HatId, Mcc118};
 use daqhats::ScanOptions;
 
 fn main() -> anyhow::Result<(), anyhow::Error> {
@@ -15,11 +15,11 @@ fn main() -> anyhow::Result<(), anyhow::Error> {
 
     let mut read_buf: [f64; 8000] = [0.0; 8000]; // 1000 samples * 8 possible channels
     let scan_rate: f64 = 1000.0; // Hz
-    let _actual_scan_rate = Mcc118::a_in_scan_actual_rate(num_channels, scan_rate);
 
     let opts = ScanOptions::CONTINUOUS;
 
     let mut dev = Mcc118::open(addr)?;
+    let _actual_scan_rate = dev.a_in_scan_actual_rate(num_channels, scan_rate);
     dev.a_in_scan_start(channel_mask, samples_per_channel, scan_rate, opts)?;
 
     println!("Internal data buffer size: {}", dev.a_in_scan_buffer_size()?);
@@ -0,0 +1,45 @@
+use daqhats::core::{hat_list, HatId, Mcc118};
+use daqhats::recorder::{RecordFormat, Recorder, RecorderOptions, Rotation};
+use daqhats::stream::scan_blocks;
+use daqhats::ScanOptions;
+use std::time::Duration;
+
+fn main() -> anyhow::Result<()> {
+    let avail_devices = hat_list(HatId::Mcc118);
+    let info = avail_devices.first().ok_or_else(|| anyhow::Error::msg("No MCC 118 devices found"))?;
+    let dev = Mcc118::open(info.address)?;
+
+    let opts = ScanOptions {
+        channel_mask: 0b1111,
+        sample_rate_per_channel: 1000.0,
+
+        scale_data: true,
+        calibrate_data: true,
+        external_clock: false,
+        external_trigger: false,
+    };
+
+    let stream = scan_blocks(dev, opts, 100).map_err(|(_, err)| err)?;
+
+    // a new file every 10 seconds of data: mcc118_0000.csv, mcc118_0001.csv, ...
+    let rec_opts = RecorderOptions { format: RecordFormat::Csv, rotation: Rotation::Duration(Duration::from_secs(10)) };
+    let mut recorder = Recorder::create("mcc118.csv", stream.info().clone(), rec_opts)?;
+
+    // stop after 30 seconds
+    let stop = stream.stop_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(30));
+        stop.stop();
+    });
+
+    println!("Recording for 30 seconds");
+    recorder.record(&stream)?;
+
+    let (_dev, end) = stream.join();
+    println!("Scan ended: {:?}", end);
+    for path in recorder.finish()? {
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
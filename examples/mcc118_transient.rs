@@ -0,0 +1,54 @@
+use daqhats::core::{hat_list, HatId, Mcc118};
+use daqhats::recorder::{Recorder, RecorderOptions};
+use daqhats::stream::scan_blocks;
+use daqhats::trigger::{Trigger, TriggerCondition, TriggerConfig};
+use daqhats::ScanOptions;
+
+fn main() -> anyhow::Result<()> {
+    let avail_devices = hat_list(HatId::Mcc118);
+    let info = avail_devices.first().ok_or_else(|| anyhow::Error::msg("No MCC 118 devices found"))?;
+    let dev = Mcc118::open(info.address)?;
+
+    let opts = ScanOptions {
+        channel_mask: 0b0011,
+        sample_rate_per_channel: 20000.0,
+
+        scale_data: true,
+        calibrate_data: true,
+        external_clock: false,
+        external_trigger: false,
+    };
+    let stream = scan_blocks(dev, opts, 1000).map_err(|(_, err)| err)?;
+
+    // capture 10 ms before and 90 ms after channel 0 rises through 2 V
+    let mut trigger = Trigger::new(
+        stream.info(),
+        TriggerConfig {
+            channel: 0,
+            condition: TriggerCondition::Rising { level: 2.0, hysteresis: 0.1 },
+            pre_trigger: 200,
+            post_trigger: 1800,
+            rearm: true,
+        },
+    )?;
+
+    println!("Waiting for 5 transients");
+    let mut count = 0;
+    while let Some(block) = stream.recv() {
+        for record in trigger.process(&block)? {
+            let path = format!("transient_{:02}.csv", count);
+            let mut recorder = Recorder::create(&path, stream.info().clone(), RecorderOptions::default())?;
+            recorder.write_block(&record.block)?;
+            recorder.finish()?;
+            println!("Triggered at sample {}, wrote {}", record.trigger_sample, path);
+            count += 1;
+        }
+        if count == 5 {
+            break;
+        }
+    }
+
+    let (_dev, end) = stream.stop();
+    println!("Scan ended: {:?}", end);
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use daqhats::core::{hat_list, HatId, Mcc134, TcType, Thermocouple};
+use daqhats::recorder::{RecordFormat, Recorder, RecorderOptions, Rotation};
+use daqhats::stream::{ScanBlock, StreamInfo};
+use std::time::{Duration, SystemTime};
+
+fn main() -> anyhow::Result<()> {
+    let avail_devices = hat_list(HatId::Mcc134);
+    let info = avail_devices.first().ok_or_else(|| anyhow::Error::msg("No MCC 134 devices found"))?;
+    let mut dev = Mcc134::open(info.address)?;
+
+    let channels = [0, 1];
+    for &ch in &channels {
+        dev.tc_type_write(ch, TcType::K)?;
+    }
+
+    // the MCC 134 has no scan mode, so poll once a second and record each round as a block
+    let stream_info = StreamInfo::polled(&dev, &channels, "°C")?;
+    let rec_opts = RecorderOptions { format: RecordFormat::Tsv, rotation: Rotation::Size(1 << 20) };
+    let mut recorder = Recorder::create("mcc134.tsv", stream_info, rec_opts)?;
+
+    for i in 0..60 {
+        let values = channels.iter().map(|&ch| dev.t_in_read(ch)).collect::<Result<Vec<_>, _>>()?;
+        recorder.write_block(&ScanBlock::from_frame(i, SystemTime::now(), values))?;
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    for path in recorder.finish()? {
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
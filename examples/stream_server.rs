@@ -0,0 +1,29 @@
+use daqhats::core::{AnyHat, HatId};
+use daqhats::server::Server;
+use daqhats::sim::SimulatedHat;
+
+fn main() -> anyhow::Result<()> {
+    let mut server = Server::new();
+    for (info, result) in AnyHat::open_all() {
+        match result {
+            Ok(dev) => {
+                if let Ok(dev) = dev.into_a_in_scanner() {
+                    server.add_device(dev)?;
+                }
+            }
+            Err(err) => eprintln!("Skipping the {} at address {}: {}", info.product_name, info.address, err),
+        }
+    }
+    if server.is_empty() {
+        println!("No scanning boards found, serving a simulated MCC 118");
+        let sim = SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, t: f64| (2.0 * std::f64::consts::PI * (ch as f64 + 1.0) * t).sin())?;
+        server.add_device(sim)?;
+    }
+
+    let handle = server.bind("0.0.0.0:7118")?;
+    println!("Listening on {}, press enter to stop", handle.local_addr());
+    std::io::stdin().read_line(&mut String::new())?;
+    handle.stop();
+
+    Ok(())
+}
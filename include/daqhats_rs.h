@@ -0,0 +1,120 @@
+#ifndef DAQHATS_RS_H
+#define DAQHATS_RS_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// How a session's scan ended.
+typedef enum DaqhatsRsOutcome {
+  DAQHATS_RS_OUTCOME_RUNNING = 0,
+  DAQHATS_RS_OUTCOME_STOPPED = 1,
+  DAQHATS_RS_OUTCOME_HARDWARE_OVERRUN = 2,
+  DAQHATS_RS_OUTCOME_BUFFER_OVERRUN = 3,
+  DAQHATS_RS_OUTCOME_FINISHED = 4,
+  // See the error code from [`daqhats_rs_session_outcome`].
+  DAQHATS_RS_OUTCOME_ERROR = 5,
+} DaqhatsRsOutcome;
+
+// A running or finished scan. Opaque to C.
+typedef struct DaqhatsRsSession DaqhatsRsSession;
+
+typedef struct DaqhatsRsScanOptions {
+  uint8_t channel_mask;
+  double sample_rate_per_channel;
+  bool scale_data;
+  bool calibrate_data;
+  bool external_clock;
+  bool external_trigger;
+} DaqhatsRsScanOptions;
+
+// Where a block read by [`daqhats_rs_session_read_block`] belongs in the stream.
+typedef struct DaqhatsRsBlockInfo {
+  // Samples per channel in the block; 0 once the scan has ended.
+  size_t samples_per_channel;
+  // Per-channel index of the block's first sample.
+  uint64_t first_sample;
+  // Time of the first sample, in seconds since the Unix epoch.
+  double timestamp;
+} DaqhatsRsBlockInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Opens the board at `address` and starts a continuous scan delivered in blocks of up to
+// `samples_per_block` samples per channel.
+//
+// # Safety
+//
+// `options` must point to a valid `DaqhatsRsScanOptions` and `session` to writable storage for
+// the new session pointer.
+int daqhats_rs_session_start(uint8_t address,
+                             const struct DaqhatsRsScanOptions *options,
+                             size_t samples_per_block,
+                             struct DaqhatsRsSession **session);
+
+// The number of channels interleaved in each block.
+//
+// # Safety
+//
+// `session` must be a live session pointer.
+size_t daqhats_rs_session_channel_count(const struct DaqhatsRsSession *session);
+
+// The actual per-channel sample rate in S/s.
+//
+// # Safety
+//
+// `session` must be a live session pointer.
+double daqhats_rs_session_sample_rate(const struct DaqhatsRsSession *session);
+
+// Copies the next block's interleaved samples into `buffer`, waiting up to `timeout_s` seconds
+// (negative to wait indefinitely, 0 to not wait). Returns `RESULT_TIMEOUT` if no block arrived in
+// time. Once the scan has ended, succeeds with `samples_per_channel` 0; see
+// [`daqhats_rs_session_outcome`]. If the block doesn't fit, returns `RESULT_BAD_PARAMETER` with
+// the block's size in `info` and keeps the block for the next call.
+//
+// # Safety
+//
+// `session` must be a live session pointer, `buffer` must point to `buffer_len` writable doubles
+// and `info` to a writable `DaqhatsRsBlockInfo`.
+int daqhats_rs_session_read_block(struct DaqhatsRsSession *session,
+                                  double timeout_s,
+                                  double *buffer,
+                                  size_t buffer_len,
+                                  struct DaqhatsRsBlockInfo *info);
+
+// Stops the scan. Blocks not yet read are discarded. Stopping an ended session does nothing.
+//
+// # Safety
+//
+// `session` must be a live session pointer.
+int daqhats_rs_session_stop(struct DaqhatsRsSession *session);
+
+// How the scan ended, or `DAQHATS_RS_OUTCOME_RUNNING`. For `DAQHATS_RS_OUTCOME_ERROR`,
+// `error_code` (if not null) receives the result code.
+//
+// # Safety
+//
+// `session` must be a live session pointer and `error_code` null or writable.
+enum DaqhatsRsOutcome daqhats_rs_session_outcome(const struct DaqhatsRsSession *session,
+                                                 int *error_code);
+
+// Stops the scan if it is still running, closes the board and frees the session.
+//
+// # Safety
+//
+// `session` must be null or a live session pointer, which is invalid afterwards.
+void daqhats_rs_session_free(struct DaqhatsRsSession *session);
+
+// A description of the latest failure on the calling thread, valid until the next call that
+// fails on that thread.
+const char *daqhats_rs_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DAQHATS_RS_H */
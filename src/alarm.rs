@@ -0,0 +1,318 @@
+//! Per-channel threshold alarms with hysteresis, debounce and latching.
+//!
+//! [`Alarms`] checks each sample of a stream, or individual readings from periodic
+//! `a_in_read`/`t_in_read` polls, against high and low limits and reports when an alarm is raised
+//! or cleared.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::ErrorCode;
+use crate::stream::{ScanBlock, StreamInfo};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlarmConfig {
+    pub channel: u8,
+    /// Raise when the value goes above this.
+    pub high: Option<f64>,
+    /// Raise when the value goes below this.
+    pub low: Option<f64>,
+    /// How far back inside the limit the value has to come to clear the alarm.
+    pub hysteresis: f64,
+    /// How long the value has to stay past the limit before the alarm is raised, and back inside
+    /// it before the alarm clears.
+    pub min_duration: Duration,
+    /// Latched alarms stay raised until [`Alarms::acknowledge`] is called, even once the value
+    /// is back inside the limit.
+    pub latching: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AlarmKind {
+    High,
+    Low,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlarmTransition {
+    Raised,
+    Cleared,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlarmEvent {
+    pub channel: u8,
+    pub kind: AlarmKind,
+    pub transition: AlarmTransition,
+    pub timestamp: SystemTime,
+    /// The reading that raised or cleared the alarm.
+    pub value: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Normal,
+    /// Past the limit since the given time, not for long enough yet.
+    Pending(SystemTime),
+    Raised,
+    /// Raised, but back inside the limit since the given time.
+    Clearing(SystemTime),
+    /// Latched: back inside the limit but waiting for an acknowledgement.
+    Latched,
+}
+
+struct Limit {
+    channel: u8,
+    kind: AlarmKind,
+    limit: f64,
+    hysteresis: f64,
+    min_duration: Duration,
+    latching: bool,
+    state: State,
+    acknowledged: bool,
+    last_value: f64,
+}
+
+impl Limit {
+    fn update(&mut self, value: f64, t: SystemTime) -> Option<AlarmEvent> {
+        self.last_value = value;
+        let (beyond, inside) = match self.kind {
+            AlarmKind::High => (value > self.limit, value < self.limit - self.hysteresis.abs()),
+            AlarmKind::Low => (value < self.limit, value > self.limit + self.hysteresis.abs()),
+        };
+        let min_duration = self.min_duration;
+        let held = |since: SystemTime| t.duration_since(since).unwrap_or_default() >= min_duration;
+
+        let transition = match self.state {
+            State::Normal | State::Pending(_) if !beyond => {
+                self.state = State::Normal;
+                None
+            }
+            State::Normal => {
+                self.state = State::Pending(t);
+                // no debounce: raise right away
+                held(t).then(|| self.raise())
+            }
+            State::Pending(since) => held(since).then(|| self.raise()),
+            State::Raised | State::Clearing(_) | State::Latched if beyond => {
+                self.state = State::Raised;
+                None
+            }
+            State::Raised if inside => {
+                self.state = State::Clearing(t);
+                held(t).then(|| self.clear()).flatten()
+            }
+            State::Clearing(since) if inside => held(since).then(|| self.clear()).flatten(),
+            // in the hysteresis band
+            State::Clearing(_) => {
+                self.state = State::Raised;
+                None
+            }
+            State::Raised | State::Latched => None,
+        };
+
+        transition.map(|transition| AlarmEvent { channel: self.channel, kind: self.kind, transition, timestamp: t, value })
+    }
+
+    fn raise(&mut self) -> AlarmTransition {
+        self.state = State::Raised;
+        self.acknowledged = false;
+        AlarmTransition::Raised
+    }
+
+    // latched alarms only clear once acknowledged
+    fn clear(&mut self) -> Option<AlarmTransition> {
+        if self.latching && !self.acknowledged {
+            self.state = State::Latched;
+            return None;
+        }
+        self.state = State::Normal;
+        Some(AlarmTransition::Cleared)
+    }
+
+    fn is_raised(&self) -> bool {
+        matches!(self.state, State::Raised | State::Clearing(_) | State::Latched)
+    }
+}
+
+/// A set of alarms on the channels of one stream.
+pub struct Alarms {
+    channels: Vec<u8>,
+    sample_rate: f64,
+    limits: Vec<Limit>,
+}
+
+impl Alarms {
+    /// Fails with [`ErrorCode::BadParameter`] if an alarm's channel isn't part of the stream.
+    pub fn new(info: &StreamInfo, configs: &[AlarmConfig]) -> Result<Alarms, ErrorCode> {
+        let mut limits = Vec::new();
+        for config in configs {
+            if !info.channels.contains(&config.channel) {
+                return Err(ErrorCode::BadParameter);
+            }
+            let kinds = [(AlarmKind::High, config.high), (AlarmKind::Low, config.low)];
+            for (kind, limit) in kinds.into_iter().filter_map(|(kind, limit)| Some((kind, limit?))) {
+                limits.push(Limit {
+                    channel: config.channel,
+                    kind,
+                    limit,
+                    hysteresis: config.hysteresis,
+                    min_duration: config.min_duration,
+                    latching: config.latching,
+                    state: State::Normal,
+                    acknowledged: false,
+                    last_value: f64::NAN,
+                });
+            }
+        }
+
+        Ok(Alarms { channels: info.channels.clone(), sample_rate: info.sample_rate, limits })
+    }
+
+    /// Checks every sample of a block of the stream. Fails with [`ErrorCode::BadParameter`] if
+    /// the block doesn't have the stream's channel count.
+    pub fn process(&mut self, block: &ScanBlock) -> Result<Vec<AlarmEvent>, ErrorCode> {
+        if block.channel_count != self.channels.len() {
+            return Err(ErrorCode::BadParameter);
+        }
+        let mut events = Vec::new();
+
+        for (i, frame) in block.frames().enumerate() {
+            let t = block.sample_time(i, self.sample_rate);
+            for (&channel, &value) in self.channels.iter().zip(frame) {
+                events.extend(self.limits.iter_mut().filter(|limit| limit.channel == channel).filter_map(|limit| limit.update(value, t)));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Checks a single reading, e.g. from a periodic poll.
+    pub fn check(&mut self, channel: u8, value: f64, timestamp: SystemTime) -> Vec<AlarmEvent> {
+        self.limits
+            .iter_mut()
+            .filter(|limit| limit.channel == channel)
+            .filter_map(|limit| limit.update(value, timestamp))
+            .collect()
+    }
+
+    /// Acknowledges a latched alarm. It clears now if the value is already back inside the limit
+    /// (returning the cleared event), otherwise as soon as it is.
+    pub fn acknowledge(&mut self, channel: u8, kind: AlarmKind, timestamp: SystemTime) -> Option<AlarmEvent> {
+        let limit = self.limits.iter_mut().find(|limit| limit.channel == channel && limit.kind == kind)?;
+        if !limit.is_raised() {
+            return None;
+        }
+        limit.acknowledged = true;
+        if limit.state != State::Latched {
+            return None;
+        }
+        limit.state = State::Normal;
+        Some(AlarmEvent { channel, kind, transition: AlarmTransition::Cleared, timestamp, value: limit.last_value })
+    }
+
+    /// Alarms currently raised, including latched ones.
+    pub fn active(&self) -> Vec<(u8, AlarmKind)> {
+        self.limits.iter().filter(|limit| limit.is_raised()).map(|limit| (limit.channel, limit.kind)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::core::HatId;
+    use AlarmKind::{High, Low};
+    use AlarmTransition::{Cleared, Raised};
+
+    fn info() -> StreamInfo {
+        StreamInfo { units: vec!["°C".to_string(); 2], ..StreamInfo::test(HatId::Mcc134, &[1, 3], 0.0) }
+    }
+
+    fn config(hysteresis: f64, min_duration_ms: u64, latching: bool) -> AlarmConfig {
+        AlarmConfig { channel: 3, high: Some(100.0), low: Some(0.0), hysteresis, min_duration: Duration::from_millis(min_duration_ms), latching }
+    }
+
+    fn at(ms: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    /// Feeds `(ms, value)` readings of channel 3 and returns `(ms, kind, transition)` per event.
+    fn feed(alarms: &mut Alarms, readings: &[(u64, f64)]) -> Vec<(u64, AlarmKind, AlarmTransition)> {
+        readings
+            .iter()
+            .flat_map(|&(ms, value)| alarms.check(3, value, at(ms)))
+            .map(|event| (event.timestamp.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64, event.kind, event.transition))
+            .collect()
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut alarms = Alarms::new(&info(), &[config(5.0, 0, false)]).unwrap();
+        let events = feed(&mut alarms, &[(0, 50.0), (1, 101.0), (2, 99.0), (3, 102.0), (4, 95.5), (5, 94.9), (6, 101.0), (7, -1.0), (8, 4.0), (9, 6.0)]);
+        assert_eq!(events, [(1, High, Raised), (5, High, Cleared), (6, High, Raised), (7, High, Cleared), (7, Low, Raised), (9, Low, Cleared)]);
+        assert!(alarms.active().is_empty());
+    }
+
+    #[test]
+    fn debounce() {
+        let mut alarms = Alarms::new(&info(), &[config(0.0, 100, false)]).unwrap();
+        // a 50 ms spike doesn't raise the alarm; 100 ms past the limit does
+        let events = feed(&mut alarms, &[(0, 101.0), (50, 101.0), (60, 90.0), (100, 101.0), (150, 105.0), (200, 101.0)]);
+        assert_eq!(events, [(200, High, Raised)]);
+        assert_eq!(alarms.active(), [(3, High)]);
+
+        // and it has to stay back inside for 100 ms to clear
+        let events = feed(&mut alarms, &[(250, 90.0), (300, 101.0), (310, 90.0), (400, 90.0), (410, 90.0)]);
+        assert_eq!(events, [(410, High, Cleared)]);
+    }
+
+    #[test]
+    fn latch_and_acknowledge() {
+        let mut alarms = Alarms::new(&info(), &[config(0.0, 0, true)]).unwrap();
+        assert_eq!(feed(&mut alarms, &[(0, 120.0), (1, 50.0), (2, 50.0)]), [(0, High, Raised)]);
+        assert_eq!(alarms.active(), [(3, High)]);
+
+        // acknowledging once the value is back clears right away
+        let event = alarms.acknowledge(3, High, at(3)).unwrap();
+        assert_eq!((event.transition, event.value, event.timestamp), (Cleared, 50.0, at(3)));
+        assert!(alarms.active().is_empty());
+        assert_eq!(alarms.acknowledge(3, High, at(4)), None);
+
+        // acknowledging while still past the limit clears as soon as the value comes back
+        assert_eq!(feed(&mut alarms, &[(5, 130.0)]), [(5, High, Raised)]);
+        assert_eq!(alarms.acknowledge(3, High, at(6)), None);
+        assert_eq!(feed(&mut alarms, &[(7, 130.0), (8, 50.0)]), [(8, High, Cleared)]);
+
+        // a new raise needs a new acknowledgement
+        assert_eq!(feed(&mut alarms, &[(9, 130.0), (10, 50.0)]), [(9, High, Raised)]);
+        assert_eq!(alarms.active(), [(3, High)]);
+        // a latched alarm that goes past the limit again stays raised without a new event
+        assert_eq!(feed(&mut alarms, &[(11, 130.0), (12, 50.0)]), []);
+        assert!(alarms.acknowledge(3, High, at(13)).is_some());
+    }
+
+    #[test]
+    fn process_blocks() {
+        let mut alarms = Alarms::new(&info(), &[config(0.0, 0, false)]).unwrap();
+        // channel 1 has no alarm, so its out-of-range values are ignored
+        let block = ScanBlock { first_sample: 0, timestamp: at(0), channel_count: 2, data: vec![500.0, 50.0, 500.0, 150.0, -500.0, -10.0] };
+        let events = alarms.process(&block).unwrap();
+        assert_eq!(events.iter().map(|e| (e.channel, e.kind, e.transition, e.value)).collect::<Vec<_>>(), [(3, High, Raised, 150.0), (3, High, Cleared, -10.0), (3, Low, Raised, -10.0)]);
+    }
+
+    #[test]
+    fn rejects_unknown_channels() {
+        let config = AlarmConfig { channel: 2, ..config(0.0, 0, false) };
+        assert_eq!(Alarms::new(&info(), &[config]).err(), Some(ErrorCode::BadParameter));
+    }
+
+    #[test]
+    fn rejects_blocks_with_other_channels() {
+        let mut alarms = Alarms::new(&info(), &[config(0.0, 0, false)]).unwrap();
+        let block = ScanBlock { first_sample: 0, timestamp: at(0), channel_count: 1, data: vec![500.0] };
+        assert_eq!(alarms.process(&block), Err(ErrorCode::BadParameter));
+        assert!(alarms.active().is_empty());
+    }
+}
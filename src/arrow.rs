@@ -0,0 +1,335 @@
+//! Arrow and Parquet export, behind the `arrow` feature.
+//!
+//! Blocks become [`RecordBatch`]es with a `timestamp` column (UTC nanoseconds) followed by one
+//! `Float64` column per channel, named `chN`. Each channel column carries metadata with the board's
+//! device type, address, serial, channel number, units and calibration date, so the values can be
+//! traced back to the board from pandas or polars.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, TimestampNanosecondArray};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+
+use crate::capture::CaptureReader;
+use crate::core::Capabilities;
+use crate::recorder::Rotation;
+use crate::stream::{ScanBlock, ScanStream, StreamInfo};
+
+/// Converts the blocks of one stream into record batches.
+pub struct BatchConverter {
+    info: StreamInfo,
+    schema: SchemaRef,
+}
+
+impl BatchConverter {
+    pub fn new(info: &StreamInfo) -> BatchConverter {
+        let product = Capabilities::of(info.id).map(|caps| caps.product_name.to_string()).unwrap_or_else(|| format!("{:?}", info.id));
+
+        let mut fields = vec![Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false)];
+        for (channel, unit) in info.channels.iter().zip(&info.units) {
+            let mut metadata = HashMap::from([
+                ("device".to_string(), product.clone()),
+                ("address".to_string(), info.address.to_string()),
+                ("serial".to_string(), info.serial.clone()),
+                ("channel".to_string(), channel.to_string()),
+                ("units".to_string(), unit.clone()),
+            ]);
+            if let Some(date) = &info.calibration_date {
+                metadata.insert("calibration_date".to_string(), date.clone());
+            }
+            fields.push(Field::new(format!("ch{}", channel), DataType::Float64, false).with_metadata(metadata));
+        }
+
+        let metadata = HashMap::from([
+            ("sample_rate".to_string(), info.sample_rate.to_string()),
+            ("scale_data".to_string(), info.scale_data.to_string()),
+            ("calibrate_data".to_string(), info.calibrate_data.to_string()),
+        ]);
+        let schema = Arc::new(Schema::new(fields).with_metadata(metadata));
+
+        BatchConverter { info: info.clone(), schema }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    pub fn block(&self, block: &ScanBlock) -> Result<RecordBatch, ArrowError> {
+        if block.channel_count != self.info.channel_count() {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "block has {} channels, the stream has {}",
+                block.channel_count,
+                self.info.channel_count()
+            )));
+        }
+
+        let timestamps = (0..block.samples_per_channel()).map(|i| unix_nanos(block.sample_time(i, self.info.sample_rate)));
+        let channels = (0..block.channel_count).map(|ch| block.channel(ch).collect()).collect();
+        self.batch(timestamps.collect(), channels)
+    }
+
+    /// A batch from per-channel arrays whose first sample is `first_sample` samples after the
+    /// stream's start time, e.g. from [`CaptureReader::read_channels`].
+    pub fn columns(&self, first_sample: u64, channels: Vec<Vec<f64>>) -> Result<RecordBatch, ArrowError> {
+        let rows = channels.first().map(Vec::len).unwrap_or(0);
+        let start = unix_nanos(self.info.start_time);
+        let timestamps = (0..rows as u64)
+            .map(|i| {
+                if self.info.sample_rate > 0.0 {
+                    start + ((first_sample + i) as f64 * 1e9 / self.info.sample_rate).round() as i64
+                } else {
+                    start
+                }
+            })
+            .collect();
+        self.batch(timestamps, channels)
+    }
+
+    fn batch(&self, timestamps: Vec<i64>, channels: Vec<Vec<f64>>) -> Result<RecordBatch, ArrowError> {
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(TimestampNanosecondArray::from(timestamps).with_timezone("UTC"))];
+        columns.extend(channels.into_iter().map(|values| Arc::new(Float64Array::from(values)) as ArrayRef));
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+}
+
+/// Converts a whole capture file into batches of up to `rows_per_batch` rows.
+pub fn capture_batches<R: Read>(mut reader: CaptureReader<R>, rows_per_batch: usize) -> Result<Vec<RecordBatch>, ArrowError> {
+    let converter = BatchConverter::new(&reader.header().info);
+    let mut batches = Vec::new();
+    let mut first_sample = 0;
+    while let Some(channels) = reader.read_channels(rows_per_batch).map_err(|e| ArrowError::IoError(e.to_string(), e))? {
+        let rows = channels.first().map(Vec::len).unwrap_or(0);
+        batches.push(converter.columns(first_sample, channels)?);
+        first_sample += rows as u64;
+    }
+    Ok(batches)
+}
+
+/// Writes record batches to Parquet files, starting a new file according to `rotation`. Rotated
+/// files are named like those of [`crate::recorder::Recorder`].
+pub struct ParquetRecorder {
+    path: PathBuf,
+    rotation: Rotation,
+    schema: SchemaRef,
+    props: WriterProperties,
+    writer: ArrowWriter<File>,
+    file_start: Option<i64>,
+    files: Vec<PathBuf>,
+}
+
+impl ParquetRecorder {
+    pub fn create(path: impl AsRef<Path>, schema: SchemaRef, rotation: Rotation) -> Result<ParquetRecorder, ParquetError> {
+        ParquetRecorder::with_properties(path, schema, rotation, WriterProperties::default())
+    }
+
+    pub fn with_properties(path: impl AsRef<Path>, schema: SchemaRef, rotation: Rotation, props: WriterProperties) -> Result<ParquetRecorder, ParquetError> {
+        let path = path.as_ref().to_path_buf();
+        let first = crate::recorder::rotated_path(&path, rotation, 0);
+        let writer = ArrowWriter::try_new(File::create(&first)?, schema.clone(), Some(props.clone()))?;
+        Ok(ParquetRecorder { path, rotation, schema, props, writer, file_start: None, files: vec![first] })
+    }
+
+    /// Files written so far, including the current one.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Writes a batch with the recorder's schema. Files are only switched between batches.
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), ParquetError> {
+        let first_time = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .filter(|ts| !ts.is_empty())
+            .map(|ts| ts.value(0));
+
+        // an empty file already holds the Parquet magic, so only rotate files with rows in them
+        let empty = self.writer.in_progress_rows() == 0 && self.writer.flushed_row_groups().is_empty();
+        let rotate = !empty && match self.rotation {
+            Rotation::Never => false,
+            Rotation::Size(limit) => (self.writer.bytes_written() + self.writer.in_progress_size()) as u64 >= limit,
+            Rotation::Duration(limit) => match (self.file_start, first_time) {
+                (Some(start), Some(t)) => t - start >= limit.as_nanos() as i64,
+                _ => false,
+            },
+        };
+        if rotate {
+            self.rotate()?;
+        }
+        if self.file_start.is_none() {
+            self.file_start = first_time;
+        }
+
+        self.writer.write(batch)
+    }
+
+    /// Converts and writes blocks until the stream ends.
+    pub fn record<T>(&mut self, stream: &ScanStream<T>) -> Result<(), ParquetError> {
+        let converter = BatchConverter::new(stream.info());
+        for block in stream.iter() {
+            self.write(&converter.block(&block)?)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the current file and returns every file written.
+    pub fn finish(self) -> Result<Vec<PathBuf>, ParquetError> {
+        self.writer.close()?;
+        Ok(self.files)
+    }
+
+    fn rotate(&mut self) -> Result<(), ParquetError> {
+        let next = crate::recorder::rotated_path(&self.path, self.rotation, self.files.len());
+        let writer = ArrowWriter::try_new(File::create(&next)?, self.schema.clone(), Some(self.props.clone()))?;
+        std::mem::replace(&mut self.writer, writer).close()?;
+        self.files.push(next);
+        self.file_start = None;
+        Ok(())
+    }
+}
+
+fn unix_nanos(t: SystemTime) -> i64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+    use crate::capture::{CaptureWriter, SampleFormat};
+    use crate::core::HatId;
+
+    const START_NS: i64 = 1_700_000_000_000_000_000;
+
+    fn info() -> StreamInfo {
+        StreamInfo {
+            address: 1,
+            serial: "0ABC".to_string(),
+            calibration_date: Some("2024-01-02".to_string()),
+            start_time: UNIX_EPOCH + Duration::from_nanos(START_NS as u64),
+            ..StreamInfo::test(HatId::Mcc128, &[2, 5], 10.0)
+        }
+    }
+
+    /// `frames` samples from sample `first`: channel 2 counts up, channel 5 counts down.
+    fn block(first: u64, frames: u64) -> ScanBlock {
+        let timestamp = info().start_time + Duration::from_millis(first * 100);
+        let data = (first..first + frames).flat_map(|i| [i as f64, -(i as f64)]).collect();
+        ScanBlock { first_sample: first, timestamp, channel_count: 2, data }
+    }
+
+    fn timestamps(batch: &RecordBatch) -> Vec<i64> {
+        batch.column(0).as_any().downcast_ref::<TimestampNanosecondArray>().unwrap().values().to_vec()
+    }
+
+    fn values(batch: &RecordBatch, column: usize) -> Vec<f64> {
+        batch.column(column).as_any().downcast_ref::<Float64Array>().unwrap().values().to_vec()
+    }
+
+    fn read_parquet(path: &Path) -> (SchemaRef, Vec<RecordBatch>) {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+        let schema = builder.schema().clone();
+        let batches = builder.build().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        (schema, batches)
+    }
+
+    #[test]
+    fn batches_have_a_timestamp_and_a_column_per_channel() {
+        let converter = BatchConverter::new(&info());
+        let schema = converter.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|field| field.name().as_str()).collect();
+        assert_eq!(names, ["timestamp", "ch2", "ch5"]);
+        assert_eq!(schema.field(0).data_type(), &DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())));
+        let metadata = schema.field(2).metadata();
+        assert_eq!(metadata["device"], "MCC 128");
+        assert_eq!(metadata["address"], "1");
+        assert_eq!(metadata["serial"], "0ABC");
+        assert_eq!(metadata["channel"], "5");
+        assert_eq!(metadata["units"], "V");
+        assert_eq!(metadata["calibration_date"], "2024-01-02");
+        assert_eq!(schema.metadata()["sample_rate"], "10");
+
+        let batch = converter.block(&block(3, 2)).unwrap();
+        assert_eq!(timestamps(&batch), [START_NS + 300_000_000, START_NS + 400_000_000]);
+        assert_eq!(values(&batch, 1), [3.0, 4.0]);
+        assert_eq!(values(&batch, 2), [-3.0, -4.0]);
+
+        let wrong = ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 1, data: vec![0.0] };
+        assert!(matches!(converter.block(&wrong), Err(ArrowError::InvalidArgumentError(_))));
+    }
+
+    #[test]
+    fn capture_files_convert_in_batches() {
+        let mut writer = CaptureWriter::new(Cursor::new(Vec::new()), &info(), SampleFormat::F64).unwrap();
+        writer.write_block(&block(0, 7)).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let batches = capture_batches(CaptureReader::new(Cursor::new(bytes)).unwrap(), 3).unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).collect::<Vec<_>>(), [3, 3, 1]);
+        assert_eq!(timestamps(&batches[1]), [START_NS + 300_000_000, START_NS + 400_000_000, START_NS + 500_000_000]);
+        assert_eq!(values(&batches[2], 1), [6.0]);
+        assert_eq!(values(&batches[2], 2), [-6.0]);
+    }
+
+    #[test]
+    fn parquet_files_round_trip_and_rotate() {
+        let dir = std::env::temp_dir().join(format!("daqhats-parquet-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let converter = BatchConverter::new(&info());
+        let mut recorder = ParquetRecorder::create(dir.join("run.parquet"), converter.schema(), Rotation::Duration(Duration::from_secs(1))).unwrap();
+        // blocks of 0.5 s: two per file
+        for first in (0..25).step_by(5) {
+            recorder.write(&converter.block(&block(first, 5)).unwrap()).unwrap();
+        }
+        let files = recorder.finish().unwrap();
+        assert_eq!(files, [dir.join("run_0000.parquet"), dir.join("run_0001.parquet"), dir.join("run_0002.parquet")]);
+
+        let mut all = Vec::new();
+        for (i, file) in files.iter().enumerate() {
+            let (schema, batches) = read_parquet(file);
+            assert_eq!(schema.fields().len(), 3);
+            assert_eq!(schema.field(1).name(), "ch2");
+            assert_eq!(schema.field(1).metadata()["serial"], "0ABC");
+            assert_eq!(schema.metadata()["sample_rate"], "10");
+            let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+            assert_eq!(rows, if i < 2 { 10 } else { 5 });
+            for batch in &batches {
+                all.extend(timestamps(batch).into_iter().zip(values(batch, 1)).zip(values(batch, 2)));
+            }
+        }
+        let expected: Vec<((i64, f64), f64)> = (0..25).map(|i| ((START_NS + i * 100_000_000, i as f64), -(i as f64))).collect();
+        assert_eq!(all, expected);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parquet_files_rotate_by_size() {
+        let dir = std::env::temp_dir().join(format!("daqhats-parquet-size-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let converter = BatchConverter::new(&info());
+        let mut recorder = ParquetRecorder::create(dir.join("run.parquet"), converter.schema(), Rotation::Size(1)).unwrap();
+        for first in [0, 100] {
+            recorder.write(&converter.block(&block(first, 100)).unwrap()).unwrap();
+        }
+        let files = recorder.finish().unwrap();
+        assert_eq!(files.len(), 2);
+        let (_, batches) = read_parquet(&files[1]);
+        assert_eq!(values(&batches[0], 1)[0], 100.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
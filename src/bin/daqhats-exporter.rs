@@ -0,0 +1,79 @@
+//! Serves Prometheus metrics for every board found, polling all analog and thermocouple inputs.
+//!
+//! Usage: `daqhats-exporter [--listen ADDR] [--interval SECONDS] [--tc-type TYPE]`
+//!
+//! `--tc-type` sets the thermocouple type of every MCC 134 channel (J, K, T, E, R, S, B or N);
+//! without it the channels keep their current configuration.
+
+use std::time::Duration;
+
+use daqhats::core::{AnyHat, Hat, TcType};
+use daqhats::metrics::Exporter;
+
+fn usage() -> ! {
+    eprintln!("usage: daqhats-exporter [--listen ADDR] [--interval SECONDS] [--tc-type J|K|T|E|R|S|B|N]");
+    std::process::exit(2);
+}
+
+fn parse_tc_type(name: &str) -> Option<TcType> {
+    Some(match name.to_ascii_uppercase().as_str() {
+        "J" => TcType::J,
+        "K" => TcType::K,
+        "T" => TcType::T,
+        "E" => TcType::E,
+        "R" => TcType::R,
+        "S" => TcType::S,
+        "B" => TcType::B,
+        "N" => TcType::N,
+        _ => return None,
+    })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut listen = "0.0.0.0:9118".to_string();
+    let mut interval = Duration::from_secs(5);
+    let mut tc_type = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => listen = value,
+            "--interval" => {
+                let secs = value.parse::<f64>().ok().filter(|s| *s > 0.0).unwrap_or_else(|| usage());
+                interval = Duration::from_secs_f64(secs);
+            }
+            "--tc-type" => tc_type = Some(parse_tc_type(&value).unwrap_or_else(|| usage())),
+            _ => usage(),
+        }
+    }
+
+    let mut exporter = Exporter::new();
+    for (info, result) in AnyHat::open_all() {
+        let mut dev = match result {
+            Ok(dev) => dev,
+            Err(err) => {
+                eprintln!("Skipping the {} at address {}: {}", info.product_name, info.address, err);
+                continue;
+            }
+        };
+        let caps = dev.capabilities();
+        let channels = (0..caps.ai_channels).collect::<Vec<u8>>();
+        if let (Some(tc), Some(tc_type)) = (dev.as_thermocouple(), tc_type) {
+            for &ch in &channels {
+                tc.tc_type_write(ch, tc_type)?;
+            }
+        }
+        match exporter.add_board(dev, &channels) {
+            Ok(()) => println!("Exporting {} channels of the {}", channels.len(), caps.product_name),
+            // boards without single-sample reads
+            Err(_) => println!("Skipping the {}", caps.product_name),
+        }
+    }
+
+    let handle = exporter.serve(&listen, interval)?;
+    println!("Serving metrics on http://{}/metrics", handle.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,63 @@
+//! Runs an acquisition described in a TOML job file (see `daqhats::job`).
+//!
+//! Usage: `daqhats-job [--check] JOB.toml`
+//!
+//! `--check` only validates the job against the connected boards. Otherwise the job runs until its
+//! `duration_s` has been acquired or it is interrupted with Ctrl-C or SIGTERM, printing each
+//! board's progress every second. An interrupted job still stops its scans and finishes its
+//! files.
+
+use std::time::Duration;
+
+use daqhats::core::{hat_list, HatId};
+use daqhats::job::JobConfig;
+
+fn usage() -> ! {
+    eprintln!("usage: daqhats-job [--check] JOB.toml");
+    std::process::exit(2);
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut check = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+
+    let config = JobConfig::load(&path)?;
+    config.validate(&hat_list(HatId::ANY))?;
+    if check {
+        println!("{}: OK", path);
+        return Ok(());
+    }
+
+    let job = config.start()?;
+    let stop = job.stop_handle();
+    ctrlc::set_handler(move || stop.stop())?;
+    println!("Started {}", config.name.as_deref().unwrap_or(&path));
+    while !job.is_finished() {
+        std::thread::sleep(Duration::from_secs(1));
+        for status in job.status() {
+            println!("board {} ({}): {} samples per channel", status.address, status.serial, status.samples_per_channel);
+        }
+    }
+
+    let (_, status) = job.join();
+    let mut failed = false;
+    for status in status {
+        println!("board {} ({}): ended with {:?}", status.address, status.serial, status.end);
+        if let Some(err) = status.error {
+            eprintln!("board {}: {}", status.address, err);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
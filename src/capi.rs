@@ -0,0 +1,387 @@
+//! A C ABI for block scanning, for C and C++ programs that want this crate's scan sessions.
+//!
+//! Build the shared library with `cargo rustc --release --lib --features capi --crate-type cdylib`,
+//! install it as `libdaqhats_rs.so` (the build names it `libdaqhats.so`, like the vendor library)
+//! and include `include/daqhats_rs.h`, which is generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/daqhats_rs.h`.
+//!
+//! A session wraps a [`scan_blocks`] stream behind an opaque pointer: start it, read blocks of
+//! interleaved samples, stop it and ask how it ended. Functions return 0 (`RESULT_SUCCESS`) or one
+//! of libdaqhats' negative result codes, so `hat_error_message` still applies; requests the board
+//! type can't do return `RESULT_BAD_PARAMETER`. [`daqhats_rs_last_error`] describes the latest
+//! failure on the calling thread.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CString};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::core::{AInScanner, AnyHat, ErrorCode};
+use crate::stream::{scan_blocks, ScanBlock, ScanEnd, ScanStream, StreamInfo};
+use crate::{Error, ScanOptions};
+
+const SUCCESS: c_int = 0;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(err: impl Into<Error>) -> c_int {
+    let err = err.into();
+    let code = match &err {
+        Error::Device(code) => *code,
+        Error::Capability(_) => ErrorCode::BadParameter,
+    };
+    let msg = CString::new(err.to_string()).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = msg);
+    code as c_int
+}
+
+/// How a session's scan ended.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Running = 0,
+    Stopped = 1,
+    HardwareOverrun = 2,
+    BufferOverrun = 3,
+    Finished = 4,
+    /// See the error code from [`daqhats_rs_session_outcome`].
+    Error = 5,
+}
+
+/// Where a block read by [`daqhats_rs_session_read_block`] belongs in the stream.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BlockInfo {
+    /// Samples per channel in the block; 0 once the scan has ended.
+    pub samples_per_channel: usize,
+    /// Per-channel index of the block's first sample.
+    pub first_sample: u64,
+    /// Time of the first sample, in seconds since the Unix epoch.
+    pub timestamp: f64,
+}
+
+/// A running or finished scan. Opaque to C.
+pub struct Session {
+    stream: Option<ScanStream<Box<dyn AInScanner + Send>>>,
+    info: StreamInfo,
+    /// A block that didn't fit the caller's buffer, returned by the next read.
+    pending: Option<ScanBlock>,
+    /// The device once the scan has ended, kept open until the session is freed.
+    device: Option<Box<dyn AInScanner + Send>>,
+    end: Option<ScanEnd>,
+}
+
+impl Session {
+    /// Starts a session on any scanner, e.g. a [`crate::sim::SimulatedHat`], for Rust code that
+    /// hands sessions to C through [`Session::into_raw`].
+    pub fn start<T: AInScanner + Send + 'static>(dev: T, opts: ScanOptions, samples_per_block: usize) -> Result<Box<Session>, Error> {
+        let stream = scan_blocks(Box::new(dev) as Box<dyn AInScanner + Send>, opts, samples_per_block).map_err(|(_, err)| err)?;
+        Ok(Box::new(Session { info: stream.info().clone(), stream: Some(stream), pending: None, device: None, end: None }))
+    }
+
+    /// The session as a pointer for the `daqhats_rs_session_*` functions, to be released with
+    /// [`daqhats_rs_session_free`].
+    pub fn into_raw(self: Box<Session>) -> *mut Session {
+        Box::into_raw(self)
+    }
+
+    fn finish(&mut self, stop: bool) {
+        if let Some(stream) = self.stream.take() {
+            let (dev, end) = if stop { stream.stop() } else { stream.join() };
+            self.device = Some(dev);
+            self.end = Some(end);
+        }
+    }
+}
+
+/// Opens the board at `address` and starts a continuous scan delivered in blocks of up to
+/// `samples_per_block` samples per channel.
+///
+/// # Safety
+///
+/// `options` must point to a valid `DaqhatsRsScanOptions` and `session` to writable storage for
+/// the new session pointer.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_start(address: u8, options: *const ScanOptions, samples_per_block: usize, session: *mut *mut Session) -> c_int {
+    if options.is_null() || session.is_null() {
+        return fail(ErrorCode::BadParameter);
+    }
+    let dev = match AnyHat::open(address) {
+        Ok(dev) => dev,
+        Err(err) => return fail(err),
+    };
+    let Ok(dev) = dev.into_a_in_scanner() else {
+        return fail(ErrorCode::InvalidDevice);
+    };
+    match Session::start(dev, *options, samples_per_block) {
+        Ok(new) => {
+            *session = new.into_raw();
+            SUCCESS
+        }
+        Err(err) => fail(err),
+    }
+}
+
+/// The number of channels interleaved in each block.
+///
+/// # Safety
+///
+/// `session` must be a live session pointer.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_channel_count(session: *const Session) -> usize {
+    session.as_ref().map_or(0, |session| session.info.channel_count())
+}
+
+/// The actual per-channel sample rate in S/s.
+///
+/// # Safety
+///
+/// `session` must be a live session pointer.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_sample_rate(session: *const Session) -> f64 {
+    session.as_ref().map_or(0.0, |session| session.info.sample_rate)
+}
+
+/// Copies the next block's interleaved samples into `buffer`, waiting up to `timeout_s` seconds
+/// (negative to wait indefinitely, 0 to not wait). Returns `RESULT_TIMEOUT` if no block arrived in
+/// time. Once the scan has ended, succeeds with `samples_per_channel` 0; see
+/// [`daqhats_rs_session_outcome`]. If the block doesn't fit, returns `RESULT_BAD_PARAMETER` with
+/// the block's size in `info` and keeps the block for the next call.
+///
+/// # Safety
+///
+/// `session` must be a live session pointer, `buffer` must point to `buffer_len` writable doubles
+/// and `info` to a writable `DaqhatsRsBlockInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_read_block(session: *mut Session, timeout_s: f64, buffer: *mut f64, buffer_len: usize, info: *mut BlockInfo) -> c_int {
+    let (Some(session), false, false) = (session.as_mut(), buffer.is_null(), info.is_null()) else {
+        return fail(ErrorCode::BadParameter);
+    };
+    *info = BlockInfo::default();
+
+    let block = match session.pending.take() {
+        Some(block) => block,
+        None => {
+            let Some(stream) = &session.stream else {
+                return SUCCESS;
+            };
+            let next = if timeout_s < 0.0 {
+                stream.recv().ok_or(RecvTimeoutError::Disconnected)
+            } else if timeout_s == 0.0 {
+                stream.try_recv().map_err(|err| match err {
+                    TryRecvError::Empty => RecvTimeoutError::Timeout,
+                    TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                })
+            } else {
+                stream.recv_timeout(Duration::from_secs_f64(timeout_s))
+            };
+            match next {
+                Ok(block) => block,
+                Err(RecvTimeoutError::Timeout) => return fail(ErrorCode::Timeout),
+                Err(RecvTimeoutError::Disconnected) => {
+                    session.finish(false);
+                    return SUCCESS;
+                }
+            }
+        }
+    };
+
+    *info = BlockInfo {
+        samples_per_channel: block.samples_per_channel(),
+        first_sample: block.first_sample,
+        timestamp: block.timestamp.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0),
+    };
+    if block.data.len() > buffer_len {
+        session.pending = Some(block);
+        return fail(ErrorCode::BadParameter);
+    }
+    std::slice::from_raw_parts_mut(buffer, block.data.len()).copy_from_slice(&block.data);
+    SUCCESS
+}
+
+/// Stops the scan. Blocks not yet read are discarded. Stopping an ended session does nothing.
+///
+/// # Safety
+///
+/// `session` must be a live session pointer.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_stop(session: *mut Session) -> c_int {
+    let Some(session) = session.as_mut() else {
+        return fail(ErrorCode::BadParameter);
+    };
+    session.pending = None;
+    session.finish(true);
+    SUCCESS
+}
+
+/// How the scan ended, or `DAQHATS_RS_OUTCOME_RUNNING`. For `DAQHATS_RS_OUTCOME_ERROR`,
+/// `error_code` (if not null) receives the result code.
+///
+/// # Safety
+///
+/// `session` must be a live session pointer and `error_code` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_outcome(session: *const Session, error_code: *mut c_int) -> Outcome {
+    let end = session.as_ref().and_then(|session| session.end);
+    if let (Some(ScanEnd::Error(code)), Some(error_code)) = (end, error_code.as_mut()) {
+        *error_code = code as c_int;
+    }
+    match end {
+        None => Outcome::Running,
+        Some(ScanEnd::Stopped) => Outcome::Stopped,
+        Some(ScanEnd::HardwareOverrun) => Outcome::HardwareOverrun,
+        Some(ScanEnd::BufferOverrun) => Outcome::BufferOverrun,
+        Some(ScanEnd::Finished) => Outcome::Finished,
+        Some(ScanEnd::Error(_)) => Outcome::Error,
+    }
+}
+
+/// Stops the scan if it is still running, closes the board and frees the session.
+///
+/// # Safety
+///
+/// `session` must be null or a live session pointer, which is invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn daqhats_rs_session_free(session: *mut Session) {
+    if !session.is_null() {
+        let mut session = Box::from_raw(session);
+        session.finish(true);
+    }
+}
+
+/// A description of the latest failure on the calling thread, valid until the next call that
+/// fails on that thread.
+#[no_mangle]
+pub extern "C" fn daqhats_rs_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    use super::*;
+    use crate::core::HatId;
+    use crate::sim::{Replay, SimulatedHat};
+
+    fn opts(channel_mask: u8) -> ScanOptions {
+        ScanOptions { channel_mask, sample_rate_per_channel: 1000.0, scale_data: true, calibrate_data: true, external_clock: false, external_trigger: false }
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(daqhats_rs_last_error()) }.to_string_lossy().into_owned()
+    }
+
+    fn outcome(session: *const Session) -> (Outcome, c_int) {
+        let mut code = 0;
+        let outcome = unsafe { daqhats_rs_session_outcome(session, &mut code) };
+        (outcome, code)
+    }
+
+    #[test]
+    fn blocks_that_dont_fit_are_kept() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, _t: f64| ch as f64).unwrap();
+        let session = Session::start(dev, opts(0b11), 10).unwrap().into_raw();
+        unsafe {
+            assert_eq!(daqhats_rs_session_channel_count(session), 2);
+            assert_eq!(daqhats_rs_session_sample_rate(session), 1000.0);
+
+            let mut small = [0.0; 3];
+            let mut info = BlockInfo::default();
+            assert_eq!(daqhats_rs_session_read_block(session, 1.0, small.as_mut_ptr(), small.len(), &mut info), ErrorCode::BadParameter as c_int);
+            assert!(info.samples_per_channel > 1);
+            assert!(!last_error().is_empty());
+            let wanted = info;
+
+            let mut buffer = [f64::NAN; 20];
+            assert_eq!(daqhats_rs_session_read_block(session, 0.0, buffer.as_mut_ptr(), buffer.len(), &mut info), SUCCESS);
+            assert_eq!((info.samples_per_channel, info.first_sample, info.timestamp), (wanted.samples_per_channel, wanted.first_sample, wanted.timestamp));
+            let values = &buffer[..info.samples_per_channel * 2];
+            assert!(values.chunks(2).all(|frame| frame == [0.0, 1.0]), "{:?}", values);
+
+            // the next block follows on
+            let first = info.first_sample + info.samples_per_channel as u64;
+            assert_eq!(daqhats_rs_session_read_block(session, 1.0, buffer.as_mut_ptr(), buffer.len(), &mut info), SUCCESS);
+            assert_eq!(info.first_sample, first);
+            daqhats_rs_session_free(session);
+        }
+    }
+
+    #[test]
+    fn reads_after_the_end_return_empty_blocks() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, Replay::new(vec![vec![0.5; 25]], 1000.0)).unwrap();
+        let session = Session::start(dev, opts(0b1), 10).unwrap().into_raw();
+        let mut buffer = [0.0; 10];
+        let mut info = BlockInfo::default();
+        let mut samples = 0;
+        unsafe {
+            loop {
+                assert_eq!(daqhats_rs_session_read_block(session, -1.0, buffer.as_mut_ptr(), buffer.len(), &mut info), SUCCESS);
+                if info.samples_per_channel == 0 {
+                    break;
+                }
+                samples += info.samples_per_channel;
+            }
+            assert_eq!(samples, 25);
+            assert_eq!(outcome(session), (Outcome::Finished, 0));
+
+            // and keep doing so
+            info.samples_per_channel = 99;
+            assert_eq!(daqhats_rs_session_read_block(session, 1.0, buffer.as_mut_ptr(), buffer.len(), &mut info), SUCCESS);
+            assert_eq!(info.samples_per_channel, 0);
+            assert_eq!(daqhats_rs_session_stop(session), SUCCESS);
+            assert_eq!(outcome(session), (Outcome::Finished, 0));
+            daqhats_rs_session_free(session);
+        }
+    }
+
+    #[test]
+    fn outcome_after_stop() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        let session = Session::start(dev, opts(0b1), 100).unwrap().into_raw();
+        unsafe {
+            assert_eq!(outcome(session), (Outcome::Running, 0));
+            assert_eq!(daqhats_rs_session_stop(session), SUCCESS);
+            assert_eq!(outcome(session), (Outcome::Stopped, 0));
+            assert_eq!(daqhats_rs_session_stop(session), SUCCESS);
+
+            let mut buffer = [0.0; 100];
+            let mut info = BlockInfo::default();
+            assert_eq!(daqhats_rs_session_read_block(session, -1.0, buffer.as_mut_ptr(), buffer.len(), &mut info), SUCCESS);
+            assert_eq!(info.samples_per_channel, 0);
+            daqhats_rs_session_free(session);
+        }
+    }
+
+    #[test]
+    fn failures_set_the_last_error() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        let session = Session::start(dev, opts(0b1), 100).unwrap().into_raw();
+        let mut buffer = [0.0; 100];
+        let mut info = BlockInfo::default();
+        unsafe {
+            // a 100-sample block at 1 kS/s can't have arrived yet
+            assert_eq!(daqhats_rs_session_read_block(session, 0.0, buffer.as_mut_ptr(), buffer.len(), &mut info), ErrorCode::Timeout as c_int);
+            assert_eq!(last_error(), Error::from(ErrorCode::Timeout).to_string());
+
+            assert_eq!(daqhats_rs_session_read_block(session, 0.0, ptr::null_mut(), 0, &mut info), ErrorCode::BadParameter as c_int);
+            assert_eq!(last_error(), Error::from(ErrorCode::BadParameter).to_string());
+            assert_eq!(daqhats_rs_session_stop(ptr::null_mut()), ErrorCode::BadParameter as c_int);
+            assert_eq!(daqhats_rs_session_channel_count(ptr::null()), 0);
+            assert_eq!(daqhats_rs_session_outcome(ptr::null(), ptr::null_mut()), Outcome::Running);
+            daqhats_rs_session_free(session);
+            daqhats_rs_session_free(ptr::null_mut());
+        }
+
+        // each thread has its own last error
+        std::thread::spawn(|| assert_eq!(last_error(), "")).join().unwrap();
+
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        let err = Session::start(dev, opts(0), 100).err().unwrap();
+        assert_eq!(fail(err), ErrorCode::BadParameter as c_int);
+        assert!(last_error().contains("channel"), "{}", last_error());
+    }
+}
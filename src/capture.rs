@@ -0,0 +1,466 @@
+//! A compact binary capture format for full-rate scans.
+//!
+//! A capture file is a header followed by the samples, interleaved by channel exactly as the scan
+//! delivered them. All values are little-endian.
+//!
+//! | field          | type                                                           |
+//! |----------------|----------------------------------------------------------------|
+//! | magic          | `b"DAQHCAP\0"`                                                 |
+//! | version        | `u16`, currently 1                                             |
+//! | header length  | `u32`, bytes from the start of the file to the first sample    |
+//! | device type    | `u16` [`HatId`]                                                |
+//! | address        | `u8`                                                           |
+//! | flags          | `u8`: bit 0 scaled, bit 1 calibrated                           |
+//! | sample format  | `u8` [`SampleFormat`]                                          |
+//! | channel count  | `u8` *n*                                                       |
+//! | channels       | *n* × `u8` physical channel numbers                            |
+//! | sample rate    | `f64` actual per-channel rate in S/s                           |
+//! | start time     | `u64` seconds + `u32` nanoseconds since the Unix epoch         |
+//! | serial         | `u16` length + UTF-8 bytes                                     |
+//! | units          | *n* × (`u8` length + UTF-8 bytes)                              |
+//! | cal. date      | `u8` length + UTF-8 bytes, empty if unknown                    |
+//!
+//! Readers skip any bytes between the last known field and the header length, so later versions
+//! can append fields. The number of samples is implied by the file length.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::HatId;
+use crate::stream::{ScanBlock, ScanStream, StreamInfo};
+
+const MAGIC: &[u8; 8] = b"DAQHCAP\0";
+const VERSION: u16 = 1;
+
+const FLAG_SCALED: u8 = 1 << 0;
+const FLAG_CALIBRATED: u8 = 1 << 1;
+
+/// How samples are stored in the payload.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// `f64` values as delivered by the scan.
+    F64 = 0,
+    /// `i32` ADC codes, for scans with `scale_data: false`. Values are rounded when written.
+    RawCode = 1,
+}
+
+impl SampleFormat {
+    /// The natural format for a stream: raw codes for unscaled data, `f64` otherwise.
+    pub fn for_stream(info: &StreamInfo) -> SampleFormat {
+        if info.scale_data {
+            SampleFormat::F64
+        } else {
+            SampleFormat::RawCode
+        }
+    }
+
+    pub fn sample_size(self) -> usize {
+        match self {
+            SampleFormat::F64 => 8,
+            SampleFormat::RawCode => 4,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<SampleFormat> {
+        match value {
+            0 => Some(SampleFormat::F64),
+            1 => Some(SampleFormat::RawCode),
+            _ => None,
+        }
+    }
+}
+
+/// Everything a capture file says about its samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureHeader {
+    pub info: StreamInfo,
+    pub format: SampleFormat,
+}
+
+impl CaptureHeader {
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let info = &self.info;
+        if info.channels.len() > u8::MAX as usize || info.units.len() != info.channels.len() {
+            return Err(invalid_input("a capture needs one unit per channel and at most 255 channels"));
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&(info.id as u16).to_le_bytes());
+        body.push(info.address);
+        let mut flags = 0;
+        if info.scale_data {
+            flags |= FLAG_SCALED;
+        }
+        if info.calibrate_data {
+            flags |= FLAG_CALIBRATED;
+        }
+        body.push(flags);
+        body.push(self.format as u8);
+        body.push(info.channels.len() as u8);
+        body.extend_from_slice(&info.channels);
+        body.extend_from_slice(&info.sample_rate.to_le_bytes());
+        let start = info.start_time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        body.extend_from_slice(&start.as_secs().to_le_bytes());
+        body.extend_from_slice(&start.subsec_nanos().to_le_bytes());
+        write_str(&mut body, &info.serial, u16::MAX as usize)?;
+        for unit in &info.units {
+            write_str(&mut body, unit, u8::MAX as usize)?;
+        }
+        write_str(&mut body, info.calibration_date.as_deref().unwrap_or(""), u8::MAX as usize)?;
+
+        let header_len = (MAGIC.len() + 2 + 4 + body.len()) as u32;
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&header_len.to_le_bytes())?;
+        w.write_all(&body)
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<CaptureHeader> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a capture file"));
+        }
+        let version = u16::from_le_bytes(read_array(r)?);
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported capture version {}", version)));
+        }
+        let header_len = u32::from_le_bytes(read_array(r)?) as usize;
+        let body_len = header_len
+            .checked_sub(MAGIC.len() + 2 + 4)
+            .ok_or_else(|| invalid_data("header length too short"))?;
+        // read through `take` so a corrupt length can't make us allocate gigabytes up front
+        let mut body = Vec::new();
+        r.take(body_len as u64).read_to_end(&mut body)?;
+        if body.len() < body_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut b = &body[..];
+
+        let id = hat_id_from_u16(u16::from_le_bytes(read_array(&mut b)?))?;
+        let [address, flags, format, channel_count] = read_array(&mut b)?;
+        let format = SampleFormat::from_u8(format).ok_or_else(|| invalid_data(format!("unknown sample format {}", format)))?;
+        let mut channels = vec![0u8; channel_count as usize];
+        b.read_exact(&mut channels)?;
+        let sample_rate = f64::from_le_bytes(read_array(&mut b)?);
+        let secs = u64::from_le_bytes(read_array(&mut b)?);
+        let nanos = u32::from_le_bytes(read_array(&mut b)?);
+        let serial_len = u16::from_le_bytes(read_array(&mut b)?) as usize;
+        let serial = read_str(&mut b, serial_len)?;
+        let mut units = Vec::with_capacity(channels.len());
+        for _ in 0..channels.len() {
+            let [len] = read_array(&mut b)?;
+            units.push(read_str(&mut b, len as usize)?);
+        }
+        let [len] = read_array(&mut b)?;
+        let calibration_date = Some(read_str(&mut b, len as usize)?).filter(|date| !date.is_empty());
+
+        Ok(CaptureHeader {
+            info: StreamInfo {
+                id,
+                address,
+                serial,
+                channels,
+                units,
+                sample_rate,
+                scale_data: flags & FLAG_SCALED != 0,
+                calibrate_data: flags & FLAG_CALIBRATED != 0,
+                calibration_date,
+                start_time: UNIX_EPOCH + Duration::new(secs, nanos),
+            },
+            format,
+        })
+    }
+}
+
+/// Streams scan blocks into a capture.
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+    format: SampleFormat,
+    channel_count: usize,
+    frames: u64,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, info: &StreamInfo, format: SampleFormat) -> io::Result<Self> {
+        CaptureWriter::new(BufWriter::new(File::create(path)?), info, format)
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the header. `info` should come from the stream that will feed the writer.
+    pub fn new(mut inner: W, info: &StreamInfo, format: SampleFormat) -> io::Result<Self> {
+        CaptureHeader { info: info.clone(), format }.write_to(&mut inner)?;
+        Ok(CaptureWriter { inner, format, channel_count: info.channel_count(), frames: 0 })
+    }
+
+    /// Samples per channel written so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn write_block(&mut self, block: &ScanBlock) -> io::Result<()> {
+        if block.channel_count != self.channel_count {
+            return Err(invalid_input(format!(
+                "block has {} channels, the capture has {}",
+                block.channel_count, self.channel_count
+            )));
+        }
+
+        let mut bytes = Vec::with_capacity(block.data.len() * self.format.sample_size());
+        match self.format {
+            SampleFormat::F64 => block.data.iter().for_each(|v| bytes.extend_from_slice(&v.to_le_bytes())),
+            SampleFormat::RawCode => block.data.iter().for_each(|v| bytes.extend_from_slice(&(v.round() as i32).to_le_bytes())),
+        }
+        self.inner.write_all(&bytes)?;
+        self.frames += block.samples_per_channel() as u64;
+        Ok(())
+    }
+
+    /// Writes blocks until the stream ends, then flushes.
+    pub fn record<T>(&mut self, stream: &ScanStream<T>) -> io::Result<()> {
+        for block in stream.iter() {
+            self.write_block(&block)?;
+        }
+        self.inner.flush()
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads a capture back as per-channel arrays.
+pub struct CaptureReader<R: Read> {
+    inner: R,
+    header: CaptureHeader,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        CaptureReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let header = CaptureHeader::read_from(&mut inner)?;
+        Ok(CaptureReader { inner, header })
+    }
+
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Reads up to `max_frames` samples per channel, one `Vec` per channel in the order of
+    /// `header().info.channels`. Returns `None` at the end of the capture. A trailing partial
+    /// frame, e.g. from a capture that was cut off, is ignored.
+    pub fn read_channels(&mut self, max_frames: usize) -> io::Result<Option<Vec<Vec<f64>>>> {
+        let channel_count = self.header.info.channel_count();
+        let frame_size = channel_count * self.header.format.sample_size();
+        if frame_size == 0 {
+            return Ok(None);
+        }
+
+        let mut bytes = Vec::with_capacity(max_frames * frame_size);
+        (&mut self.inner).take((max_frames * frame_size) as u64).read_to_end(&mut bytes)?;
+        let frames = bytes.len() / frame_size;
+        if frames == 0 {
+            return Ok(None);
+        }
+
+        let mut channels = vec![Vec::with_capacity(frames); channel_count];
+        let size = self.header.format.sample_size();
+        for (i, sample) in bytes[..frames * frame_size].chunks_exact(size).enumerate() {
+            let value = match self.header.format {
+                SampleFormat::F64 => f64::from_le_bytes(sample.try_into().unwrap()),
+                SampleFormat::RawCode => i32::from_le_bytes(sample.try_into().unwrap()) as f64,
+            };
+            channels[i % channel_count].push(value);
+        }
+        Ok(Some(channels))
+    }
+
+    /// Reads the rest of the capture into one array per channel.
+    pub fn read_all(mut self) -> io::Result<Vec<Vec<f64>>> {
+        let mut all = vec![Vec::new(); self.header.info.channel_count()];
+        while let Some(chunk) = self.read_channels(65536)? {
+            for (dst, src) in all.iter_mut().zip(chunk) {
+                dst.extend(src);
+            }
+        }
+        Ok(all)
+    }
+
+    /// Time of sample `index` of every channel.
+    pub fn sample_time(&self, index: u64) -> SystemTime {
+        let info = &self.header.info;
+        if info.sample_rate > 0.0 {
+            info.start_time + Duration::from_secs_f64(index as f64 / info.sample_rate)
+        } else {
+            info.start_time
+        }
+    }
+}
+
+fn hat_id_from_u16(value: u16) -> io::Result<HatId> {
+    [HatId::Mcc118, HatId::Mcc118Bootloader, HatId::Mcc128, HatId::Mcc134, HatId::Mcc152, HatId::Mcc172, HatId::ANY]
+        .into_iter()
+        .find(|&id| id as u16 == value)
+        .ok_or_else(|| invalid_data(format!("unknown device type {:#x}", value)))
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str, max_len: usize) -> io::Result<()> {
+    if s.len() > max_len {
+        return Err(invalid_input(format!("\"{}\" is too long for a capture header", s)));
+    }
+    if max_len > u8::MAX as usize {
+        buf.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    } else {
+        buf.push(s.len() as u8);
+    }
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn read_str<R: Read>(r: &mut R, len: usize) -> io::Result<String> {
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| invalid_data("header string is not UTF-8"))
+}
+
+fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn invalid_input(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(channels: &[u8], scale_data: bool) -> StreamInfo {
+        let unit = if scale_data { "V" } else { "code" };
+        StreamInfo {
+            address: 3,
+            serial: "01ABCDEF".to_string(),
+            units: vec![unit.to_string(); channels.len()],
+            scale_data,
+            calibration_date: Some("2023-05-17".to_string()),
+            start_time: UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+            ..StreamInfo::test(HatId::Mcc128, channels, 1000.0)
+        }
+    }
+
+    fn block(first_sample: u64, channel_count: usize, data: Vec<f64>) -> ScanBlock {
+        ScanBlock { first_sample, timestamp: UNIX_EPOCH, channel_count, data }
+    }
+
+    fn capture(info: &StreamInfo, format: SampleFormat, blocks: &[ScanBlock]) -> Vec<u8> {
+        let mut writer = CaptureWriter::new(Vec::new(), info, format).unwrap();
+        for block in blocks {
+            writer.write_block(block).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn f64_round_trip() {
+        let info = info(&[0, 2], true);
+        let blocks = [block(0, 2, vec![0.5, -1.25, 1.0e-9, 9.75]), block(2, 2, vec![f64::MAX, f64::MIN_POSITIVE])];
+        let bytes = capture(&info, SampleFormat::F64, &blocks);
+
+        let reader = CaptureReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.header(), &CaptureHeader { info, format: SampleFormat::F64 });
+        assert_eq!(reader.sample_time(500), UNIX_EPOCH + Duration::new(1_700_000_000, 623_456_789));
+        assert_eq!(reader.read_all().unwrap(), vec![vec![0.5, 1.0e-9, f64::MAX], vec![-1.25, 9.75, f64::MIN_POSITIVE]]);
+    }
+
+    #[test]
+    fn raw_code_round_trip() {
+        let mut info = info(&[1, 4, 7], false);
+        info.calibration_date = None;
+        let blocks = [block(0, 3, vec![0.0, 8191.6, -8192.0]), block(1, 3, vec![1.4, -2.5, 65535.0]), block(2, 3, vec![3.0, 4.0, 5.0])];
+        let bytes = capture(&info, SampleFormat::RawCode, &blocks);
+
+        let reader = CaptureReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.header().info, info);
+        assert_eq!(reader.header().format, SampleFormat::RawCode);
+        assert_eq!(reader.read_all().unwrap(), vec![vec![0.0, 1.0, 3.0], vec![8192.0, -3.0, 4.0], vec![-8192.0, 65535.0, 5.0]]);
+    }
+
+    #[test]
+    fn reads_in_chunks_and_ignores_a_partial_frame() {
+        let info = info(&[0, 1], true);
+        let data = (0..20).map(f64::from).collect::<Vec<_>>();
+        let mut bytes = capture(&info, SampleFormat::F64, &[block(0, 2, data)]);
+        bytes.extend_from_slice(&1.0f64.to_le_bytes());
+
+        let mut reader = CaptureReader::new(&bytes[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(chunk) = reader.read_channels(3).unwrap() {
+            assert!(chunk[0].len() <= 3);
+            frames.extend(chunk[0].iter().zip(&chunk[1]).map(|(&a, &b)| (a, b)));
+        }
+        assert_eq!(frames, (0..10).map(|i| (2.0 * i as f64, 2.0 * i as f64 + 1.0)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rejects_mismatched_blocks() {
+        let mut writer = CaptureWriter::new(Vec::new(), &info(&[0, 1], true), SampleFormat::F64).unwrap();
+        let err = writer.write_block(&block(0, 3, vec![0.0; 3])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.frames(), 0);
+    }
+
+    #[test]
+    fn corrupt_headers() {
+        let bytes = capture(&info(&[0], true), SampleFormat::F64, &[block(0, 1, vec![1.0])]);
+        let read_err = |bytes: &[u8]| CaptureReader::new(bytes).err().expect("header should not parse").kind();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(read_err(&bad_magic), io::ErrorKind::InvalidData);
+
+        let mut bad_version = bytes.clone();
+        bad_version[8..10].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(read_err(&bad_version), io::ErrorKind::InvalidData);
+
+        let mut short_len = bytes.clone();
+        short_len[10..14].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(read_err(&short_len), io::ErrorKind::InvalidData);
+
+        let mut huge_len = bytes.clone();
+        huge_len[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_err(&huge_len), io::ErrorKind::UnexpectedEof);
+
+        let mut bad_id = bytes.clone();
+        bad_id[14..16].copy_from_slice(&0xbeefu16.to_le_bytes());
+        assert_eq!(read_err(&bad_id), io::ErrorKind::InvalidData);
+
+        let mut bad_format = bytes.clone();
+        bad_format[18] = 7;
+        assert_eq!(read_err(&bad_format), io::ErrorKind::InvalidData);
+
+        let header_len = u32::from_le_bytes(bytes[10..14].try_into().unwrap()) as usize;
+        let mut no_date = bytes[..header_len - 11].to_vec();
+        no_date[10..14].copy_from_slice(&(header_len as u32 - 11).to_le_bytes());
+        assert_eq!(read_err(&no_date), io::ErrorKind::UnexpectedEof);
+
+        for len in [0, 5, 12, 20, header_len - 1] {
+            assert_eq!(read_err(&bytes[..len]), io::ErrorKind::UnexpectedEof, "header cut to {} bytes", len);
+        }
+    }
+}
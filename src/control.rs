@@ -0,0 +1,577 @@
+//! Closed-loop PID control from an analog input to an analog output, e.g. regulating pressure
+//! read on an MCC 118 with an MCC 152 output.
+//!
+//! [`Pid`] is the controller on its own. [`ControlLoop`] runs it at a fixed rate from a timer,
+//! polling any [`AIn`] device with `a_in_read`; [`ScanControl`] runs it from the blocks of a
+//! stream instead, so the loop is paced by the scan clock.
+
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{AIn, AOut, CapabilityError, ErrorCode, ScanOptions};
+use crate::stream::{ScanBlock, StopHandle, StreamInfo};
+use crate::Error;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PidConfig {
+    pub kp: f64,
+    /// Integral gain, per second.
+    pub ki: f64,
+    /// Derivative gain, in seconds. The derivative is taken of the measurement rather than the
+    /// error, so setpoint changes don't kick the output.
+    pub kd: f64,
+    pub setpoint: f64,
+    /// Limit on how fast the setpoint moves towards a new value, in units per second. `None` to
+    /// step straight to it.
+    pub setpoint_ramp: Option<f64>,
+    pub output_min: f64,
+    pub output_max: f64,
+}
+
+/// A PID controller with output clamping, anti-windup and setpoint ramping.
+///
+/// The integral stops accumulating in the direction that would drive the output further into
+/// saturation, so the controller recovers as soon as the error changes sign.
+#[derive(Clone, Debug)]
+pub struct Pid {
+    config: PidConfig,
+    // where the setpoint is ramping from, `None` until the first update
+    setpoint: Option<f64>,
+    integral: f64,
+    last_measurement: Option<f64>,
+    output: f64,
+}
+
+impl Pid {
+    pub fn new(config: PidConfig) -> Pid {
+        assert!(config.output_min <= config.output_max, "output_min must not be above output_max");
+        Pid { config, setpoint: None, integral: 0.0, last_measurement: None, output: config.output_min }
+    }
+
+    pub fn config(&self) -> &PidConfig {
+        &self.config
+    }
+
+    /// Changes the target setpoint. With a ramp, the setpoint moves towards it over the
+    /// following updates.
+    pub fn set_setpoint(&mut self, setpoint: f64) {
+        self.config.setpoint = setpoint;
+    }
+
+    /// The setpoint in effect, which lags the target while ramping.
+    pub fn setpoint(&self) -> f64 {
+        self.setpoint.unwrap_or(self.config.setpoint)
+    }
+
+    /// Changes the gains without a bump in the output, since the integral is stored already
+    /// multiplied by `ki`.
+    pub fn set_gains(&mut self, kp: f64, ki: f64, kd: f64) {
+        self.config.kp = kp;
+        self.config.ki = ki;
+        self.config.kd = kd;
+    }
+
+    /// The output of the last update, or `output_min` before the first one.
+    pub fn output(&self) -> f64 {
+        self.output
+    }
+
+    /// Clears the integral and derivative history. The setpoint ramp restarts from the next
+    /// measurement.
+    pub fn reset(&mut self) {
+        self.setpoint = None;
+        self.integral = 0.0;
+        self.last_measurement = None;
+        self.output = self.config.output_min;
+    }
+
+    /// Advances the controller by `dt` with a new measurement and returns the clamped output.
+    pub fn update(&mut self, measurement: f64, dt: Duration) -> f64 {
+        let PidConfig { kp, ki, kd, setpoint: target, setpoint_ramp, output_min, output_max } = self.config;
+        let dt = dt.as_secs_f64();
+
+        let setpoint = match (self.setpoint, setpoint_ramp) {
+            (Some(current), Some(rate)) if rate > 0.0 => current + (target - current).clamp(-rate * dt, rate * dt),
+            // a ramp starts from wherever the process is, so starting the loop doesn't step the output
+            (None, Some(rate)) if rate > 0.0 => measurement,
+            _ => target,
+        };
+        self.setpoint = Some(setpoint);
+
+        let error = setpoint - measurement;
+        let p = kp * error;
+        let d = match self.last_measurement {
+            Some(last) if dt > 0.0 => -kd * (measurement - last) / dt,
+            _ => 0.0,
+        };
+        self.last_measurement = Some(measurement);
+
+        // the integral may grow only until the output saturates, and never shrinks because of it
+        let integral = self.integral + ki * error * dt;
+        let integral = if integral > self.integral {
+            integral.min((output_max - p - d).max(self.integral))
+        } else {
+            integral.max((output_min - p - d).min(self.integral))
+        };
+        self.integral = integral.clamp(output_min, output_max);
+
+        self.output = (p + self.integral + d).clamp(output_min, output_max);
+        self.output
+    }
+}
+
+/// One iteration of a control loop.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ControlSample {
+    pub timestamp: SystemTime,
+    pub setpoint: f64,
+    pub measurement: f64,
+    pub output: f64,
+}
+
+/// Where a loop writes its output.
+struct Output<O> {
+    dev: O,
+    channel: u8,
+    /// Written when the loop stops.
+    safe_value: Option<f64>,
+}
+
+impl<O: AOut> Output<O> {
+    fn new(dev: O, channel: u8) -> Result<Output<O>, Error> {
+        let caps = dev.capabilities();
+        if caps.ao_channels == 0 {
+            return Err(ErrorCode::InvalidDevice.into());
+        }
+        if channel >= caps.ao_channels {
+            return Err(CapabilityError::ChannelOutOfRange { product: caps.product_name, channel, num_channels: caps.ao_channels }.into());
+        }
+        Ok(Output { dev, channel, safe_value: None })
+    }
+
+    /// Narrows the controller's output limits to what the board can produce, e.g. 0–5 V on the
+    /// MCC 152.
+    fn clamp_config(&self, mut config: PidConfig) -> PidConfig {
+        if let Some(range) = self.dev.capabilities().ao_range {
+            config.output_min = config.output_min.clamp(range.min, range.max);
+            config.output_max = config.output_max.clamp(range.min, range.max);
+        }
+        config
+    }
+
+    fn write(&mut self, value: f64) -> Result<(), ErrorCode> {
+        self.dev.a_out_write(self.channel, ScanOptions::DEFAULT, value)
+    }
+
+    fn write_safe(&mut self) -> Result<(), ErrorCode> {
+        match self.safe_value {
+            Some(value) => self.write(value),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A PID loop that reads one channel of an analog input device and drives one analog output,
+/// paced by a timer.
+pub struct ControlLoop<I, O> {
+    input: I,
+    input_channel: u8,
+    input_options: ScanOptions,
+    output: Output<O>,
+    pid: Pid,
+}
+
+impl<I: AIn, O: AOut> ControlLoop<I, O> {
+    /// The output limits in `config` are narrowed to the output board's range. Fails if either
+    /// channel doesn't exist on its board.
+    pub fn new(input: I, input_channel: u8, output: O, output_channel: u8, config: PidConfig) -> Result<ControlLoop<I, O>, Error> {
+        let caps = input.capabilities();
+        if input_channel >= caps.ai_channels {
+            return Err(CapabilityError::ChannelOutOfRange { product: caps.product_name, channel: input_channel, num_channels: caps.ai_channels }.into());
+        }
+        let output = Output::new(output, output_channel)?;
+        let pid = Pid::new(output.clamp_config(config));
+        Ok(ControlLoop { input, input_channel, input_options: ScanOptions::DEFAULT, output, pid })
+    }
+
+    /// Options for the input's `a_in_read`, e.g. [`ScanOptions::NOCALIBRATEDATA`].
+    pub fn with_input_options(mut self, options: ScanOptions) -> Self {
+        self.input_options = options;
+        self
+    }
+
+    /// A value written to the output when the loop stops, on its own or after an error.
+    pub fn with_safe_output(mut self, value: f64) -> Self {
+        self.output.safe_value = Some(value);
+        self
+    }
+
+    pub fn pid(&self) -> &Pid {
+        &self.pid
+    }
+
+    pub fn pid_mut(&mut self) -> &mut Pid {
+        &mut self.pid
+    }
+
+    /// Reads the input, updates the controller by `dt` and writes the output.
+    pub fn step(&mut self, dt: Duration) -> Result<ControlSample, ErrorCode> {
+        let measurement = self.input.a_in_read(self.input_channel, self.input_options)?;
+        let output = self.pid.update(measurement, dt);
+        self.output.write(output)?;
+        Ok(ControlSample { timestamp: SystemTime::now(), setpoint: self.pid.setpoint(), measurement, output })
+    }
+
+    pub fn into_devices(self) -> (I, O) {
+        (self.input, self.output.dev)
+    }
+}
+
+impl<I: AIn + Send + 'static, O: AOut + Send + 'static> ControlLoop<I, O> {
+    /// Runs the loop on its own thread at `rate` updates per second until stopped or a read or
+    /// write fails. Late iterations are skipped rather than run back to back, and each update
+    /// uses the time actually elapsed since the previous one.
+    pub fn run(mut self, rate: f64) -> Result<ControlHandle<ControlLoop<I, O>>, Error> {
+        if rate.is_nan() || rate <= 0.0 {
+            return Err(CapabilityError::InvalidRate { requested: rate }.into());
+        }
+        let period = Duration::from_secs_f64(1.0 / rate);
+
+        let (sample_tx, sample_rx) = mpsc::sync_channel(SAMPLE_QUEUE);
+        let (setpoint_tx, setpoint_rx) = mpsc::channel::<f64>();
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+
+        let handle = std::thread::spawn(move || {
+            let mut last = Instant::now();
+            let mut next = last;
+            let mut result = loop {
+                if thread_stop.is_stopped() {
+                    break Ok(());
+                }
+                if let Some(setpoint) = setpoint_rx.try_iter().last() {
+                    self.pid.set_setpoint(setpoint);
+                }
+
+                let now = Instant::now();
+                let sample = match self.step(now - last) {
+                    Ok(sample) => sample,
+                    Err(err) => break Err(err),
+                };
+                last = now;
+                // nobody is reading the samples or they're behind; the loop must not wait on them
+                let _ = sample_tx.try_send(sample);
+
+                next += period;
+                let now = Instant::now();
+                if next < now {
+                    let behind = (now - next).as_secs_f64() / period.as_secs_f64();
+                    next += period * (behind.ceil() as u32);
+                }
+                // wake up often enough to stop promptly, even at slow loop rates
+                let mut now = now;
+                while now < next && !thread_stop.is_stopped() {
+                    std::thread::sleep((next - now).min(POLL_STOP_INTERVAL));
+                    now = Instant::now();
+                }
+            };
+
+            if let Err(err) = self.output.write_safe() {
+                result = result.and(Err(err));
+            }
+            (self, result)
+        });
+
+        Ok(ControlHandle { samples: sample_rx, setpoints: setpoint_tx, stop, handle })
+    }
+}
+
+/// Drives a PID loop from the blocks of a stream, updating at a fixed rate derived from the
+/// stream's sample rate. The samples between updates are averaged, so the loop sees the whole
+/// period rather than one possibly noisy sample.
+pub struct ScanControl<O> {
+    index: usize,
+    sample_rate: f64,
+    samples_per_update: usize,
+    output: Output<O>,
+    pid: Pid,
+    sum: f64,
+    count: usize,
+    last_update: Option<SystemTime>,
+}
+
+impl<O: AOut> ScanControl<O> {
+    /// Updates every `sample_rate / loop_rate` samples of `input_channel`, at least every sample.
+    /// For streams without a fixed rate (polled streams) every sample is an update, with `dt`
+    /// taken from the block timestamps. Fails with [`ErrorCode::BadParameter`] if the channel
+    /// isn't part of the stream.
+    pub fn new(info: &StreamInfo, input_channel: u8, output: O, output_channel: u8, config: PidConfig, loop_rate: f64) -> Result<ScanControl<O>, Error> {
+        let index = info.channels.iter().position(|&ch| ch == input_channel).ok_or(ErrorCode::BadParameter)?;
+        if loop_rate.is_nan() || loop_rate <= 0.0 {
+            return Err(CapabilityError::InvalidRate { requested: loop_rate }.into());
+        }
+        let samples_per_update = if info.sample_rate > 0.0 { ((info.sample_rate / loop_rate).round() as usize).max(1) } else { 1 };
+        let output = Output::new(output, output_channel)?;
+        let pid = Pid::new(output.clamp_config(config));
+
+        Ok(ScanControl { index, sample_rate: info.sample_rate, samples_per_update, output, pid, sum: 0.0, count: 0, last_update: None })
+    }
+
+    /// A value written to the output by [`ScanControl::stop`].
+    pub fn with_safe_output(mut self, value: f64) -> Self {
+        self.output.safe_value = Some(value);
+        self
+    }
+
+    pub fn pid(&self) -> &Pid {
+        &self.pid
+    }
+
+    pub fn pid_mut(&mut self) -> &mut Pid {
+        &mut self.pid
+    }
+
+    /// Runs the updates completed by this block and returns them. Stops at the first failed
+    /// write; the updates before it were applied.
+    pub fn process(&mut self, block: &ScanBlock) -> Result<Vec<ControlSample>, ErrorCode> {
+        let mut samples = Vec::new();
+        let dt = if self.sample_rate > 0.0 { Duration::from_secs_f64(self.samples_per_update as f64 / self.sample_rate) } else { Duration::ZERO };
+
+        for (i, x) in block.channel(self.index).enumerate() {
+            self.sum += x;
+            self.count += 1;
+            if self.count < self.samples_per_update {
+                continue;
+            }
+
+            let measurement = self.sum / self.count as f64;
+            let timestamp = block.sample_time(i, self.sample_rate);
+            let dt = if self.sample_rate > 0.0 {
+                dt
+            } else {
+                self.last_update.and_then(|last| timestamp.duration_since(last).ok()).unwrap_or_default()
+            };
+            self.last_update = Some(timestamp);
+            self.sum = 0.0;
+            self.count = 0;
+
+            let output = self.pid.update(measurement, dt);
+            self.output.write(output)?;
+            samples.push(ControlSample { timestamp, setpoint: self.pid.setpoint(), measurement, output });
+        }
+
+        Ok(samples)
+    }
+
+    /// Writes the safe output, if any, and returns the output device.
+    pub fn stop(mut self) -> Result<O, ErrorCode> {
+        self.output.write_safe()?;
+        Ok(self.output.dev)
+    }
+}
+
+// samples kept for a slow reader before new ones are dropped
+const SAMPLE_QUEUE: usize = 1024;
+
+const POLL_STOP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A control loop running on its own thread. See [`ControlLoop::run`].
+pub struct ControlHandle<T> {
+    samples: mpsc::Receiver<ControlSample>,
+    setpoints: mpsc::Sender<f64>,
+    stop: StopHandle,
+    handle: JoinHandle<(T, Result<(), ErrorCode>)>,
+}
+
+impl<T> ControlHandle<T> {
+    /// Changes the target setpoint from the next iteration on.
+    pub fn set_setpoint(&self, setpoint: f64) {
+        // fails only once the loop has ended
+        let _ = self.setpoints.send(setpoint);
+    }
+
+    /// Iterations of the loop, oldest first. If they aren't received, up to 1024 are kept and
+    /// later ones are dropped.
+    pub fn samples(&self) -> mpsc::TryIter<'_, ControlSample> {
+        self.samples.try_iter()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ControlSample, mpsc::RecvTimeoutError> {
+        self.samples.recv_timeout(timeout)
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops the loop and returns it along with the error that ended it, if any.
+    pub fn stop(self) -> (T, Result<(), ErrorCode>) {
+        self.stop.stop();
+        self.handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::core::HatId;
+    use crate::sim::SimulatedHat;
+
+    fn config(kp: f64, ki: f64, kd: f64, setpoint: f64, output_min: f64, output_max: f64) -> PidConfig {
+        PidConfig { kp, ki, kd, setpoint, setpoint_ramp: None, output_min, output_max }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn integral_is_clamped_to_the_output_range() {
+        let mut pid = Pid::new(config(0.0, 1.0, 0.0, 10.0, 0.0, 1.0));
+        for _ in 0..100 {
+            assert!(pid.update(0.0, Duration::from_secs(1)) <= 1.0);
+        }
+        assert_eq!(pid.output(), 1.0);
+        // 100 s of an error of 10 would have wound the integral up to 1000; one step back is enough
+        assert_close(pid.update(20.0, Duration::from_millis(100)), 0.0);
+    }
+
+    #[test]
+    fn integral_stops_while_saturated() {
+        let mut pid = Pid::new(config(0.5, 1.0, 0.0, 10.0, -1.0, 1.0));
+        for _ in 0..50 {
+            assert_eq!(pid.update(0.0, Duration::from_millis(100)), 1.0);
+        }
+        // the integral didn't grow while the proportional term alone saturated the output
+        assert_close(pid.update(10.5, Duration::from_millis(100)), -0.25 - 0.05);
+        // and unwinding it isn't blocked when it pulls out of saturation
+        assert_close(pid.update(10.5, Duration::from_millis(100)), -0.25 - 0.1);
+    }
+
+    #[test]
+    fn setpoint_ramp_limits_the_rate() {
+        let mut pid = Pid::new(PidConfig { setpoint_ramp: Some(2.0), ..config(1.0, 0.0, 0.0, 10.0, -100.0, 100.0) });
+        let dt = Duration::from_millis(500);
+        let mut setpoints = Vec::new();
+        for _ in 0..9 {
+            let output = pid.update(4.0, dt);
+            assert_close(output, pid.setpoint() - 4.0);
+            setpoints.push(pid.setpoint());
+        }
+        // starts where the process is, then moves 1 per half second
+        assert_eq!(setpoints, [4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 10.0]);
+
+        pid.set_setpoint(8.5);
+        pid.update(4.0, dt);
+        assert_eq!(pid.setpoint(), 9.0);
+        pid.update(4.0, dt);
+        assert_eq!(pid.setpoint(), 8.5);
+
+        pid.reset();
+        assert_eq!(pid.output(), -100.0);
+        pid.update(1.0, dt);
+        assert_eq!(pid.setpoint(), 1.0);
+    }
+
+    #[test]
+    fn setpoint_steps_without_a_ramp() {
+        let mut pid = Pid::new(config(1.0, 0.0, 0.0, 10.0, -100.0, 100.0));
+        assert_close(pid.update(4.0, Duration::from_millis(500)), 6.0);
+        assert_eq!(pid.setpoint(), 10.0);
+    }
+
+    #[test]
+    fn derivative_ignores_setpoint_changes() {
+        let mut pid = Pid::new(config(0.0, 0.0, 1.0, 0.0, -10.0, 10.0));
+        let dt = Duration::from_millis(100);
+        pid.update(1.0, dt);
+        pid.set_setpoint(5.0);
+        assert_close(pid.update(1.0, dt), 0.0);
+        // a rising measurement pushes the output down
+        assert_close(pid.update(1.2, dt), -2.0);
+    }
+
+    #[test]
+    fn scan_control_averages_and_writes() {
+        let info = StreamInfo::test(HatId::Mcc118, &[4, 6], 100.0);
+        let output = SimulatedHat::new(HatId::Mcc152, 1, |_, _| 0.0).unwrap();
+        // the output limits are narrowed to the MCC 152's 0-5 V
+        let mut control = ScanControl::new(&info, 6, output, 1, config(1.0, 0.0, 0.0, 2.0, -10.0, 10.0), 10.0).unwrap().with_safe_output(0.5);
+        assert_eq!((control.pid().config().output_min, control.pid().config().output_max), (0.0, 5.0));
+
+        // 25 frames of channel 6 at 0.0, 0.1, 0.2, ...: updates after frames 10 and 20
+        let data = (0..25).flat_map(|i| [9.0, i as f64 / 10.0]).collect();
+        let block = ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 2, data };
+        let samples = control.process(&block).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_close(samples[0].measurement, 0.45);
+        assert_close(samples[0].output, 1.55);
+        assert_eq!(samples[0].timestamp, UNIX_EPOCH + Duration::from_millis(90));
+        assert_close(samples[1].measurement, 1.45);
+        assert_close(samples[1].output, 0.55);
+
+        let output = control.stop().unwrap();
+        assert_eq!(output.analog_outputs(), [0.0, 0.5]);
+    }
+
+    #[test]
+    fn run_updates_until_stopped() {
+        let input = SimulatedHat::new(HatId::Mcc118, 0, |ch, _| if ch == 3 { 1.0 } else { 0.0 }).unwrap();
+        let output = SimulatedHat::new(HatId::Mcc152, 1, |_, _| 0.0).unwrap();
+        let control = ControlLoop::new(input, 3, output, 0, config(1.0, 0.0, 0.0, 2.5, -10.0, 10.0)).unwrap().with_safe_output(0.25);
+        let handle = control.run(50.0).unwrap();
+
+        let sample = handle.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_close(sample.measurement, 1.0);
+        assert_close(sample.output, 1.5);
+        handle.set_setpoint(4.0);
+        let sample = std::iter::repeat_with(|| handle.recv_timeout(Duration::from_secs(1)).unwrap()).find(|sample| sample.setpoint == 4.0).unwrap();
+        assert_close(sample.output, 3.0);
+
+        let (control, result) = handle.stop();
+        result.unwrap();
+        let (_, output) = control.into_devices();
+        assert_eq!(output.analog_outputs(), [0.25, 0.0]);
+    }
+
+    #[test]
+    fn run_stops_promptly_at_slow_rates() {
+        let input = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        let output = SimulatedHat::new(HatId::Mcc152, 1, |_, _| 0.0).unwrap();
+        let control = ControlLoop::new(input, 0, output, 1, config(1.0, 0.0, 0.0, 1.0, 0.0, 5.0)).unwrap().with_safe_output(0.5);
+        let handle = control.run(0.1).unwrap();
+        handle.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        let started = Instant::now();
+        let (control, result) = handle.stop();
+        assert!(started.elapsed() < Duration::from_secs(1), "stopping took {:?}", started.elapsed());
+        result.unwrap();
+        let (input, output) = control.into_devices();
+        assert_eq!(output.analog_outputs(), [0.0, 0.5]);
+
+        let control = ControlLoop::new(input, 0, output, 1, config(1.0, 0.0, 0.0, 1.0, 0.0, 5.0)).unwrap();
+        assert!(matches!(control.run(0.0), Err(Error::Capability(CapabilityError::InvalidRate { .. }))));
+    }
+
+    #[test]
+    fn rejects_bad_channels() {
+        let input = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        let output = SimulatedHat::new(HatId::Mcc152, 1, |_, _| 0.0).unwrap();
+        let err = ControlLoop::new(input, 8, output, 0, config(1.0, 0.0, 0.0, 0.0, 0.0, 5.0)).err().unwrap();
+        assert!(matches!(err, Error::Capability(CapabilityError::ChannelOutOfRange { channel: 8, .. })));
+
+        let input = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        let output = SimulatedHat::new(HatId::Mcc118, 1, |_, _| 0.0).unwrap();
+        let err = ControlLoop::new(input, 0, output, 0, config(1.0, 0.0, 0.0, 0.0, 0.0, 5.0)).err().unwrap();
+        assert!(matches!(err, Error::Device(ErrorCode::InvalidDevice)));
+    }
+}
@@ -0,0 +1,197 @@
+use super::{hat_list, AIn, AInScanner, AOut, Dio, ErrorCode, FirmwareVersion, Hat, HatId, HatInfo, Iepe, Thermocouple};
+use super::{Mcc118, Mcc128, Mcc134, Mcc152, Mcc172};
+
+/// Any supported board, opened as the concrete wrapper matching the ID the board reports in
+/// [`hat_list`].
+pub enum AnyHat {
+    Mcc118(Mcc118),
+    Mcc128(Mcc128),
+    Mcc134(Mcc134),
+    Mcc152(Mcc152),
+    Mcc172(Mcc172),
+}
+
+impl AnyHat {
+    /// Opens the board at `address`, whatever its type. Fails with [`ErrorCode::InvalidDevice`] if
+    /// there is no board there or it is in bootloader mode.
+    pub fn open(address: u8) -> Result<AnyHat, ErrorCode> {
+        let info = hat_list(HatId::ANY)
+            .into_iter()
+            .find(|info| info.address == address)
+            .ok_or(ErrorCode::InvalidDevice)?;
+
+        AnyHat::open_info(&info)
+    }
+
+    /// Opens the board described by an entry of [`hat_list`].
+    pub fn open_info(info: &HatInfo) -> Result<AnyHat, ErrorCode> {
+        match info.id {
+            HatId::Mcc118 => Mcc118::open(info.address).map(AnyHat::Mcc118),
+            HatId::Mcc128 => Mcc128::open(info.address).map(AnyHat::Mcc128),
+            HatId::Mcc134 => Mcc134::open(info.address).map(AnyHat::Mcc134),
+            HatId::Mcc152 => Mcc152::open(info.address).map(AnyHat::Mcc152),
+            HatId::Mcc172 => Mcc172::open(info.address).map(AnyHat::Mcc172),
+            HatId::ANY | HatId::Mcc118Bootloader => Err(ErrorCode::InvalidDevice),
+        }
+    }
+
+    /// Opens every board listed by [`hat_list`], returning each entry with the result of opening
+    /// it, so one board that fails (e.g. in bootloader mode or already open elsewhere) doesn't
+    /// hide the others.
+    pub fn open_all() -> Vec<(HatInfo, Result<AnyHat, ErrorCode>)> {
+        AnyHat::open_list(hat_list(HatId::ANY))
+    }
+
+    fn open_list(list: Vec<HatInfo>) -> Vec<(HatInfo, Result<AnyHat, ErrorCode>)> {
+        list.into_iter()
+            .map(|info| {
+                let result = AnyHat::open_info(&info);
+                (info, result)
+            })
+            .collect()
+    }
+
+    /// The board as a single-sample analog input, if it has analog inputs that support it.
+    pub fn as_a_in(&mut self) -> Option<&mut dyn AIn> {
+        match self {
+            AnyHat::Mcc118(dev) => Some(dev),
+            AnyHat::Mcc128(dev) => Some(dev),
+            AnyHat::Mcc134(dev) => Some(dev),
+            AnyHat::Mcc152(_) | AnyHat::Mcc172(_) => None,
+        }
+    }
+
+    /// The board as a hardware-paced analog input scanner, if it supports scanning.
+    pub fn as_a_in_scanner(&mut self) -> Option<&mut dyn AInScanner> {
+        match self {
+            AnyHat::Mcc118(dev) => Some(dev),
+            AnyHat::Mcc128(dev) => Some(dev),
+            AnyHat::Mcc172(dev) => Some(dev),
+            AnyHat::Mcc134(_) | AnyHat::Mcc152(_) => None,
+        }
+    }
+
+    pub fn as_a_out(&mut self) -> Option<&mut dyn AOut> {
+        match self {
+            AnyHat::Mcc152(dev) => Some(dev),
+            _ => None,
+        }
+    }
+
+    pub fn as_dio(&mut self) -> Option<&mut dyn Dio> {
+        match self {
+            AnyHat::Mcc152(dev) => Some(dev),
+            _ => None,
+        }
+    }
+
+    pub fn as_thermocouple(&mut self) -> Option<&mut dyn Thermocouple> {
+        match self {
+            AnyHat::Mcc134(dev) => Some(dev),
+            _ => None,
+        }
+    }
+
+    pub fn as_iepe(&mut self) -> Option<&mut dyn Iepe> {
+        match self {
+            AnyHat::Mcc172(dev) => Some(dev),
+            _ => None,
+        }
+    }
+
+    /// Converts the board into an owned scanner, e.g. to hand it to [`crate::scan_channels`].
+    /// Gives the board back unchanged if it can't scan.
+    pub fn into_a_in_scanner(self) -> Result<Box<dyn AInScanner + Send>, AnyHat> {
+        match self {
+            AnyHat::Mcc118(dev) => Ok(Box::new(dev)),
+            AnyHat::Mcc128(dev) => Ok(Box::new(dev)),
+            AnyHat::Mcc172(dev) => Ok(Box::new(dev)),
+            other => Err(other),
+        }
+    }
+
+    fn as_hat(&self) -> &dyn Hat {
+        match self {
+            AnyHat::Mcc118(dev) => dev,
+            AnyHat::Mcc128(dev) => dev,
+            AnyHat::Mcc134(dev) => dev,
+            AnyHat::Mcc152(dev) => dev,
+            AnyHat::Mcc172(dev) => dev,
+        }
+    }
+
+    fn as_hat_mut(&mut self) -> &mut dyn Hat {
+        match self {
+            AnyHat::Mcc118(dev) => dev,
+            AnyHat::Mcc128(dev) => dev,
+            AnyHat::Mcc134(dev) => dev,
+            AnyHat::Mcc152(dev) => dev,
+            AnyHat::Mcc172(dev) => dev,
+        }
+    }
+}
+
+impl Hat for AnyHat {
+    fn address(&self) -> u8 {
+        self.as_hat().address()
+    }
+
+    fn id(&self) -> HatId {
+        self.as_hat().id()
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        self.as_hat().serial()
+    }
+
+    fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+        self.as_hat().firmware_version()
+    }
+
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        self.as_hat().calibration_date()
+    }
+
+    fn blink_led(&mut self, count: u8) -> Result<(), ErrorCode> {
+        self.as_hat_mut().blink_led(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(address: u8, id: HatId) -> HatInfo {
+        HatInfo { address, id, version: 0, product_name: format!("{:?}", id) }
+    }
+
+    #[test]
+    fn every_listed_board_gets_a_result() {
+        let results = AnyHat::open_list(vec![info(2, HatId::Mcc118Bootloader), info(5, HatId::ANY)]);
+        let summary: Vec<(u8, Option<ErrorCode>)> = results.iter().map(|(info, result)| (info.address, result.as_ref().err().copied())).collect();
+        assert_eq!(summary, [(2, Some(ErrorCode::InvalidDevice)), (5, Some(ErrorCode::InvalidDevice))]);
+    }
+
+    #[test]
+    fn addresses_without_a_board_are_invalid() {
+        assert!(matches!(AnyHat::open(8), Err(ErrorCode::InvalidDevice)));
+    }
+
+    #[test]
+    fn boards_expose_only_their_interfaces() {
+        let mut tc = AnyHat::Mcc134(Mcc134 { address: 7 });
+        assert_eq!((tc.address(), tc.id()), (7, HatId::Mcc134));
+        assert!(tc.as_a_in().is_some() && tc.as_thermocouple().is_some());
+        assert!(tc.as_a_in_scanner().is_none() && tc.as_a_out().is_none() && tc.as_dio().is_none() && tc.as_iepe().is_none());
+        assert!(matches!(tc.into_a_in_scanner(), Err(AnyHat::Mcc134(dev)) if dev.address == 7));
+
+        let mut dio = AnyHat::Mcc152(Mcc152 { address: 6 });
+        assert_eq!(dio.id(), HatId::Mcc152);
+        assert!(dio.as_a_out().is_some() && dio.as_dio().is_some());
+        assert!(dio.as_a_in().is_none() && dio.as_a_in_scanner().is_none());
+
+        let mut iepe = AnyHat::Mcc172(Mcc172 { address: 5 });
+        assert!(iepe.as_iepe().is_some() && iepe.as_a_in_scanner().is_some() && iepe.as_a_in().is_none());
+        assert_eq!(iepe.into_a_in_scanner().ok().unwrap().address(), 5);
+    }
+}
@@ -0,0 +1,308 @@
+use serde::Serialize;
+
+use super::HatId;
+
+
+/// An analog voltage range, in volts.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct VoltageRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// What a board type can do, independent of any particular board. Use [`Capabilities::of`] or
+/// [`super::Hat::capabilities`] to look one up.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Capabilities {
+    pub id: HatId,
+    pub product_name: &'static str,
+
+    pub ai_channels: u8,
+    /// Channels available in differential mode; 0 if the board has no differential mode.
+    pub ai_differential_channels: u8,
+    pub ai_ranges: &'static [VoltageRange],
+    pub ai_resolution_bits: u8,
+    /// Whether the analog inputs support hardware-paced scanning.
+    pub ai_scan: bool,
+    /// Maximum total sample rate across all scanned channels, in S/s.
+    pub ai_max_aggregate_rate: f64,
+    /// Maximum sample rate of a single channel, in S/s.
+    pub ai_max_rate_per_channel: f64,
+
+    pub ao_channels: u8,
+    pub ao_range: Option<VoltageRange>,
+    pub ao_resolution_bits: u8,
+
+    pub dio_channels: u8,
+
+    /// Whether scans can be paced by a clock on the CLK pin (`OPTS_EXTCLOCK`).
+    pub external_clock: bool,
+    /// Whether scans can wait for a trigger (`OPTS_EXTTRIGGER`).
+    pub external_trigger: bool,
+    /// Whether several boards can share a sample clock (MCC 172 master/slave clocking).
+    pub shared_clock: bool,
+    pub thermocouple: bool,
+    pub iepe: bool,
+}
+
+const BIP_10V: &[VoltageRange] = &[VoltageRange { min: -10.0, max: 10.0 }];
+
+const MCC118: Capabilities = Capabilities {
+    id: HatId::Mcc118,
+    product_name: "MCC 118",
+    ai_channels: 8,
+    ai_differential_channels: 0,
+    ai_ranges: BIP_10V,
+    ai_resolution_bits: 12,
+    ai_scan: true,
+    ai_max_aggregate_rate: 100_000.0,
+    ai_max_rate_per_channel: 100_000.0,
+    ao_channels: 0,
+    ao_range: None,
+    ao_resolution_bits: 0,
+    dio_channels: 0,
+    external_clock: true,
+    external_trigger: true,
+    shared_clock: false,
+    thermocouple: false,
+    iepe: false,
+};
+
+const MCC128: Capabilities = Capabilities {
+    id: HatId::Mcc128,
+    product_name: "MCC 128",
+    ai_channels: 8,
+    ai_differential_channels: 4,
+    ai_ranges: &[
+        VoltageRange { min: -10.0, max: 10.0 },
+        VoltageRange { min: -5.0, max: 5.0 },
+        VoltageRange { min: -2.0, max: 2.0 },
+        VoltageRange { min: -1.0, max: 1.0 },
+    ],
+    ai_resolution_bits: 16,
+    ai_scan: true,
+    ai_max_aggregate_rate: 100_000.0,
+    ai_max_rate_per_channel: 100_000.0,
+    ao_channels: 0,
+    ao_range: None,
+    ao_resolution_bits: 0,
+    dio_channels: 0,
+    external_clock: true,
+    external_trigger: true,
+    shared_clock: false,
+    thermocouple: false,
+    iepe: false,
+};
+
+// the MCC 134 converts continuously at a fixed rate; temperatures are read on demand
+const MCC134: Capabilities = Capabilities {
+    id: HatId::Mcc134,
+    product_name: "MCC 134",
+    ai_channels: 4,
+    ai_differential_channels: 4,
+    ai_ranges: &[VoltageRange { min: -0.078125, max: 0.078125 }],
+    ai_resolution_bits: 24,
+    ai_scan: false,
+    ai_max_aggregate_rate: 0.0,
+    ai_max_rate_per_channel: 0.0,
+    ao_channels: 0,
+    ao_range: None,
+    ao_resolution_bits: 0,
+    dio_channels: 0,
+    external_clock: false,
+    external_trigger: false,
+    shared_clock: false,
+    thermocouple: true,
+    iepe: false,
+};
+
+const MCC152: Capabilities = Capabilities {
+    id: HatId::Mcc152,
+    product_name: "MCC 152",
+    ai_channels: 0,
+    ai_differential_channels: 0,
+    ai_ranges: &[],
+    ai_resolution_bits: 0,
+    ai_scan: false,
+    ai_max_aggregate_rate: 0.0,
+    ai_max_rate_per_channel: 0.0,
+    ao_channels: 2,
+    ao_range: Some(VoltageRange { min: 0.0, max: 5.0 }),
+    ao_resolution_bits: 12,
+    dio_channels: 8,
+    external_clock: false,
+    external_trigger: false,
+    shared_clock: false,
+    thermocouple: false,
+    iepe: false,
+};
+
+const MCC172: Capabilities = Capabilities {
+    id: HatId::Mcc172,
+    product_name: "MCC 172",
+    ai_channels: 2,
+    ai_differential_channels: 0,
+    ai_ranges: &[VoltageRange { min: -5.0, max: 5.0 }],
+    ai_resolution_bits: 24,
+    ai_scan: true,
+    ai_max_aggregate_rate: 102_400.0,
+    ai_max_rate_per_channel: 51_200.0,
+    ao_channels: 0,
+    ao_range: None,
+    ao_resolution_bits: 0,
+    dio_channels: 0,
+    external_clock: false,
+    external_trigger: true,
+    shared_clock: true,
+    thermocouple: false,
+    iepe: true,
+};
+
+impl Capabilities {
+    /// Capabilities of a board type, or `None` for [`HatId::ANY`] and bootloader IDs.
+    pub fn of(id: HatId) -> Option<&'static Capabilities> {
+        match id {
+            HatId::Mcc118 => Some(&MCC118),
+            HatId::Mcc128 => Some(&MCC128),
+            HatId::Mcc134 => Some(&MCC134),
+            HatId::Mcc152 => Some(&MCC152),
+            HatId::Mcc172 => Some(&MCC172),
+            HatId::ANY | HatId::Mcc118Bootloader => None,
+        }
+    }
+
+    /// The highest per-channel rate a scan of `channel_count` channels can run at.
+    pub fn max_scan_rate(&self, channel_count: u8) -> f64 {
+        if channel_count == 0 {
+            return 0.0;
+        }
+        self.ai_max_rate_per_channel.min(self.ai_max_aggregate_rate / channel_count as f64)
+    }
+
+    /// Checks a scan request against the board's limits without touching the hardware, assuming
+    /// single-ended inputs. `sample_rate_per_channel` is ignored when `external_clock` is set.
+    pub fn validate_scan(&self, channel_mask: u8, sample_rate_per_channel: f64, external_clock: bool, external_trigger: bool) -> Result<(), CapabilityError> {
+        self.validate_scan_channels(self.ai_channels, channel_mask, sample_rate_per_channel, external_clock, external_trigger)
+    }
+
+    /// Like [`Capabilities::validate_scan`] with `num_channels` inputs available, e.g.
+    /// `ai_differential_channels` for an MCC 128 in differential mode.
+    pub fn validate_scan_channels(&self, num_channels: u8, channel_mask: u8, sample_rate_per_channel: f64, external_clock: bool, external_trigger: bool) -> Result<(), CapabilityError> {
+        if !self.ai_scan {
+            return Err(CapabilityError::ScanNotSupported { product: self.product_name });
+        }
+
+        if channel_mask == 0 {
+            return Err(CapabilityError::NoChannels);
+        }
+        let highest_channel = 7 - channel_mask.leading_zeros() as u8;
+        if highest_channel >= num_channels {
+            return Err(CapabilityError::ChannelOutOfRange {
+                product: self.product_name,
+                channel: highest_channel,
+                num_channels,
+            });
+        }
+
+        if external_clock && !self.external_clock {
+            return Err(CapabilityError::ExternalClockNotSupported { product: self.product_name });
+        }
+        if external_trigger && !self.external_trigger {
+            return Err(CapabilityError::ExternalTriggerNotSupported { product: self.product_name });
+        }
+
+        if !external_clock {
+            if sample_rate_per_channel.is_nan() || sample_rate_per_channel <= 0.0 {
+                return Err(CapabilityError::InvalidRate { requested: sample_rate_per_channel });
+            }
+
+            let channel_count = channel_mask.count_ones() as u8;
+            let max = self.max_scan_rate(channel_count);
+            if sample_rate_per_channel > max {
+                return Err(CapabilityError::RateTooHigh {
+                    product: self.product_name,
+                    channel_count,
+                    requested: sample_rate_per_channel,
+                    max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A request that a board type can't satisfy, found before the hardware is touched.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CapabilityError {
+    ScanNotSupported { product: &'static str },
+    NoChannels,
+    ChannelOutOfRange { product: &'static str, channel: u8, num_channels: u8 },
+    InvalidRate { requested: f64 },
+    RateTooHigh { product: &'static str, channel_count: u8, requested: f64, max: f64 },
+    ExternalClockNotSupported { product: &'static str },
+    ExternalTriggerNotSupported { product: &'static str },
+    OutputOutOfRange { product: &'static str, channel: u8, value: f64, min: f64, max: f64 },
+}
+
+impl std::fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CapabilityError::ScanNotSupported { product } => write!(f, "the {} does not support analog input scans", product),
+            CapabilityError::NoChannels => write!(f, "the channel mask selects no channels"),
+            CapabilityError::ChannelOutOfRange { product, channel, num_channels } => {
+                write!(f, "channel {} does not exist; the {} has channels 0 to {}", channel, product, num_channels - 1)
+            }
+            CapabilityError::InvalidRate { requested } => write!(f, "sample rate {} S/s is not a positive number", requested),
+            CapabilityError::RateTooHigh { product, channel_count, requested, max } => write!(
+                f,
+                "{} channel(s) at {} S/s each exceeds the {} limit; the maximum with {} channel(s) is {} S/s per channel",
+                channel_count, requested, product, channel_count, max
+            ),
+            CapabilityError::ExternalClockNotSupported { product } => write!(f, "the {} does not support an external scan clock", product),
+            CapabilityError::ExternalTriggerNotSupported { product } => write!(f, "the {} does not support an external trigger", product),
+            CapabilityError::OutputOutOfRange { product, channel, value, min, max } => {
+                write!(f, "{} V on output channel {} is outside the {} range of {} to {} V", value, channel, product, min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CapabilityError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mcc118_aggregate_rate_limit() {
+        let caps = Capabilities::of(HatId::Mcc118).unwrap();
+        assert!(matches!(caps.validate_scan(0xff, 20_000.0, false, false), Err(CapabilityError::RateTooHigh { channel_count: 8, .. })));
+        assert_eq!(caps.validate_scan(0xff, 12_500.0, false, false), Ok(()));
+        // the rate doesn't matter with an external clock
+        assert_eq!(caps.validate_scan(0xff, 20_000.0, true, false), Ok(()));
+    }
+
+    #[test]
+    fn mcc128_differential_channels() {
+        let caps = Capabilities::of(HatId::Mcc128).unwrap();
+        assert_eq!(caps.validate_scan(0x80, 1_000.0, false, false), Ok(()));
+        assert_eq!(caps.validate_scan_channels(caps.ai_differential_channels, 0x0f, 1_000.0, false, false), Ok(()));
+        assert_eq!(
+            caps.validate_scan_channels(caps.ai_differential_channels, 0x10, 1_000.0, false, false),
+            Err(CapabilityError::ChannelOutOfRange { product: "MCC 128", channel: 4, num_channels: 4 })
+        );
+    }
+
+    #[test]
+    fn rejects_impossible_requests() {
+        let mcc118 = Capabilities::of(HatId::Mcc118).unwrap();
+        assert_eq!(mcc118.validate_scan(0, 1_000.0, false, false), Err(CapabilityError::NoChannels));
+        assert_eq!(mcc118.validate_scan(1, 0.0, false, false), Err(CapabilityError::InvalidRate { requested: 0.0 }));
+        assert!(matches!(mcc118.validate_scan(1, f64::NAN, false, false), Err(CapabilityError::InvalidRate { .. })));
+        let mcc172 = Capabilities::of(HatId::Mcc172).unwrap();
+        assert_eq!(mcc172.validate_scan(1, 1_000.0, true, false), Err(CapabilityError::ExternalClockNotSupported { product: "MCC 172" }));
+        let mcc134 = Capabilities::of(HatId::Mcc134).unwrap();
+        assert_eq!(mcc134.validate_scan(1, 1.0, false, false), Err(CapabilityError::ScanNotSupported { product: "MCC 134" }));
+    }
+}
@@ -1,6 +1,6 @@
 use super::bindings;
-use super::{AIn, AInScanner};
-use super::{ErrorCode, ScanOptions, ScanStatus, TriggerMode, result_c_to_rs};
+use super::{AIn, AInScanner, Hat};
+use super::{ErrorCode, FirmwareVersion, HatId, ScanOptions, ScanStatus, TriggerMode, result_c_to_rs, string_from_c_buf};
 
 
 pub struct Mcc118DeviceInfo {
@@ -38,39 +38,14 @@ impl Mcc118 {
     }
 
     pub fn close(self) -> Result<(), ErrorCode> {
-        let res = unsafe { bindings::mcc118_close(self.address) };
+        // `Drop` would close it a second time, releasing another open elsewhere in the process
+        let dev = std::mem::ManuallyDrop::new(self);
+        let res = unsafe { bindings::mcc118_close(dev.address) };
         result_c_to_rs(res)
     }
 
-    pub fn is_open(&self) -> () {
-        let res = unsafe { bindings::mcc118_is_open(self.address) == 1 };
-        if !res {
-            panic!("mcc118 device at address {} is not open", self.address);
-        }
-    }
-
-    pub fn blink_led(&mut self, count: u8) -> Result<(), ErrorCode> {
-        let res = unsafe { bindings::mcc118_blink_led(self.address, count) };
-        result_c_to_rs(res)
-    }
-
-    pub fn firmware_version(&self) -> Result<(u16, u16), ErrorCode> {
-        let mut version = 0;
-        let mut boot_version = 0;
-        let res = unsafe { bindings::mcc118_firmware_version(self.address, &mut version, &mut boot_version) };
-        result_c_to_rs(res).map(|_| (version, boot_version))
-    }
-
-    pub fn serial(&self) -> Result<String, ErrorCode> {
-        let mut serial = [0u8; 9];
-        let res = unsafe { bindings::mcc118_serial(self.address, serial.as_mut_ptr()) };
-        result_c_to_rs(res).map(|_| String::from_utf8_lossy(&serial).into_owned())
-    }
-
-    pub fn calibration_date(&self) -> Result<String, ErrorCode> {
-        let mut date = [0u8; 9];
-        let res = unsafe { bindings::mcc118_calibration_date(self.address, date.as_mut_ptr()) };
-        result_c_to_rs(res).map(|_| String::from_utf8_lossy(&date).into_owned())
+    pub fn is_open(&self) -> bool {
+        unsafe { bindings::mcc118_is_open(self.address) == 1 }
     }
 
     pub fn calibration_coefficient_read(&self, index: u8) -> Result<(f64, f64), ErrorCode> {
@@ -95,6 +70,41 @@ impl Mcc118 {
     }
 }
 
+impl Hat for Mcc118 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn id(&self) -> HatId {
+        HatId::Mcc118
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        let mut serial = [0u8; 9];
+        let res = unsafe { bindings::mcc118_serial(self.address, serial.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| string_from_c_buf(&serial))
+    }
+
+    fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+        let mut version = 0;
+        let mut boot_version = 0;
+        let res = unsafe { bindings::mcc118_firmware_version(self.address, &mut version, &mut boot_version) };
+        result_c_to_rs(res).map(|_| Some(FirmwareVersion { version, bootloader_version: Some(boot_version) }))
+    }
+
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        // "YYYY-MM-DD" plus the terminator
+        let mut date = [0u8; 11];
+        let res = unsafe { bindings::mcc118_calibration_date(self.address, date.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| Some(string_from_c_buf(&date)))
+    }
+
+    fn blink_led(&mut self, count: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc118_blink_led(self.address, count) };
+        result_c_to_rs(res)
+    }
+}
+
 impl AIn for Mcc118 {
     fn a_in_read(&mut self, channel: u8, options: ScanOptions) -> Result<f64, ErrorCode> {
         let mut value = 0.0;
@@ -104,7 +114,7 @@ impl AIn for Mcc118 {
 }
 
 impl AInScanner for Mcc118 {
-    fn a_in_scan_actual_rate(channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+    fn a_in_scan_actual_rate(&self, channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
         let mut actual_sample_rate = 0.0;
         let res = unsafe { bindings::mcc118_a_in_scan_actual_rate(channel_count, sample_rate_per_channel, &mut actual_sample_rate) };
         result_c_to_rs(res).map(|_| actual_sample_rate)
@@ -168,11 +178,12 @@ impl AInScanner for Mcc118 {
 impl Drop for Mcc118 {
     fn drop(&mut self) {
         if let Ok(scan_status) = self.a_in_scan_status() {
+            // errors can't be reported from here, and panicking could abort during unwinding
             if scan_status.0.contains(ScanStatus::RUNNING) {
-                self.a_in_scan_stop().unwrap();
+                let _ = self.a_in_scan_stop();
             }
 
-            self.a_in_scan_cleanup().unwrap();
+            let _ = self.a_in_scan_cleanup();
         }
         unsafe { bindings::mcc118_close(self.address) };
     }
@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+
+use super::bindings;
+use super::{AIn, AInScanner, Hat};
+use super::{ErrorCode, FirmwareVersion, HatId, ScanOptions, ScanStatus, TriggerMode, result_c_to_rs, string_from_c_buf};
+
+
+pub struct Mcc128DeviceInfo {
+    pub num_ai_modes: u8,
+    pub num_ai_channels: [u8; 2],
+    pub ai_min_code: u16,
+    pub ai_max_code: u16,
+    pub num_ai_ranges: u8,
+    pub ai_min_voltage: [f64; 4],
+    pub ai_max_voltage: [f64; 4],
+    pub ai_min_range: [f64; 4],
+    pub ai_max_range: [f64; 4],
+}
+
+impl From<bindings::MCC128DeviceInfo> for Mcc128DeviceInfo {
+    fn from(info: bindings::MCC128DeviceInfo) -> Self {
+        Mcc128DeviceInfo {
+            num_ai_modes: info.NUM_AI_MODES,
+            num_ai_channels: info.NUM_AI_CHANNELS,
+            ai_min_code: info.AI_MIN_CODE,
+            ai_max_code: info.AI_MAX_CODE,
+            num_ai_ranges: info.NUM_AI_RANGES,
+            ai_min_voltage: info.AI_MIN_VOLTAGE,
+            ai_max_voltage: info.AI_MAX_VOLTAGE,
+            ai_min_range: info.AI_MIN_RANGE,
+            ai_max_range: info.AI_MAX_RANGE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnalogInputMode {
+    SingleEnded=bindings::AnalogInputMode_A_IN_MODE_SE as isize,
+    Differential=bindings::AnalogInputMode_A_IN_MODE_DIFF as isize,
+}
+
+impl From<u8> for AnalogInputMode {
+    fn from(mode: u8) -> Self {
+        match mode as u32 {
+            bindings::AnalogInputMode_A_IN_MODE_SE => AnalogInputMode::SingleEnded,
+            bindings::AnalogInputMode_A_IN_MODE_DIFF => AnalogInputMode::Differential,
+            _ => panic!("Invalid AnalogInputMode"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnalogInputRange {
+    Bip10V=bindings::AnalogInputRange_A_IN_RANGE_BIP_10V as isize,
+    Bip5V=bindings::AnalogInputRange_A_IN_RANGE_BIP_5V as isize,
+    Bip2V=bindings::AnalogInputRange_A_IN_RANGE_BIP_2V as isize,
+    Bip1V=bindings::AnalogInputRange_A_IN_RANGE_BIP_1V as isize,
+}
+
+impl From<u8> for AnalogInputRange {
+    fn from(range: u8) -> Self {
+        match range as u32 {
+            bindings::AnalogInputRange_A_IN_RANGE_BIP_10V => AnalogInputRange::Bip10V,
+            bindings::AnalogInputRange_A_IN_RANGE_BIP_5V => AnalogInputRange::Bip5V,
+            bindings::AnalogInputRange_A_IN_RANGE_BIP_2V => AnalogInputRange::Bip2V,
+            bindings::AnalogInputRange_A_IN_RANGE_BIP_1V => AnalogInputRange::Bip1V,
+            _ => panic!("Invalid AnalogInputRange"),
+        }
+    }
+}
+
+pub struct Mcc128 {
+    pub address: u8,
+}
+
+impl Mcc128 {
+    pub fn open(address: u8) -> Result<Mcc128, ErrorCode> {
+        let res = unsafe { bindings::mcc128_open(address) };
+        result_c_to_rs(res).map(|_| Mcc128 { address })
+    }
+
+    pub fn close(self) -> Result<(), ErrorCode> {
+        // `Drop` would close it a second time, releasing another open elsewhere in the process
+        let dev = std::mem::ManuallyDrop::new(self);
+        let res = unsafe { bindings::mcc128_close(dev.address) };
+        result_c_to_rs(res)
+    }
+
+    pub fn is_open(&self) -> bool {
+        unsafe { bindings::mcc128_is_open(self.address) == 1 }
+    }
+
+    pub fn calibration_coefficient_read(&self, range: AnalogInputRange) -> Result<(f64, f64), ErrorCode> {
+        let mut slope = 0.0;
+        let mut offset = 0.0;
+        let res = unsafe { bindings::mcc128_calibration_coefficient_read(self.address, range as u8, &mut slope, &mut offset) };
+        result_c_to_rs(res).map(|_| (slope, offset))
+    }
+
+    pub fn calibration_coefficient_write(&mut self, range: AnalogInputRange, slope: f64, offset: f64) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_calibration_coefficient_write(self.address, range as u8, slope, offset) };
+        result_c_to_rs(res)
+    }
+
+    pub fn a_in_mode_write(&mut self, mode: AnalogInputMode) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_a_in_mode_write(self.address, mode as u8) };
+        result_c_to_rs(res)
+    }
+
+    pub fn a_in_mode_read(&self) -> Result<AnalogInputMode, ErrorCode> {
+        let mut mode = 0;
+        let res = unsafe { bindings::mcc128_a_in_mode_read(self.address, &mut mode) };
+        result_c_to_rs(res).map(|_| mode.into())
+    }
+
+    pub fn a_in_range_write(&mut self, range: AnalogInputRange) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_a_in_range_write(self.address, range as u8) };
+        result_c_to_rs(res)
+    }
+
+    pub fn a_in_range_read(&self) -> Result<AnalogInputRange, ErrorCode> {
+        let mut range = 0;
+        let res = unsafe { bindings::mcc128_a_in_range_read(self.address, &mut range) };
+        result_c_to_rs(res).map(|_| range.into())
+    }
+
+    pub fn trigger_mode(&mut self, mode: TriggerMode) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_trigger_mode(self.address, mode as u8) };
+        result_c_to_rs(res)
+    }
+
+    pub fn info() -> Mcc128DeviceInfo {
+        unsafe { (*bindings::mcc128_info()).into() }
+    }
+}
+
+impl Hat for Mcc128 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn id(&self) -> HatId {
+        HatId::Mcc128
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        let mut serial = [0u8; 9];
+        let res = unsafe { bindings::mcc128_serial(self.address, serial.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| string_from_c_buf(&serial))
+    }
+
+    fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+        let mut version = 0;
+        let res = unsafe { bindings::mcc128_firmware_version(self.address, &mut version) };
+        result_c_to_rs(res).map(|_| Some(FirmwareVersion { version, bootloader_version: None }))
+    }
+
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        let mut date = [0u8; 11];
+        let res = unsafe { bindings::mcc128_calibration_date(self.address, date.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| Some(string_from_c_buf(&date)))
+    }
+
+    fn blink_led(&mut self, count: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_blink_led(self.address, count) };
+        result_c_to_rs(res)
+    }
+
+    fn a_in_num_channels(&self) -> u8 {
+        let caps = self.capabilities();
+        match self.a_in_mode_read() {
+            Ok(AnalogInputMode::Differential) => caps.ai_differential_channels,
+            // if the mode can't be read, the scan will fail in the library anyway
+            Ok(AnalogInputMode::SingleEnded) | Err(_) => caps.ai_channels,
+        }
+    }
+}
+
+impl AIn for Mcc128 {
+    fn a_in_read(&mut self, channel: u8, options: ScanOptions) -> Result<f64, ErrorCode> {
+        let mut value = 0.0;
+        let res = unsafe { bindings::mcc128_a_in_read(self.address, channel, options.bits(), &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+}
+
+impl AInScanner for Mcc128 {
+    fn a_in_scan_actual_rate(&self, channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+        let mut actual_sample_rate = 0.0;
+        let res = unsafe { bindings::mcc128_a_in_scan_actual_rate(channel_count, sample_rate_per_channel, &mut actual_sample_rate) };
+        result_c_to_rs(res).map(|_| actual_sample_rate)
+    }
+
+    fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: ScanOptions) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_a_in_scan_start(self.address, channel_mask, samples_per_channel, sample_rate_per_channel, options.bits()) };
+        result_c_to_rs(res)
+    }
+
+    fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode> {
+        let mut size = 0;
+        let res = unsafe { bindings::mcc128_a_in_scan_buffer_size(self.address, &mut size) };
+        result_c_to_rs(res).map(|_| size)
+    }
+
+    fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode> {
+        let mut status = 0;
+        let mut samples = 0;
+        let res = unsafe { bindings::mcc128_a_in_scan_status(self.address, &mut status, &mut samples) };
+        result_c_to_rs(res).map(|_| (ScanStatus::from_bits(status).unwrap(), samples))
+    }
+
+    fn a_in_scan_read(&mut self, samples_per_channel: i32, timeout_s: f64, buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode> {
+        let mut status: u16 = 0;
+        let mut samples_read = 0;
+        let res = unsafe {
+            bindings::mcc128_a_in_scan_read(
+                self.address,
+                &mut status,
+                samples_per_channel,
+                timeout_s,
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                &mut samples_read,
+            )
+        };
+
+        result_c_to_rs(res).map(|_| (ScanStatus::from_bits(status).unwrap(), samples_read))
+    }
+
+    fn a_in_scan_channel_count(&self) -> u8 {
+        let channel_count = unsafe { bindings::mcc128_a_in_scan_channel_count(self.address) };
+        assert!(channel_count >= 0);
+        assert!(channel_count <= 8);
+
+        channel_count as u8
+    }
+
+    fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_a_in_scan_stop(self.address) };
+        result_c_to_rs(res)
+    }
+
+    fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc128_a_in_scan_cleanup(self.address) };
+        result_c_to_rs(res)
+    }
+}
+
+impl Drop for Mcc128 {
+    fn drop(&mut self) {
+        if let Ok(scan_status) = self.a_in_scan_status() {
+            // errors can't be reported from here, and panicking could abort during unwinding
+            if scan_status.0.contains(ScanStatus::RUNNING) {
+                let _ = self.a_in_scan_stop();
+            }
+
+            let _ = self.a_in_scan_cleanup();
+        }
+        unsafe { bindings::mcc128_close(self.address) };
+    }
+}
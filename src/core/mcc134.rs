@@ -0,0 +1,175 @@
+use super::bindings;
+use super::{AIn, Hat, Thermocouple};
+use super::{ErrorCode, HatId, ScanOptions, result_c_to_rs, string_from_c_buf};
+
+
+/// Value returned by [`Thermocouple::t_in_read`] when the thermocouple is open.
+pub const OPEN_TC_VALUE: f64 = -9999.0;
+/// Value returned by [`Thermocouple::t_in_read`] when the thermocouple voltage is outside the valid range.
+pub const OVERRANGE_TC_VALUE: f64 = -8888.0;
+/// Value returned by [`Thermocouple::t_in_read`] when the thermocouple voltage is outside the common-mode range.
+pub const COMMON_MODE_TC_VALUE: f64 = -7777.0;
+
+pub struct Mcc134DeviceInfo {
+    pub num_ai_channels: u8,
+    pub ai_min_code: i32,
+    pub ai_max_code: i32,
+    pub ai_min_voltage: f64,
+    pub ai_max_voltage: f64,
+    pub ai_min_range: f64,
+    pub ai_max_range: f64,
+}
+
+impl From<bindings::MCC134DeviceInfo> for Mcc134DeviceInfo {
+    fn from(info: bindings::MCC134DeviceInfo) -> Self {
+        Mcc134DeviceInfo {
+            num_ai_channels: info.NUM_AI_CHANNELS,
+            ai_min_code: info.AI_MIN_CODE,
+            ai_max_code: info.AI_MAX_CODE,
+            ai_min_voltage: info.AI_MIN_VOLTAGE,
+            ai_max_voltage: info.AI_MAX_VOLTAGE,
+            ai_min_range: info.AI_MIN_RANGE,
+            ai_max_range: info.AI_MAX_RANGE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcType {
+    J=bindings::TcTypes_TC_TYPE_J as isize,
+    K=bindings::TcTypes_TC_TYPE_K as isize,
+    T=bindings::TcTypes_TC_TYPE_T as isize,
+    E=bindings::TcTypes_TC_TYPE_E as isize,
+    R=bindings::TcTypes_TC_TYPE_R as isize,
+    S=bindings::TcTypes_TC_TYPE_S as isize,
+    B=bindings::TcTypes_TC_TYPE_B as isize,
+    N=bindings::TcTypes_TC_TYPE_N as isize,
+    Disabled=bindings::TC_DISABLED as isize,
+}
+
+impl From<u8> for TcType {
+    fn from(tc_type: u8) -> Self {
+        match tc_type as u32 {
+            bindings::TcTypes_TC_TYPE_J => TcType::J,
+            bindings::TcTypes_TC_TYPE_K => TcType::K,
+            bindings::TcTypes_TC_TYPE_T => TcType::T,
+            bindings::TcTypes_TC_TYPE_E => TcType::E,
+            bindings::TcTypes_TC_TYPE_R => TcType::R,
+            bindings::TcTypes_TC_TYPE_S => TcType::S,
+            bindings::TcTypes_TC_TYPE_B => TcType::B,
+            bindings::TcTypes_TC_TYPE_N => TcType::N,
+            bindings::TC_DISABLED => TcType::Disabled,
+            _ => panic!("Invalid TcType"),
+        }
+    }
+}
+
+pub struct Mcc134 {
+    pub address: u8,
+}
+
+impl Mcc134 {
+    pub fn open(address: u8) -> Result<Mcc134, ErrorCode> {
+        let res = unsafe { bindings::mcc134_open(address) };
+        result_c_to_rs(res).map(|_| Mcc134 { address })
+    }
+
+    pub fn close(self) -> Result<(), ErrorCode> {
+        // `Drop` would close it a second time, releasing another open elsewhere in the process
+        let dev = std::mem::ManuallyDrop::new(self);
+        let res = unsafe { bindings::mcc134_close(dev.address) };
+        result_c_to_rs(res)
+    }
+
+    pub fn is_open(&self) -> bool {
+        unsafe { bindings::mcc134_is_open(self.address) == 1 }
+    }
+
+    pub fn calibration_coefficient_read(&self, channel: u8) -> Result<(f64, f64), ErrorCode> {
+        let mut slope = 0.0;
+        let mut offset = 0.0;
+        let res = unsafe { bindings::mcc134_calibration_coefficient_read(self.address, channel, &mut slope, &mut offset) };
+        result_c_to_rs(res).map(|_| (slope, offset))
+    }
+
+    pub fn calibration_coefficient_write(&mut self, channel: u8, slope: f64, offset: f64) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc134_calibration_coefficient_write(self.address, channel, slope, offset) };
+        result_c_to_rs(res)
+    }
+
+    pub fn info() -> Mcc134DeviceInfo {
+        unsafe { (*bindings::mcc134_info()).into() }
+    }
+}
+
+impl Hat for Mcc134 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn id(&self) -> HatId {
+        HatId::Mcc134
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        let mut serial = [0u8; 9];
+        let res = unsafe { bindings::mcc134_serial(self.address, serial.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| string_from_c_buf(&serial))
+    }
+
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        let mut date = [0u8; 11];
+        let res = unsafe { bindings::mcc134_calibration_date(self.address, date.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| Some(string_from_c_buf(&date)))
+    }
+}
+
+impl Thermocouple for Mcc134 {
+    fn tc_type_write(&mut self, channel: u8, tc_type: TcType) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc134_tc_type_write(self.address, channel, tc_type as u8) };
+        result_c_to_rs(res)
+    }
+
+    fn tc_type_read(&self, channel: u8) -> Result<TcType, ErrorCode> {
+        let mut tc_type = 0;
+        let res = unsafe { bindings::mcc134_tc_type_read(self.address, channel, &mut tc_type) };
+        result_c_to_rs(res).map(|_| tc_type.into())
+    }
+
+    fn update_interval_write(&mut self, interval_s: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc134_update_interval_write(self.address, interval_s) };
+        result_c_to_rs(res)
+    }
+
+    fn update_interval_read(&self) -> Result<u8, ErrorCode> {
+        let mut interval = 0;
+        let res = unsafe { bindings::mcc134_update_interval_read(self.address, &mut interval) };
+        result_c_to_rs(res).map(|_| interval)
+    }
+
+    fn t_in_read(&mut self, channel: u8) -> Result<f64, ErrorCode> {
+        let mut value = 0.0;
+        let res = unsafe { bindings::mcc134_t_in_read(self.address, channel, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+
+    fn cjc_read(&mut self, channel: u8) -> Result<f64, ErrorCode> {
+        let mut value = 0.0;
+        let res = unsafe { bindings::mcc134_cjc_read(self.address, channel, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+}
+
+impl AIn for Mcc134 {
+    fn a_in_read(&mut self, channel: u8, options: ScanOptions) -> Result<f64, ErrorCode> {
+        let mut value = 0.0;
+        let res = unsafe { bindings::mcc134_a_in_read(self.address, channel, options.bits(), &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+}
+
+impl Drop for Mcc134 {
+    fn drop(&mut self) {
+        unsafe { bindings::mcc134_close(self.address) };
+    }
+}
@@ -0,0 +1,184 @@
+use super::bindings;
+use super::{AOut, Dio, Hat};
+use super::{ErrorCode, HatId, ScanOptions, result_c_to_rs, string_from_c_buf};
+
+
+pub struct Mcc152DeviceInfo {
+    pub num_dio_channels: u8,
+    pub num_ao_channels: u8,
+    pub ao_min_code: u16,
+    pub ao_max_code: u16,
+    pub ao_min_voltage: f64,
+    pub ao_max_voltage: f64,
+    pub ao_min_range: f64,
+    pub ao_max_range: f64,
+}
+
+impl From<bindings::MCC152DeviceInfo> for Mcc152DeviceInfo {
+    fn from(info: bindings::MCC152DeviceInfo) -> Self {
+        Mcc152DeviceInfo {
+            num_dio_channels: info.NUM_DIO_CHANNELS,
+            num_ao_channels: info.NUM_AO_CHANNELS,
+            ao_min_code: info.AO_MIN_CODE,
+            ao_max_code: info.AO_MAX_CODE,
+            ao_min_voltage: info.AO_MIN_VOLTAGE,
+            ao_max_voltage: info.AO_MAX_VOLTAGE,
+            ao_min_range: info.AO_MIN_RANGE,
+            ao_max_range: info.AO_MAX_RANGE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DioConfigItem {
+    /// 1 = input, 0 = output
+    Direction=bindings::DIOConfigItem_DIO_DIRECTION as isize,
+    /// 1 = pull-up, 0 = pull-down
+    PullConfig=bindings::DIOConfigItem_DIO_PULL_CONFIG as isize,
+    /// 1 = enabled, 0 = disabled
+    PullEnable=bindings::DIOConfigItem_DIO_PULL_ENABLE as isize,
+    /// 1 = inverted, 0 = normal
+    InputInvert=bindings::DIOConfigItem_DIO_INPUT_INVERT as isize,
+    /// 1 = latched, 0 = transparent
+    InputLatch=bindings::DIOConfigItem_DIO_INPUT_LATCH as isize,
+    /// 1 = open-drain, 0 = push-pull
+    OutputType=bindings::DIOConfigItem_DIO_OUTPUT_TYPE as isize,
+    /// 1 = interrupt disabled, 0 = interrupt enabled
+    IntMask=bindings::DIOConfigItem_DIO_INT_MASK as isize,
+}
+
+pub struct Mcc152 {
+    pub address: u8,
+}
+
+impl Mcc152 {
+    pub fn open(address: u8) -> Result<Mcc152, ErrorCode> {
+        let res = unsafe { bindings::mcc152_open(address) };
+        result_c_to_rs(res).map(|_| Mcc152 { address })
+    }
+
+    pub fn close(self) -> Result<(), ErrorCode> {
+        // `Drop` would close it a second time, releasing another open elsewhere in the process
+        let dev = std::mem::ManuallyDrop::new(self);
+        let res = unsafe { bindings::mcc152_close(dev.address) };
+        result_c_to_rs(res)
+    }
+
+    pub fn is_open(&self) -> bool {
+        unsafe { bindings::mcc152_is_open(self.address) == 1 }
+    }
+
+    pub fn info() -> Mcc152DeviceInfo {
+        unsafe { (*bindings::mcc152_info()).into() }
+    }
+}
+
+impl Hat for Mcc152 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn id(&self) -> HatId {
+        HatId::Mcc152
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        let mut serial = [0u8; 9];
+        let res = unsafe { bindings::mcc152_serial(self.address, serial.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| string_from_c_buf(&serial))
+    }
+}
+
+impl AOut for Mcc152 {
+    fn a_out_write(&mut self, channel: u8, options: ScanOptions, value: f64) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc152_a_out_write(self.address, channel, options.bits(), value) };
+        result_c_to_rs(res)
+    }
+
+    fn a_out_write_all(&mut self, options: ScanOptions, values: &[f64]) -> Result<(), ErrorCode> {
+        let mut values: [f64; 2] = values.try_into().map_err(|_| ErrorCode::BadParameter)?;
+        let res = unsafe { bindings::mcc152_a_out_write_all(self.address, options.bits(), values.as_mut_ptr()) };
+        result_c_to_rs(res)
+    }
+}
+
+impl Dio for Mcc152 {
+    fn dio_reset(&mut self) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc152_dio_reset(self.address) };
+        result_c_to_rs(res)
+    }
+
+    fn dio_input_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_input_read_bit(self.address, channel, &mut value) };
+        result_c_to_rs(res).map(|_| value != 0)
+    }
+
+    fn dio_input_read_port(&mut self) -> Result<u8, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_input_read_port(self.address, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+
+    fn dio_output_write_bit(&mut self, channel: u8, value: bool) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc152_dio_output_write_bit(self.address, channel, value as u8) };
+        result_c_to_rs(res)
+    }
+
+    fn dio_output_write_port(&mut self, value: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc152_dio_output_write_port(self.address, value) };
+        result_c_to_rs(res)
+    }
+
+    fn dio_output_read_bit(&self, channel: u8) -> Result<bool, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_output_read_bit(self.address, channel, &mut value) };
+        result_c_to_rs(res).map(|_| value != 0)
+    }
+
+    fn dio_output_read_port(&self) -> Result<u8, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_output_read_port(self.address, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+
+    fn dio_int_status_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_int_status_read_bit(self.address, channel, &mut value) };
+        result_c_to_rs(res).map(|_| value != 0)
+    }
+
+    fn dio_int_status_read_port(&mut self) -> Result<u8, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_int_status_read_port(self.address, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+
+    fn dio_config_write_bit(&mut self, channel: u8, item: DioConfigItem, value: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc152_dio_config_write_bit(self.address, channel, item as u8, value) };
+        result_c_to_rs(res)
+    }
+
+    fn dio_config_write_port(&mut self, item: DioConfigItem, value: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc152_dio_config_write_port(self.address, item as u8, value) };
+        result_c_to_rs(res)
+    }
+
+    fn dio_config_read_bit(&self, channel: u8, item: DioConfigItem) -> Result<u8, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_config_read_bit(self.address, channel, item as u8, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+
+    fn dio_config_read_port(&self, item: DioConfigItem) -> Result<u8, ErrorCode> {
+        let mut value = 0;
+        let res = unsafe { bindings::mcc152_dio_config_read_port(self.address, item as u8, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+}
+
+impl Drop for Mcc152 {
+    fn drop(&mut self) {
+        unsafe { bindings::mcc152_close(self.address) };
+    }
+}
@@ -0,0 +1,252 @@
+use super::bindings;
+use super::{AInScanner, Hat, Iepe};
+use super::{ErrorCode, FirmwareVersion, HatId, ScanOptions, ScanStatus, TriggerMode, result_c_to_rs, string_from_c_buf};
+
+
+/// Base ADC clock; the per-channel sample rate is this divided by an integer from 1 to 256.
+const MAX_SAMPLE_RATE: f64 = 51200.0;
+
+pub struct Mcc172DeviceInfo {
+    pub num_ai_channels: u8,
+    pub ai_min_code: i32,
+    pub ai_max_code: i32,
+    pub ai_min_voltage: f64,
+    pub ai_max_voltage: f64,
+    pub ai_min_range: f64,
+    pub ai_max_range: f64,
+}
+
+impl From<bindings::MCC172DeviceInfo> for Mcc172DeviceInfo {
+    fn from(info: bindings::MCC172DeviceInfo) -> Self {
+        Mcc172DeviceInfo {
+            num_ai_channels: info.NUM_AI_CHANNELS,
+            ai_min_code: info.AI_MIN_CODE,
+            ai_max_code: info.AI_MAX_CODE,
+            ai_min_voltage: info.AI_MIN_VOLTAGE,
+            ai_max_voltage: info.AI_MAX_VOLTAGE,
+            ai_min_range: info.AI_MIN_RANGE,
+            ai_max_range: info.AI_MAX_RANGE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SourceType {
+    Local=bindings::SourceType_SOURCE_LOCAL as isize,
+    Master=bindings::SourceType_SOURCE_MASTER as isize,
+    Slave=bindings::SourceType_SOURCE_SLAVE as isize,
+}
+
+impl From<u8> for SourceType {
+    fn from(source: u8) -> Self {
+        match source as u32 {
+            bindings::SourceType_SOURCE_LOCAL => SourceType::Local,
+            bindings::SourceType_SOURCE_MASTER => SourceType::Master,
+            bindings::SourceType_SOURCE_SLAVE => SourceType::Slave,
+            _ => panic!("Invalid SourceType"),
+        }
+    }
+}
+
+pub struct Mcc172 {
+    pub address: u8,
+}
+
+impl Mcc172 {
+    pub fn open(address: u8) -> Result<Mcc172, ErrorCode> {
+        let res = unsafe { bindings::mcc172_open(address) };
+        result_c_to_rs(res).map(|_| Mcc172 { address })
+    }
+
+    pub fn close(self) -> Result<(), ErrorCode> {
+        // `Drop` would close it a second time, releasing another open elsewhere in the process
+        let dev = std::mem::ManuallyDrop::new(self);
+        let res = unsafe { bindings::mcc172_close(dev.address) };
+        result_c_to_rs(res)
+    }
+
+    pub fn is_open(&self) -> bool {
+        unsafe { bindings::mcc172_is_open(self.address) == 1 }
+    }
+
+    pub fn calibration_coefficient_read(&self, channel: u8) -> Result<(f64, f64), ErrorCode> {
+        let mut slope = 0.0;
+        let mut offset = 0.0;
+        let res = unsafe { bindings::mcc172_calibration_coefficient_read(self.address, channel, &mut slope, &mut offset) };
+        result_c_to_rs(res).map(|_| (slope, offset))
+    }
+
+    pub fn calibration_coefficient_write(&mut self, channel: u8, slope: f64, offset: f64) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_calibration_coefficient_write(self.address, channel, slope, offset) };
+        result_c_to_rs(res)
+    }
+
+    pub fn a_in_clock_config_write(&mut self, clock_source: SourceType, sample_rate_per_channel: f64) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_a_in_clock_config_write(self.address, clock_source as u8, sample_rate_per_channel) };
+        result_c_to_rs(res)
+    }
+
+    /// Returns the clock source, the actual per-channel sample rate and whether the ADCs are synchronized.
+    pub fn a_in_clock_config_read(&self) -> Result<(SourceType, f64, bool), ErrorCode> {
+        let mut clock_source = 0;
+        let mut sample_rate = 0.0;
+        let mut synced = 0;
+        let res = unsafe { bindings::mcc172_a_in_clock_config_read(self.address, &mut clock_source, &mut sample_rate, &mut synced) };
+        result_c_to_rs(res).map(|_| (clock_source.into(), sample_rate, synced != 0))
+    }
+
+    pub fn trigger_config(&mut self, source: SourceType, mode: TriggerMode) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_trigger_config(self.address, source as u8, mode as u8) };
+        result_c_to_rs(res)
+    }
+
+    pub fn info() -> Mcc172DeviceInfo {
+        unsafe { (*bindings::mcc172_info()).into() }
+    }
+}
+
+impl Hat for Mcc172 {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn id(&self) -> HatId {
+        HatId::Mcc172
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        let mut serial = [0u8; 9];
+        let res = unsafe { bindings::mcc172_serial(self.address, serial.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| string_from_c_buf(&serial))
+    }
+
+    fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+        let mut version = 0;
+        let res = unsafe { bindings::mcc172_firmware_version(self.address, &mut version) };
+        result_c_to_rs(res).map(|_| Some(FirmwareVersion { version, bootloader_version: None }))
+    }
+
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        let mut date = [0u8; 11];
+        let res = unsafe { bindings::mcc172_calibration_date(self.address, date.as_mut_ptr()) };
+        result_c_to_rs(res).map(|_| Some(string_from_c_buf(&date)))
+    }
+
+    fn blink_led(&mut self, count: u8) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_blink_led(self.address, count) };
+        result_c_to_rs(res)
+    }
+}
+
+impl Iepe for Mcc172 {
+    fn iepe_config_write(&mut self, channel: u8, enable: bool) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_iepe_config_write(self.address, channel, enable as u8) };
+        result_c_to_rs(res)
+    }
+
+    fn iepe_config_read(&self, channel: u8) -> Result<bool, ErrorCode> {
+        let mut config = 0;
+        let res = unsafe { bindings::mcc172_iepe_config_read(self.address, channel, &mut config) };
+        result_c_to_rs(res).map(|_| config != 0)
+    }
+
+    fn a_in_sensitivity_write(&mut self, channel: u8, value: f64) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_a_in_sensitivity_write(self.address, channel, value) };
+        result_c_to_rs(res)
+    }
+
+    fn a_in_sensitivity_read(&self, channel: u8) -> Result<f64, ErrorCode> {
+        let mut value = 0.0;
+        let res = unsafe { bindings::mcc172_a_in_sensitivity_read(self.address, channel, &mut value) };
+        result_c_to_rs(res).map(|_| value)
+    }
+}
+
+pub(crate) fn actual_rate(sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+    if sample_rate_per_channel.is_nan() || sample_rate_per_channel <= 0.0 {
+        return Err(ErrorCode::BadParameter);
+    }
+    let divisor = (MAX_SAMPLE_RATE / sample_rate_per_channel).round().clamp(1.0, 256.0);
+    Ok(MAX_SAMPLE_RATE / divisor)
+}
+
+impl AInScanner for Mcc172 {
+    // the MCC 172 has no actual-rate query; the rate is the base clock divided by an integer, and
+    // does not depend on the channel count
+    fn a_in_scan_actual_rate(&self, _channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+        actual_rate(sample_rate_per_channel)
+    }
+
+    // the sample rate is part of the clock configuration on the MCC 172, so it is written here
+    // while keeping the configured clock source (a slave ignores the rate)
+    fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: ScanOptions) -> Result<(), ErrorCode> {
+        let (clock_source, _, _) = self.a_in_clock_config_read()?;
+        self.a_in_clock_config_write(clock_source, sample_rate_per_channel)?;
+
+        let res = unsafe { bindings::mcc172_a_in_scan_start(self.address, channel_mask, samples_per_channel, options.bits()) };
+        result_c_to_rs(res)
+    }
+
+    fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode> {
+        let mut size = 0;
+        let res = unsafe { bindings::mcc172_a_in_scan_buffer_size(self.address, &mut size) };
+        result_c_to_rs(res).map(|_| size)
+    }
+
+    fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode> {
+        let mut status = 0;
+        let mut samples = 0;
+        let res = unsafe { bindings::mcc172_a_in_scan_status(self.address, &mut status, &mut samples) };
+        result_c_to_rs(res).map(|_| (ScanStatus::from_bits(status).unwrap(), samples))
+    }
+
+    fn a_in_scan_read(&mut self, samples_per_channel: i32, timeout_s: f64, buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode> {
+        let mut status: u16 = 0;
+        let mut samples_read = 0;
+        let res = unsafe {
+            bindings::mcc172_a_in_scan_read(
+                self.address,
+                &mut status,
+                samples_per_channel,
+                timeout_s,
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                &mut samples_read,
+            )
+        };
+
+        result_c_to_rs(res).map(|_| (ScanStatus::from_bits(status).unwrap(), samples_read))
+    }
+
+    fn a_in_scan_channel_count(&self) -> u8 {
+        let channel_count = unsafe { bindings::mcc172_a_in_scan_channel_count(self.address) };
+        assert!(channel_count >= 0);
+        assert!(channel_count <= 2);
+
+        channel_count as u8
+    }
+
+    fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_a_in_scan_stop(self.address) };
+        result_c_to_rs(res)
+    }
+
+    fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode> {
+        let res = unsafe { bindings::mcc172_a_in_scan_cleanup(self.address) };
+        result_c_to_rs(res)
+    }
+}
+
+impl Drop for Mcc172 {
+    fn drop(&mut self) {
+        if let Ok(scan_status) = self.a_in_scan_status() {
+            // errors can't be reported from here, and panicking could abort during unwinding
+            if scan_status.0.contains(ScanStatus::RUNNING) {
+                let _ = self.a_in_scan_stop();
+            }
+
+            let _ = self.a_in_scan_cleanup();
+        }
+        unsafe { bindings::mcc172_close(self.address) };
+    }
+}
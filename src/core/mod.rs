@@ -1,9 +1,25 @@
+mod any_hat;
 mod bindings;
+mod capabilities;
 mod mcc118;
+mod mcc128;
+mod mcc134;
+mod mcc152;
+mod mcc172;
+mod traits;
 
-pub use mcc118::Mcc118;
+pub use any_hat::AnyHat;
+pub use capabilities::{Capabilities, CapabilityError, VoltageRange};
+pub use mcc118::{Mcc118, Mcc118DeviceInfo};
+pub use mcc128::{AnalogInputMode, AnalogInputRange, Mcc128, Mcc128DeviceInfo};
+pub use mcc134::{Mcc134, Mcc134DeviceInfo, TcType, COMMON_MODE_TC_VALUE, OPEN_TC_VALUE, OVERRANGE_TC_VALUE};
+pub use mcc152::{DioConfigItem, Mcc152, Mcc152DeviceInfo};
+pub use mcc172::{Mcc172, Mcc172DeviceInfo, SourceType};
+pub(crate) use mcc172::actual_rate as mcc172_actual_rate;
+pub use traits::{AIn, AInScanner, AOut, Dio, Hat, Iepe, Thermocouple};
 
 use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
 
 pub fn result_c_to_rs(code: i32) -> Result<(), ErrorCode> {
     if code == bindings::ResultCode_RESULT_SUCCESS {
@@ -13,8 +29,15 @@ pub fn result_c_to_rs(code: i32) -> Result<(), ErrorCode> {
     }
 }
 
+/// Converts a NUL-terminated C string buffer into an owned `String`, dropping the terminator and
+/// anything after it.
+pub(crate) fn string_from_c_buf(buf: &[u8]) -> String {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum HatId {
     ANY=bindings::HatIDs_HAT_ID_ANY as isize,
     Mcc118=bindings::HatIDs_HAT_ID_MCC_118 as isize,
@@ -40,7 +63,7 @@ impl From<u16> for HatId {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ErrorCode {
     BadParameter=bindings::ResultCode_RESULT_BAD_PARAMETER as isize,
     Busy=bindings::ResultCode_RESULT_BUSY as isize,
@@ -91,6 +114,7 @@ impl ErrorCode {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HatInfo {
     pub address: u8,
     pub id: HatId,
@@ -100,7 +124,7 @@ pub struct HatInfo {
 
 impl From<bindings::HatInfo> for HatInfo {
     fn from(info: bindings::HatInfo) -> Self {
-        let product_name = String::from_utf8_lossy(&info.product_name).into_owned();
+        let product_name = string_from_c_buf(&info.product_name);
 
         HatInfo {
             address: info.address,
@@ -111,7 +135,15 @@ impl From<bindings::HatInfo> for HatInfo {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareVersion {
+    pub version: u16,
+    /// Only the MCC 118 reports a separate bootloader version.
+    pub bootloader_version: Option<u16>,
+}
+
 bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct ScanOptions: u32 {
         const DEFAULT = bindings::OPTS_DEFAULT;
         const NOSCALEDATA = bindings::OPTS_NOSCALEDATA;
@@ -131,6 +163,7 @@ bitflags! {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TriggerMode {
     RisingEdge=bindings::TriggerMode_TRIG_RISING_EDGE as isize,
     FallingEdge=bindings::TriggerMode_TRIG_FALLING_EDGE as isize,
@@ -158,6 +191,63 @@ pub fn hat_list(filter_id: HatId) -> Vec<HatInfo> {
     hats
 }
 
+/// Identification details for one installed board, as returned by [`board_inventory`].
+///
+/// Fields the board type doesn't support (e.g. the MCC 134 and 152 have no firmware version, and
+/// the MCC 152 has no calibration date) are `None`, as are those that couldn't be read.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardInventory {
+    pub info: HatInfo,
+    pub serial: Option<String>,
+    pub firmware_version: Option<u16>,
+    pub bootloader_version: Option<u16>,
+    pub calibration_date: Option<String>,
+    /// Why the board couldn't be queried, e.g. [`ErrorCode::Busy`] while another process holds it.
+    pub error: Option<ErrorCode>,
+}
+
+/// Lists every installed board along with its serial number, firmware/bootloader version and
+/// calibration date.
+///
+/// Each board is opened only for the duration of the query. The library reference counts opens, so
+/// this is safe to call while the board is also open elsewhere in the process. A board that can't
+/// be queried is still listed, with the error recorded in [`BoardInventory::error`].
+pub fn board_inventory() -> Vec<BoardInventory> {
+    hat_list(HatId::ANY).into_iter().map(read_board_inventory).collect()
+}
+
+fn read_board_inventory(info: HatInfo) -> BoardInventory {
+    let mut inventory = BoardInventory {
+        info,
+        serial: None,
+        firmware_version: None,
+        bootloader_version: None,
+        calibration_date: None,
+        error: None,
+    };
+    // a board in bootloader mode can't be opened, so only the EEPROM info is available
+    if matches!(inventory.info.id, HatId::ANY | HatId::Mcc118Bootloader) {
+        return inventory;
+    }
+    // the wrapper closes the board again when it's dropped
+    if let Err(err) = AnyHat::open_info(&inventory.info).and_then(|dev| query_board(&dev, &mut inventory)) {
+        inventory.error = Some(err);
+    }
+    inventory
+}
+
+fn query_board(dev: &impl Hat, inventory: &mut BoardInventory) -> Result<(), ErrorCode> {
+    let serial = dev.serial()?;
+    let firmware = dev.firmware_version()?;
+    let calibration_date = dev.calibration_date()?;
+
+    inventory.serial = Some(serial);
+    inventory.firmware_version = firmware.map(|firmware| firmware.version);
+    inventory.bootloader_version = firmware.and_then(|firmware| firmware.bootloader_version);
+    inventory.calibration_date = calibration_date;
+    Ok(())
+}
+
 pub fn hat_wait_for_interrupt(timeout_ms: i32) -> Result<(), ErrorCode> {
     let res = unsafe { bindings::hat_wait_for_interrupt(timeout_ms) };
     result_c_to_rs(res)
@@ -185,17 +275,75 @@ pub fn hat_interrupt_callback_disable() -> Result<(), ErrorCode> {
     result_c_to_rs(res)
 }
 
-pub trait AIn {
-    fn a_in_read(&mut self, channel: u8, options: ScanOptions) -> Result<f64, ErrorCode>;
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Board {
+        id: HatId,
+        serial: Result<String, ErrorCode>,
+        firmware: Option<FirmwareVersion>,
+        calibration_date: Option<String>,
+    }
+
+    impl Hat for Board {
+        fn address(&self) -> u8 {
+            3
+        }
+
+        fn id(&self) -> HatId {
+            self.id
+        }
 
-pub trait AInScanner {
-    fn a_in_scan_actual_rate(channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode>;
-    fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: ScanOptions) -> Result<(), ErrorCode>;
-    fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode>;
-    fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode>;
-    fn a_in_scan_read(&mut self, samples_per_channel: i32, timeout_s: f64, buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode>;
-    fn a_in_scan_channel_count(&self) -> u8;
-    fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode>;
-    fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode>;
+        fn serial(&self) -> Result<String, ErrorCode> {
+            self.serial.clone()
+        }
+
+        fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+            Ok(self.firmware)
+        }
+
+        fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+            Ok(self.calibration_date.clone())
+        }
+    }
+
+    fn inventory(id: HatId) -> BoardInventory {
+        let info = HatInfo { address: 3, id, version: 1, product_name: String::new() };
+        BoardInventory { info, serial: None, firmware_version: None, bootloader_version: None, calibration_date: None, error: None }
+    }
+
+    #[test]
+    fn inventory_comes_from_the_hat_trait() {
+        let board = Board {
+            id: HatId::Mcc118,
+            serial: Ok("01234567".to_string()),
+            firmware: Some(FirmwareVersion { version: 0x0106, bootloader_version: Some(0x0103) }),
+            calibration_date: Some("2024-05-06".to_string()),
+        };
+        let mut mcc118 = inventory(HatId::Mcc118);
+        query_board(&board, &mut mcc118).unwrap();
+        assert_eq!(mcc118.serial.as_deref(), Some("01234567"));
+        assert_eq!((mcc118.firmware_version, mcc118.bootloader_version), (Some(0x0106), Some(0x0103)));
+        assert_eq!(mcc118.calibration_date.as_deref(), Some("2024-05-06"));
+
+        // the MCC 152 has neither firmware nor a calibration date
+        let board = Board { id: HatId::Mcc152, serial: Ok("89ABCDEF".to_string()), firmware: None, calibration_date: None };
+        let mut mcc152 = inventory(HatId::Mcc152);
+        query_board(&board, &mut mcc152).unwrap();
+        assert_eq!(mcc152.serial.as_deref(), Some("89ABCDEF"));
+        assert_eq!((mcc152.firmware_version, mcc152.bootloader_version, mcc152.calibration_date), (None, None, None));
+    }
+
+    #[test]
+    fn failed_reads_leave_the_inventory_empty() {
+        let board = Board { id: HatId::Mcc128, serial: Err(ErrorCode::Busy), firmware: Some(FirmwareVersion { version: 1, bootloader_version: None }), calibration_date: None };
+        let mut mcc128 = inventory(HatId::Mcc128);
+        assert_eq!(query_board(&board, &mut mcc128), Err(ErrorCode::Busy));
+        assert_eq!((mcc128.serial, mcc128.firmware_version), (None, None));
+
+        // boards in bootloader mode aren't opened at all
+        let bootloader = read_board_inventory(inventory(HatId::Mcc118Bootloader).info);
+        assert_eq!((bootloader.serial, bootloader.error), (None, None));
+    }
 }
@@ -0,0 +1,179 @@
+use super::{Capabilities, DioConfigItem, ErrorCode, FirmwareVersion, HatId, ScanOptions, ScanStatus, TcType};
+
+
+/// Functionality shared by every board. All device traits are object safe and extend this one, so
+/// mixed-device code can hold e.g. `Box<dyn AInScanner + Send>` and still identify the board.
+pub trait Hat {
+    fn address(&self) -> u8;
+    fn id(&self) -> HatId;
+    fn serial(&self) -> Result<String, ErrorCode>;
+
+    /// Static limits of this board type.
+    fn capabilities(&self) -> &'static Capabilities {
+        Capabilities::of(self.id()).expect("Hat::id must be a concrete board type")
+    }
+
+    /// `None` for boards without a microcontroller (MCC 134, 152).
+    fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+        Ok(None)
+    }
+
+    /// The factory calibration date as "YYYY-MM-DD", or `None` for boards that aren't calibrated (MCC 152).
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        Ok(None)
+    }
+
+    /// Fails with [`ErrorCode::InvalidDevice`] on boards without a controllable LED (MCC 134, 152).
+    fn blink_led(&mut self, _count: u8) -> Result<(), ErrorCode> {
+        Err(ErrorCode::InvalidDevice)
+    }
+
+    /// The number of analog inputs in the board's current input mode, e.g. 4 for an MCC 128 in
+    /// differential mode.
+    fn a_in_num_channels(&self) -> u8 {
+        self.capabilities().ai_channels
+    }
+}
+
+pub trait AIn: Hat {
+    fn a_in_read(&mut self, channel: u8, options: ScanOptions) -> Result<f64, ErrorCode>;
+}
+
+pub trait AInScanner: Hat {
+    fn a_in_scan_actual_rate(&self, channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode>;
+    fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: ScanOptions) -> Result<(), ErrorCode>;
+    fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode>;
+    fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode>;
+    fn a_in_scan_read(&mut self, samples_per_channel: i32, timeout_s: f64, buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode>;
+    fn a_in_scan_channel_count(&self) -> u8;
+    fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode>;
+    fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode>;
+}
+
+pub trait AOut: Hat {
+    fn a_out_write(&mut self, channel: u8, options: ScanOptions, value: f64) -> Result<(), ErrorCode>;
+    /// Updates all outputs at the same time. `values` must have one entry per output channel.
+    fn a_out_write_all(&mut self, options: ScanOptions, values: &[f64]) -> Result<(), ErrorCode>;
+}
+
+pub trait Dio: Hat {
+    fn dio_reset(&mut self) -> Result<(), ErrorCode>;
+    fn dio_input_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode>;
+    fn dio_input_read_port(&mut self) -> Result<u8, ErrorCode>;
+    fn dio_output_write_bit(&mut self, channel: u8, value: bool) -> Result<(), ErrorCode>;
+    fn dio_output_write_port(&mut self, value: u8) -> Result<(), ErrorCode>;
+    fn dio_output_read_bit(&self, channel: u8) -> Result<bool, ErrorCode>;
+    fn dio_output_read_port(&self) -> Result<u8, ErrorCode>;
+    fn dio_int_status_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode>;
+    fn dio_int_status_read_port(&mut self) -> Result<u8, ErrorCode>;
+    fn dio_config_write_bit(&mut self, channel: u8, item: DioConfigItem, value: u8) -> Result<(), ErrorCode>;
+    fn dio_config_write_port(&mut self, item: DioConfigItem, value: u8) -> Result<(), ErrorCode>;
+    fn dio_config_read_bit(&self, channel: u8, item: DioConfigItem) -> Result<u8, ErrorCode>;
+    fn dio_config_read_port(&self, item: DioConfigItem) -> Result<u8, ErrorCode>;
+}
+
+pub trait Thermocouple: Hat {
+    fn tc_type_write(&mut self, channel: u8, tc_type: TcType) -> Result<(), ErrorCode>;
+    fn tc_type_read(&self, channel: u8) -> Result<TcType, ErrorCode>;
+    fn update_interval_write(&mut self, interval_s: u8) -> Result<(), ErrorCode>;
+    fn update_interval_read(&self) -> Result<u8, ErrorCode>;
+    /// Reads a thermocouple temperature in °C. The result may be one of [`super::OPEN_TC_VALUE`],
+    /// [`super::OVERRANGE_TC_VALUE`] or [`super::COMMON_MODE_TC_VALUE`] instead of a temperature.
+    fn t_in_read(&mut self, channel: u8) -> Result<f64, ErrorCode>;
+    fn cjc_read(&mut self, channel: u8) -> Result<f64, ErrorCode>;
+}
+
+pub trait Iepe: Hat {
+    fn iepe_config_write(&mut self, channel: u8, enable: bool) -> Result<(), ErrorCode>;
+    fn iepe_config_read(&self, channel: u8) -> Result<bool, ErrorCode>;
+    /// Sets the sensor sensitivity in mV per engineering unit. Scaled scan data is divided by this.
+    fn a_in_sensitivity_write(&mut self, channel: u8, value: f64) -> Result<(), ErrorCode>;
+    fn a_in_sensitivity_read(&self, channel: u8) -> Result<f64, ErrorCode>;
+}
+
+// forwarding impls so boxed trait objects can be passed wherever a device is taken by value, e.g.
+// `scan_channels(Box<dyn AInScanner + Send>, ...)`
+
+impl<T: Hat + ?Sized> Hat for Box<T> {
+    fn address(&self) -> u8 {
+        (**self).address()
+    }
+
+    fn id(&self) -> HatId {
+        (**self).id()
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        (**self).serial()
+    }
+
+    fn capabilities(&self) -> &'static Capabilities {
+        (**self).capabilities()
+    }
+
+    fn firmware_version(&self) -> Result<Option<FirmwareVersion>, ErrorCode> {
+        (**self).firmware_version()
+    }
+
+    fn calibration_date(&self) -> Result<Option<String>, ErrorCode> {
+        (**self).calibration_date()
+    }
+
+    fn blink_led(&mut self, count: u8) -> Result<(), ErrorCode> {
+        (**self).blink_led(count)
+    }
+
+    fn a_in_num_channels(&self) -> u8 {
+        (**self).a_in_num_channels()
+    }
+}
+
+impl<T: AIn + ?Sized> AIn for Box<T> {
+    fn a_in_read(&mut self, channel: u8, options: ScanOptions) -> Result<f64, ErrorCode> {
+        (**self).a_in_read(channel, options)
+    }
+}
+
+impl<T: AInScanner + ?Sized> AInScanner for Box<T> {
+    fn a_in_scan_actual_rate(&self, channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+        (**self).a_in_scan_actual_rate(channel_count, sample_rate_per_channel)
+    }
+
+    fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: ScanOptions) -> Result<(), ErrorCode> {
+        (**self).a_in_scan_start(channel_mask, samples_per_channel, sample_rate_per_channel, options)
+    }
+
+    fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode> {
+        (**self).a_in_scan_buffer_size()
+    }
+
+    fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode> {
+        (**self).a_in_scan_status()
+    }
+
+    fn a_in_scan_read(&mut self, samples_per_channel: i32, timeout_s: f64, buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode> {
+        (**self).a_in_scan_read(samples_per_channel, timeout_s, buffer)
+    }
+
+    fn a_in_scan_channel_count(&self) -> u8 {
+        (**self).a_in_scan_channel_count()
+    }
+
+    fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode> {
+        (**self).a_in_scan_stop()
+    }
+
+    fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode> {
+        (**self).a_in_scan_cleanup()
+    }
+}
+
+impl<T: AOut + ?Sized> AOut for Box<T> {
+    fn a_out_write(&mut self, channel: u8, options: ScanOptions, value: f64) -> Result<(), ErrorCode> {
+        (**self).a_out_write(channel, options, value)
+    }
+
+    fn a_out_write_all(&mut self, options: ScanOptions, values: &[f64]) -> Result<(), ErrorCode> {
+        (**self).a_out_write_all(options, values)
+    }
+}
@@ -0,0 +1,508 @@
+//! Filtering and decimation stages for block streams.
+//!
+//! Each [`Stage`] filters every channel of a block independently and keeps its state between
+//! blocks, so a stream can be processed block by block with the same result as filtering it in
+//! one piece. Stages are chained with a [`Pipeline`]. Filter delays are not compensated: output
+//! samples keep the timestamps of the input samples they were computed at.
+
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::stream::{ScanBlock, StreamInfo};
+
+/// A processing step between the scanner and the consumer.
+pub trait Stage: Send {
+    /// Filters the next block of a stream sampled at `sample_rate`.
+    fn process(&mut self, block: &ScanBlock, sample_rate: f64) -> ScanBlock;
+
+    /// How many input samples make one output sample.
+    fn decimation(&self) -> usize {
+        1
+    }
+
+    /// Forgets all history, as if the stream started over.
+    fn reset(&mut self);
+}
+
+// applies a per-sample filter with one state per channel
+fn map_samples<S>(states: &mut Vec<S>, block: &ScanBlock, new_state: impl Fn() -> S, mut filter: impl FnMut(&mut S, f64) -> f64) -> ScanBlock {
+    if states.len() != block.channel_count {
+        states.clear();
+        states.resize_with(block.channel_count, new_state);
+    }
+
+    let mut out = block.clone();
+    for frame in out.data.chunks_exact_mut(block.channel_count.max(1)) {
+        for (value, state) in frame.iter_mut().zip(states.iter_mut()) {
+            *value = filter(state, *value);
+        }
+    }
+    out
+}
+
+/// Averages the last `length` samples.
+pub struct MovingAverage {
+    length: usize,
+    states: Vec<AverageState>,
+}
+
+struct AverageState {
+    window: Vec<f64>,
+    pos: usize,
+    filled: usize,
+    sum: f64,
+}
+
+impl MovingAverage {
+    pub fn new(length: usize) -> MovingAverage {
+        MovingAverage { length: length.max(1), states: Vec::new() }
+    }
+}
+
+impl Stage for MovingAverage {
+    fn process(&mut self, block: &ScanBlock, _sample_rate: f64) -> ScanBlock {
+        let length = self.length;
+        map_samples(
+            &mut self.states,
+            block,
+            || AverageState { window: vec![0.0; length], pos: 0, filled: 0, sum: 0.0 },
+            |state, x| {
+                state.sum += x - state.window[state.pos];
+                state.window[state.pos] = x;
+                state.pos = (state.pos + 1) % length;
+                state.filled = (state.filled + 1).min(length);
+                // re-sum once per window so rounding errors don't accumulate
+                if state.pos == 0 {
+                    state.sum = state.window.iter().sum();
+                }
+                state.sum / state.filled as f64
+            },
+        )
+    }
+
+    fn reset(&mut self) {
+        self.states.clear();
+    }
+}
+
+/// Window functions for FIR design and spectral analysis.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    /// The window's `n`th of `len` coefficients.
+    pub fn coefficient(self, n: usize, len: usize) -> f64 {
+        if len <= 1 {
+            return 1.0;
+        }
+        let x = 2.0 * PI * n as f64 / (len - 1) as f64;
+        match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * x.cos(),
+            Window::Hamming => 0.54 - 0.46 * x.cos(),
+            Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+/// A finite impulse response filter.
+#[derive(Clone)]
+pub struct Fir {
+    taps: Vec<f64>,
+    states: Vec<FirState>,
+}
+
+#[derive(Clone)]
+struct FirState {
+    history: Vec<f64>,
+    pos: usize,
+}
+
+impl FirState {
+    fn new(len: usize) -> FirState {
+        FirState { history: vec![0.0; len], pos: 0 }
+    }
+
+    fn push(&mut self, x: f64) {
+        self.history[self.pos] = x;
+        self.pos = (self.pos + 1) % self.history.len();
+    }
+
+    // convolution with the newest sample at taps[0]
+    fn output(&self, taps: &[f64]) -> f64 {
+        let len = self.history.len();
+        taps.iter().enumerate().map(|(k, tap)| tap * self.history[(self.pos + len - 1 - k) % len]).sum()
+    }
+}
+
+impl Fir {
+    pub fn new(taps: Vec<f64>) -> Fir {
+        assert!(!taps.is_empty(), "a FIR filter needs at least one tap");
+        Fir { taps, states: Vec::new() }
+    }
+
+    /// Windowed-sinc low-pass with unity gain at DC. `num_taps` is rounded up to an odd number.
+    pub fn low_pass(sample_rate: f64, cutoff_hz: f64, num_taps: usize, window: Window) -> Fir {
+        Fir::new(windowed_sinc(cutoff_hz / sample_rate, num_taps, window))
+    }
+
+    /// Windowed-sinc high-pass, made by spectral inversion of the matching low-pass.
+    pub fn high_pass(sample_rate: f64, cutoff_hz: f64, num_taps: usize, window: Window) -> Fir {
+        let mut taps = windowed_sinc(cutoff_hz / sample_rate, num_taps, window);
+        taps.iter_mut().for_each(|tap| *tap = -*tap);
+        let mid = taps.len() / 2;
+        taps[mid] += 1.0;
+        Fir::new(taps)
+    }
+
+    /// Windowed-sinc band-pass, the difference of two low-passes.
+    pub fn band_pass(sample_rate: f64, low_hz: f64, high_hz: f64, num_taps: usize, window: Window) -> Fir {
+        let high = windowed_sinc(high_hz / sample_rate, num_taps, window);
+        let low = windowed_sinc(low_hz / sample_rate, num_taps, window);
+        Fir::new(high.iter().zip(low).map(|(h, l)| h - l).collect())
+    }
+
+    pub fn taps(&self) -> &[f64] {
+        &self.taps
+    }
+}
+
+impl Stage for Fir {
+    fn process(&mut self, block: &ScanBlock, _sample_rate: f64) -> ScanBlock {
+        let taps = &self.taps;
+        map_samples(&mut self.states, block, || FirState::new(taps.len()), |state, x| {
+            state.push(x);
+            state.output(taps)
+        })
+    }
+
+    fn reset(&mut self) {
+        self.states.clear();
+    }
+}
+
+// low-pass taps for a cutoff given as a fraction of the sample rate
+fn windowed_sinc(cutoff: f64, num_taps: usize, window: Window) -> Vec<f64> {
+    let len = num_taps.max(1) | 1;
+    let mid = (len / 2) as f64;
+    let mut taps = (0..len)
+        .map(|n| {
+            let x = n as f64 - mid;
+            let sinc = if x == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * x).sin() / (PI * x) };
+            sinc * window.coefficient(n, len)
+        })
+        .collect::<Vec<_>>();
+
+    let sum: f64 = taps.iter().sum();
+    if sum != 0.0 {
+        taps.iter_mut().for_each(|tap| *tap /= sum);
+    }
+    taps
+}
+
+/// A second-order IIR section, designed with the RBJ audio EQ cookbook formulas.
+#[derive(Clone)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    states: Vec<[f64; 2]>,
+}
+
+impl Biquad {
+    /// A section with normalized coefficients: `y = b0 x + b1 x[-1] + b2 x[-2] - a1 y[-1] - a2 y[-2]`.
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Biquad {
+        Biquad { b, a, states: Vec::new() }
+    }
+
+    pub fn low_pass(sample_rate: f64, cutoff_hz: f64, q: f64) -> Biquad {
+        let (cos, alpha) = params(sample_rate, cutoff_hz, q);
+        Biquad::normalized([(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    pub fn high_pass(sample_rate: f64, cutoff_hz: f64, q: f64) -> Biquad {
+        let (cos, alpha) = params(sample_rate, cutoff_hz, q);
+        Biquad::normalized([(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// Band-pass with 0 dB gain at `center_hz`.
+    pub fn band_pass(sample_rate: f64, center_hz: f64, q: f64) -> Biquad {
+        let (cos, alpha) = params(sample_rate, center_hz, q);
+        Biquad::normalized([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    pub fn notch(sample_rate: f64, center_hz: f64, q: f64) -> Biquad {
+        let (cos, alpha) = params(sample_rate, center_hz, q);
+        Biquad::normalized([1.0, -2.0 * cos, 1.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// Notch for mains interference at `mains_hz` (50 or 60 Hz).
+    pub fn mains_notch(sample_rate: f64, mains_hz: f64) -> Biquad {
+        Biquad::notch(sample_rate, mains_hz, 10.0)
+    }
+
+    fn normalized(b: [f64; 3], a: [f64; 3]) -> Biquad {
+        Biquad::new([b[0] / a[0], b[1] / a[0], b[2] / a[0]], [a[1] / a[0], a[2] / a[0]])
+    }
+}
+
+fn params(sample_rate: f64, freq_hz: f64, q: f64) -> (f64, f64) {
+    let w0 = 2.0 * PI * freq_hz / sample_rate;
+    (w0.cos(), w0.sin() / (2.0 * q))
+}
+
+impl Stage for Biquad {
+    fn process(&mut self, block: &ScanBlock, _sample_rate: f64) -> ScanBlock {
+        let (b, a) = (self.b, self.a);
+        // transposed direct form II
+        map_samples(&mut self.states, block, || [0.0; 2], |s, x| {
+            let y = b[0] * x + s[0];
+            s[0] = b[1] * x - a[0] * y + s[1];
+            s[1] = b[2] * x - a[1] * y;
+            y
+        })
+    }
+
+    fn reset(&mut self) {
+        self.states.clear();
+    }
+}
+
+/// Low-pass filters below the new Nyquist frequency and keeps every `factor`th sample.
+pub struct Decimate {
+    factor: usize,
+    filter: Fir,
+    /// Input samples per channel seen so far.
+    input_count: u64,
+    /// Output samples per channel produced so far.
+    output_count: u64,
+}
+
+impl Decimate {
+    /// Uses a Blackman-windowed FIR with its cutoff at 80% of the output Nyquist frequency.
+    pub fn new(factor: usize) -> Decimate {
+        let factor = factor.max(1);
+        let taps = windowed_sinc(0.4 / factor as f64, 16 * factor + 1, Window::Blackman);
+        Decimate::with_filter(factor, Fir::new(taps))
+    }
+
+    /// Decimates with a custom anti-aliasing filter.
+    pub fn with_filter(factor: usize, filter: Fir) -> Decimate {
+        Decimate { factor: factor.max(1), filter, input_count: 0, output_count: 0 }
+    }
+}
+
+impl Stage for Decimate {
+    fn process(&mut self, block: &ScanBlock, sample_rate: f64) -> ScanBlock {
+        let channel_count = block.channel_count;
+        if self.filter.states.len() != channel_count {
+            self.filter.states = vec![FirState::new(self.filter.taps.len()); channel_count];
+        }
+
+        let mut data = Vec::with_capacity(block.data.len() / self.factor + channel_count);
+        let mut first_kept = None;
+        for (i, frame) in block.frames().enumerate() {
+            let keep = self.input_count.is_multiple_of(self.factor as u64);
+            for (x, state) in frame.iter().zip(self.filter.states.iter_mut()) {
+                state.push(*x);
+                if keep {
+                    data.push(state.output(&self.filter.taps));
+                }
+            }
+            if keep && first_kept.is_none() {
+                first_kept = Some(i);
+            }
+            self.input_count += 1;
+        }
+
+        let out = ScanBlock {
+            first_sample: self.output_count,
+            timestamp: block.sample_time(first_kept.unwrap_or(0), sample_rate),
+            channel_count,
+            data,
+        };
+        self.output_count += out.samples_per_channel() as u64;
+        out
+    }
+
+    fn decimation(&self) -> usize {
+        self.factor
+    }
+
+    fn reset(&mut self) {
+        self.filter.reset();
+        self.input_count = 0;
+        self.output_count = 0;
+    }
+}
+
+/// Stages applied in order. Stages after a [`Decimate`] run at the reduced rate, so design them
+/// with [`Pipeline::output_rate`] as it is when they are added.
+pub struct Pipeline {
+    input_rate: f64,
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    pub fn new(input: &StreamInfo) -> Pipeline {
+        Pipeline { input_rate: input.sample_rate, stages: Vec::new() }
+    }
+
+    pub fn stage(mut self, stage: impl Stage + 'static) -> Pipeline {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// The sample rate after all stages added so far.
+    pub fn output_rate(&self) -> f64 {
+        self.input_rate / self.stages.iter().map(|stage| stage.decimation()).product::<usize>() as f64
+    }
+
+    /// Describes the pipeline's output stream.
+    pub fn output_info(&self, input: &StreamInfo) -> StreamInfo {
+        StreamInfo { sample_rate: self.output_rate(), ..input.clone() }
+    }
+
+    pub fn process(&mut self, block: &ScanBlock) -> ScanBlock {
+        let mut rate = self.input_rate;
+        let mut block = block.clone();
+        for stage in &mut self.stages {
+            block = stage.process(&block, rate);
+            rate /= stage.decimation() as f64;
+        }
+        block
+    }
+
+    pub fn reset(&mut self) {
+        self.stages.iter_mut().for_each(|stage| stage.reset());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    const RATE: f64 = 1000.0;
+
+    /// Two channels of a chirp-like signal plus noise-ish harmonics, `frames` samples long.
+    fn signal(frames: usize) -> ScanBlock {
+        let data = (0..frames)
+            .flat_map(|i| {
+                let t = i as f64 / RATE;
+                [(2.0 * PI * 37.0 * t).sin() + 0.3 * (2.0 * PI * 410.0 * t * t).sin(), ((i * 7919) % 13) as f64 - 6.0]
+            })
+            .collect();
+        ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 2, data }
+    }
+
+    /// Splits `block` into blocks of the given lengths, repeated until the block is used up.
+    fn split(block: &ScanBlock, lengths: &[usize]) -> Vec<ScanBlock> {
+        let mut blocks = Vec::new();
+        let mut start = 0;
+        for &len in lengths.iter().cycle() {
+            let frames = block.samples_per_channel();
+            if start >= frames {
+                break;
+            }
+            let end = (start + len).min(frames);
+            blocks.push(ScanBlock {
+                first_sample: start as u64,
+                timestamp: block.sample_time(start, RATE),
+                channel_count: block.channel_count,
+                data: block.data[start * block.channel_count..end * block.channel_count].to_vec(),
+            });
+            start = end;
+        }
+        blocks
+    }
+
+    /// Processing in uneven blocks must give exactly what processing in one block gives.
+    fn assert_block_independent(mut whole: impl Stage, mut pieces: impl Stage) {
+        let input = signal(1000);
+        let expected = whole.process(&input, RATE);
+
+        let mut data = Vec::new();
+        let mut first_samples = Vec::new();
+        for block in split(&input, &[1, 2, 7, 3, 64, 5, 0, 11, 250]) {
+            let out = pieces.process(&block, RATE);
+            assert_eq!(out.channel_count, 2);
+            if !out.data.is_empty() {
+                first_samples.push((out.first_sample, out.timestamp));
+            }
+            data.extend(out.data);
+        }
+        assert_eq!(data, expected.data);
+
+        // every block starts where the previous one ended
+        let step = Duration::from_secs_f64(pieces.decimation() as f64 / RATE);
+        let mut next = 0;
+        for (first_sample, timestamp) in first_samples {
+            assert!(first_sample >= next);
+            let elapsed = timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+            assert!((elapsed - first_sample as f64 * step.as_secs_f64()).abs() < 1e-9);
+            next = first_sample;
+        }
+    }
+
+    #[test]
+    fn moving_average_across_blocks() {
+        assert_block_independent(MovingAverage::new(9), MovingAverage::new(9));
+    }
+
+    #[test]
+    fn fir_across_blocks() {
+        assert_block_independent(Fir::low_pass(RATE, 50.0, 31, Window::Hamming), Fir::low_pass(RATE, 50.0, 31, Window::Hamming));
+    }
+
+    #[test]
+    fn biquad_across_blocks() {
+        assert_block_independent(Biquad::low_pass(RATE, 80.0, 0.707), Biquad::low_pass(RATE, 80.0, 0.707));
+        assert_block_independent(Biquad::mains_notch(RATE, 50.0), Biquad::mains_notch(RATE, 50.0));
+    }
+
+    #[test]
+    fn decimate_across_blocks() {
+        assert_block_independent(Decimate::new(4), Decimate::new(4));
+        assert_block_independent(Decimate::new(3), Decimate::new(3));
+    }
+
+    #[test]
+    fn pipeline_across_blocks() {
+        let info = StreamInfo::test(crate::core::HatId::Mcc118, &[0, 1], RATE);
+        let pipeline = || Pipeline::new(&info).stage(Biquad::mains_notch(RATE, 60.0)).stage(Decimate::new(5)).stage(MovingAverage::new(3));
+        let input = signal(1000);
+        let expected = pipeline().process(&input);
+        assert_eq!(expected.samples_per_channel(), 200);
+
+        let mut pieces = pipeline();
+        assert_eq!(pieces.output_rate(), RATE / 5.0);
+        let data = split(&input, &[13, 1, 40]).iter().flat_map(|block| pieces.process(block).data).collect::<Vec<_>>();
+        assert_eq!(data, expected.data);
+    }
+
+    #[test]
+    fn reset_forgets_history() {
+        let input = signal(100);
+        let mut fir = Fir::low_pass(RATE, 50.0, 15, Window::Blackman);
+        let first = fir.process(&input, RATE);
+        fir.reset();
+        assert_eq!(fir.process(&input, RATE), first);
+    }
+
+    #[test]
+    fn low_pass_has_unity_dc_gain() {
+        for window in [Window::Rectangular, Window::Hann, Window::Hamming, Window::Blackman] {
+            let sum: f64 = Fir::low_pass(RATE, 100.0, 20, window).taps().iter().sum();
+            assert!((sum - 1.0).abs() < 1e-12, "{:?}", window);
+        }
+        let high_pass: f64 = Fir::high_pass(RATE, 100.0, 21, Window::Hann).taps().iter().sum();
+        assert!(high_pass.abs() < 1e-12);
+    }
+}
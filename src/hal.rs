@@ -0,0 +1,285 @@
+//! `embedded-hal` digital pins over an MCC 152's DIO bits, so drivers written against
+//! [`embedded_hal::digital`] can drive the HAT.
+//!
+//! [`split`] turns a [`Dio`] device into eight [`DioPin`]s that share it. A pin's direction is
+//! part of its type: [`Input`] pins implement [`InputPin`], [`Output`] pins implement
+//! [`OutputPin`] and [`StatefulOutputPin`], and the `into_*` methods reconfigure the bit and
+//! return it with the new type. The device is released when the last pin is dropped.
+
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use embedded_hal::digital::{ErrorKind, ErrorType, InputPin, OutputPin, PinState, StatefulOutputPin};
+
+use crate::core::{Dio, DioConfigItem, ErrorCode};
+
+/// Type state of a pin configured as an input.
+pub struct Input;
+
+/// Type state of a pin configured as an output.
+pub struct Output;
+
+impl embedded_hal::digital::Error for ErrorCode {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// One DIO bit of a shared device, in direction `S`.
+pub struct DioPin<D: Dio, S> {
+    dev: Arc<Mutex<D>>,
+    bit: u8,
+    _state: PhantomData<S>,
+}
+
+/// Configures every bit of `dev` as an input and returns a pin for each, indexed by bit.
+pub fn split<D: Dio>(mut dev: D) -> Result<[DioPin<D, Input>; 8], ErrorCode> {
+    dev.dio_config_write_port(DioConfigItem::Direction, 0xff)?;
+    let dev = Arc::new(Mutex::new(dev));
+    Ok(std::array::from_fn(|bit| DioPin::new(dev.clone(), bit as u8)))
+}
+
+impl<D: Dio, S> DioPin<D, S> {
+    fn new(dev: Arc<Mutex<D>>, bit: u8) -> DioPin<D, S> {
+        DioPin { dev, bit, _state: PhantomData }
+    }
+
+    fn dev(&self) -> MutexGuard<'_, D> {
+        // the device holds no invariants a panicking pin could break
+        self.dev.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn config(&self, item: DioConfigItem, value: bool) -> Result<(), ErrorCode> {
+        self.dev().dio_config_write_bit(self.bit, item, value as u8)
+    }
+
+    /// The bit number, 0-7.
+    pub fn bit(&self) -> u8 {
+        self.bit
+    }
+
+    /// Makes the pin a floating input.
+    pub fn into_floating_input(self) -> Result<DioPin<D, Input>, ErrorCode> {
+        self.config(DioConfigItem::PullEnable, false)?;
+        self.config(DioConfigItem::Direction, true)?;
+        Ok(DioPin::new(self.dev, self.bit))
+    }
+
+    /// Makes the pin an input with the internal pull-up resistor enabled.
+    pub fn into_pull_up_input(self) -> Result<DioPin<D, Input>, ErrorCode> {
+        self.into_pulled_input(true)
+    }
+
+    /// Makes the pin an input with the internal pull-down resistor enabled.
+    pub fn into_pull_down_input(self) -> Result<DioPin<D, Input>, ErrorCode> {
+        self.into_pulled_input(false)
+    }
+
+    fn into_pulled_input(self, up: bool) -> Result<DioPin<D, Input>, ErrorCode> {
+        self.config(DioConfigItem::PullConfig, up)?;
+        self.config(DioConfigItem::PullEnable, true)?;
+        self.config(DioConfigItem::Direction, true)?;
+        Ok(DioPin::new(self.dev, self.bit))
+    }
+
+    /// Makes the pin an output. The level is set before the direction, so the pin never drives
+    /// the previous output value.
+    pub fn into_output(self, state: PinState) -> Result<DioPin<D, Output>, ErrorCode> {
+        self.dev().dio_output_write_bit(self.bit, state == PinState::High)?;
+        self.config(DioConfigItem::Direction, false)?;
+        Ok(DioPin::new(self.dev, self.bit))
+    }
+}
+
+impl<D: Dio, S> ErrorType for DioPin<D, S> {
+    type Error = ErrorCode;
+}
+
+impl<D: Dio> InputPin for DioPin<D, Input> {
+    fn is_high(&mut self) -> Result<bool, ErrorCode> {
+        self.dev().dio_input_read_bit(self.bit)
+    }
+
+    fn is_low(&mut self) -> Result<bool, ErrorCode> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+impl<D: Dio> OutputPin for DioPin<D, Output> {
+    fn set_low(&mut self) -> Result<(), ErrorCode> {
+        self.dev().dio_output_write_bit(self.bit, false)
+    }
+
+    fn set_high(&mut self) -> Result<(), ErrorCode> {
+        self.dev().dio_output_write_bit(self.bit, true)
+    }
+}
+
+impl<D: Dio> StatefulOutputPin for DioPin<D, Output> {
+    fn is_set_high(&mut self) -> Result<bool, ErrorCode> {
+        self.dev().dio_output_read_bit(self.bit)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, ErrorCode> {
+        self.is_set_high().map(|high| !high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Hat, HatId};
+    use crate::sim::SimulatedHat;
+
+    /// A simulated MCC 152 that records the DIO writes made through it.
+    struct Logged(SimulatedHat, Vec<String>);
+
+    impl Hat for Logged {
+        fn address(&self) -> u8 {
+            self.0.address()
+        }
+
+        fn id(&self) -> HatId {
+            self.0.id()
+        }
+
+        fn serial(&self) -> Result<String, ErrorCode> {
+            self.0.serial()
+        }
+    }
+
+    impl Dio for Logged {
+        fn dio_reset(&mut self) -> Result<(), ErrorCode> {
+            self.0.dio_reset()
+        }
+
+        fn dio_input_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode> {
+            self.0.dio_input_read_bit(channel)
+        }
+
+        fn dio_input_read_port(&mut self) -> Result<u8, ErrorCode> {
+            self.0.dio_input_read_port()
+        }
+
+        fn dio_output_write_bit(&mut self, channel: u8, value: bool) -> Result<(), ErrorCode> {
+            self.1.push(format!("output {} {}", channel, value as u8));
+            self.0.dio_output_write_bit(channel, value)
+        }
+
+        fn dio_output_write_port(&mut self, value: u8) -> Result<(), ErrorCode> {
+            self.1.push(format!("output port {:#04x}", value));
+            self.0.dio_output_write_port(value)
+        }
+
+        fn dio_output_read_bit(&self, channel: u8) -> Result<bool, ErrorCode> {
+            self.0.dio_output_read_bit(channel)
+        }
+
+        fn dio_output_read_port(&self) -> Result<u8, ErrorCode> {
+            self.0.dio_output_read_port()
+        }
+
+        fn dio_int_status_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode> {
+            self.0.dio_int_status_read_bit(channel)
+        }
+
+        fn dio_int_status_read_port(&mut self) -> Result<u8, ErrorCode> {
+            self.0.dio_int_status_read_port()
+        }
+
+        fn dio_config_write_bit(&mut self, channel: u8, item: DioConfigItem, value: u8) -> Result<(), ErrorCode> {
+            self.1.push(format!("{:?} {} {}", item, channel, value));
+            self.0.dio_config_write_bit(channel, item, value)
+        }
+
+        fn dio_config_write_port(&mut self, item: DioConfigItem, value: u8) -> Result<(), ErrorCode> {
+            self.1.push(format!("{:?} port {:#04x}", item, value));
+            self.0.dio_config_write_port(item, value)
+        }
+
+        fn dio_config_read_bit(&self, channel: u8, item: DioConfigItem) -> Result<u8, ErrorCode> {
+            self.0.dio_config_read_bit(channel, item)
+        }
+
+        fn dio_config_read_port(&self, item: DioConfigItem) -> Result<u8, ErrorCode> {
+            self.0.dio_config_read_port(item)
+        }
+    }
+
+    fn logged() -> Logged {
+        Logged(SimulatedHat::new(HatId::Mcc152, 0, |_, _| 0.0).unwrap(), Vec::new())
+    }
+
+    /// Takes the writes logged so far.
+    fn writes<S>(pin: &DioPin<Logged, S>) -> Vec<String> {
+        std::mem::take(&mut pin.dev().1)
+    }
+
+    #[test]
+    fn split_makes_every_bit_an_input() {
+        let mut dev = logged();
+        dev.0.dio_config_write_port(DioConfigItem::Direction, 0x0f).unwrap();
+        let pins = split(dev).unwrap();
+        assert_eq!(pins.iter().map(DioPin::bit).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(writes(&pins[0]), ["Direction port 0xff"]);
+        assert_eq!(pins[0].dev().dio_config_read_port(DioConfigItem::Direction), Ok(0xff));
+        assert_eq!(Arc::strong_count(&pins[0].dev), 8);
+    }
+
+    #[test]
+    fn outputs_get_their_level_before_their_direction() {
+        let [_, _, pin, ..] = split(logged()).unwrap();
+        writes(&pin);
+        let mut pin = pin.into_output(PinState::Low).unwrap();
+        assert_eq!(writes(&pin), ["output 2 0", "Direction 2 0"]);
+        assert_eq!(pin.is_set_high(), Ok(false));
+        assert_eq!(pin.is_set_low(), Ok(true));
+
+        pin.set_high().unwrap();
+        assert_eq!(pin.is_set_high(), Ok(true));
+        assert_eq!(pin.dev().dio_output_read_port(), Ok(0xff));
+        pin.set_low().unwrap();
+        assert_eq!(pin.dev().dio_output_read_port(), Ok(0b1111_1011));
+        assert_eq!(writes(&pin), ["output 2 1", "output 2 0"]);
+
+        let pin = pin.into_output(PinState::High).unwrap();
+        assert_eq!(writes(&pin), ["output 2 1", "Direction 2 0"]);
+    }
+
+    #[test]
+    fn inputs_can_be_pulled_or_floating() {
+        let [pin, ..] = split(logged()).unwrap();
+        let pin = pin.into_pull_down_input().unwrap();
+        let dev = |item| pin.dev().dio_config_read_bit(0, item).unwrap();
+        assert_eq!((dev(DioConfigItem::PullConfig), dev(DioConfigItem::PullEnable), dev(DioConfigItem::Direction)), (0, 1, 1));
+
+        let pin = pin.into_pull_up_input().unwrap();
+        let dev = |item| pin.dev().dio_config_read_bit(0, item).unwrap();
+        assert_eq!((dev(DioConfigItem::PullConfig), dev(DioConfigItem::PullEnable), dev(DioConfigItem::Direction)), (1, 1, 1));
+
+        let pin = pin.into_output(PinState::High).unwrap().into_floating_input().unwrap();
+        let dev = |item| pin.dev().dio_config_read_bit(0, item).unwrap();
+        assert_eq!((dev(DioConfigItem::PullEnable), dev(DioConfigItem::Direction)), (0, 1));
+    }
+
+    #[test]
+    fn inputs_follow_the_pin_levels() {
+        let dev = logged();
+        let inputs = dev.0.dio_inputs();
+        let [_, _, _, _, _, mut pin, ..] = split(dev).unwrap();
+        assert_eq!(pin.is_high(), Ok(true));
+        inputs.set_bit(5, false);
+        assert_eq!(pin.is_high(), Ok(false));
+        assert_eq!(pin.is_low(), Ok(true));
+        inputs.set_bit(4, true);
+        assert_eq!(pin.is_low(), Ok(true));
+        inputs.set_bit(5, true);
+        assert_eq!(pin.is_high(), Ok(true));
+    }
+
+    #[test]
+    fn boards_without_dio_cant_be_split() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        assert!(matches!(split(dev), Err(ErrorCode::InvalidDevice)));
+    }
+}
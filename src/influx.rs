@@ -0,0 +1,625 @@
+//! InfluxDB line protocol export.
+//!
+//! [`LineMapping`] turns [`Summary`]s or blocks of periodic reads into [`Point`]s, one per channel
+//! and sample time, tagged with the board's serial number, address and channel. The points go to
+//! rotating files with [`LineFileWriter`] or to an InfluxDB write endpoint with [`HttpWriter`],
+//! which batches them and retries failed requests.
+//!
+//! ```text
+//! daqhats,serial=01234567,address=0,channel=2 min=0.1,max=0.3,mean=0.2,std_dev=0.05,rms=0.21,count=1000i 1700000000000000000
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::recorder::{rotated_path, Rotation};
+use crate::stats::Summary;
+use crate::stream::{ScanBlock, StreamInfo};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Float(f64),
+    Integer(i64),
+}
+
+/// One line of line protocol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub measurement: String,
+    pub tags: Vec<(String, String)>,
+    pub fields: Vec<(String, FieldValue)>,
+    pub time: SystemTime,
+}
+
+impl fmt::Display for Point {
+    /// Formats the point with a nanosecond timestamp. Non-finite float fields are left out, as
+    /// InfluxDB rejects them, and so are tags with an empty key or value, which line protocol
+    /// can't express.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&escape(&self.measurement, &[',', ' ']))?;
+        for (key, value) in self.tags.iter().filter(|(key, value)| !key.is_empty() && !value.is_empty()) {
+            write!(f, ",{}={}", escape(key, &[',', '=', ' ']), escape(value, &[',', '=', ' ']))?;
+        }
+        let fields = self.fields.iter().filter(|(_, value)| !matches!(value, FieldValue::Float(v) if !v.is_finite()));
+        for (i, (key, value)) in fields.enumerate() {
+            f.write_str(if i == 0 { " " } else { "," })?;
+            f.write_str(&escape(key, &[',', '=', ' ']))?;
+            match value {
+                FieldValue::Float(v) => write!(f, "={}", v)?,
+                FieldValue::Integer(v) => write!(f, "={}i", v)?,
+            }
+        }
+        let nanos = self.time.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        write!(f, " {}", nanos)
+    }
+}
+
+impl Point {
+    /// Whether the point has a field that can be written. Lines without fields are invalid.
+    pub fn has_fields(&self) -> bool {
+        self.fields.iter().any(|(_, value)| !matches!(value, FieldValue::Float(v) if !v.is_finite()))
+    }
+}
+
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// How stream data maps onto measurements, tags and fields. Tags whose key is `None` are left out,
+/// as are empty values, e.g. the unit of a channel without units.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LineMapping {
+    pub measurement: String,
+    pub serial_tag: Option<String>,
+    pub address_tag: Option<String>,
+    pub channel_tag: Option<String>,
+    pub unit_tag: Option<String>,
+    /// Tags added to every point, e.g. the site or rig name.
+    pub tags: BTreeMap<String, String>,
+    /// Field holding the sample value of points made from blocks.
+    pub value_field: String,
+}
+
+impl Default for LineMapping {
+    fn default() -> Self {
+        LineMapping {
+            measurement: "daqhats".to_string(),
+            serial_tag: Some("serial".to_string()),
+            address_tag: Some("address".to_string()),
+            channel_tag: Some("channel".to_string()),
+            unit_tag: None,
+            tags: BTreeMap::new(),
+            value_field: "value".to_string(),
+        }
+    }
+}
+
+impl LineMapping {
+    fn tags(&self, serial: &str, address: u8, channel: u8, unit: &str) -> Vec<(String, String)> {
+        let mut tags = Vec::new();
+        let board_tags = [(&self.serial_tag, serial.to_string()), (&self.address_tag, address.to_string()), (&self.channel_tag, channel.to_string()), (&self.unit_tag, unit.to_string())];
+        for (key, value) in board_tags {
+            if let Some(key) = key {
+                tags.push((key.clone(), value));
+            }
+        }
+        tags.extend(self.tags.iter().map(|(key, value)| (key.clone(), value.clone())));
+        // InfluxDB prefers tags sorted by key
+        tags.sort();
+        tags
+    }
+
+    /// One point per channel, at the start of the summary's interval, with `min`, `max`, `mean`,
+    /// `std_dev`, `rms` and `count` fields.
+    pub fn summary_points(&self, summary: &Summary) -> Vec<Point> {
+        summary
+            .channels
+            .iter()
+            .map(|ch| Point {
+                measurement: self.measurement.clone(),
+                tags: self.tags(&summary.serial, summary.address, ch.channel, &ch.units),
+                fields: vec![
+                    ("min".to_string(), FieldValue::Float(ch.min)),
+                    ("max".to_string(), FieldValue::Float(ch.max)),
+                    ("mean".to_string(), FieldValue::Float(ch.mean)),
+                    ("std_dev".to_string(), FieldValue::Float(ch.std_dev)),
+                    ("rms".to_string(), FieldValue::Float(ch.rms)),
+                    ("count".to_string(), FieldValue::Integer(ch.count as i64)),
+                ],
+                time: summary.start,
+            })
+            .collect()
+    }
+
+    /// One point per sample, meant for blocks of periodic reads (see [`StreamInfo::polled`])
+    /// rather than full-rate scans.
+    pub fn block_points(&self, info: &StreamInfo, block: &ScanBlock) -> Vec<Point> {
+        let mut points = Vec::with_capacity(block.data.len());
+        for (i, frame) in block.frames().enumerate() {
+            let time = block.sample_time(i, info.sample_rate);
+            for ((&channel, unit), &value) in info.channels.iter().zip(&info.units).zip(frame) {
+                points.push(Point {
+                    measurement: self.measurement.clone(),
+                    tags: self.tags(&info.serial, info.address, channel, unit),
+                    fields: vec![(self.value_field.clone(), FieldValue::Float(value))],
+                    time,
+                });
+            }
+        }
+        points
+    }
+}
+
+/// Writes points to line protocol files. With rotation enabled the files are named like a
+/// [`crate::recorder::Recorder`]'s, and [`Rotation::Duration`] is measured in point time.
+pub struct LineFileWriter {
+    path: PathBuf,
+    rotation: Rotation,
+    writer: BufWriter<File>,
+    file_bytes: u64,
+    file_start: Option<SystemTime>,
+    files: Vec<PathBuf>,
+}
+
+impl LineFileWriter {
+    pub fn create(path: impl AsRef<Path>, rotation: Rotation) -> io::Result<LineFileWriter> {
+        let path = path.as_ref().to_path_buf();
+        let first = rotated_path(&path, rotation, 0);
+        Ok(LineFileWriter {
+            writer: BufWriter::new(File::create(&first)?),
+            path,
+            rotation,
+            file_bytes: 0,
+            file_start: None,
+            files: vec![first],
+        })
+    }
+
+    /// Every file written so far.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Writes the points with fields; files are only switched between calls.
+    pub fn write(&mut self, points: &[Point]) -> io::Result<()> {
+        let Some(first) = points.iter().find(|point| point.has_fields()) else {
+            return Ok(());
+        };
+        if self.should_rotate(first.time) {
+            self.rotate()?;
+        }
+        self.file_start.get_or_insert(first.time);
+
+        for point in points.iter().filter(|point| point.has_fields()) {
+            let line = format!("{}\n", point);
+            self.writer.write_all(line.as_bytes())?;
+            self.file_bytes += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and closes the current file and returns every file written.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(self.files)
+    }
+
+    fn should_rotate(&self, next: SystemTime) -> bool {
+        match self.rotation {
+            Rotation::Never => false,
+            Rotation::Size(limit) => self.file_bytes >= limit,
+            Rotation::Duration(limit) => match self.file_start {
+                Some(start) => next.duration_since(start).map(|d| d >= limit).unwrap_or(false),
+                None => false,
+            },
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let next = rotated_path(&self.path, self.rotation, self.files.len());
+        self.writer = BufWriter::new(File::create(&next)?);
+        self.files.push(next);
+        self.file_bytes = 0;
+        self.file_start = None;
+        Ok(())
+    }
+}
+
+/// Posts points to an InfluxDB write endpoint over plain HTTP, e.g.
+/// `http://localhost:8086/api/v2/write?org=lab&bucket=daq&precision=ns` (2.x) or
+/// `http://localhost:8086/write?db=daq` (1.x).
+///
+/// Points are sent in batches of [`HttpWriter::batch_size`] lines, or sooner once the oldest
+/// unsent line is [`HttpWriter::max_delay`] old. A request that fails to connect, times out, or
+/// gets a 429 or 5xx response is retried with exponential backoff; if every attempt fails the
+/// lines are kept and sent with the next batch.
+///
+/// Requests run on the calling thread, so [`HttpWriter::write`] and [`HttpWriter::flush`] block
+/// while a batch is sent and retried. With the defaults, an unreachable endpoint holds up the
+/// caller for over 40 seconds per batch (four attempts with 10 s timeouts, plus backoff). Don't
+/// call them from a loop that has to keep up with a scan or periodic reads; hand the points to
+/// a thread of their own, or lower [`HttpWriter::timeout`] and [`HttpWriter::retries`].
+pub struct HttpWriter {
+    host: String,
+    path: String,
+    token: Option<String>,
+    batch_size: usize,
+    max_delay: Duration,
+    retries: u32,
+    retry_delay: Duration,
+    timeout: Duration,
+    max_pending: usize,
+    pending: VecDeque<String>,
+    oldest: Option<Instant>,
+    dropped: u64,
+}
+
+impl HttpWriter {
+    /// Fails with [`io::ErrorKind::InvalidInput`] for URLs other than `http://host[:port]/path`.
+    pub fn new(url: &str) -> io::Result<HttpWriter> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| invalid_input("only http:// URLs are supported"))?;
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(invalid_input("the URL has no host"));
+        }
+        let host = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+
+        Ok(HttpWriter {
+            host,
+            path: path.to_string(),
+            token: None,
+            batch_size: 5000,
+            max_delay: Duration::from_secs(10),
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            max_pending: 100_000,
+            pending: VecDeque::new(),
+            oldest: None,
+            dropped: 0,
+        })
+    }
+
+    /// Sends `Authorization: Token {token}`, as InfluxDB 2.x expects.
+    pub fn token(mut self, token: &str) -> HttpWriter {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub fn batch_size(mut self, lines: usize) -> HttpWriter {
+        self.batch_size = lines.max(1);
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> HttpWriter {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Retries each request up to `retries` times, waiting `delay`, then twice as long, and so on.
+    pub fn retries(mut self, retries: u32, delay: Duration) -> HttpWriter {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> HttpWriter {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The most unsent lines kept while the endpoint is unreachable. Beyond that the oldest lines
+    /// are dropped and counted in [`HttpWriter::dropped`].
+    pub fn max_pending(mut self, lines: usize) -> HttpWriter {
+        self.max_pending = lines.max(1);
+        self
+    }
+
+    /// Lines waiting to be sent.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Lines dropped because of [`HttpWriter::max_pending`] or rejected by the server.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Queues the points with fields and sends any batches that are due.
+    pub fn write(&mut self, points: &[Point]) -> io::Result<()> {
+        for point in points.iter().filter(|point| point.has_fields()) {
+            self.pending.push_back(point.to_string());
+            self.oldest.get_or_insert_with(Instant::now);
+        }
+        while self.pending.len() > self.max_pending {
+            self.pending.pop_front();
+            self.dropped += 1;
+        }
+
+        while self.pending.len() >= self.batch_size {
+            self.send_batch()?;
+        }
+        if self.oldest.is_some_and(|t| t.elapsed() >= self.max_delay) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sends every pending line.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            self.send_batch()?;
+        }
+        Ok(())
+    }
+
+    fn send_batch(&mut self) -> io::Result<()> {
+        let count = self.pending.len().min(self.batch_size);
+        let mut body = String::new();
+        for line in self.pending.iter().take(count) {
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        let result = loop {
+            let err = match self.post(&body) {
+                Ok(status) if (200..300).contains(&status) => break Ok(()),
+                Ok(status) if status == 429 || status >= 500 => io::Error::other(format!("the server responded with status {}", status)),
+                Ok(status) => {
+                    // the server won't take these lines however often they are sent
+                    self.dropped += count as u64;
+                    self.pending.drain(..count);
+                    self.oldest = (!self.pending.is_empty()).then(Instant::now);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("the server rejected {} lines with status {}", count, status)));
+                }
+                Err(err) => err,
+            };
+            if attempt >= self.retries {
+                break Err(err);
+            }
+            attempt += 1;
+            std::thread::sleep(delay);
+            delay *= 2;
+        };
+
+        if result.is_ok() {
+            self.pending.drain(..count);
+            self.oldest = (!self.pending.is_empty()).then(Instant::now);
+        }
+        result
+    }
+
+    /// Posts `body` and returns the response status.
+    fn post(&self, body: &str) -> io::Result<u16> {
+        let addr = std::net::ToSocketAddrs::to_socket_addrs(&self.host)?.next().ok_or_else(|| invalid_input("the host has no address"))?;
+        let mut socket = TcpStream::connect_timeout(&addr, self.timeout)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.set_write_timeout(Some(self.timeout))?;
+
+        let mut request = format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n", self.path, self.host, body.len());
+        if let Some(token) = &self.token {
+            request.push_str(&format!("Authorization: Token {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        socket.write_all(request.as_bytes())?;
+        socket.write_all(body.as_bytes())?;
+
+        // only the status line matters
+        let mut response = Vec::new();
+        socket.read_to_end(&mut response)?;
+        let status_line = response.split(|&b| b == b'\n').next().unwrap_or_default();
+        String::from_utf8_lossy(status_line)
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use super::*;
+    use crate::core::HatId;
+
+    fn point(value: f64) -> Point {
+        Point { measurement: "m".to_string(), tags: Vec::new(), fields: vec![("v".to_string(), FieldValue::Float(value))], time: UNIX_EPOCH + Duration::from_secs(1) }
+    }
+
+    fn points(values: impl IntoIterator<Item = f64>) -> Vec<Point> {
+        values.into_iter().map(point).collect()
+    }
+
+    /// A stand-in endpoint that answers one request with each status in turn and returns the
+    /// request heads and bodies.
+    fn serve(statuses: &[u16]) -> (String, JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/write?db=daq", listener.local_addr().unwrap());
+        let statuses = statuses.to_vec();
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (socket, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(socket);
+                let mut head = String::new();
+                while !head.ends_with("\r\n\r\n") {
+                    assert!(reader.read_line(&mut head).unwrap() > 0);
+                }
+                let length = head.lines().find_map(|line| line.strip_prefix("Content-Length: ")).unwrap().parse().unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(reader.get_mut(), "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+                requests.push((head, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn values(body: &str) -> Vec<String> {
+        body.lines().map(|line| line.split(' ').nth(1).unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn points_are_escaped() {
+        let point = Point {
+            measurement: "air temp,room=1".to_string(),
+            tags: vec![("rig name".to_string(), "a=b,c".to_string()), ("path".to_string(), "C:\\x".to_string())],
+            fields: vec![("max value".to_string(), FieldValue::Float(1.5)), ("n=1,2".to_string(), FieldValue::Integer(-3))],
+            time: UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789),
+        };
+        assert_eq!(point.to_string(), "air\\ temp\\,room=1,rig\\ name=a\\=b\\,c,path=C:\\\\x max\\ value=1.5,n\\=1\\,2=-3i 1700000000123456789");
+    }
+
+    #[test]
+    fn non_finite_fields_and_empty_tags_are_left_out() {
+        let mut point = point(f64::NAN);
+        point.fields.push(("inf".to_string(), FieldValue::Float(f64::INFINITY)));
+        assert!(!point.has_fields());
+        point.fields.push(("ok".to_string(), FieldValue::Float(2.0)));
+        point.tags = vec![("empty".to_string(), String::new()), (String::new(), "x".to_string()), ("site".to_string(), "lab".to_string())];
+        assert!(point.has_fields());
+        assert_eq!(point.to_string(), "m,site=lab ok=2 1000000000");
+
+        let mut writer = HttpWriter::new("http://127.0.0.1:9/write").unwrap().max_delay(Duration::from_secs(3600));
+        writer.write(&points([f64::NAN, f64::NEG_INFINITY, 1.0])).unwrap();
+        assert_eq!(writer.pending(), 1);
+    }
+
+    #[test]
+    fn block_points_skip_empty_units() {
+        let info = StreamInfo {
+            address: 3,
+            serial: "0123".to_string(),
+            units: vec!["V".to_string(), String::new()],
+            ..StreamInfo::test(HatId::Mcc118, &[0, 5], 10.0)
+        };
+        let block = ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 2, data: vec![1.0, 2.0, 3.0, 4.0] };
+        let mapping = LineMapping { unit_tag: Some("unit".to_string()), ..LineMapping::default() };
+        let lines: Vec<String> = mapping.block_points(&info, &block).iter().map(|point| point.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "daqhats,address=3,channel=0,serial=0123,unit=V value=1 0",
+                "daqhats,address=3,channel=5,serial=0123 value=2 0",
+                "daqhats,address=3,channel=0,serial=0123,unit=V value=3 100000000",
+                "daqhats,address=3,channel=5,serial=0123 value=4 100000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_are_sent_in_batches() {
+        let (url, server) = serve(&[204, 204, 204]);
+        let mut writer = HttpWriter::new(&url).unwrap().token("secret").batch_size(3).max_delay(Duration::from_secs(3600));
+        writer.write(&points((0..7).map(f64::from))).unwrap();
+        assert_eq!(writer.pending(), 1);
+        writer.flush().unwrap();
+        assert_eq!(writer.pending(), 0);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].0.starts_with("POST /write?db=daq HTTP/1.1\r\n"));
+        assert!(requests[0].0.contains("\r\nAuthorization: Token secret\r\n"));
+        let batches: Vec<Vec<String>> = requests.iter().map(|(_, body)| values(body)).collect();
+        assert_eq!(batches, [vec!["v=0", "v=1", "v=2"], vec!["v=3", "v=4", "v=5"], vec!["v=6"]]);
+    }
+
+    #[test]
+    fn old_lines_are_sent_after_max_delay() {
+        let (url, server) = serve(&[204]);
+        let mut writer = HttpWriter::new(&url).unwrap().max_delay(Duration::from_millis(50));
+        writer.write(&points([1.0, 2.0])).unwrap();
+        assert_eq!(writer.pending(), 2);
+        std::thread::sleep(Duration::from_millis(60));
+        writer.write(&[]).unwrap();
+        assert_eq!(writer.pending(), 0);
+        assert_eq!(values(&server.join().unwrap()[0].1), ["v=1", "v=2"]);
+    }
+
+    #[test]
+    fn busy_and_failing_servers_are_retried() {
+        let (url, server) = serve(&[503, 429, 204]);
+        let mut writer = HttpWriter::new(&url).unwrap().batch_size(2).retries(2, Duration::from_millis(1));
+        writer.write(&points([1.0, 2.0])).unwrap();
+        assert_eq!(writer.pending(), 0);
+        assert_eq!(writer.dropped(), 0);
+        let requests = server.join().unwrap();
+        assert!(requests.iter().all(|(_, body)| values(body) == ["v=1", "v=2"]));
+
+        // once the retries run out the lines are kept for the next attempt
+        let (url, server) = serve(&[500, 500, 204]);
+        let mut writer = HttpWriter::new(&url).unwrap().batch_size(2).retries(1, Duration::from_millis(1));
+        assert!(writer.write(&points([1.0, 2.0])).is_err());
+        assert_eq!(writer.pending(), 2);
+        writer.flush().unwrap();
+        assert_eq!(writer.pending(), 0);
+        assert_eq!(server.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rejected_lines_are_dropped() {
+        let (url, server) = serve(&[400, 204]);
+        let mut writer = HttpWriter::new(&url).unwrap().batch_size(2).retries(3, Duration::from_millis(1));
+        let err = writer.write(&points([1.0, 2.0, 3.0])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(writer.dropped(), 2);
+        assert_eq!(writer.pending(), 1);
+        writer.flush().unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(values(&requests[0].1), ["v=1", "v=2"]);
+        assert_eq!(values(&requests[1].1), ["v=3"]);
+    }
+
+    #[test]
+    fn pending_lines_are_capped() {
+        let (url, server) = serve(&[204]);
+        let mut writer = HttpWriter::new(&url).unwrap().max_delay(Duration::from_secs(3600)).max_pending(3);
+        writer.write(&points([1.0, 2.0])).unwrap();
+        writer.write(&points([3.0, 4.0, 5.0])).unwrap();
+        assert_eq!(writer.pending(), 3);
+        assert_eq!(writer.dropped(), 2);
+        writer.flush().unwrap();
+        assert_eq!(values(&server.join().unwrap()[0].1), ["v=3", "v=4", "v=5"]);
+    }
+
+    #[test]
+    fn only_http_urls_are_accepted() {
+        for url in ["https://localhost/write", "http:///write", "localhost:8086"] {
+            assert_eq!(HttpWriter::new(url).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidInput), "{}", url);
+        }
+    }
+}
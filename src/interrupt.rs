@@ -0,0 +1,273 @@
+//! Digital input change events from MCC 152 boards, driven by the shared HAT interrupt line.
+//!
+//! [`DioWatcher`] unmasks the interrupt for selected bits on one or more boards and waits on the
+//! line with [`hat_wait_for_interrupt`]. Every board is asked for its interrupt status whenever
+//! the line is active, so boards sharing it are told apart, and reading the inputs clears both
+//! the interrupt and the latched levels. With latching, a pulse shorter than the interrupt
+//! latency still produces an event for each edge.
+
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use crate::core::{hat_interrupt_state, hat_wait_for_interrupt, Dio, DioConfigItem, ErrorCode};
+use crate::stream::StopHandle;
+
+/// How long a wait for the interrupt lasts before checking for a stop request.
+const WAIT_MS: i32 = 100;
+/// The pause before looking again when the line stays active but none of the watched bits
+/// changed, e.g. because a board that isn't watched is holding it.
+const BUSY_LINE_PAUSE: Duration = Duration::from_millis(1);
+
+/// A watched input that changed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DioEvent {
+    pub address: u8,
+    pub bit: u8,
+    /// The new level, i.e. the latched level when latching is enabled.
+    pub level: bool,
+    /// When the change was read from the board.
+    pub timestamp: SystemTime,
+}
+
+struct Board<D> {
+    dev: D,
+    bits: u8,
+}
+
+/// Watches digital inputs for changes. Add boards with [`DioWatcher::add_board`], then
+/// [`DioWatcher::start`] it.
+pub struct DioWatcher<D> {
+    boards: Vec<Board<D>>,
+    latch: bool,
+    poll_interval: Option<Duration>,
+}
+
+impl<D: Dio + Send + 'static> Default for DioWatcher<D> {
+    fn default() -> Self {
+        DioWatcher::new()
+    }
+}
+
+impl<D: Dio + Send + 'static> DioWatcher<D> {
+    pub fn new() -> DioWatcher<D> {
+        DioWatcher { boards: Vec::new(), latch: true, poll_interval: None }
+    }
+
+    /// Watches the bits set in `bits` on `dev`. They are configured as inputs when the watcher
+    /// starts.
+    pub fn add_board(mut self, dev: D, bits: u8) -> DioWatcher<D> {
+        self.boards.push(Board { dev, bits });
+        self
+    }
+
+    /// Whether to latch the watched inputs, so the level at the time of a change is reported
+    /// rather than the level when it is read. On by default.
+    pub fn latch(mut self, latch: bool) -> DioWatcher<D> {
+        self.latch = latch;
+        self
+    }
+
+    /// Reads the interrupt status at this interval instead of waiting on the interrupt line, for
+    /// simulated boards or systems where the line isn't available.
+    pub fn poll_interval(mut self, interval: Duration) -> DioWatcher<D> {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    /// Configures the boards and calls `callback` from a background thread for each change.
+    pub fn start(mut self, callback: impl FnMut(DioEvent) + Send + 'static) -> Result<DioWatch<D>, ErrorCode> {
+        for board in &mut self.boards {
+            for bit in (0..8).filter(|bit| board.bits & (1 << bit) != 0) {
+                board.dev.dio_config_write_bit(bit, DioConfigItem::Direction, 1)?;
+                board.dev.dio_config_write_bit(bit, DioConfigItem::InputLatch, self.latch as u8)?;
+                board.dev.dio_config_write_bit(bit, DioConfigItem::IntMask, 0)?;
+            }
+            // start from the current levels rather than reporting changes from before the start
+            board.dev.dio_input_read_port()?;
+        }
+
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+        let handle = std::thread::spawn(move || self.run(callback, &thread_stop));
+        Ok(DioWatch { stop, handle })
+    }
+
+    /// Like [`DioWatcher::start`], delivering the events through a channel.
+    pub fn start_channel(self) -> Result<(DioWatch<D>, mpsc::Receiver<DioEvent>), ErrorCode> {
+        let (tx, rx) = mpsc::channel();
+        let watch = self.start(move |event| {
+            // the receiver may be dropped while the watcher keeps running
+            let _ = tx.send(event);
+        })?;
+        Ok((watch, rx))
+    }
+
+    fn run(mut self, mut callback: impl FnMut(DioEvent), stop: &StopHandle) -> (Vec<D>, Result<(), ErrorCode>) {
+        let mut res = Ok(());
+        while !stop.is_stopped() {
+            let active = match self.poll_interval {
+                Some(interval) => {
+                    std::thread::sleep(interval);
+                    true
+                }
+                None => match hat_wait_for_interrupt(WAIT_MS) {
+                    Ok(()) => true,
+                    // the line may have been active before the wait started
+                    Err(ErrorCode::Timeout) => hat_interrupt_state(),
+                    Err(err) => {
+                        res = Err(err);
+                        break;
+                    }
+                },
+            };
+            if !active {
+                continue;
+            }
+
+            match self.service(&mut callback) {
+                Ok(0) if self.poll_interval.is_none() && hat_interrupt_state() => std::thread::sleep(BUSY_LINE_PAUSE),
+                Ok(_) => {}
+                Err(err) => {
+                    res = Err(err);
+                    break;
+                }
+            }
+        }
+
+        // mask the watched bits again, leaving the others as they were
+        let mut devs = Vec::with_capacity(self.boards.len());
+        for mut board in self.boards {
+            for bit in (0..8).filter(|bit| board.bits & (1 << bit) != 0) {
+                if let Err(err) = board.dev.dio_config_write_bit(bit, DioConfigItem::IntMask, 1) {
+                    res = res.and(Err(err));
+                }
+            }
+            devs.push(board.dev);
+        }
+        (devs, res)
+    }
+
+    /// Reads and clears every board's interrupt and reports the watched bits that changed.
+    /// Returns the number of events.
+    fn service(&mut self, callback: &mut impl FnMut(DioEvent)) -> Result<usize, ErrorCode> {
+        let mut events = 0;
+        for board in &mut self.boards {
+            let status = board.dev.dio_int_status_read_port()?;
+            if status == 0 {
+                continue;
+            }
+            let levels = board.dev.dio_input_read_port()?;
+            let timestamp = SystemTime::now();
+            for bit in (0..8).filter(|bit| status & board.bits & (1 << bit) != 0) {
+                callback(DioEvent { address: board.dev.address(), bit, level: levels & (1 << bit) != 0, timestamp });
+                events += 1;
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// A running [`DioWatcher`].
+pub struct DioWatch<D> {
+    stop: StopHandle,
+    handle: JoinHandle<(Vec<D>, Result<(), ErrorCode>)>,
+}
+
+impl<D> DioWatch<D> {
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Whether the watcher has stopped, either on request or because a board returned an error.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops watching, masks the watched bits' interrupts again and returns the boards along with the
+    /// error that ended the watch, if any.
+    pub fn stop(self) -> (Vec<D>, Result<(), ErrorCode>) {
+        self.stop.stop();
+        self.handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Hat, HatId};
+    use crate::sim::SimulatedHat;
+
+    fn board(address: u8) -> SimulatedHat {
+        SimulatedHat::new(HatId::Mcc152, address, |_, _| 0.0).unwrap()
+    }
+
+    fn next(events: &mpsc::Receiver<DioEvent>) -> (u8, u8, bool) {
+        let event = events.recv_timeout(Duration::from_secs(1)).expect("no event");
+        (event.address, event.bit, event.level)
+    }
+
+    #[test]
+    fn only_watched_bits_report() {
+        let mut dev = board(0);
+        // an interrupt the watcher doesn't own
+        dev.dio_config_write_bit(5, DioConfigItem::IntMask, 0).unwrap();
+        let inputs = dev.dio_inputs();
+        let (watch, events) = DioWatcher::new().add_board(dev, 0b0000_0011).poll_interval(Duration::from_millis(1)).start_channel().unwrap();
+
+        inputs.set_bit(5, false);
+        inputs.set_bit(7, false);
+        inputs.set_bit(1, false);
+        assert_eq!(next(&events), (0, 1, false));
+        inputs.set_bit(0, false);
+        assert_eq!(next(&events), (0, 0, false));
+        inputs.set_bit(1, true);
+        assert_eq!(next(&events), (0, 1, true));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(events.try_recv().is_err());
+
+        let (devs, result) = watch.stop();
+        result.unwrap();
+        // the watched bits are masked again; the rest keep their settings
+        assert_eq!(devs[0].dio_config_read_port(DioConfigItem::IntMask), Ok(0b1101_1111));
+        assert_eq!(devs[0].dio_config_read_port(DioConfigItem::Direction), Ok(0xff));
+    }
+
+    #[test]
+    fn boards_sharing_the_line_are_told_apart() {
+        let (a, b) = (board(0), board(3));
+        let (inputs_a, inputs_b) = (a.dio_inputs(), b.dio_inputs());
+        let (watch, events) = DioWatcher::new().add_board(a, 0b1).add_board(b, 0b1).poll_interval(Duration::from_millis(1)).start_channel().unwrap();
+
+        inputs_b.set_bit(0, false);
+        assert_eq!(next(&events), (3, 0, false));
+        inputs_a.set_bit(0, false);
+        assert_eq!(next(&events), (0, 0, false));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(events.try_recv().is_err());
+
+        let (devs, result) = watch.stop();
+        result.unwrap();
+        assert_eq!(devs.iter().map(|dev| dev.address()).collect::<Vec<_>>(), [0, 3]);
+    }
+
+    #[test]
+    fn start_configures_the_watched_bits() {
+        let mut dev = board(1);
+        dev.dio_config_write_port(DioConfigItem::Direction, 0).unwrap();
+        let watch = DioWatcher::new().add_board(dev, 0b1000_0001).latch(false).poll_interval(Duration::from_millis(1)).start(|_| {}).unwrap();
+        assert!(!watch.is_finished());
+        let (devs, result) = watch.stop();
+        result.unwrap();
+        let dev = &devs[0];
+        assert_eq!(dev.dio_config_read_port(DioConfigItem::Direction), Ok(0b1000_0001));
+        assert_eq!(dev.dio_config_read_port(DioConfigItem::InputLatch), Ok(0));
+        assert_eq!(dev.dio_config_read_port(DioConfigItem::IntMask), Ok(0xff));
+    }
+
+    #[test]
+    fn boards_without_dio_fail_to_start() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap();
+        assert!(matches!(DioWatcher::new().add_board(dev, 0b1).start(|_| {}), Err(ErrorCode::InvalidDevice)));
+    }
+}
@@ -0,0 +1,780 @@
+//! Acquisitions described in a TOML file instead of code.
+//!
+//! A job lists boards, by address or serial number, with the channels, rate and scan flags to use,
+//! the [`crate::filter`] stages to run and where to write the result:
+//!
+//! ```toml
+//! duration_s = 30.0
+//!
+//! [[board]]
+//! address = 0
+//! channels = [0, 1, 2, 3]
+//! sample_rate = 1000.0
+//!
+//! [[board.stage]]
+//! type = "mains_notch"
+//! mains_hz = 50.0
+//!
+//! [[board.sink]]
+//! type = "csv"
+//! path = "run.csv"
+//! rotate_s = 3600.0
+//! ```
+//!
+//! [`JobConfig::validate`] checks a job against the boards listed by [`hat_list`] and their
+//! [`Capabilities`]; [`JobConfig::start`] opens the boards, starts every scan and returns a
+//! [`Job`] that reports each board's progress.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::capture::{CaptureWriter, SampleFormat};
+use crate::core::{hat_list, AInScanner, AnalogInputMode, AnalogInputRange, AnyHat, Capabilities, Hat, HatId, HatInfo, SourceType, TriggerMode};
+use crate::filter::{Biquad, Decimate, Fir, MovingAverage, Pipeline, Window};
+use crate::recorder::{RecordFormat, Recorder, RecorderOptions, Rotation};
+use crate::stats::{Summarizer, SummaryWriter};
+use crate::stream::{scan_blocks, ScanBlock, ScanEnd, ScanStream, StopHandle, StreamInfo};
+use crate::wav::{WavOptions, WavWriter};
+use crate::{Error, ScanOptions};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobConfig {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Stop each board after this much sample time. Runs until stopped if not set.
+    #[serde(default)]
+    pub duration_s: Option<f64>,
+    #[serde(rename = "board")]
+    pub boards: Vec<BoardConfig>,
+}
+
+/// One board's scan. Set either `address` or `serial`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoardConfig {
+    #[serde(default)]
+    pub address: Option<u8>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    pub channels: Vec<u8>,
+    /// Requested per-channel rate in S/s.
+    pub sample_rate: f64,
+    #[serde(default = "default_samples_per_block")]
+    pub samples_per_block: usize,
+    #[serde(default = "default_true")]
+    pub scale_data: bool,
+    #[serde(default = "default_true")]
+    pub calibrate_data: bool,
+    #[serde(default)]
+    pub external_clock: bool,
+    #[serde(default)]
+    pub external_trigger: bool,
+    /// The external trigger condition. Requires `external_trigger`.
+    #[serde(default)]
+    pub trigger_mode: Option<TriggerMode>,
+    /// MCC 128 only.
+    #[serde(default)]
+    pub input_mode: Option<AnalogInputMode>,
+    /// MCC 128 only.
+    #[serde(default)]
+    pub input_range: Option<AnalogInputRange>,
+    #[serde(default, rename = "stage")]
+    pub stages: Vec<StageConfig>,
+    #[serde(default, rename = "sink")]
+    pub sinks: Vec<SinkConfig>,
+}
+
+fn default_samples_per_block() -> usize {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_taps() -> usize {
+    101
+}
+
+fn default_window() -> Window {
+    Window::Hamming
+}
+
+/// A [`crate::filter`] stage, applied in the order listed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StageConfig {
+    MovingAverage {
+        length: usize,
+    },
+    LowPass {
+        cutoff_hz: f64,
+        #[serde(default = "default_taps")]
+        taps: usize,
+        #[serde(default = "default_window")]
+        window: Window,
+    },
+    HighPass {
+        cutoff_hz: f64,
+        #[serde(default = "default_taps")]
+        taps: usize,
+        #[serde(default = "default_window")]
+        window: Window,
+    },
+    BandPass {
+        low_hz: f64,
+        high_hz: f64,
+        #[serde(default = "default_taps")]
+        taps: usize,
+        #[serde(default = "default_window")]
+        window: Window,
+    },
+    MainsNotch {
+        mains_hz: f64,
+    },
+    Decimate {
+        factor: usize,
+    },
+}
+
+/// Where a board's processed blocks go.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SinkConfig {
+    /// A [`Recorder`], rotating by size or sample time if either is set.
+    Csv {
+        path: PathBuf,
+        #[serde(default)]
+        rotate_bytes: Option<u64>,
+        #[serde(default)]
+        rotate_s: Option<f64>,
+    },
+    /// A binary [`crate::capture`] file.
+    Capture { path: PathBuf },
+    /// A 32-bit float WAV file.
+    Wav { path: PathBuf },
+    /// [`crate::stats`] summaries as CSV, one row per interval.
+    Summary { path: PathBuf, interval_s: f64 },
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    /// The file isn't valid TOML or doesn't describe a job.
+    Parse(toml::de::Error),
+    /// The job is inconsistent or doesn't fit the connected boards.
+    Invalid(String),
+    /// Opening or starting a board failed.
+    Device(Error),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JobError::Parse(err) => err.fmt(f),
+            JobError::Invalid(msg) => f.write_str(msg),
+            JobError::Device(err) => err.fmt(f),
+            JobError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+impl From<toml::de::Error> for JobError {
+    fn from(err: toml::de::Error) -> Self {
+        JobError::Parse(err)
+    }
+}
+
+impl From<Error> for JobError {
+    fn from(err: Error) -> Self {
+        JobError::Device(err)
+    }
+}
+
+impl From<crate::core::ErrorCode> for JobError {
+    fn from(err: crate::core::ErrorCode) -> Self {
+        JobError::Device(err.into())
+    }
+}
+
+impl From<io::Error> for JobError {
+    fn from(err: io::Error) -> Self {
+        JobError::Io(err)
+    }
+}
+
+fn invalid(msg: String) -> JobError {
+    JobError::Invalid(msg)
+}
+
+impl BoardConfig {
+    fn name(&self) -> String {
+        match (&self.address, &self.serial) {
+            (Some(address), _) => format!("board {}", address),
+            (None, Some(serial)) => format!("board {}", serial),
+            (None, None) => "board".to_string(),
+        }
+    }
+
+    fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            channel_mask: self.channels.iter().fold(0, |mask, ch| mask | 1u8.checked_shl(*ch as u32).unwrap_or(0)),
+            sample_rate_per_channel: self.sample_rate,
+            scale_data: self.scale_data,
+            calibrate_data: self.calibrate_data,
+            external_clock: self.external_clock,
+            external_trigger: self.external_trigger,
+        }
+    }
+
+    /// Checks everything that doesn't depend on the board type.
+    fn check(&self) -> Result<(), JobError> {
+        let name = self.name();
+        if self.address.is_some() == self.serial.is_some() {
+            return Err(invalid(format!("{}: set exactly one of address and serial", name)));
+        }
+        if self.channels.is_empty() {
+            return Err(invalid(format!("{}: no channels", name)));
+        }
+        let mut channels = self.channels.clone();
+        channels.sort_unstable();
+        channels.dedup();
+        if channels != self.channels || channels.iter().any(|&ch| ch >= 8) {
+            return Err(invalid(format!("{}: channels must be distinct, in ascending order and below 8", name)));
+        }
+        if self.samples_per_block == 0 {
+            return Err(invalid(format!("{}: samples_per_block must be at least 1", name)));
+        }
+        if self.trigger_mode.is_some() && !self.external_trigger {
+            return Err(invalid(format!("{}: trigger_mode requires external_trigger", name)));
+        }
+        for stage in &self.stages {
+            let ok = match *stage {
+                StageConfig::MovingAverage { length } => length > 0,
+                StageConfig::LowPass { cutoff_hz, taps, .. } | StageConfig::HighPass { cutoff_hz, taps, .. } => cutoff_hz > 0.0 && taps > 0,
+                StageConfig::BandPass { low_hz, high_hz, taps, .. } => 0.0 < low_hz && low_hz < high_hz && taps > 0,
+                StageConfig::MainsNotch { mains_hz } => mains_hz > 0.0,
+                StageConfig::Decimate { factor } => factor > 0,
+            };
+            if !ok {
+                return Err(invalid(format!("{}: invalid {:?} stage", name, stage)));
+            }
+        }
+        for sink in &self.sinks {
+            let ok = match *sink {
+                SinkConfig::Csv { rotate_bytes, rotate_s, .. } => !(rotate_bytes.is_some() && rotate_s.is_some()) && rotate_s.is_none_or(|s| s > 0.0),
+                SinkConfig::Summary { interval_s, .. } => interval_s > 0.0,
+                SinkConfig::Capture { .. } | SinkConfig::Wav { .. } => true,
+            };
+            if !ok {
+                return Err(invalid(format!("{}: invalid {:?} sink", name, sink)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the settings against the type of board found for this entry.
+    fn check_board(&self, id: HatId) -> Result<(), JobError> {
+        let name = self.name();
+        let caps = Capabilities::of(id).ok_or_else(|| invalid(format!("{}: unsupported board", name)))?;
+        if !caps.ai_scan {
+            return Err(invalid(format!("{}: the {} can't scan", name, caps.product_name)));
+        }
+        let num_channels = match self.input_mode {
+            Some(AnalogInputMode::Differential) => caps.ai_differential_channels,
+            _ => caps.ai_channels,
+        };
+        let opts = self.scan_options();
+        caps.validate_scan_channels(num_channels, opts.channel_mask, opts.sample_rate_per_channel, opts.external_clock, opts.external_trigger)
+            .map_err(|err| invalid(format!("{}: {}", name, err)))?;
+        if (self.input_mode.is_some() || self.input_range.is_some()) && id != HatId::Mcc128 {
+            return Err(invalid(format!("{}: input_mode and input_range are only for the MCC 128", name)));
+        }
+        Ok(())
+    }
+
+    fn pipeline(&self, info: &StreamInfo) -> Pipeline {
+        let mut pipeline = Pipeline::new(info);
+        for stage in &self.stages {
+            let rate = pipeline.output_rate();
+            pipeline = match *stage {
+                StageConfig::MovingAverage { length } => pipeline.stage(MovingAverage::new(length)),
+                StageConfig::LowPass { cutoff_hz, taps, window } => pipeline.stage(Fir::low_pass(rate, cutoff_hz, taps, window)),
+                StageConfig::HighPass { cutoff_hz, taps, window } => pipeline.stage(Fir::high_pass(rate, cutoff_hz, taps, window)),
+                StageConfig::BandPass { low_hz, high_hz, taps, window } => pipeline.stage(Fir::band_pass(rate, low_hz, high_hz, taps, window)),
+                StageConfig::MainsNotch { mains_hz } => pipeline.stage(Biquad::mains_notch(rate, mains_hz)),
+                StageConfig::Decimate { factor } => pipeline.stage(Decimate::new(factor)),
+            };
+        }
+        pipeline
+    }
+}
+
+impl JobConfig {
+    pub fn from_toml(text: &str) -> Result<JobConfig, JobError> {
+        let job: JobConfig = toml::from_str(text)?;
+        job.check()?;
+        Ok(job)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<JobConfig, JobError> {
+        JobConfig::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    fn check(&self) -> Result<(), JobError> {
+        if self.boards.is_empty() {
+            return Err(invalid("the job has no boards".to_string()));
+        }
+        if self.duration_s.is_some_and(|s| s.is_nan() || s <= 0.0) {
+            return Err(invalid("duration_s must be positive".to_string()));
+        }
+        for (i, board) in self.boards.iter().enumerate() {
+            board.check()?;
+            let duplicate = self.boards[..i]
+                .iter()
+                .any(|other| (board.address.is_some() && other.address == board.address) || (board.serial.is_some() && other.serial == board.serial));
+            if duplicate {
+                return Err(invalid(format!("{} is listed twice", board.name())));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the job against boards listed by [`hat_list`], e.g. `hat_list(HatId::ANY)`, without
+    /// opening them. Boards given by serial number can only be found by opening them, so those
+    /// are checked by [`JobConfig::start`].
+    pub fn validate(&self, hats: &[HatInfo]) -> Result<(), JobError> {
+        self.check()?;
+        for board in &self.boards {
+            if let Some(address) = board.address {
+                let info = hats.iter().find(|info| info.address == address).ok_or_else(|| invalid(format!("no board at address {}", address)))?;
+                board.check_board(info.id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the boards, applies the trigger mode and MCC 128 input settings, and starts the job.
+    pub fn start(&self) -> Result<Job, JobError> {
+        self.validate(&hat_list(HatId::ANY))?;
+
+        let mut available = Vec::new();
+        let mut failed = Vec::new();
+        for (info, result) in AnyHat::open_all() {
+            match result {
+                Ok(dev) => available.push(dev),
+                Err(err) => failed.push((info.address, err)),
+            }
+        }
+        let mut devices: Vec<Box<dyn AInScanner + Send>> = Vec::new();
+        for board in &self.boards {
+            // a board given by address that failed to open reports why, rather than "not found"
+            let index = self.find(board, &available).map_err(|not_found| match failed.iter().find(|(address, _)| Some(*address) == board.address) {
+                Some(&(_, err)) => err.into(),
+                None => not_found,
+            })?;
+            let mut dev = available.swap_remove(index);
+            board.check_board(dev.id())?;
+
+            if let Some(mode) = board.trigger_mode {
+                match &mut dev {
+                    AnyHat::Mcc118(dev) => dev.trigger_mode(mode)?,
+                    AnyHat::Mcc128(dev) => dev.trigger_mode(mode)?,
+                    AnyHat::Mcc172(dev) => dev.trigger_config(SourceType::Local, mode)?,
+                    AnyHat::Mcc134(_) | AnyHat::Mcc152(_) => {}
+                }
+            }
+            if let AnyHat::Mcc128(dev) = &mut dev {
+                if let Some(mode) = board.input_mode {
+                    dev.a_in_mode_write(mode)?;
+                }
+                if let Some(range) = board.input_range {
+                    dev.a_in_range_write(range)?;
+                }
+            }
+            devices.push(dev.into_a_in_scanner().map_err(|_| invalid(format!("{} can't scan", board.name())))?);
+        }
+
+        self.start_with(devices)
+    }
+
+    fn find<T: Hat>(&self, board: &BoardConfig, devices: &[T]) -> Result<usize, JobError> {
+        devices
+            .iter()
+            .position(|dev| match (&board.address, &board.serial) {
+                (Some(address), _) => dev.address() == *address,
+                (None, Some(serial)) => dev.serial().is_ok_and(|s| s == *serial),
+                (None, None) => false,
+            })
+            .ok_or_else(|| invalid(format!("{} not found", board.name())))
+    }
+
+    /// Starts the job on already opened devices, e.g. [`crate::sim::SimulatedHat`]s. Each board
+    /// entry takes the device with its address or serial number. Trigger modes and MCC 128 input
+    /// settings are not applied; configure the devices beforehand.
+    pub fn start_with(&self, mut devices: Vec<Box<dyn AInScanner + Send>>) -> Result<Job, JobError> {
+        self.check()?;
+
+        let mut started = Vec::new();
+        for board in &self.boards {
+            match self.start_board(board, &mut devices) {
+                Ok(parts) => started.push(parts),
+                Err(err) => {
+                    started.into_iter().for_each(|(stream, _, _)| drop(stream.stop()));
+                    return Err(err);
+                }
+            }
+        }
+
+        let stop = StopHandle::new();
+        let status = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (index, (stream, pipeline, sinks)) in started.into_iter().enumerate() {
+            let info = stream.info();
+            status.lock().unwrap_or_else(|e| e.into_inner()).push(BoardStatus {
+                address: info.address,
+                serial: info.serial.clone(),
+                sample_rate: info.sample_rate,
+                blocks: 0,
+                samples_per_channel: 0,
+                end: None,
+                error: None,
+            });
+
+            let limit = self.duration_s.map(|s| (s * info.sample_rate).round() as u64);
+            let worker = Worker { stream, pipeline, sinks, limit, stop: stop.clone(), status: status.clone(), index };
+            handles.push(std::thread::spawn(move || worker.run()));
+        }
+
+        Ok(Job { stop, status, handles })
+    }
+
+    fn start_board(&self, board: &BoardConfig, devices: &mut Vec<Box<dyn AInScanner + Send>>) -> Result<(BoardStream, Pipeline, Vec<Sink>), JobError> {
+        let dev = devices.swap_remove(self.find(board, devices)?);
+        board.check_board(dev.id())?;
+        let stream = match scan_blocks(dev, board.scan_options(), board.samples_per_block) {
+            Ok(stream) => stream,
+            Err((dev, err)) => {
+                devices.push(dev);
+                return Err(err.into());
+            }
+        };
+
+        let pipeline = board.pipeline(stream.info());
+        let output_info = pipeline.output_info(stream.info());
+        match board.sinks.iter().map(|sink| Sink::create(sink, &output_info)).collect() {
+            Ok(sinks) => Ok((stream, pipeline, sinks)),
+            Err(err) => {
+                drop(stream.stop());
+                Err(err.into())
+            }
+        }
+    }
+}
+
+type BoardStream = ScanStream<Box<dyn AInScanner + Send>>;
+
+enum Sink {
+    Csv(Recorder),
+    Capture(CaptureWriter<BufWriter<File>>),
+    Wav(WavWriter<BufWriter<File>>),
+    Summary(Summarizer, SummaryWriter<BufWriter<File>>),
+}
+
+impl Sink {
+    fn create(config: &SinkConfig, info: &StreamInfo) -> io::Result<Sink> {
+        Ok(match config {
+            SinkConfig::Csv { path, rotate_bytes, rotate_s } => {
+                let rotation = match (rotate_bytes, rotate_s) {
+                    (Some(bytes), _) => Rotation::Size(*bytes),
+                    (None, Some(secs)) => Rotation::Duration(Duration::from_secs_f64(*secs)),
+                    (None, None) => Rotation::Never,
+                };
+                Sink::Csv(Recorder::create(path, info.clone(), RecorderOptions { format: RecordFormat::Csv, rotation })?)
+            }
+            SinkConfig::Capture { path } => Sink::Capture(CaptureWriter::create(path, info, SampleFormat::for_stream(info))?),
+            SinkConfig::Wav { path } => Sink::Wav(WavWriter::create(path, info, &WavOptions::default())?),
+            SinkConfig::Summary { path, interval_s } => Sink::Summary(
+                Summarizer::new(info, Duration::from_secs_f64(*interval_s)),
+                SummaryWriter::new(BufWriter::new(File::create(path)?), info)?,
+            ),
+        })
+    }
+
+    fn write_block(&mut self, block: &ScanBlock) -> io::Result<()> {
+        match self {
+            Sink::Csv(rec) => rec.write_block(block),
+            Sink::Capture(writer) => writer.write_block(block),
+            Sink::Wav(writer) => writer.write_block(block),
+            Sink::Summary(summarizer, writer) => {
+                let summaries = summarizer.process(block).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                summaries.iter().try_for_each(|summary| writer.write(summary))
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Csv(rec) => rec.finish().map(drop),
+            Sink::Capture(writer) => writer.finish().map(drop),
+            Sink::Wav(writer) => writer.finish().map(drop),
+            Sink::Summary(mut summarizer, mut writer) => {
+                if let Some(summary) = summarizer.flush() {
+                    writer.write(&summary)?;
+                }
+                writer.finish().map(drop)
+            }
+        }
+    }
+}
+
+struct Worker {
+    stream: BoardStream,
+    pipeline: Pipeline,
+    sinks: Vec<Sink>,
+    /// Samples per channel to acquire, if the job has a duration.
+    limit: Option<u64>,
+    stop: StopHandle,
+    status: Arc<Mutex<Vec<BoardStatus>>>,
+    index: usize,
+}
+
+impl Worker {
+    fn run(mut self) -> Box<dyn AInScanner + Send> {
+        let mut acquired = 0u64;
+        let mut error = None;
+
+        while !self.stop.is_stopped() && self.limit.is_none_or(|limit| acquired < limit) {
+            let mut block = match self.stream.recv_timeout(Duration::from_millis(100)) {
+                Ok(block) => block,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if let Some(limit) = self.limit {
+                let frames = (limit - acquired).min(block.samples_per_channel() as u64) as usize;
+                block.data.truncate(frames * block.channel_count);
+            }
+            acquired += block.samples_per_channel() as u64;
+
+            let output = self.pipeline.process(&block);
+            if let Err(err) = self.sinks.iter_mut().try_for_each(|sink| sink.write_block(&output)) {
+                error = Some(err);
+                break;
+            }
+            self.update(|status| {
+                status.blocks += 1;
+                status.samples_per_channel = acquired;
+            });
+        }
+
+        let (dev, end) = self.stream.stop();
+        for sink in self.sinks {
+            if let Err(err) = sink.finish() {
+                error.get_or_insert(err);
+            }
+        }
+        // reaching the duration counts as a normal end
+        let end = if self.limit.is_some_and(|limit| acquired >= limit) && end == ScanEnd::Stopped { ScanEnd::Finished } else { end };
+        self.status.lock().unwrap_or_else(|e| e.into_inner())[self.index].end = Some(end);
+        if let Some(err) = error {
+            self.status.lock().unwrap_or_else(|e| e.into_inner())[self.index].error = Some(err.to_string());
+        }
+        dev
+    }
+
+    fn update(&self, f: impl FnOnce(&mut BoardStatus)) {
+        f(&mut self.status.lock().unwrap_or_else(|e| e.into_inner())[self.index]);
+    }
+}
+
+/// Progress of one board in a running [`Job`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardStatus {
+    pub address: u8,
+    pub serial: String,
+    /// Actual per-channel scan rate in S/s, before any decimation.
+    pub sample_rate: f64,
+    pub blocks: u64,
+    /// Samples per channel acquired so far.
+    pub samples_per_channel: u64,
+    /// Why the board's scan ended, once it has.
+    pub end: Option<ScanEnd>,
+    /// The first sink error, which stops the board's scan.
+    pub error: Option<String>,
+}
+
+/// A running job. See [`JobConfig::start`].
+pub struct Job {
+    stop: StopHandle,
+    status: Arc<Mutex<Vec<BoardStatus>>>,
+    handles: Vec<JoinHandle<Box<dyn AInScanner + Send>>>,
+}
+
+impl Job {
+    /// One entry per board, in the order of the job file.
+    pub fn status(&self) -> Vec<BoardStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Whether every board has finished, e.g. because the duration has been reached.
+    pub fn is_finished(&self) -> bool {
+        self.handles.iter().all(|handle| handle.is_finished())
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Stops every board, finishes the output files and returns the devices with the final status.
+    pub fn stop(self) -> (Vec<Box<dyn AInScanner + Send>>, Vec<BoardStatus>) {
+        self.stop.stop();
+        self.join()
+    }
+
+    /// Waits for every board to finish without stopping them.
+    pub fn join(self) -> (Vec<Box<dyn AInScanner + Send>>, Vec<BoardStatus>) {
+        let devices = self.handles.into_iter().map(|handle| handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e))).collect();
+        let status = self.status.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        (devices, status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureReader;
+    use crate::sim::SimulatedHat;
+    use crate::wav::read_wav;
+
+    fn invalid_message(text: &str) -> String {
+        match JobConfig::from_toml(text) {
+            Err(JobError::Invalid(msg)) => msg,
+            other => panic!("expected an invalid job, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_toml_rejects_bad_jobs() {
+        assert!(matches!(JobConfig::from_toml("duration_s = 1.0\n[[board]]\naddress = 0\n"), Err(JobError::Parse(_))));
+        assert!(matches!(JobConfig::from_toml("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\ncolour = 1\n"), Err(JobError::Parse(_))));
+
+        assert!(invalid_message("board = []\n").contains("no boards"));
+        assert!(invalid_message("duration_s = 0.0\n[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\n").contains("duration_s"));
+        assert!(invalid_message("[[board]]\nchannels = [0]\nsample_rate = 10.0\n").contains("exactly one"));
+        assert!(invalid_message("[[board]]\naddress = 0\nserial = \"A\"\nchannels = [0]\nsample_rate = 10.0\n").contains("exactly one"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = []\nsample_rate = 10.0\n").contains("no channels"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [1, 0]\nsample_rate = 10.0\n").contains("ascending"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [8]\nsample_rate = 10.0\n").contains("below 8"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\nsamples_per_block = 0\n").contains("samples_per_block"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\ntrigger_mode = \"RisingEdge\"\n").contains("external_trigger"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\n[[board.stage]]\ntype = \"decimate\"\nfactor = 0\n").contains("stage"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\n[[board.sink]]\ntype = \"csv\"\npath = \"a.csv\"\nrotate_bytes = 10\nrotate_s = 1.0\n").contains("sink"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\n[[board.sink]]\ntype = \"summary\"\npath = \"a.csv\"\ninterval_s = 0.0\n").contains("sink"));
+        assert!(invalid_message("[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 10.0\n[[board]]\naddress = 0\nchannels = [1]\nsample_rate = 10.0\n").contains("twice"));
+    }
+
+    #[test]
+    fn validate_checks_the_board_type() {
+        let job = JobConfig::from_toml("[[board]]\naddress = 1\nchannels = [0]\nsample_rate = 100.0\n").unwrap();
+        let hat = |id| HatInfo { address: 1, id, version: 0, product_name: String::new() };
+        job.validate(&[hat(HatId::Mcc118)]).unwrap();
+        assert!(matches!(job.validate(&[]), Err(JobError::Invalid(msg)) if msg.contains("no board at address 1")));
+        assert!(matches!(job.validate(&[hat(HatId::Mcc134)]), Err(JobError::Invalid(msg)) if msg.contains("can't scan")));
+
+        let job = JobConfig::from_toml("[[board]]\naddress = 1\nchannels = [0]\nsample_rate = 100.0\ninput_mode = \"SingleEnded\"\n").unwrap();
+        assert!(matches!(job.validate(&[hat(HatId::Mcc118)]), Err(JobError::Invalid(msg)) if msg.contains("MCC 128")));
+    }
+
+    #[test]
+    fn start_with_finds_boards_by_address_and_serial() {
+        let job = JobConfig::from_toml("[[board]]\nserial = \"NOPE\"\nchannels = [0]\nsample_rate = 100.0\n").unwrap();
+        let devices: Vec<Box<dyn AInScanner + Send>> = vec![Box::new(SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap())];
+        assert!(matches!(job.start_with(devices), Err(JobError::Invalid(msg)) if msg.contains("not found")));
+
+        let job = JobConfig::from_toml("duration_s = 0.05\n[[board]]\nserial = \"ABC\"\nchannels = [0]\nsample_rate = 1000.0\nsamples_per_block = 10\n").unwrap();
+        let devices: Vec<Box<dyn AInScanner + Send>> = vec![
+            Box::new(SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap()),
+            Box::new(SimulatedHat::new(HatId::Mcc118, 1, |_, _| 0.0).unwrap().with_serial("ABC")),
+        ];
+        let (devices, status) = job.start_with(devices).unwrap().join();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].address(), 1);
+        assert_eq!(status[0].serial, "ABC");
+        assert_eq!(status[0].samples_per_channel, 50);
+    }
+
+    #[test]
+    fn short_run_writes_finished_files() {
+        let dir = std::env::temp_dir().join(format!("daqhats-job-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = format!(
+            "duration_s = 0.25\n\
+             [[board]]\naddress = 0\nchannels = [0, 2]\nsample_rate = 1000.0\nsamples_per_block = 100\n\
+             [[board.sink]]\ntype = \"capture\"\npath = {:?}\n\
+             [[board.sink]]\ntype = \"wav\"\npath = {:?}\n\
+             [[board.sink]]\ntype = \"summary\"\npath = {:?}\ninterval_s = 0.1\n",
+            dir.join("run.daq"),
+            dir.join("run.wav"),
+            dir.join("summary.csv"),
+        );
+        let job = JobConfig::from_toml(&text).unwrap();
+        let devices: Vec<Box<dyn AInScanner + Send>> = vec![Box::new(SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, _t: f64| ch as f64 * 0.5).unwrap())];
+        let (devices, status) = job.start_with(devices).unwrap().join();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(status[0].end, Some(ScanEnd::Finished));
+        assert_eq!(status[0].error, None);
+        // the last block is cut short at the duration
+        assert_eq!(status[0].samples_per_channel, 250);
+
+        let capture = CaptureReader::open(dir.join("run.daq")).unwrap();
+        assert_eq!(capture.header().info.channels, vec![0, 2]);
+        let channels = capture.read_all().unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].len(), 250);
+        assert!(channels[0].iter().all(|&v| v.abs() < 0.01));
+        assert!(channels[1].iter().all(|&v| (v - 1.0).abs() < 0.01));
+
+        let wav = read_wav(dir.join("run.wav")).unwrap();
+        assert_eq!(wav.sample_rate, 1000);
+        assert_eq!(wav.channels.len(), 2);
+        assert_eq!(wav.channels[1].len(), 250);
+
+        let summary = std::fs::read_to_string(dir.join("summary.csv")).unwrap();
+        assert!(summary.lines().count() >= 2, "{}", summary);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stop_finishes_an_open_ended_run() {
+        let dir = std::env::temp_dir().join(format!("daqhats-job-stop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = format!(
+            "[[board]]\naddress = 0\nchannels = [0]\nsample_rate = 1000.0\nsamples_per_block = 50\n[[board.sink]]\ntype = \"wav\"\npath = {:?}\n",
+            dir.join("run.wav"),
+        );
+        let job = JobConfig::from_toml(&text).unwrap().start_with(vec![Box::new(SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap())]).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!job.is_finished());
+        job.stop_handle().stop();
+        let (_, status) = job.join();
+        assert_eq!(status[0].end, Some(ScanEnd::Stopped));
+        assert_eq!(status[0].error, None);
+
+        let wav = read_wav(dir.join("run.wav")).unwrap();
+        assert_eq!(wav.channels[0].len() as u64, status[0].samples_per_channel);
+        assert!(status[0].samples_per_channel > 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
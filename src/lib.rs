@@ -1,8 +1,38 @@
+pub mod alarm;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod capture;
+pub mod control;
 pub mod core;
+pub mod filter;
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+pub mod influx;
+pub mod interrupt;
+#[cfg(feature = "job")]
+pub mod job;
+pub mod metrics;
+pub mod modbus;
+#[cfg(feature = "python")]
+mod python;
+pub mod recorder;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sim;
+pub mod spectrum;
+pub mod stats;
+pub mod stream;
+pub mod thermocouple;
+pub mod trigger;
+pub mod wav;
+pub mod waveform;
 
 use std::sync::mpsc;
 use std::thread::JoinHandle;
 
+#[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct ScanOptions {
     pub channel_mask: u8,
@@ -18,23 +48,78 @@ impl ScanOptions {
     pub fn channel_count(&self) -> usize {
         self.channel_mask.count_ones() as usize
     }
-}
 
-pub fn scan_channels<T: core::AInScanner + std::marker::Send + 'static>(mut dev: T, opts: ScanOptions) -> Result<(JoinHandle<T>, Vec<mpsc::Receiver<f64>>), core::ErrorCode> {
-    let mut low_opts = core::ScanOptions::CONTINUOUS;
-    if !opts.scale_data {//             low_opts |= core::ScanOptions::NOSCALEDATA;
+    /// The physical channel numbers selected by the mask, in the order the samples are interleaved.
+    pub fn channels(&self) -> Vec<u8> {
+        (0..8).filter(|ch| self.channel_mask & (1 << ch) != 0).collect()
+    }
+
+    /// The equivalent low-level options for a continuous scan.
+    pub fn low_level_options(&self) -> core::ScanOptions {
+        let mut low_opts = core::ScanOptions::CONTINUOUS;
+        if !self.scale_data {
+            low_opts |= core::ScanOptions::NOSCALEDATA;
+        }
+        if !self.calibrate_data {
+            low_opts |= core::ScanOptions::NOCALIBRATEDATA;
+        }
+        if self.external_clock {
+            low_opts |= core::ScanOptions::EXTCLOCK;
+        }
+        if self.external_trigger {
+            low_opts |= core::ScanOptions::EXTTRIGGER;
+        }
+        low_opts
     }
-    if !opts.calibrate_data {
-        low_opts |= core::ScanOptions::NOCALIBRATEDATA;
+
+    /// Checks the options against a board type's limits, e.g. `dev.capabilities()`, assuming
+    /// single-ended inputs.
+    pub fn validate(&self, caps: &core::Capabilities) -> Result<(), core::CapabilityError> {
+        caps.validate_scan(self.channel_mask, self.sample_rate_per_channel, self.external_clock, self.external_trigger)
     }
-    if opts.external_clock {
-        low_opts |= core::ScanOptions::EXTCLOCK;
+
+    /// Checks the options against a board's limits in its current input mode.
+    pub fn validate_device<T: core::Hat + ?Sized>(&self, dev: &T) -> Result<(), core::CapabilityError> {
+        let caps = dev.capabilities();
+        caps.validate_scan_channels(dev.a_in_num_channels(), self.channel_mask, self.sample_rate_per_channel, self.external_clock, self.external_trigger)
     }
-    if opts.external_trigger {
-        low_opts |= core::ScanOptions::EXTTRIGGER;
+}
+
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// The library or device reported an error.
+    Device(core::ErrorCode),
+    /// The request is impossible for the board type; nothing was sent to the device.
+    Capability(core::CapabilityError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Device(err) => err.fmt(f),
+            Error::Capability(err) => err.fmt(f),
+        }
     }
+}
 
-    dev.a_in_scan_start(opts.channel_mask, 0, opts.sample_rate_per_channel, low_opts)?;
+impl std::error::Error for Error {}
+
+impl From<core::ErrorCode> for Error {
+    fn from(err: core::ErrorCode) -> Self {
+        Error::Device(err)
+    }
+}
+
+impl From<core::CapabilityError> for Error {
+    fn from(err: core::CapabilityError) -> Self {
+        Error::Capability(err)
+    }
+}
+
+pub fn scan_channels<T: core::AInScanner + std::marker::Send + 'static>(mut dev: T, opts: ScanOptions) -> Result<(JoinHandle<T>, Vec<mpsc::Receiver<f64>>), Error> {
+    opts.validate_device(&dev)?;
+
+    dev.a_in_scan_start(opts.channel_mask, 0, opts.sample_rate_per_channel, opts.low_level_options())?;
 
     let n_ch = opts.channel_count();
     let channels = (0..n_ch).map(|_| mpsc::channel::<f64>()).collect::<Vec<_>>();
@@ -98,3 +183,29 @@ pub fn scan_channels<T: core::AInScanner + std::marker::Send + 'static>(mut dev:
 
     Ok((handle, receivers))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(scale_data: bool, calibrate_data: bool, external_clock: bool, external_trigger: bool) -> ScanOptions {
+        ScanOptions { channel_mask: 0b101, sample_rate_per_channel: 1000.0, scale_data, calibrate_data, external_clock, external_trigger }
+    }
+
+    #[test]
+    fn low_level_options() {
+        assert_eq!(options(true, true, false, false).low_level_options(), core::ScanOptions::CONTINUOUS);
+        assert_eq!(options(false, true, false, false).low_level_options(), core::ScanOptions::CONTINUOUS | core::ScanOptions::NOSCALEDATA);
+        assert_eq!(options(true, false, false, false).low_level_options(), core::ScanOptions::CONTINUOUS | core::ScanOptions::NOCALIBRATEDATA);
+        assert_eq!(
+            options(false, false, true, true).low_level_options(),
+            core::ScanOptions::CONTINUOUS | core::ScanOptions::NOSCALEDATA | core::ScanOptions::NOCALIBRATEDATA | core::ScanOptions::EXTCLOCK | core::ScanOptions::EXTTRIGGER
+        );
+    }
+
+    #[test]
+    fn channels_in_mask_order() {
+        assert_eq!(options(true, true, false, false).channels(), vec![0, 2]);
+        assert_eq!(options(true, true, false, false).channel_count(), 2);
+    }
+}
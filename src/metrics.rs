@@ -0,0 +1,546 @@
+//! Prometheus metrics for slow monitoring, e.g. temperatures and supply voltages.
+//!
+//! An [`Exporter`] polls the configured channels at a fixed interval with `a_in_read`, `t_in_read`
+//! and `cjc_read`, and serves the latest readings on an HTTP `/metrics` endpoint in the Prometheus
+//! text format:
+//!
+//! - `daqhats_voltage_volts`, `daqhats_temperature_celsius` and
+//!   `daqhats_cold_junction_celsius` gauges, labelled with `address`, `serial` and `channel`
+//! - `daqhats_read_errors_total`, labelled with the `error` ([`ErrorCode`] name, or `OpenTc`,
+//!   `OverrangeTc` and `CommonModeTc` for thermocouple faults)
+//! - `daqhats_scan_overruns_total`, fed by [`Metrics::record_scan_end`] from scans running
+//!   alongside, labelled with the overrun `kind`
+//! - `daqhats_board_info`, always 1, labelled with `product`, `firmware` and `calibration_date`
+//! - `daqhats_last_poll_timestamp_seconds` and `daqhats_poll_duration_seconds`
+//!
+//! Gauges of a channel whose last read failed are left out rather than reporting a stale value.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::core::{AIn, AnyHat, ErrorCode, Hat, ScanOptions, Thermocouple};
+use crate::core::{COMMON_MODE_TC_VALUE, OPEN_TC_VALUE, OVERRANGE_TC_VALUE};
+use crate::stream::{ScanEnd, StopHandle};
+
+/// What is read from a channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Quantity {
+    Voltage,
+    Temperature,
+    ColdJunction,
+}
+
+impl Quantity {
+    fn metric(self) -> &'static str {
+        match self {
+            Quantity::Voltage => "daqhats_voltage_volts",
+            Quantity::Temperature => "daqhats_temperature_celsius",
+            Quantity::ColdJunction => "daqhats_cold_junction_celsius",
+        }
+    }
+}
+
+/// A board the exporter can read from.
+trait Source: Send {
+    fn hat(&self) -> &dyn Hat;
+    fn read(&mut self, quantity: Quantity, channel: u8) -> Result<f64, ErrorCode>;
+}
+
+struct Analog<T>(T);
+
+impl<T: AIn + Send> Source for Analog<T> {
+    fn hat(&self) -> &dyn Hat {
+        &self.0
+    }
+
+    fn read(&mut self, _quantity: Quantity, channel: u8) -> Result<f64, ErrorCode> {
+        self.0.a_in_read(channel, ScanOptions::DEFAULT)
+    }
+}
+
+struct Tc<T>(T);
+
+impl<T: Thermocouple + Send> Source for Tc<T> {
+    fn hat(&self) -> &dyn Hat {
+        &self.0
+    }
+
+    fn read(&mut self, quantity: Quantity, channel: u8) -> Result<f64, ErrorCode> {
+        match quantity {
+            Quantity::ColdJunction => self.0.cjc_read(channel),
+            _ => self.0.t_in_read(channel),
+        }
+    }
+}
+
+impl Source for AnyHat {
+    fn hat(&self) -> &dyn Hat {
+        self
+    }
+
+    fn read(&mut self, quantity: Quantity, channel: u8) -> Result<f64, ErrorCode> {
+        match quantity {
+            Quantity::Voltage => self.as_a_in().ok_or(ErrorCode::InvalidDevice)?.a_in_read(channel, ScanOptions::DEFAULT),
+            Quantity::Temperature => self.as_thermocouple().ok_or(ErrorCode::InvalidDevice)?.t_in_read(channel),
+            Quantity::ColdJunction => self.as_thermocouple().ok_or(ErrorCode::InvalidDevice)?.cjc_read(channel),
+        }
+    }
+}
+
+struct Board {
+    source: Box<dyn Source>,
+    labels: BoardLabels,
+    probes: Vec<(Quantity, u8)>,
+}
+
+#[derive(Clone)]
+struct BoardLabels {
+    address: u8,
+    serial: String,
+}
+
+impl BoardLabels {
+    fn of(hat: &dyn Hat) -> Result<BoardLabels, ErrorCode> {
+        Ok(BoardLabels { address: hat.address(), serial: hat.serial()? })
+    }
+
+    fn with(&self, extra: &[(&str, &str)]) -> String {
+        let mut labels = format!("address=\"{}\",serial=\"{}\"", self.address, escape(&self.serial));
+        for (name, value) in extra {
+            let _ = write!(labels, ",{}=\"{}\"", name, escape(value));
+        }
+        labels
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Gauge,
+    Counter,
+}
+
+struct Family {
+    help: &'static str,
+    kind: Kind,
+    // by label set, so the output order is stable
+    samples: BTreeMap<String, f64>,
+}
+
+/// The current metric values, shared between the poller, the HTTP endpoint and anything else
+/// reporting into them.
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Mutex<BTreeMap<&'static str, Family>>>);
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    fn set(&self, name: &'static str, help: &'static str, labels: String, value: f64) {
+        self.update(name, help, Kind::Gauge, labels, |x| *x = value);
+    }
+
+    fn remove(&self, name: &'static str, labels: &str) {
+        let mut families = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(family) = families.get_mut(name) {
+            family.samples.remove(labels);
+        }
+    }
+
+    fn increment(&self, name: &'static str, help: &'static str, labels: String) {
+        self.update(name, help, Kind::Counter, labels, |x| *x += 1.0);
+    }
+
+    fn update(&self, name: &'static str, help: &'static str, kind: Kind, labels: String, f: impl FnOnce(&mut f64)) {
+        let mut families = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let family = families.entry(name).or_insert_with(|| Family { help, kind, samples: BTreeMap::new() });
+        f(family.samples.entry(labels).or_insert(0.0));
+    }
+
+    /// Counts hardware and buffer overruns of a scan on the board at `address`, e.g. with the
+    /// [`ScanEnd`] from [`crate::stream::ScanStream::join`]. Other endings are ignored.
+    pub fn record_scan_end(&self, address: u8, serial: &str, end: &ScanEnd) {
+        let kind = match end {
+            ScanEnd::HardwareOverrun => "hardware",
+            ScanEnd::BufferOverrun => "buffer",
+            _ => return,
+        };
+        let labels = BoardLabels { address, serial: serial.to_string() }.with(&[("kind", kind)]);
+        self.increment("daqhats_scan_overruns_total", "Scans ended by an overrun.", labels);
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let kind = match family.kind {
+                Kind::Gauge => "gauge",
+                Kind::Counter => "counter",
+            };
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in &family.samples {
+                if labels.is_empty() {
+                    let _ = writeln!(out, "{} {}", name, value);
+                } else {
+                    let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Polls boards and keeps [`Metrics`] up to date. See [`Exporter::serve`].
+pub struct Exporter {
+    boards: Vec<Board>,
+    metrics: Metrics,
+}
+
+impl Exporter {
+    pub fn new() -> Exporter {
+        Exporter { boards: Vec::new(), metrics: Metrics::new() }
+    }
+
+    /// Exports voltages of `channels`, read with `a_in_read`.
+    pub fn add_analog<T: AIn + Send + 'static>(&mut self, dev: T, channels: &[u8]) -> Result<(), ErrorCode> {
+        let probes = channels.iter().map(|&ch| (Quantity::Voltage, ch)).collect();
+        self.add(Box::new(Analog(dev)), probes)
+    }
+
+    /// Exports thermocouple and cold-junction temperatures of `channels`. The thermocouple types
+    /// must already be set.
+    pub fn add_thermocouple<T: Thermocouple + Send + 'static>(&mut self, dev: T, channels: &[u8]) -> Result<(), ErrorCode> {
+        let probes = channels.iter().flat_map(|&ch| [(Quantity::Temperature, ch), (Quantity::ColdJunction, ch)]).collect();
+        self.add(Box::new(Tc(dev)), probes)
+    }
+
+    /// Exports temperatures for thermocouple boards and voltages for the rest. Fails with
+    /// [`ErrorCode::InvalidDevice`] for boards without single-sample reads (MCC 152, 172).
+    pub fn add_board(&mut self, mut dev: AnyHat, channels: &[u8]) -> Result<(), ErrorCode> {
+        let probes = if dev.as_thermocouple().is_some() {
+            channels.iter().flat_map(|&ch| [(Quantity::Temperature, ch), (Quantity::ColdJunction, ch)]).collect()
+        } else if dev.as_a_in().is_some() {
+            channels.iter().map(|&ch| (Quantity::Voltage, ch)).collect()
+        } else {
+            return Err(ErrorCode::InvalidDevice);
+        };
+        self.add(Box::new(dev), probes)
+    }
+
+    fn add(&mut self, source: Box<dyn Source>, probes: Vec<(Quantity, u8)>) -> Result<(), ErrorCode> {
+        let hat = source.hat();
+        let labels = BoardLabels::of(hat)?;
+        let product = hat.capabilities().product_name;
+        let firmware = hat.firmware_version()?.map(|fw| format!("{:X}.{:02X}", fw.version >> 8, fw.version & 0xff)).unwrap_or_default();
+        let calibration_date = hat.calibration_date()?.unwrap_or_default();
+        self.metrics.set(
+            "daqhats_board_info",
+            "Board details; always 1.",
+            labels.with(&[("product", product), ("firmware", &firmware), ("calibration_date", &calibration_date)]),
+            1.0,
+        );
+
+        self.boards.push(Board { source, labels, probes });
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Reads every channel once and updates the metrics.
+    pub fn poll(&mut self) {
+        let start = Instant::now();
+        for board in &mut self.boards {
+            for &(quantity, channel) in &board.probes {
+                let labels = board.labels.with(&[("channel", &channel.to_string())]);
+                let error = match board.source.read(quantity, channel) {
+                    Ok(value) if value == OPEN_TC_VALUE => "OpenTc".to_string(),
+                    Ok(value) if value == OVERRANGE_TC_VALUE => "OverrangeTc".to_string(),
+                    Ok(value) if value == COMMON_MODE_TC_VALUE => "CommonModeTc".to_string(),
+                    Ok(value) => {
+                        self.metrics.set(quantity.metric(), help(quantity), labels, value);
+                        continue;
+                    }
+                    Err(err) => format!("{:?}", err),
+                };
+                self.metrics.remove(quantity.metric(), &labels);
+                self.metrics.increment("daqhats_read_errors_total", "Failed channel reads.", board.labels.with(&[("error", &error)]));
+            }
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        self.metrics.set("daqhats_last_poll_timestamp_seconds", "When the channels were last polled.", String::new(), now);
+        self.metrics.set("daqhats_poll_duration_seconds", "How long the last poll took.", String::new(), start.elapsed().as_secs_f64());
+    }
+
+    /// Polls every `interval` on one background thread and serves `GET /metrics` on another.
+    pub fn serve(mut self, addr: impl ToSocketAddrs, interval: Duration) -> io::Result<ExporterHandle> {
+        assert!(!interval.is_zero(), "the poll interval must not be zero");
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let stop = StopHandle::new();
+        let metrics = self.metrics.clone();
+
+        let poll_stop = stop.clone();
+        let poller = std::thread::spawn(move || {
+            let mut next = Instant::now();
+            while !poll_stop.is_stopped() {
+                if Instant::now() >= next {
+                    self.poll();
+                    // after a slow poll, carry on from now rather than catching up
+                    next = (next + interval).max(Instant::now());
+                }
+                // wake up often enough to stop promptly
+                std::thread::sleep(next.saturating_duration_since(Instant::now()).min(POLL_STOP_INTERVAL));
+            }
+        });
+
+        let http_stop = stop.clone();
+        let http_metrics = metrics.clone();
+        let http = std::thread::spawn(move || {
+            while !http_stop.is_stopped() {
+                match listener.accept() {
+                    Ok((socket, _)) => {
+                        // scrapes are small and infrequent, so one at a time is enough
+                        let _ = respond(socket, &http_metrics);
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_STOP_INTERVAL),
+                    Err(_) => {}
+                }
+            }
+        });
+
+        Ok(ExporterHandle { local_addr, metrics, stop, threads: vec![poller, http] })
+    }
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Exporter::new()
+    }
+}
+
+fn help(quantity: Quantity) -> &'static str {
+    match quantity {
+        Quantity::Voltage => "Analog input voltage.",
+        Quantity::Temperature => "Thermocouple temperature.",
+        Quantity::ColdJunction => "Cold-junction temperature of a thermocouple channel.",
+    }
+}
+
+const POLL_STOP_INTERVAL: Duration = Duration::from_millis(50);
+
+fn respond(socket: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    socket.set_nonblocking(false)?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(socket);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next().map(|path| path.split('?').next().unwrap_or(path))) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain; charset=utf-8", "not found; metrics are at /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "only GET is supported\n".to_string()),
+    };
+
+    let mut socket = reader.into_inner();
+    write!(socket, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    socket.write_all(body.as_bytes())?;
+    socket.flush()
+}
+
+/// A running exporter. See [`Exporter::serve`].
+pub struct ExporterHandle {
+    local_addr: SocketAddr,
+    metrics: Metrics,
+    stop: StopHandle,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ExporterHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The exported metrics, e.g. to pass scan overruns to [`Metrics::record_scan_end`].
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn stop(self) {
+        self.stop.stop();
+        for thread in self.threads {
+            thread.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::core::HatId;
+    use crate::sim::SimulatedHat;
+
+    /// A thermocouple board whose readings the test sets, one per channel.
+    struct Fixed(SimulatedHat, Arc<Mutex<Vec<Result<f64, ErrorCode>>>>);
+
+    impl Source for Fixed {
+        fn hat(&self) -> &dyn Hat {
+            &self.0
+        }
+
+        fn read(&mut self, quantity: Quantity, channel: u8) -> Result<f64, ErrorCode> {
+            match quantity {
+                Quantity::ColdJunction => Ok(21.5),
+                _ => self.1.lock().unwrap()[channel as usize],
+            }
+        }
+    }
+
+    fn thermocouples(exporter: &mut Exporter, readings: Vec<Result<f64, ErrorCode>>) -> Arc<Mutex<Vec<Result<f64, ErrorCode>>>> {
+        let readings = Arc::new(Mutex::new(readings));
+        let channels = (0..readings.lock().unwrap().len() as u8).flat_map(|ch| [(Quantity::Temperature, ch), (Quantity::ColdJunction, ch)]).collect();
+        let dev = SimulatedHat::new(HatId::Mcc134, 1, |_, _| 0.0).unwrap().with_serial("TC1");
+        exporter.add(Box::new(Fixed(dev, readings.clone())), channels).unwrap();
+        readings
+    }
+
+    /// The sample lines of `metric`, without the timestamps that change from poll to poll.
+    fn samples(metrics: &Metrics, metric: &str) -> Vec<String> {
+        metrics.render().lines().filter(|line| line.starts_with(&format!("{}{{", metric))).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn render_writes_help_type_and_escaped_labels() {
+        let metrics = Metrics::new();
+        metrics.record_scan_end(3, "a\"b\\c\nd", &ScanEnd::BufferOverrun);
+        metrics.record_scan_end(3, "a\"b\\c\nd", &ScanEnd::BufferOverrun);
+        metrics.record_scan_end(3, "a\"b\\c\nd", &ScanEnd::HardwareOverrun);
+        metrics.record_scan_end(3, "x", &ScanEnd::Stopped);
+        metrics.record_scan_end(3, "x", &ScanEnd::Finished);
+        metrics.set("daqhats_poll_duration_seconds", "How long the last poll took.", String::new(), 0.25);
+
+        assert_eq!(
+            metrics.render(),
+            "# HELP daqhats_poll_duration_seconds How long the last poll took.\n\
+             # TYPE daqhats_poll_duration_seconds gauge\n\
+             daqhats_poll_duration_seconds 0.25\n\
+             # HELP daqhats_scan_overruns_total Scans ended by an overrun.\n\
+             # TYPE daqhats_scan_overruns_total counter\n\
+             daqhats_scan_overruns_total{address=\"3\",serial=\"a\\\"b\\\\c\\nd\",kind=\"buffer\"} 2\n\
+             daqhats_scan_overruns_total{address=\"3\",serial=\"a\\\"b\\\\c\\nd\",kind=\"hardware\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn poll_sets_gauges_and_drops_failed_channels() {
+        let mut exporter = Exporter::new();
+        let readings = thermocouples(&mut exporter, vec![Ok(24.0), Ok(30.5)]);
+        let dev = SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, _t: f64| ch as f64 / 4.0).unwrap();
+        exporter.add_analog(dev, &[0, 3]).unwrap();
+
+        exporter.poll();
+        let metrics = exporter.metrics();
+        assert_eq!(
+            samples(metrics, "daqhats_temperature_celsius"),
+            [
+                "daqhats_temperature_celsius{address=\"1\",serial=\"TC1\",channel=\"0\"} 24",
+                "daqhats_temperature_celsius{address=\"1\",serial=\"TC1\",channel=\"1\"} 30.5",
+            ]
+        );
+        assert_eq!(samples(metrics, "daqhats_cold_junction_celsius").len(), 2);
+        assert_eq!(
+            samples(metrics, "daqhats_voltage_volts"),
+            ["daqhats_voltage_volts{address=\"0\",serial=\"SIM00000\",channel=\"0\"} 0", "daqhats_voltage_volts{address=\"0\",serial=\"SIM00000\",channel=\"3\"} 0.75"]
+        );
+        assert!(samples(metrics, "daqhats_read_errors_total").is_empty());
+        assert_eq!(
+            samples(metrics, "daqhats_board_info"),
+            [
+                "daqhats_board_info{address=\"0\",serial=\"SIM00000\",product=\"MCC 118\",firmware=\"\",calibration_date=\"\"} 1",
+                "daqhats_board_info{address=\"1\",serial=\"TC1\",product=\"MCC 134\",firmware=\"\",calibration_date=\"\"} 1",
+            ]
+        );
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE daqhats_last_poll_timestamp_seconds gauge\n"));
+        assert!(rendered.contains("# TYPE daqhats_poll_duration_seconds gauge\n"));
+
+        // a failed read removes the channel's gauge until it reads again
+        *readings.lock().unwrap() = vec![Err(ErrorCode::Timeout), Ok(OPEN_TC_VALUE)];
+        exporter.poll();
+        exporter.poll();
+        let metrics = exporter.metrics();
+        assert!(samples(metrics, "daqhats_temperature_celsius").is_empty());
+        assert_eq!(samples(metrics, "daqhats_cold_junction_celsius").len(), 2);
+        assert_eq!(
+            samples(metrics, "daqhats_read_errors_total"),
+            [
+                "daqhats_read_errors_total{address=\"1\",serial=\"TC1\",error=\"OpenTc\"} 2",
+                "daqhats_read_errors_total{address=\"1\",serial=\"TC1\",error=\"Timeout\"} 2",
+            ]
+        );
+
+        *readings.lock().unwrap() = vec![Ok(OVERRANGE_TC_VALUE), Ok(COMMON_MODE_TC_VALUE)];
+        exporter.poll();
+        let errors = samples(exporter.metrics(), "daqhats_read_errors_total");
+        assert!(errors.contains(&"daqhats_read_errors_total{address=\"1\",serial=\"TC1\",error=\"OverrangeTc\"} 1".to_string()), "{:?}", errors);
+        assert!(errors.contains(&"daqhats_read_errors_total{address=\"1\",serial=\"TC1\",error=\"CommonModeTc\"} 1".to_string()), "{:?}", errors);
+
+        *readings.lock().unwrap() = vec![Ok(25.0), Ok(26.0)];
+        exporter.poll();
+        assert_eq!(samples(exporter.metrics(), "daqhats_temperature_celsius").len(), 2);
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(socket, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn metrics_are_served_over_http() {
+        let mut exporter = Exporter::new();
+        exporter.add_analog(SimulatedHat::new(HatId::Mcc118, 0, |_, _| 1.25).unwrap(), &[2]).unwrap();
+        let handle = exporter.serve("127.0.0.1:0", Duration::from_millis(10)).unwrap();
+        // the first poll happens straight away
+        std::thread::sleep(Duration::from_millis(100));
+        handle.metrics().record_scan_end(0, "SIM00000", &ScanEnd::HardwareOverrun);
+
+        let response = get(handle.local_addr(), "/metrics?x=1");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(body.contains("daqhats_voltage_volts{address=\"0\",serial=\"SIM00000\",channel=\"2\"} 1.25\n"), "{}", body);
+        assert!(body.contains("daqhats_scan_overruns_total{address=\"0\",serial=\"SIM00000\",kind=\"hardware\"} 1\n"), "{}", body);
+
+        assert!(get(handle.local_addr(), "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let started = Instant::now();
+        handle.stop();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
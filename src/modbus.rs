@@ -0,0 +1,785 @@
+//! A Modbus TCP server exposing boards to PLCs and SCADA systems.
+//!
+//! A [`RegisterMap`] says what each Modbus address means:
+//!
+//! - **Input registers** read an analog input (MCC 118, 128) or thermocouple (MCC 134) channel,
+//!   scaled to integer units: `round(value * scale + offset)`, clamped to `i16` or `u16`.
+//! - **Holding registers** drive analog outputs (MCC 152). Written values are converted back with
+//!   `(register - offset) / scale` volts; reads return the last value written. The outputs are
+//!   set to 0 V (or as close as a register value gets) when the server starts.
+//! - **Coils** drive digital outputs and **discrete inputs** read digital inputs (MCC 152). Bits
+//!   mapped to coils are configured as outputs when the server starts.
+//!
+//! Reads and writes go to the device when the request arrives. Requests that touch an unmapped
+//! address fail with exception 2 (illegal data address), values outside an output's range with
+//! exception 3 (illegal data value) and device errors with exception 4 (server device failure).
+//! Multiple writes check every address and value before writing any. The unit identifier is
+//! ignored.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{AIn, AOut, AnyHat, Dio, DioConfigItem, ErrorCode, Hat, ScanOptions, Thermocouple};
+use crate::core::{COMMON_MODE_TC_VALUE, OPEN_TC_VALUE, OVERRANGE_TC_VALUE};
+use crate::stream::StopHandle;
+
+/// What an input register reads.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    /// `a_in_read`, in volts.
+    #[default]
+    Voltage,
+    /// `t_in_read`, in °C.
+    Temperature,
+    /// `cjc_read`, in °C.
+    ColdJunction,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputRegister {
+    pub register: u16,
+    /// Address of the board.
+    pub board: u8,
+    pub channel: u8,
+    #[serde(default)]
+    pub input: Input,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    /// Whether the register holds an `i16` rather than a `u16`. Failed reads and thermocouple
+    /// faults read as `i16::MIN` (0x8000) if signed and 0xFFFF if not.
+    #[serde(default)]
+    pub signed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HoldingRegister {
+    pub register: u16,
+    pub board: u8,
+    pub channel: u8,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+/// A coil or discrete input mapped to one DIO bit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DigitalPoint {
+    pub address: u16,
+    pub board: u8,
+    pub bit: u8,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegisterMap {
+    pub input_registers: Vec<InputRegister>,
+    pub holding_registers: Vec<HoldingRegister>,
+    pub coils: Vec<DigitalPoint>,
+    pub discrete_inputs: Vec<DigitalPoint>,
+}
+
+/// A board the server can use. Operations the board doesn't support fail with
+/// [`ErrorCode::InvalidDevice`].
+trait Device: Send {
+    fn hat(&self) -> &dyn Hat;
+
+    fn read(&mut self, _input: Input, _channel: u8) -> Result<f64, ErrorCode> {
+        Err(ErrorCode::InvalidDevice)
+    }
+
+    fn a_out_write(&mut self, _channel: u8, _value: f64) -> Result<(), ErrorCode> {
+        Err(ErrorCode::InvalidDevice)
+    }
+
+    fn dio(&mut self) -> Option<&mut dyn Dio> {
+        None
+    }
+}
+
+struct Analog<T>(T);
+
+impl<T: AIn + Send> Device for Analog<T> {
+    fn hat(&self) -> &dyn Hat {
+        &self.0
+    }
+
+    fn read(&mut self, input: Input, channel: u8) -> Result<f64, ErrorCode> {
+        match input {
+            Input::Voltage => self.0.a_in_read(channel, ScanOptions::DEFAULT),
+            _ => Err(ErrorCode::InvalidDevice),
+        }
+    }
+}
+
+struct Tc<T>(T);
+
+impl<T: Thermocouple + Send> Device for Tc<T> {
+    fn hat(&self) -> &dyn Hat {
+        &self.0
+    }
+
+    fn read(&mut self, input: Input, channel: u8) -> Result<f64, ErrorCode> {
+        match input {
+            Input::Temperature => self.0.t_in_read(channel),
+            Input::ColdJunction => self.0.cjc_read(channel),
+            Input::Voltage => Err(ErrorCode::InvalidDevice),
+        }
+    }
+}
+
+struct Outputs<T>(T);
+
+impl<T: AOut + Dio + Send> Device for Outputs<T> {
+    fn hat(&self) -> &dyn Hat {
+        &self.0
+    }
+
+    fn a_out_write(&mut self, channel: u8, value: f64) -> Result<(), ErrorCode> {
+        self.0.a_out_write(channel, ScanOptions::DEFAULT, value)
+    }
+
+    fn dio(&mut self) -> Option<&mut dyn Dio> {
+        Some(&mut self.0)
+    }
+}
+
+impl Device for AnyHat {
+    fn hat(&self) -> &dyn Hat {
+        self
+    }
+
+    fn read(&mut self, input: Input, channel: u8) -> Result<f64, ErrorCode> {
+        match input {
+            Input::Voltage => self.as_a_in().ok_or(ErrorCode::InvalidDevice)?.a_in_read(channel, ScanOptions::DEFAULT),
+            Input::Temperature => self.as_thermocouple().ok_or(ErrorCode::InvalidDevice)?.t_in_read(channel),
+            Input::ColdJunction => self.as_thermocouple().ok_or(ErrorCode::InvalidDevice)?.cjc_read(channel),
+        }
+    }
+
+    fn a_out_write(&mut self, channel: u8, value: f64) -> Result<(), ErrorCode> {
+        self.as_a_out().ok_or(ErrorCode::InvalidDevice)?.a_out_write(channel, ScanOptions::DEFAULT, value)
+    }
+
+    fn dio(&mut self) -> Option<&mut dyn Dio> {
+        self.as_dio()
+    }
+}
+
+// Modbus exception codes
+const ILLEGAL_FUNCTION: u8 = 1;
+const ILLEGAL_DATA_ADDRESS: u8 = 2;
+const ILLEGAL_DATA_VALUE: u8 = 3;
+const SERVER_DEVICE_FAILURE: u8 = 4;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct State {
+    devices: HashMap<u8, Box<dyn Device>>,
+    input_registers: HashMap<u16, InputRegister>,
+    holding_registers: HashMap<u16, (HoldingRegister, u16)>,
+    coils: HashMap<u16, DigitalPoint>,
+    discrete_inputs: HashMap<u16, DigitalPoint>,
+}
+
+impl State {
+    fn device(&mut self, board: u8) -> Result<&mut Box<dyn Device>, u8> {
+        self.devices.get_mut(&board).ok_or(SERVER_DEVICE_FAILURE)
+    }
+
+    fn read_input_register(&mut self, address: u16) -> Result<u16, u8> {
+        let reg = self.input_registers.get(&address).ok_or(ILLEGAL_DATA_ADDRESS)?.clone();
+        let value = self.device(reg.board)?.read(reg.input, reg.channel);
+        let value = match value {
+            Ok(v) if v != OPEN_TC_VALUE && v != OVERRANGE_TC_VALUE && v != COMMON_MODE_TC_VALUE => v * reg.scale + reg.offset,
+            _ => return Ok(if reg.signed { i16::MIN as u16 } else { u16::MAX }),
+        };
+        Ok(if reg.signed {
+            value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16 as u16
+        } else {
+            value.round().clamp(0.0, u16::MAX as f64) as u16
+        })
+    }
+
+    fn read_holding_register(&mut self, address: u16) -> Result<u16, u8> {
+        self.holding_registers.get(&address).map(|(_, raw)| *raw).ok_or(ILLEGAL_DATA_ADDRESS)
+    }
+
+    /// The register's output and the volts `raw` stands for, if the output can produce them.
+    fn check_holding_register(&mut self, address: u16, raw: u16) -> Result<(HoldingRegister, f64), u8> {
+        let reg = self.holding_registers.get(&address).ok_or(ILLEGAL_DATA_ADDRESS)?.0.clone();
+        let volts = (raw as f64 - reg.offset) / reg.scale;
+        let range = self.device(reg.board)?.hat().capabilities().ao_range.ok_or(SERVER_DEVICE_FAILURE)?;
+        if !(range.min..=range.max).contains(&volts) {
+            return Err(ILLEGAL_DATA_VALUE);
+        }
+        Ok((reg, volts))
+    }
+
+    fn write_holding_register(&mut self, address: u16, raw: u16) -> Result<(), u8> {
+        let (reg, volts) = self.check_holding_register(address, raw)?;
+        self.device(reg.board)?.a_out_write(reg.channel, volts).map_err(|err| match err {
+            // out of the output's range
+            ErrorCode::BadParameter => ILLEGAL_DATA_VALUE,
+            _ => SERVER_DEVICE_FAILURE,
+        })?;
+        if let Some((_, cached)) = self.holding_registers.get_mut(&address) {
+            *cached = raw;
+        }
+        Ok(())
+    }
+
+    fn read_coil(&mut self, address: u16) -> Result<bool, u8> {
+        let point = self.coils.get(&address).ok_or(ILLEGAL_DATA_ADDRESS)?.clone();
+        let dio = self.device(point.board)?.dio().ok_or(SERVER_DEVICE_FAILURE)?;
+        dio.dio_output_read_bit(point.bit).map_err(|_| SERVER_DEVICE_FAILURE)
+    }
+
+    fn check_coil(&self, address: u16) -> Result<(), u8> {
+        self.coils.get(&address).map(drop).ok_or(ILLEGAL_DATA_ADDRESS)
+    }
+
+    fn write_coil(&mut self, address: u16, value: bool) -> Result<(), u8> {
+        let point = self.coils.get(&address).ok_or(ILLEGAL_DATA_ADDRESS)?.clone();
+        let dio = self.device(point.board)?.dio().ok_or(SERVER_DEVICE_FAILURE)?;
+        dio.dio_output_write_bit(point.bit, value).map_err(|_| SERVER_DEVICE_FAILURE)
+    }
+
+    fn read_discrete_input(&mut self, address: u16) -> Result<bool, u8> {
+        let point = self.discrete_inputs.get(&address).ok_or(ILLEGAL_DATA_ADDRESS)?.clone();
+        let dio = self.device(point.board)?.dio().ok_or(SERVER_DEVICE_FAILURE)?;
+        dio.dio_input_read_bit(point.bit).map_err(|_| SERVER_DEVICE_FAILURE)
+    }
+}
+
+/// Boards and their register map, waiting to be served. See [`ModbusServer::bind`].
+pub struct ModbusServer {
+    map: RegisterMap,
+    devices: HashMap<u8, Box<dyn Device>>,
+}
+
+impl ModbusServer {
+    pub fn new(map: RegisterMap) -> ModbusServer {
+        ModbusServer { map, devices: HashMap::new() }
+    }
+
+    /// Adds a board with analog inputs read by `a_in_read` (MCC 118, 128).
+    pub fn add_analog<T: AIn + Send + 'static>(&mut self, dev: T) {
+        self.add(Box::new(Analog(dev)));
+    }
+
+    /// Adds a thermocouple board (MCC 134). The thermocouple types must already be set.
+    pub fn add_thermocouple<T: Thermocouple + Send + 'static>(&mut self, dev: T) {
+        self.add(Box::new(Tc(dev)));
+    }
+
+    /// Adds a board with analog outputs and digital I/O (MCC 152).
+    pub fn add_outputs<T: AOut + Dio + Send + 'static>(&mut self, dev: T) {
+        self.add(Box::new(Outputs(dev)));
+    }
+
+    /// Adds any board, e.g. from [`AnyHat::open_all`].
+    pub fn add_board(&mut self, dev: AnyHat) {
+        self.add(Box::new(dev));
+    }
+
+    fn add(&mut self, dev: Box<dyn Device>) {
+        self.devices.insert(dev.hat().address(), dev);
+    }
+
+    /// Checks the register map against the boards, sets the outputs mapped to holding registers to
+    /// 0 V, configures the DIO bits mapped to coils as outputs and starts serving on background
+    /// threads. Fails with
+    /// [`io::ErrorKind::InvalidInput`] if an address is mapped twice or refers to a board that
+    /// wasn't added.
+    pub fn bind(mut self, addr: impl ToSocketAddrs) -> io::Result<ModbusHandle> {
+        let map = std::mem::take(&mut self.map);
+        let mut state = State {
+            devices: self.devices,
+            input_registers: HashMap::new(),
+            holding_registers: HashMap::new(),
+            coils: HashMap::new(),
+            discrete_inputs: HashMap::new(),
+        };
+
+        for reg in map.input_registers {
+            check_board(&state, reg.board)?;
+            insert_unique(&mut state.input_registers, reg.register, reg.clone(), "input register")?;
+        }
+        for reg in map.holding_registers {
+            check_board(&state, reg.board)?;
+            if reg.scale == 0.0 {
+                return Err(invalid_input(format!("holding register {} has a scale of 0", reg.register)));
+            }
+            // write the output so reads of the register tell the truth before the first write; if
+            // 0 V doesn't fit a register, use the register value closest to it
+            let raw = reg.offset.round().clamp(0.0, u16::MAX as f64) as u16;
+            let volts = (raw as f64 - reg.offset) / reg.scale;
+            let dev = state.device(reg.board).map_err(|_| invalid_input(format!("no board at address {} was added", reg.board)))?;
+            dev.a_out_write(reg.channel, volts)
+                .map_err(|err| io::Error::other(format!("setting the output of holding register {}: {}", reg.register, err)))?;
+            insert_unique(&mut state.holding_registers, reg.register, (reg.clone(), raw), "holding register")?;
+        }
+        for point in map.coils {
+            check_board(&state, point.board)?;
+            let dio = state.device(point.board).ok().and_then(|dev| dev.dio()).ok_or_else(|| invalid_input(format!("board {} has no digital I/O", point.board)))?;
+            dio.dio_config_write_bit(point.bit, DioConfigItem::Direction, 0).map_err(io::Error::other)?;
+            insert_unique(&mut state.coils, point.address, point.clone(), "coil")?;
+        }
+        for point in map.discrete_inputs {
+            check_board(&state, point.board)?;
+            insert_unique(&mut state.discrete_inputs, point.address, point.clone(), "discrete input")?;
+        }
+
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(state));
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+
+        let handle = std::thread::spawn(move || {
+            let mut connections: Vec<JoinHandle<()>> = Vec::new();
+            while !thread_stop.is_stopped() {
+                match listener.accept() {
+                    Ok((socket, _)) => {
+                        let state = state.clone();
+                        let stop = thread_stop.clone();
+                        connections.push(std::thread::spawn(move || {
+                            let _ = serve(socket, &state, &stop);
+                        }));
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                    Err(_) => {}
+                }
+                connections.retain(|conn| !conn.is_finished());
+            }
+            for conn in connections {
+                let _ = conn.join();
+            }
+        });
+
+        Ok(ModbusHandle { local_addr, stop, handle })
+    }
+}
+
+fn check_board(state: &State, board: u8) -> io::Result<()> {
+    if state.devices.contains_key(&board) {
+        Ok(())
+    } else {
+        Err(invalid_input(format!("no board at address {} was added", board)))
+    }
+}
+
+fn insert_unique<T>(map: &mut HashMap<u16, T>, address: u16, value: T, what: &str) -> io::Result<()> {
+    if map.insert(address, value).is_some() {
+        return Err(invalid_input(format!("{} {} is mapped twice", what, address)));
+    }
+    Ok(())
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// A running Modbus server. See [`ModbusServer::bind`].
+pub struct ModbusHandle {
+    local_addr: SocketAddr,
+    stop: StopHandle,
+    handle: JoinHandle<()>,
+}
+
+impl ModbusHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Closes all connections and stops the server.
+    pub fn stop(self) {
+        self.stop.stop();
+        self.handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+    }
+}
+
+fn serve(mut socket: TcpStream, state: &Mutex<State>, stop: &StopHandle) -> io::Result<()> {
+    socket.set_nonblocking(false)?;
+    socket.set_nodelay(true)?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 512];
+    while !stop.is_stopped() {
+        match socket.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(err) => return Err(err),
+        }
+
+        // MBAP header: transaction id, protocol id, length of the rest, unit id
+        while buf.len() >= 7 {
+            let len = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+            if len < 2 {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            if buf.len() < 6 + len {
+                break;
+            }
+            let frame = buf.drain(..6 + len).collect::<Vec<u8>>();
+            let pdu = {
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+                handle(&mut state, &frame[7..])
+            };
+            let mut reply = Vec::with_capacity(7 + pdu.len());
+            reply.extend_from_slice(&frame[..4]);
+            reply.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+            reply.push(frame[6]);
+            reply.extend_from_slice(&pdu);
+            socket.write_all(&reply)?;
+        }
+    }
+    Ok(())
+}
+
+/// Answers one request PDU, returning the response PDU.
+fn handle(state: &mut State, request: &[u8]) -> Vec<u8> {
+    let function = request[0];
+    match respond(state, function, &request[1..]) {
+        Ok(response) => response,
+        Err(code) => vec![function | 0x80, code],
+    }
+}
+
+fn respond(state: &mut State, function: u8, data: &[u8]) -> Result<Vec<u8>, u8> {
+    let word = |i: usize| data.get(i..i + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or(ILLEGAL_DATA_VALUE);
+
+    match function {
+        // read coils, read discrete inputs
+        0x01 | 0x02 => {
+            let (start, count) = (word(0)?, word(2)?);
+            if !(1..=2000).contains(&count) {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let mut bytes = vec![0u8; (count as usize).div_ceil(8)];
+            for i in 0..count {
+                let address = start.checked_add(i).ok_or(ILLEGAL_DATA_ADDRESS)?;
+                let value = if function == 0x01 { state.read_coil(address)? } else { state.read_discrete_input(address)? };
+                if value {
+                    bytes[i as usize / 8] |= 1 << (i % 8);
+                }
+            }
+            let mut response = vec![function, bytes.len() as u8];
+            response.extend_from_slice(&bytes);
+            Ok(response)
+        }
+        // read holding registers, read input registers
+        0x03 | 0x04 => {
+            let (start, count) = (word(0)?, word(2)?);
+            if !(1..=125).contains(&count) {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let mut response = vec![function, (count * 2) as u8];
+            for i in 0..count {
+                let address = start.checked_add(i).ok_or(ILLEGAL_DATA_ADDRESS)?;
+                let value = if function == 0x03 { state.read_holding_register(address)? } else { state.read_input_register(address)? };
+                response.extend_from_slice(&value.to_be_bytes());
+            }
+            Ok(response)
+        }
+        // write single coil
+        0x05 => {
+            let value = match word(2)? {
+                0xff00 => true,
+                0x0000 => false,
+                _ => return Err(ILLEGAL_DATA_VALUE),
+            };
+            state.write_coil(word(0)?, value)?;
+            Ok([&[function], &data[..4]].concat())
+        }
+        // write single register
+        0x06 => {
+            state.write_holding_register(word(0)?, word(2)?)?;
+            Ok([&[function], &data[..4]].concat())
+        }
+        // write multiple coils
+        0x0f => {
+            let (start, count) = (word(0)?, word(2)?);
+            let bytes = data.get(5..).ok_or(ILLEGAL_DATA_VALUE)?;
+            if !(1..=1968).contains(&count) || bytes.len() < (count as usize).div_ceil(8) {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let end = start.checked_add(count - 1).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            for address in start..=end {
+                state.check_coil(address)?;
+            }
+            for (i, address) in (start..=end).enumerate() {
+                state.write_coil(address, bytes[i / 8] & (1 << (i % 8)) != 0)?;
+            }
+            Ok([&[function], &data[..4]].concat())
+        }
+        // write multiple registers
+        0x10 => {
+            let (start, count) = (word(0)?, word(2)?);
+            if !(1..=123).contains(&count) || data.len() < 5 + count as usize * 2 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let end = start.checked_add(count - 1).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            let values = (start..=end).enumerate().map(|(i, address)| Ok((address, word(5 + i * 2)?))).collect::<Result<Vec<_>, u8>>()?;
+            for &(address, raw) in &values {
+                state.check_holding_register(address, raw)?;
+            }
+            for (address, raw) in values {
+                state.write_holding_register(address, raw)?;
+            }
+            Ok([&[function], &data[..4]].concat())
+        }
+        _ => Err(ILLEGAL_FUNCTION),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::core::HatId;
+    use crate::sim::{DioInputs, SimulatedHat};
+
+    /// A simulated MCC 152 that reports every analog output write.
+    struct Recorder {
+        sim: SimulatedHat,
+        writes: mpsc::Sender<(u8, f64)>,
+    }
+
+    impl Device for Recorder {
+        fn hat(&self) -> &dyn Hat {
+            &self.sim
+        }
+
+        fn a_out_write(&mut self, channel: u8, value: f64) -> Result<(), ErrorCode> {
+            self.sim.a_out_write(channel, ScanOptions::DEFAULT, value)?;
+            let _ = self.writes.send((channel, value));
+            Ok(())
+        }
+
+        fn dio(&mut self) -> Option<&mut dyn Dio> {
+            Some(&mut self.sim)
+        }
+    }
+
+    /// A thermocouple board reading fixed temperatures.
+    struct FixedTc(SimulatedHat, Vec<f64>);
+
+    impl Device for FixedTc {
+        fn hat(&self) -> &dyn Hat {
+            &self.0
+        }
+
+        fn read(&mut self, input: Input, channel: u8) -> Result<f64, ErrorCode> {
+            match input {
+                Input::Temperature => self.1.get(channel as usize).copied().ok_or(ErrorCode::BadParameter),
+                _ => Err(ErrorCode::InvalidDevice),
+            }
+        }
+    }
+
+    fn input(register: u16, board: u8, channel: u8, input: Input, scale: f64, signed: bool) -> InputRegister {
+        InputRegister { register, board, channel, input, scale, offset: 0.0, signed }
+    }
+
+    struct Fixture {
+        handle: ModbusHandle,
+        socket: TcpStream,
+        writes: mpsc::Receiver<(u8, f64)>,
+        dio_inputs: DioInputs,
+        transaction: u16,
+    }
+
+    /// Board 0 is an MCC 118 reading 1.234 V, -2.5 V and 20 V on channels 0-2, board 1 an MCC 152
+    /// and board 2 an MCC 134 reading 25 °C and an open thermocouple.
+    fn fixture() -> Fixture {
+        let map = RegisterMap {
+            input_registers: vec![
+                input(0, 0, 0, Input::Voltage, 1000.0, false),
+                input(1, 0, 1, Input::Voltage, 1000.0, true),
+                input(2, 0, 1, Input::Voltage, 1000.0, false),
+                input(3, 0, 2, Input::Voltage, 10000.0, true),
+                input(4, 2, 0, Input::Temperature, 10.0, true),
+                input(5, 2, 1, Input::Temperature, 10.0, true),
+                input(6, 2, 1, Input::Temperature, 10.0, false),
+                // the MCC 118 has no channel 9, so reads fail
+                input(7, 0, 9, Input::Voltage, 1.0, true),
+            ],
+            holding_registers: vec![
+                HoldingRegister { register: 100, board: 1, channel: 0, scale: 1000.0, offset: 0.0 },
+                HoldingRegister { register: 101, board: 1, channel: 1, scale: 1000.0, offset: 100.0 },
+            ],
+            coils: vec![DigitalPoint { address: 200, board: 1, bit: 0 }, DigitalPoint { address: 201, board: 1, bit: 1 }],
+            discrete_inputs: vec![DigitalPoint { address: 300, board: 1, bit: 4 }, DigitalPoint { address: 301, board: 1, bit: 5 }],
+        };
+
+        let mut server = ModbusServer::new(map);
+        server.add_analog(SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, _t: f64| [1.234, -2.5, 20.0][ch as usize % 3]).unwrap());
+        let outputs = SimulatedHat::new(HatId::Mcc152, 1, |_, _| 0.0).unwrap();
+        let dio_inputs = outputs.dio_inputs();
+        let (tx, writes) = mpsc::channel();
+        server.add(Box::new(Recorder { sim: outputs, writes: tx }));
+        server.add(Box::new(FixedTc(SimulatedHat::new(HatId::Mcc134, 2, |_, _| 0.0).unwrap(), vec![25.0, OPEN_TC_VALUE])));
+
+        let handle = server.bind("127.0.0.1:0").unwrap();
+        let socket = TcpStream::connect(handle.local_addr()).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        Fixture { handle, socket, writes, dio_inputs, transaction: 0 }
+    }
+
+    impl Fixture {
+        /// Sends a request PDU and returns the response PDU.
+        fn request(&mut self, pdu: &[u8]) -> Vec<u8> {
+            self.transaction += 1;
+            let mut frame = self.transaction.to_be_bytes().to_vec();
+            frame.extend_from_slice(&[0, 0]);
+            frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+            frame.push(7);
+            frame.extend_from_slice(pdu);
+            self.socket.write_all(&frame).unwrap();
+
+            let mut header = [0u8; 7];
+            self.socket.read_exact(&mut header).unwrap();
+            assert_eq!(header[..4], [self.transaction.to_be_bytes()[0], self.transaction.to_be_bytes()[1], 0, 0]);
+            assert_eq!(header[6], 7);
+            let mut response = vec![0u8; u16::from_be_bytes([header[4], header[5]]) as usize - 1];
+            self.socket.read_exact(&mut response).unwrap();
+            response
+        }
+
+        fn read_registers(&mut self, function: u8, start: u16, count: u16) -> Result<Vec<u16>, u8> {
+            let response = self.request(&[&[function], &start.to_be_bytes()[..], &count.to_be_bytes()[..]].concat());
+            if response[0] == function | 0x80 {
+                return Err(response[1]);
+            }
+            assert_eq!((response[0], response[1] as u16), (function, count * 2));
+            Ok(response[2..].chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect())
+        }
+
+        fn read_bits(&mut self, function: u8, start: u16, count: u16) -> Result<u8, u8> {
+            let response = self.request(&[&[function], &start.to_be_bytes()[..], &count.to_be_bytes()[..]].concat());
+            if response[0] == function | 0x80 {
+                return Err(response[1]);
+            }
+            assert_eq!(response[..2], [function, 1]);
+            Ok(response[2])
+        }
+
+        fn write_registers(&mut self, start: u16, values: &[u16]) -> Vec<u8> {
+            let mut pdu = vec![0x10];
+            pdu.extend_from_slice(&start.to_be_bytes());
+            pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+            pdu.push(values.len() as u8 * 2);
+            values.iter().for_each(|v| pdu.extend_from_slice(&v.to_be_bytes()));
+            self.request(&pdu)
+        }
+
+        fn output_writes(&self) -> Vec<(u8, f64)> {
+            self.writes.try_iter().collect()
+        }
+    }
+
+    #[test]
+    fn input_registers() {
+        let mut f = fixture();
+        let values = f.read_registers(0x04, 0, 8).unwrap();
+        assert_eq!(values[0], 1234);
+        assert_eq!(values[1] as i16, -2500);
+        // negative values clamp to 0 unsigned, large ones to i16::MAX signed
+        assert_eq!(values[2], 0);
+        assert_eq!(values[3] as i16, i16::MAX);
+        assert_eq!(values[4], 250);
+        // an open thermocouple and a failed read give the fault value
+        assert_eq!(values[5], 0x8000);
+        assert_eq!(values[6], 0xffff);
+        assert_eq!(values[7], 0x8000);
+
+        assert_eq!(f.read_registers(0x04, 7, 2), Err(ILLEGAL_DATA_ADDRESS));
+        assert_eq!(f.read_registers(0x04, 0, 0), Err(ILLEGAL_DATA_VALUE));
+        assert_eq!(f.read_registers(0x04, 0, 126), Err(ILLEGAL_DATA_VALUE));
+        f.handle.stop();
+    }
+
+    #[test]
+    fn holding_registers() {
+        let mut f = fixture();
+        // bind set both outputs to 0 V
+        assert_eq!(f.output_writes(), [(0, 0.0), (1, 0.0)]);
+        assert_eq!(f.read_registers(0x03, 100, 2), Ok(vec![0, 100]));
+
+        // write single register
+        assert_eq!(f.request(&[0x06, 0, 100, 0x09, 0xc4]), [0x06, 0, 100, 0x09, 0xc4]);
+        assert_eq!(f.output_writes(), [(0, 2.5)]);
+        // 6 V is past the MCC 152's 5 V
+        assert_eq!(f.request(&[0x06, 0, 100, 0x17, 0x70]), [0x86, ILLEGAL_DATA_VALUE]);
+        assert_eq!(f.request(&[0x06, 0, 102, 0, 0]), [0x86, ILLEGAL_DATA_ADDRESS]);
+
+        // write multiple registers
+        assert_eq!(f.write_registers(100, &[1000, 4100]), [0x10, 0, 100, 0, 2]);
+        assert_eq!(f.output_writes(), [(0, 1.0), (1, 4.0)]);
+        assert_eq!(f.read_registers(0x03, 100, 2), Ok(vec![1000, 4100]));
+
+        // nothing is written unless every address and value is good
+        assert_eq!(f.write_registers(100, &[2000, 6000]), [0x90, ILLEGAL_DATA_VALUE]);
+        assert_eq!(f.write_registers(100, &[2000, 2100, 0]), [0x90, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(f.output_writes(), []);
+        assert_eq!(f.read_registers(0x03, 100, 2), Ok(vec![1000, 4100]));
+        f.handle.stop();
+    }
+
+    #[test]
+    fn coils_and_discrete_inputs() {
+        let mut f = fixture();
+        // write single coil; the outputs power up high
+        assert_eq!(f.read_bits(0x01, 200, 2), Ok(0b11));
+        assert_eq!(f.request(&[0x05, 0, 200, 0x00, 0x00]), [0x05, 0, 200, 0x00, 0x00]);
+        assert_eq!(f.read_bits(0x01, 200, 2), Ok(0b10));
+        assert_eq!(f.request(&[0x05, 0, 201, 0x12, 0x34]), [0x85, ILLEGAL_DATA_VALUE]);
+        assert_eq!(f.request(&[0x05, 0, 202, 0xff, 0x00]), [0x85, ILLEGAL_DATA_ADDRESS]);
+
+        // write multiple coils
+        assert_eq!(f.request(&[0x0f, 0, 200, 0, 2, 1, 0b01]), [0x0f, 0, 200, 0, 2]);
+        assert_eq!(f.read_bits(0x01, 200, 2), Ok(0b01));
+        // one unmapped coil means no coil is written
+        assert_eq!(f.request(&[0x0f, 0, 199, 0, 3, 1, 0b110]), [0x8f, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(f.read_bits(0x01, 200, 2), Ok(0b01));
+
+        f.dio_inputs.set_bit(4, false);
+        f.dio_inputs.set_bit(5, true);
+        assert_eq!(f.read_bits(0x02, 300, 2), Ok(0b10));
+        assert_eq!(f.read_bits(0x02, 300, 3), Err(ILLEGAL_DATA_ADDRESS));
+
+        assert_eq!(f.request(&[0x2b, 0x0e, 1, 0]), [0xab, ILLEGAL_FUNCTION]);
+        f.handle.stop();
+    }
+
+    #[test]
+    fn rejects_bad_maps() {
+        let map = RegisterMap { coils: vec![DigitalPoint { address: 0, board: 3, bit: 0 }], ..RegisterMap::default() };
+        assert_eq!(ModbusServer::new(map).bind("127.0.0.1:0").err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+        let reg = input(0, 0, 0, Input::Voltage, 1.0, false);
+        let map = RegisterMap { input_registers: vec![reg.clone(), reg], ..RegisterMap::default() };
+        let mut server = ModbusServer::new(map);
+        server.add_analog(SimulatedHat::new(HatId::Mcc118, 0, |_, _| 0.0).unwrap());
+        assert_eq!(server.bind("127.0.0.1:0").err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+}
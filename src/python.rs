@@ -0,0 +1,597 @@
+//! The `daqhats` Python extension module, built with `maturin build --features python`.
+//!
+//! It mirrors the safe Rust API: [`hat_list`], one class per board with the methods of the traits
+//! it implements, and the same integer values as the vendor's Python package for IDs, options,
+//! thermocouple types and so on. Each [`ErrorCode`] raises its own exception class, all derived
+//! from `HatError`; [`core::CapabilityError`]s raise `CapabilityError`, a `ValueError`.
+//!
+//! Scan reads return NumPy arrays of shape `(channels, samples)`, de-interleaved like
+//! [`ScanBlock::channel`](crate::stream::ScanBlock::channel). Blocking calls release the GIL.
+
+use numpy::ndarray::Array2;
+use numpy::{IntoPyArray, PyArray2};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+
+use crate::core::{
+    self, AIn, AInScanner, AOut, AnalogInputMode, AnalogInputRange, Dio, DioConfigItem, ErrorCode, Hat, HatId, Iepe, Mcc118, Mcc128,
+    Mcc134, Mcc152, Mcc172, SourceType, TcType, Thermocouple, TriggerMode,
+};
+
+create_exception!(daqhats, HatError, PyException, "Base class of the errors reported by the library or a device.");
+create_exception!(daqhats, BadParameterError, HatError);
+create_exception!(daqhats, BusyError, HatError);
+create_exception!(daqhats, TimeoutError, HatError);
+create_exception!(daqhats, LockTimeoutError, HatError);
+create_exception!(daqhats, InvalidDeviceError, HatError);
+create_exception!(daqhats, ResourceUnavailError, HatError);
+create_exception!(daqhats, CommsFailureError, HatError);
+create_exception!(daqhats, UndefinedError, HatError);
+create_exception!(daqhats, CapabilityError, PyValueError, "The request is impossible for the board type; nothing was sent to the device.");
+
+impl From<ErrorCode> for PyErr {
+    fn from(err: ErrorCode) -> PyErr {
+        let msg = err.message();
+        match err {
+            ErrorCode::BadParameter => BadParameterError::new_err(msg),
+            ErrorCode::Busy => BusyError::new_err(msg),
+            ErrorCode::Timeout => TimeoutError::new_err(msg),
+            ErrorCode::LockTimeout => LockTimeoutError::new_err(msg),
+            ErrorCode::InvalidDevice => InvalidDeviceError::new_err(msg),
+            ErrorCode::ResourceUnavail => ResourceUnavailError::new_err(msg),
+            ErrorCode::CommsFailure => CommsFailureError::new_err(msg),
+            ErrorCode::Undefined => UndefinedError::new_err(msg),
+        }
+    }
+}
+
+impl From<core::CapabilityError> for PyErr {
+    fn from(err: core::CapabilityError) -> PyErr {
+        CapabilityError::new_err(err.to_string())
+    }
+}
+
+impl From<crate::Error> for PyErr {
+    fn from(err: crate::Error) -> PyErr {
+        match err {
+            crate::Error::Device(err) => err.into(),
+            crate::Error::Capability(err) => err.into(),
+        }
+    }
+}
+
+/// The variant whose integer value is `value`; the C enums' conversions panic on unknown values.
+fn variant<T: Copy>(value: u32, variants: &[T], to_int: impl Fn(T) -> u32) -> Result<T, ErrorCode> {
+    variants.iter().copied().find(|v| to_int(*v) == value).ok_or(ErrorCode::BadParameter)
+}
+
+fn hat_id(id: u16) -> Result<HatId, ErrorCode> {
+    let ids = [HatId::ANY, HatId::Mcc118, HatId::Mcc118Bootloader, HatId::Mcc128, HatId::Mcc134, HatId::Mcc152, HatId::Mcc172];
+    variant(id as u32, &ids, |id| id as u32)
+}
+
+fn options(options: u32) -> Result<core::ScanOptions, ErrorCode> {
+    core::ScanOptions::from_bits(options).ok_or(ErrorCode::BadParameter)
+}
+
+fn trigger_mode(mode: u8) -> Result<TriggerMode, ErrorCode> {
+    let modes = [TriggerMode::RisingEdge, TriggerMode::FallingEdge, TriggerMode::ActiveHigh, TriggerMode::ActiveLow];
+    variant(mode as u32, &modes, |mode| mode as u32)
+}
+
+#[pyclass(frozen, get_all, module = "daqhats")]
+#[derive(Clone)]
+struct HatInfo {
+    address: u8,
+    id: u16,
+    version: u16,
+    product_name: String,
+}
+
+#[pymethods]
+impl HatInfo {
+    fn __repr__(&self) -> String {
+        format!("HatInfo(address={}, id={}, version={}, product_name={:?})", self.address, self.id, self.version, self.product_name)
+    }
+}
+
+/// Lists the boards found, optionally only those with the given ID.
+#[pyfunction]
+#[pyo3(signature = (filter_id = HatId::ANY as u16))]
+fn hat_list(filter_id: u16) -> PyResult<Vec<HatInfo>> {
+    let infos = core::hat_list(hat_id(filter_id)?);
+    Ok(infos.into_iter().map(|info| HatInfo { address: info.address, id: info.id as u16, version: info.version, product_name: info.product_name }).collect())
+}
+
+/// Methods every board has. `$dev` is the wrapper type; boards are opened by the constructor and
+/// closed when the object is garbage collected.
+macro_rules! hat_methods {
+    ($py:ident, $dev:ident, $name:literal) => {
+        #[pyclass(name = $name, module = "daqhats")]
+        struct $py {
+            dev: $dev,
+        }
+
+        #[pymethods]
+        impl $py {
+            #[new]
+            fn new(address: u8) -> PyResult<Self> {
+                Ok($py { dev: $dev::open(address)? })
+            }
+
+            fn address(&self) -> u8 {
+                self.dev.address()
+            }
+
+            fn serial(&self) -> PyResult<String> {
+                Ok(self.dev.serial()?)
+            }
+
+            fn calibration_date(&self) -> PyResult<Option<String>> {
+                Ok(self.dev.calibration_date()?)
+            }
+
+            /// `(version, bootloader_version)`, or `None` for boards without firmware.
+            fn firmware_version(&self) -> PyResult<Option<(u16, Option<u16>)>> {
+                Ok(self.dev.firmware_version()?.map(|fw| (fw.version, fw.bootloader_version)))
+            }
+
+            fn blink_led(&mut self, py: Python<'_>, count: u8) -> PyResult<()> {
+                let dev = &mut self.dev;
+                Ok(py.detach(|| dev.blink_led(count))?)
+            }
+        }
+    };
+}
+
+macro_rules! a_in_methods {
+    ($py:ident) => {
+        #[pymethods]
+        impl $py {
+            #[pyo3(signature = (channel, options = 0))]
+            fn a_in_read(&mut self, channel: u8, options: u32) -> PyResult<f64> {
+                Ok(self.dev.a_in_read(channel, self::options(options)?)?)
+            }
+        }
+    };
+}
+
+macro_rules! scan_methods {
+    ($py:ident) => {
+        #[pymethods]
+        impl $py {
+            fn a_in_scan_actual_rate(&self, channel_count: u8, sample_rate_per_channel: f64) -> PyResult<f64> {
+                Ok(self.dev.a_in_scan_actual_rate(channel_count, sample_rate_per_channel)?)
+            }
+
+            #[pyo3(signature = (channel_mask, samples_per_channel, sample_rate_per_channel, options = 0))]
+            fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: u32) -> PyResult<()> {
+                let options = self::options(options)?;
+                self.dev.capabilities().validate_scan_channels(self.dev.a_in_num_channels(), channel_mask, sample_rate_per_channel, options.contains(core::ScanOptions::EXTCLOCK), options.contains(core::ScanOptions::EXTTRIGGER))?;
+                Ok(self.dev.a_in_scan_start(channel_mask, samples_per_channel, sample_rate_per_channel, options)?)
+            }
+
+            fn a_in_scan_buffer_size(&self) -> PyResult<u32> {
+                Ok(self.dev.a_in_scan_buffer_size()?)
+            }
+
+            /// `(status, samples_available)`, with the status as `STATUS_*` flags.
+            fn a_in_scan_status(&self) -> PyResult<(u16, u32)> {
+                let (status, available) = self.dev.a_in_scan_status()?;
+                Ok((status.bits(), available))
+            }
+
+            /// Reads up to `samples_per_channel` samples per channel (-1 for all available),
+            /// waiting up to `timeout` seconds (-1 to wait indefinitely). Returns the status flags
+            /// and an array of shape `(channels, samples)`.
+            #[pyo3(signature = (samples_per_channel = -1, timeout = 0.0))]
+            fn a_in_scan_read<'py>(&mut self, py: Python<'py>, samples_per_channel: i32, timeout: f64) -> PyResult<(u16, Bound<'py, PyArray2<f64>>)> {
+                let (status, data) = read_scan(py, &mut self.dev, samples_per_channel, timeout)?;
+                Ok((status, data.into_pyarray(py)))
+            }
+
+            fn a_in_scan_channel_count(&self) -> u8 {
+                self.dev.a_in_scan_channel_count()
+            }
+
+            fn a_in_scan_stop(&mut self) -> PyResult<()> {
+                Ok(self.dev.a_in_scan_stop()?)
+            }
+
+            fn a_in_scan_cleanup(&mut self) -> PyResult<()> {
+                Ok(self.dev.a_in_scan_cleanup()?)
+            }
+
+            /// Acquires `samples_per_channel` samples on each channel of `channel_mask` and returns
+            /// them as an array of shape `(channels, samples)`. Hardware or buffer overruns raise
+            /// `ResourceUnavailError`.
+            #[pyo3(signature = (channel_mask, samples_per_channel, sample_rate_per_channel, options = 0, timeout = -1.0))]
+            fn scan<'py>(
+                &mut self,
+                py: Python<'py>,
+                channel_mask: u8,
+                samples_per_channel: u32,
+                sample_rate_per_channel: f64,
+                options: u32,
+                timeout: f64,
+            ) -> PyResult<Bound<'py, PyArray2<f64>>> {
+                if samples_per_channel == 0 || options & core::ScanOptions::CONTINUOUS.bits() != 0 {
+                    return Err(ErrorCode::BadParameter.into());
+                }
+                self.a_in_scan_start(channel_mask, samples_per_channel, sample_rate_per_channel, options)?;
+                let result = read_scan(py, &mut self.dev, samples_per_channel as i32, timeout);
+                self.dev.a_in_scan_cleanup()?;
+
+                let (status, data) = result?;
+                if status & (core::ScanStatus::HW_OVERRUN | core::ScanStatus::BUFFER_OVERRUN).bits() != 0 {
+                    return Err(ResourceUnavailError::new_err("the scan overran"));
+                }
+                if data.ncols() < samples_per_channel as usize {
+                    return Err(ErrorCode::Timeout.into());
+                }
+                Ok(data.into_pyarray(py))
+            }
+        }
+    };
+}
+
+fn read_scan(py: Python<'_>, dev: &mut (dyn AInScanner + Send), samples_per_channel: i32, timeout: f64) -> PyResult<(u16, Array2<f64>)> {
+    let channels = dev.a_in_scan_channel_count() as usize;
+    let capacity = if samples_per_channel < 0 { dev.a_in_scan_buffer_size()? as usize / channels.max(1) } else { samples_per_channel as usize };
+    let mut buffer = vec![0.0; capacity * channels];
+
+    let (status, read) = py.detach(|| dev.a_in_scan_read(samples_per_channel, timeout, &mut buffer))?;
+    let read = read as usize;
+    let data = Array2::from_shape_fn((channels, read), |(ch, i)| buffer[i * channels + ch]);
+    Ok((status.bits(), data))
+}
+
+macro_rules! thermocouple_methods {
+    ($py:ident) => {
+        #[pymethods]
+        impl $py {
+            fn tc_type_write(&mut self, channel: u8, tc_type: u8) -> PyResult<()> {
+                let types = [TcType::J, TcType::K, TcType::T, TcType::E, TcType::R, TcType::S, TcType::B, TcType::N, TcType::Disabled];
+                Ok(self.dev.tc_type_write(channel, variant(tc_type as u32, &types, |t| t as u32)?)?)
+            }
+
+            fn tc_type_read(&self, channel: u8) -> PyResult<u8> {
+                Ok(self.dev.tc_type_read(channel)? as u8)
+            }
+
+            fn update_interval_write(&mut self, interval: u8) -> PyResult<()> {
+                Ok(self.dev.update_interval_write(interval)?)
+            }
+
+            fn update_interval_read(&self) -> PyResult<u8> {
+                Ok(self.dev.update_interval_read()?)
+            }
+
+            fn t_in_read(&mut self, py: Python<'_>, channel: u8) -> PyResult<f64> {
+                let dev = &mut self.dev;
+                Ok(py.detach(|| dev.t_in_read(channel))?)
+            }
+
+            fn cjc_read(&mut self, channel: u8) -> PyResult<f64> {
+                Ok(self.dev.cjc_read(channel)?)
+            }
+        }
+    };
+}
+
+fn dio_config_item(item: u8) -> Result<DioConfigItem, ErrorCode> {
+    let items = [
+        DioConfigItem::Direction,
+        DioConfigItem::PullConfig,
+        DioConfigItem::PullEnable,
+        DioConfigItem::InputInvert,
+        DioConfigItem::InputLatch,
+        DioConfigItem::OutputType,
+        DioConfigItem::IntMask,
+    ];
+    variant(item as u32, &items, |item| item as u32)
+}
+
+macro_rules! output_methods {
+    ($py:ident) => {
+        #[pymethods]
+        impl $py {
+            #[pyo3(signature = (channel, value, options = 0))]
+            fn a_out_write(&mut self, channel: u8, value: f64, options: u32) -> PyResult<()> {
+                Ok(self.dev.a_out_write(channel, self::options(options)?, value)?)
+            }
+
+            #[pyo3(signature = (values, options = 0))]
+            fn a_out_write_all(&mut self, values: Vec<f64>, options: u32) -> PyResult<()> {
+                Ok(self.dev.a_out_write_all(self::options(options)?, &values)?)
+            }
+
+            fn dio_reset(&mut self) -> PyResult<()> {
+                Ok(self.dev.dio_reset()?)
+            }
+
+            fn dio_input_read_bit(&mut self, channel: u8) -> PyResult<bool> {
+                Ok(self.dev.dio_input_read_bit(channel)?)
+            }
+
+            fn dio_input_read_port(&mut self) -> PyResult<u8> {
+                Ok(self.dev.dio_input_read_port()?)
+            }
+
+            fn dio_output_write_bit(&mut self, channel: u8, value: bool) -> PyResult<()> {
+                Ok(self.dev.dio_output_write_bit(channel, value)?)
+            }
+
+            fn dio_output_write_port(&mut self, value: u8) -> PyResult<()> {
+                Ok(self.dev.dio_output_write_port(value)?)
+            }
+
+            fn dio_output_read_bit(&self, channel: u8) -> PyResult<bool> {
+                Ok(self.dev.dio_output_read_bit(channel)?)
+            }
+
+            fn dio_output_read_port(&self) -> PyResult<u8> {
+                Ok(self.dev.dio_output_read_port()?)
+            }
+
+            fn dio_int_status_read_bit(&mut self, channel: u8) -> PyResult<bool> {
+                Ok(self.dev.dio_int_status_read_bit(channel)?)
+            }
+
+            fn dio_int_status_read_port(&mut self) -> PyResult<u8> {
+                Ok(self.dev.dio_int_status_read_port()?)
+            }
+
+            fn dio_config_write_bit(&mut self, channel: u8, item: u8, value: u8) -> PyResult<()> {
+                Ok(self.dev.dio_config_write_bit(channel, dio_config_item(item)?, value)?)
+            }
+
+            fn dio_config_write_port(&mut self, item: u8, value: u8) -> PyResult<()> {
+                Ok(self.dev.dio_config_write_port(dio_config_item(item)?, value)?)
+            }
+
+            fn dio_config_read_bit(&self, channel: u8, item: u8) -> PyResult<u8> {
+                Ok(self.dev.dio_config_read_bit(channel, dio_config_item(item)?)?)
+            }
+
+            fn dio_config_read_port(&self, item: u8) -> PyResult<u8> {
+                Ok(self.dev.dio_config_read_port(dio_config_item(item)?)?)
+            }
+        }
+    };
+}
+
+macro_rules! iepe_methods {
+    ($py:ident) => {
+        #[pymethods]
+        impl $py {
+            fn iepe_config_write(&mut self, channel: u8, enable: bool) -> PyResult<()> {
+                Ok(self.dev.iepe_config_write(channel, enable)?)
+            }
+
+            fn iepe_config_read(&self, channel: u8) -> PyResult<bool> {
+                Ok(self.dev.iepe_config_read(channel)?)
+            }
+
+            fn a_in_sensitivity_write(&mut self, channel: u8, value: f64) -> PyResult<()> {
+                Ok(self.dev.a_in_sensitivity_write(channel, value)?)
+            }
+
+            fn a_in_sensitivity_read(&self, channel: u8) -> PyResult<f64> {
+                Ok(self.dev.a_in_sensitivity_read(channel)?)
+            }
+        }
+    };
+}
+
+hat_methods!(PyMcc118, Mcc118, "Mcc118");
+a_in_methods!(PyMcc118);
+scan_methods!(PyMcc118);
+
+hat_methods!(PyMcc128, Mcc128, "Mcc128");
+a_in_methods!(PyMcc128);
+scan_methods!(PyMcc128);
+
+hat_methods!(PyMcc134, Mcc134, "Mcc134");
+a_in_methods!(PyMcc134);
+thermocouple_methods!(PyMcc134);
+
+hat_methods!(PyMcc152, Mcc152, "Mcc152");
+output_methods!(PyMcc152);
+
+hat_methods!(PyMcc172, Mcc172, "Mcc172");
+scan_methods!(PyMcc172);
+iepe_methods!(PyMcc172);
+
+#[pymethods]
+impl PyMcc118 {
+    fn trigger_mode(&mut self, mode: u8) -> PyResult<()> {
+        Ok(self.dev.trigger_mode(trigger_mode(mode)?)?)
+    }
+}
+
+#[pymethods]
+impl PyMcc128 {
+    fn trigger_mode(&mut self, mode: u8) -> PyResult<()> {
+        Ok(self.dev.trigger_mode(trigger_mode(mode)?)?)
+    }
+
+    fn a_in_mode_write(&mut self, mode: u8) -> PyResult<()> {
+        let modes = [AnalogInputMode::SingleEnded, AnalogInputMode::Differential];
+        Ok(self.dev.a_in_mode_write(variant(mode as u32, &modes, |mode| mode as u32)?)?)
+    }
+
+    fn a_in_mode_read(&self) -> PyResult<u8> {
+        Ok(self.dev.a_in_mode_read()? as u8)
+    }
+
+    fn a_in_range_write(&mut self, range: u8) -> PyResult<()> {
+        let ranges = [AnalogInputRange::Bip10V, AnalogInputRange::Bip5V, AnalogInputRange::Bip2V, AnalogInputRange::Bip1V];
+        Ok(self.dev.a_in_range_write(variant(range as u32, &ranges, |range| range as u32)?)?)
+    }
+
+    fn a_in_range_read(&self) -> PyResult<u8> {
+        Ok(self.dev.a_in_range_read()? as u8)
+    }
+}
+
+#[pymethods]
+impl PyMcc172 {
+    fn a_in_clock_config_write(&mut self, clock_source: u8, sample_rate_per_channel: f64) -> PyResult<()> {
+        Ok(self.dev.a_in_clock_config_write(source_type(clock_source)?, sample_rate_per_channel)?)
+    }
+
+    /// `(clock_source, sample_rate_per_channel, synchronized)`
+    fn a_in_clock_config_read(&self) -> PyResult<(u8, f64, bool)> {
+        let (source, rate, synced) = self.dev.a_in_clock_config_read()?;
+        Ok((source as u8, rate, synced))
+    }
+
+    fn trigger_config(&mut self, source: u8, mode: u8) -> PyResult<()> {
+        Ok(self.dev.trigger_config(source_type(source)?, trigger_mode(mode)?)?)
+    }
+}
+
+fn source_type(source: u8) -> Result<SourceType, ErrorCode> {
+    variant(source as u32, &[SourceType::Local, SourceType::Master, SourceType::Slave], |source| source as u32)
+}
+
+#[pymodule]
+fn daqhats(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_function(wrap_pyfunction!(hat_list, m)?)?;
+    m.add_class::<HatInfo>()?;
+    m.add_class::<PyMcc118>()?;
+    m.add_class::<PyMcc128>()?;
+    m.add_class::<PyMcc134>()?;
+    m.add_class::<PyMcc152>()?;
+    m.add_class::<PyMcc172>()?;
+
+    m.add("HatError", py.get_type::<HatError>())?;
+    m.add("BadParameterError", py.get_type::<BadParameterError>())?;
+    m.add("BusyError", py.get_type::<BusyError>())?;
+    m.add("TimeoutError", py.get_type::<TimeoutError>())?;
+    m.add("LockTimeoutError", py.get_type::<LockTimeoutError>())?;
+    m.add("InvalidDeviceError", py.get_type::<InvalidDeviceError>())?;
+    m.add("ResourceUnavailError", py.get_type::<ResourceUnavailError>())?;
+    m.add("CommsFailureError", py.get_type::<CommsFailureError>())?;
+    m.add("UndefinedError", py.get_type::<UndefinedError>())?;
+    m.add("CapabilityError", py.get_type::<CapabilityError>())?;
+
+    for (name, id) in [("ANY", HatId::ANY), ("MCC_118", HatId::Mcc118), ("MCC_118_BOOTLOADER", HatId::Mcc118Bootloader), ("MCC_128", HatId::Mcc128), ("MCC_134", HatId::Mcc134), ("MCC_152", HatId::Mcc152), ("MCC_172", HatId::Mcc172)] {
+        m.add(format!("HAT_ID_{}", name), id as u16)?;
+    }
+    let options = [
+        ("DEFAULT", core::ScanOptions::DEFAULT),
+        ("NOSCALEDATA", core::ScanOptions::NOSCALEDATA),
+        ("NOCALIBRATEDATA", core::ScanOptions::NOCALIBRATEDATA),
+        ("EXTCLOCK", core::ScanOptions::EXTCLOCK),
+        ("EXTTRIGGER", core::ScanOptions::EXTTRIGGER),
+        ("CONTINUOUS", core::ScanOptions::CONTINUOUS),
+    ];
+    for (name, option) in options {
+        m.add(format!("OPTS_{}", name), option.bits())?;
+    }
+    let statuses = [
+        ("HW_OVERRUN", core::ScanStatus::HW_OVERRUN),
+        ("BUFFER_OVERRUN", core::ScanStatus::BUFFER_OVERRUN),
+        ("TRIGGERED", core::ScanStatus::TRIGGERED),
+        ("RUNNING", core::ScanStatus::RUNNING),
+    ];
+    for (name, status) in statuses {
+        m.add(format!("STATUS_{}", name), status.bits())?;
+    }
+    for (name, mode) in [("RISING_EDGE", TriggerMode::RisingEdge), ("FALLING_EDGE", TriggerMode::FallingEdge), ("ACTIVE_HIGH", TriggerMode::ActiveHigh), ("ACTIVE_LOW", TriggerMode::ActiveLow)] {
+        m.add(format!("TRIG_{}", name), mode as u8)?;
+    }
+    let tc_types = [("J", TcType::J), ("K", TcType::K), ("T", TcType::T), ("E", TcType::E), ("R", TcType::R), ("S", TcType::S), ("B", TcType::B), ("N", TcType::N)];
+    for (name, tc_type) in tc_types {
+        m.add(format!("TC_TYPE_{}", name), tc_type as u8)?;
+    }
+    m.add("TC_DISABLED", TcType::Disabled as u8)?;
+    m.add("OPEN_TC_VALUE", core::OPEN_TC_VALUE)?;
+    m.add("OVERRANGE_TC_VALUE", core::OVERRANGE_TC_VALUE)?;
+    m.add("COMMON_MODE_TC_VALUE", core::COMMON_MODE_TC_VALUE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedHat;
+
+    #[test]
+    fn integers_map_to_the_vendor_values() {
+        assert_eq!(hat_id(0), Ok(HatId::ANY));
+        assert_eq!(hat_id(0x0142), Ok(HatId::Mcc118));
+        assert_eq!(hat_id(0x8142), Ok(HatId::Mcc118Bootloader));
+        assert_eq!(hat_id(0x0146), Ok(HatId::Mcc128));
+        assert_eq!(hat_id(0x0143), Ok(HatId::Mcc134));
+        assert_eq!(hat_id(0x0144), Ok(HatId::Mcc152));
+        assert_eq!(hat_id(0x0145), Ok(HatId::Mcc172));
+        assert_eq!(hat_id(0x0147), Err(ErrorCode::BadParameter));
+
+        assert_eq!(options(0), Ok(core::ScanOptions::DEFAULT));
+        assert_eq!(options(0x1f), Ok(core::ScanOptions::all()));
+        assert_eq!(options(0x18), Ok(core::ScanOptions::EXTTRIGGER | core::ScanOptions::CONTINUOUS));
+        assert_eq!(options(0x20), Err(ErrorCode::BadParameter));
+
+        assert_eq!(trigger_mode(0), Ok(TriggerMode::RisingEdge));
+        assert_eq!(trigger_mode(3), Ok(TriggerMode::ActiveLow));
+        assert_eq!(trigger_mode(4), Err(ErrorCode::BadParameter));
+
+        assert_eq!(dio_config_item(0), Ok(DioConfigItem::Direction));
+        assert_eq!(dio_config_item(6), Ok(DioConfigItem::IntMask));
+        assert_eq!(dio_config_item(7), Err(ErrorCode::BadParameter));
+
+        let types = [TcType::J, TcType::K, TcType::Disabled];
+        assert_eq!(variant(1, &types, |t| t as u32), Ok(TcType::K));
+        assert_eq!(variant(255, &types, |t| t as u32), Ok(TcType::Disabled));
+        assert_eq!(variant(8, &types, |t| t as u32), Err(ErrorCode::BadParameter));
+    }
+
+    #[test]
+    fn errors_raise_their_own_exceptions() {
+        Python::initialize();
+        Python::attach(|py| {
+            let cases: [(ErrorCode, Bound<'_, pyo3::types::PyType>); 8] = [
+                (ErrorCode::BadParameter, py.get_type::<BadParameterError>()),
+                (ErrorCode::Busy, py.get_type::<BusyError>()),
+                (ErrorCode::Timeout, py.get_type::<TimeoutError>()),
+                (ErrorCode::LockTimeout, py.get_type::<LockTimeoutError>()),
+                (ErrorCode::InvalidDevice, py.get_type::<InvalidDeviceError>()),
+                (ErrorCode::ResourceUnavail, py.get_type::<ResourceUnavailError>()),
+                (ErrorCode::CommsFailure, py.get_type::<CommsFailureError>()),
+                (ErrorCode::Undefined, py.get_type::<UndefinedError>()),
+            ];
+            for (code, class) in cases {
+                let err = PyErr::from(code);
+                assert!(err.get_type(py).is(&class), "{:?}", code);
+                assert!(err.is_instance_of::<HatError>(py));
+                assert_eq!(err.value(py).to_string(), code.message());
+            }
+
+            let err = PyErr::from(crate::Error::from(core::CapabilityError::NoChannels));
+            assert!(err.is_instance_of::<CapabilityError>(py));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(!err.is_instance_of::<HatError>(py));
+            assert!(PyErr::from(crate::Error::from(ErrorCode::Busy)).is_instance_of::<BusyError>(py));
+        });
+    }
+
+    #[test]
+    fn read_scan_returns_everything_buffered() {
+        Python::initialize();
+        let mut dev = SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, _t: f64| ch as f64).unwrap();
+        dev.a_in_scan_start(0b101, 500, 20_000.0, core::ScanOptions::DEFAULT).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let (status, data) = Python::attach(|py| read_scan(py, &mut dev, -1, 0.0)).unwrap();
+        dev.a_in_scan_cleanup().unwrap();
+
+        assert_eq!(status & core::ScanStatus::RUNNING.bits(), 0);
+        assert_eq!(data.dim(), (2, 500));
+        assert!(data.row(0).iter().all(|&v| v == 0.0));
+        assert!(data.row(1).iter().all(|&v| v == 2.0));
+    }
+}
@@ -0,0 +1,333 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::Capabilities;
+use crate::stream::{ScanBlock, ScanStream, StreamInfo};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    Csv,
+    Tsv,
+}
+
+impl RecordFormat {
+    fn delimiter(self) -> char {
+        match self {
+            RecordFormat::Csv => ',',
+            RecordFormat::Tsv => '\t',
+        }
+    }
+}
+
+/// When to start a new file. Files are only switched between blocks, so each file holds whole blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Never,
+    /// Once the current file has grown to at least this many bytes.
+    Size(u64),
+    /// Once the current file spans at least this much sample time.
+    Duration(Duration),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RecorderOptions {
+    pub format: RecordFormat,
+    pub rotation: Rotation,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        RecorderOptions { format: RecordFormat::Csv, rotation: Rotation::Never }
+    }
+}
+
+/// Writes a stream of [`ScanBlock`]s as delimited text, one row per sample time.
+///
+/// Each file starts with `#` comment lines naming the board, address, serial number, sample rate
+/// and scaling flags, followed by a header row: `unix_time_s`, then one `chN (unit)` column per
+/// physical channel. With rotation enabled the files are named `{stem}_0000.{ext}`,
+/// `{stem}_0001.{ext}`, ... next to `path`; otherwise `path` is used as is.
+pub struct Recorder {
+    path: PathBuf,
+    info: StreamInfo,
+    opts: RecorderOptions,
+    writer: BufWriter<File>,
+    file_bytes: u64,
+    file_start: Option<SystemTime>,
+    files: Vec<PathBuf>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, info: StreamInfo, opts: RecorderOptions) -> io::Result<Recorder> {
+        let path = path.as_ref().to_path_buf();
+        let first = rotated_path(&path, opts.rotation, 0);
+        let writer = BufWriter::new(File::create(&first)?);
+
+        let mut rec = Recorder {
+            path,
+            info,
+            opts,
+            writer,
+            file_bytes: 0,
+            file_start: None,
+            files: vec![first],
+        };
+        rec.write_header()?;
+        Ok(rec)
+    }
+
+    pub fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    /// Files written so far, including the current one.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn write_block(&mut self, block: &ScanBlock) -> io::Result<()> {
+        if block.channel_count != self.info.channel_count() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("block has {} channels, the recording has {}", block.channel_count, self.info.channel_count()),
+            ));
+        }
+
+        if self.should_rotate(block.timestamp) {
+            self.rotate()?;
+        }
+        self.file_start.get_or_insert(block.timestamp);
+
+        let delim = self.opts.format.delimiter();
+        let mut row = String::new();
+        for (i, frame) in block.frames().enumerate() {
+            row.clear();
+            let t = block.sample_time(i, self.info.sample_rate);
+            row.push_str(&format!("{:.6}", unix_seconds(t)));
+            for value in frame {
+                row.push(delim);
+                row.push_str(&value.to_string());
+            }
+            row.push('\n');
+            self.write_str(&row)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and closes the current file and returns every file written.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(self.files)
+    }
+
+    /// Writes blocks until the stream ends (e.g. after [`crate::stream::StopHandle::stop`]), then
+    /// flushes. The stream itself is left for the caller to join.
+    pub fn record<T>(&mut self, stream: &ScanStream<T>) -> io::Result<()> {
+        for block in stream.iter() {
+            self.write_block(&block)?;
+        }
+        self.flush()
+    }
+
+    fn should_rotate(&self, next_block: SystemTime) -> bool {
+        match self.opts.rotation {
+            Rotation::Never => false,
+            Rotation::Size(limit) => self.file_bytes >= limit,
+            Rotation::Duration(limit) => match self.file_start {
+                Some(start) => next_block.duration_since(start).map(|d| d >= limit).unwrap_or(false),
+                None => false,
+            },
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let next = rotated_path(&self.path, self.opts.rotation, self.files.len());
+        self.writer = BufWriter::new(File::create(&next)?);
+        self.files.push(next);
+        self.file_bytes = 0;
+        self.file_start = None;
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let info = &self.info;
+        let product = Capabilities::of(info.id).map(|caps| caps.product_name.to_string()).unwrap_or_else(|| format!("{:?}", info.id));
+        let mut header = format!("# device {} address {} serial {}", product, info.address, info.serial);
+        if let Some(date) = &info.calibration_date {
+            header.push_str(&format!(" calibration_date {}", date));
+        }
+        header.push('\n');
+        header.push_str(&format!(
+            "# sample_rate {} scale_data {} calibrate_data {} start_time {:.6}\n",
+            info.sample_rate,
+            info.scale_data,
+            info.calibrate_data,
+            unix_seconds(info.start_time)
+        ));
+
+        let delim = self.opts.format.delimiter();
+        header.push_str("unix_time_s");
+        for (channel, unit) in info.channels.iter().zip(&info.units) {
+            header.push(delim);
+            header.push_str(&format!("ch{} ({})", channel, unit));
+        }
+        header.push('\n');
+        self.write_str(&header)
+    }
+
+    fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.writer.write_all(s.as_bytes())?;
+        self.file_bytes += s.len() as u64;
+        Ok(())
+    }
+}
+
+/// The path of the `index`th file of a recording to `path`.
+pub(crate) fn rotated_path(path: &Path, rotation: Rotation, index: usize) -> PathBuf {
+    if rotation == Rotation::Never {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:04}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}_{:04}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+fn unix_seconds(t: SystemTime) -> f64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::HatId;
+
+    fn info() -> StreamInfo {
+        StreamInfo {
+            address: 2,
+            serial: "01ABCDEF".to_string(),
+            calibrate_data: false,
+            calibration_date: Some("2024-05-06".to_string()),
+            start_time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            ..StreamInfo::test(HatId::Mcc118, &[0, 3], 10.0)
+        }
+    }
+
+    /// Two-channel block of `frames` samples, channel 0 counting from `first`, channel 3 its negative.
+    fn block(first: u64, frames: u64) -> ScanBlock {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_millis(first * 100);
+        let data = (first..first + frames).flat_map(|i| [i as f64, -(i as f64)]).collect();
+        ScanBlock { first_sample: first, timestamp, channel_count: 2, data }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("daqhats-recorder-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn csv_files_have_a_header_and_a_row_per_sample_time() {
+        let dir = temp_dir("csv");
+        let mut rec = Recorder::create(dir.join("run.csv"), info(), RecorderOptions::default()).unwrap();
+        rec.write_block(&block(0, 2)).unwrap();
+        rec.write_block(&block(2, 1)).unwrap();
+        assert_eq!(rec.finish().unwrap(), [dir.join("run.csv")]);
+
+        let text = std::fs::read_to_string(dir.join("run.csv")).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "# device MCC 118 address 2 serial 01ABCDEF calibration_date 2024-05-06",
+                "# sample_rate 10 scale_data true calibrate_data false start_time 1700000000.000000",
+                "unix_time_s,ch0 (V),ch3 (V)",
+                "1700000000.000000,0,-0",
+                "1700000000.100000,1,-1",
+                "1700000000.200000,2,-2",
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tsv_files_use_tabs() {
+        let dir = temp_dir("tsv");
+        let info = StreamInfo { calibration_date: None, ..info() };
+        let mut rec = Recorder::create(dir.join("run.tsv"), info, RecorderOptions { format: RecordFormat::Tsv, rotation: Rotation::Never }).unwrap();
+        rec.write_block(&block(5, 1)).unwrap();
+        rec.finish().unwrap();
+
+        let text = std::fs::read_to_string(dir.join("run.tsv")).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "# device MCC 118 address 2 serial 01ABCDEF");
+        assert_eq!(lines[2..], ["unix_time_s\tch0 (V)\tch3 (V)", "1700000000.500000\t5\t-5"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mismatched_blocks_are_rejected() {
+        let dir = temp_dir("mismatch");
+        let mut rec = Recorder::create(dir.join("run.csv"), info(), RecorderOptions::default()).unwrap();
+        let block = ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 3, data: vec![0.0; 3] };
+        assert_eq!(rec.write_block(&block).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_rotate_by_size_between_blocks() {
+        let dir = temp_dir("size");
+        // the header is 182 bytes and each block about 95, so every file holds one block
+        let mut rec = Recorder::create(dir.join("run.csv"), info(), RecorderOptions { format: RecordFormat::Csv, rotation: Rotation::Size(250) }).unwrap();
+        for first in [0, 4, 8] {
+            rec.write_block(&block(first, 4)).unwrap();
+        }
+        let files = rec.finish().unwrap();
+        assert_eq!(files, [dir.join("run_0000.csv"), dir.join("run_0001.csv"), dir.join("run_0002.csv")]);
+        for (i, file) in files.iter().enumerate() {
+            let text = std::fs::read_to_string(file).unwrap();
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), 3 + 4);
+            assert_eq!(lines[2], "unix_time_s,ch0 (V),ch3 (V)");
+            assert!(lines[3].ends_with(&format!(",{},-{}", i * 4, i * 4)), "{}", lines[3]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_rotate_by_sample_time() {
+        let dir = temp_dir("duration");
+        let opts = RecorderOptions { format: RecordFormat::Csv, rotation: Rotation::Duration(Duration::from_secs(1)) };
+        let mut rec = Recorder::create(dir.join("run"), info(), opts).unwrap();
+        // blocks of 0.5 s: two per file
+        for first in (0..25).step_by(5) {
+            rec.write_block(&block(first, 5)).unwrap();
+        }
+        let files = rec.finish().unwrap();
+        assert_eq!(files, [dir.join("run_0000"), dir.join("run_0001"), dir.join("run_0002")]);
+        let rows: Vec<usize> = files.iter().map(|file| std::fs::read_to_string(file).unwrap().lines().count() - 3).collect();
+        assert_eq!(rows, [10, 10, 5]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotated_paths() {
+        let path = Path::new("/data/run.csv");
+        assert_eq!(rotated_path(path, Rotation::Never, 3), Path::new("/data/run.csv"));
+        assert_eq!(rotated_path(path, Rotation::Size(1), 0), Path::new("/data/run_0000.csv"));
+        assert_eq!(rotated_path(path, Rotation::Duration(Duration::from_secs(1)), 12), Path::new("/data/run_0012.csv"));
+        assert_eq!(rotated_path(Path::new("log"), Rotation::Size(1), 10000), Path::new("log_10000"));
+        assert_eq!(rotated_path(Path::new("a.b.tsv"), Rotation::Size(1), 1), Path::new("a.b_0001.tsv"));
+    }
+}
@@ -0,0 +1,666 @@
+//! A network server streaming live scans to remote clients, behind the `server` feature.
+//!
+//! The server owns a set of scanning devices. Clients connect over TCP, list the devices and start
+//! and stop scans on them; each connection runs at most one scan at a time, and a device scans for
+//! one client at a time. Two protocols share the same port, told apart by the first bytes a client
+//! sends:
+//!
+//! - **Framed binary.** Both directions exchange frames of a `u8` kind, a `u32` payload length and
+//!   the payload, all little-endian. Clients send [`Command`]s as JSON in [`FRAME_COMMAND`] frames,
+//!   the server replies with [`Message`]s as JSON in [`FRAME_MESSAGE`] frames and sends data in
+//!   [`FRAME_BLOCK`] frames (see [`encode_block`]).
+//! - **WebSocket**, for browsers. Commands and messages are JSON text messages, and blocks arrive
+//!   as [`Message::Block`].
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tungstenite::{HandshakeError, WebSocket};
+
+use crate::core::{AInScanner, Capabilities, ErrorCode, HatId};
+use crate::stream::{scan_blocks, ScanBlock, ScanStream, StopHandle, StreamInfo};
+use crate::ScanOptions;
+
+/// Client to server: a JSON [`Command`].
+pub const FRAME_COMMAND: u8 = 1;
+/// Server to client: a JSON [`Message`].
+pub const FRAME_MESSAGE: u8 = 2;
+/// Server to client: a block of samples, see [`encode_block`].
+pub const FRAME_BLOCK: u8 = 3;
+
+// frames larger than this are a protocol error rather than a reason to allocate
+const MAX_COMMAND_LEN: usize = 64 * 1024;
+// how often idle connections and the accept loop check for blocks, commands and shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A request from a client, e.g. `{"command": "start", "address": 0, "channel_mask": 3,
+/// "sample_rate_per_channel": 1000.0}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Replies with [`Message::Devices`].
+    List,
+    /// Replies with [`Message::Device`].
+    Info { address: u8 },
+    /// Starts a continuous scan, replying with [`Message::Started`] and then streaming blocks.
+    Start {
+        address: u8,
+        channel_mask: u8,
+        sample_rate_per_channel: f64,
+        #[serde(default = "default_samples_per_block")]
+        samples_per_block: usize,
+        #[serde(default = "default_true")]
+        scale_data: bool,
+        #[serde(default = "default_true")]
+        calibrate_data: bool,
+        #[serde(default)]
+        external_clock: bool,
+        #[serde(default)]
+        external_trigger: bool,
+    },
+    /// Stops this connection's scan, replying with [`Message::Stopped`].
+    Stop,
+}
+
+fn default_samples_per_block() -> usize {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A device as the server reports it.
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceEntry {
+    pub address: u8,
+    pub id: HatId,
+    pub serial: String,
+    pub calibration_date: Option<String>,
+    pub capabilities: &'static Capabilities,
+    /// Whether a client is scanning the device.
+    pub scanning: bool,
+}
+
+/// A reply or notification from the server.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Devices { devices: Vec<DeviceEntry> },
+    Device { device: DeviceEntry },
+    Started { info: StreamInfo },
+    /// The scan ended, on request or because of `reason`, e.g. an overrun.
+    Stopped { address: u8, reason: String },
+    /// WebSocket connections only; framed connections get [`FRAME_BLOCK`] frames instead.
+    Block {
+        address: u8,
+        first_sample: u64,
+        /// Time of the first sample, in seconds since the Unix epoch.
+        timestamp: f64,
+        /// One array per channel, in the order of the stream's channels.
+        data: Vec<Vec<f64>>,
+    },
+    Error { message: String },
+}
+
+/// Encodes a block as the payload of a [`FRAME_BLOCK`] frame: the device address (`u8`), the first
+/// sample index (`u64`), the timestamp of the first sample as seconds (`u64`) and nanoseconds
+/// (`u32`) since the Unix epoch, the channel count (`u8`), the samples per channel (`u32`) and
+/// then the samples as interleaved `f64`s, all little-endian.
+pub fn encode_block(address: u8, block: &ScanBlock) -> Vec<u8> {
+    let timestamp = block.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut payload = Vec::with_capacity(26 + block.data.len() * 8);
+    payload.push(address);
+    payload.extend_from_slice(&block.first_sample.to_le_bytes());
+    payload.extend_from_slice(&timestamp.as_secs().to_le_bytes());
+    payload.extend_from_slice(&timestamp.subsec_nanos().to_le_bytes());
+    payload.push(block.channel_count as u8);
+    payload.extend_from_slice(&(block.samples_per_channel() as u32).to_le_bytes());
+    for x in &block.data {
+        payload.extend_from_slice(&x.to_le_bytes());
+    }
+    payload
+}
+
+pub fn write_frame<W: Write>(w: &mut W, kind: u8, payload: &[u8]) -> io::Result<()> {
+    let mut header = [0u8; 5];
+    header[0] = kind;
+    header[1..].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+    w.write_all(&header)?;
+    w.write_all(payload)?;
+    w.flush()
+}
+
+/// Reads one frame, returning its kind and payload. Fails with [`io::ErrorKind::InvalidData`]
+/// without reading the payload if it is longer than `max_len`.
+pub fn read_frame<R: Read>(r: &mut R, max_len: usize) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    r.read_exact(&mut header)?;
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > max_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} byte frame is longer than {} bytes", len, max_len)));
+    }
+    let mut payload = vec![0u8; len];
+    r.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+struct Slot {
+    entry: DeviceEntry,
+    // `None` while a connection is scanning with it
+    dev: Option<Box<dyn AInScanner + Send>>,
+}
+
+type Devices = Arc<Mutex<HashMap<u8, Slot>>>;
+
+/// Devices waiting to be served. See [`Server::bind`].
+#[derive(Default)]
+pub struct Server {
+    devices: HashMap<u8, Slot>,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Adds a device, e.g. a [`crate::sim::SimulatedHat`] or the result of
+    /// [`crate::core::AnyHat::into_a_in_scanner`]. Fails with [`ErrorCode::Busy`] if a device
+    /// with the same address was already added.
+    pub fn add_device<T: AInScanner + Send + 'static>(&mut self, dev: T) -> Result<(), ErrorCode> {
+        let address = dev.address();
+        if self.devices.contains_key(&address) {
+            return Err(ErrorCode::Busy);
+        }
+        let entry = DeviceEntry {
+            address,
+            id: dev.id(),
+            serial: dev.serial()?,
+            calibration_date: dev.calibration_date()?,
+            capabilities: dev.capabilities(),
+            scanning: false,
+        };
+        self.devices.insert(address, Slot { entry, dev: Some(Box::new(dev)) });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Starts listening and serving clients on background threads.
+    pub fn bind(self, addr: impl ToSocketAddrs) -> io::Result<ServerHandle> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let devices: Devices = Arc::new(Mutex::new(self.devices));
+        let stop = StopHandle::new();
+        let thread_devices = devices.clone();
+        let thread_stop = stop.clone();
+
+        let handle = std::thread::spawn(move || {
+            let mut connections: Vec<JoinHandle<()>> = Vec::new();
+            while !thread_stop.is_stopped() {
+                match listener.accept() {
+                    Ok((socket, _)) => {
+                        let devices = thread_devices.clone();
+                        let stop = thread_stop.clone();
+                        connections.push(std::thread::spawn(move || accept_connection(socket, &devices, &stop)));
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                    // e.g. the client hung up before the connection was accepted
+                    Err(_) => {}
+                }
+                connections.retain(|conn| !conn.is_finished());
+            }
+            for conn in connections {
+                let _ = conn.join();
+            }
+        });
+
+        Ok(ServerHandle { local_addr, devices, stop, handle })
+    }
+}
+
+/// A running server. See [`Server::bind`].
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    devices: Devices,
+    stop: StopHandle,
+    handle: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// The address the server listens on, e.g. to find the port after binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn devices(&self) -> Vec<DeviceEntry> {
+        list_devices(&self.devices)
+    }
+
+    /// Stops all scans, closes all connections and returns the devices.
+    pub fn stop(self) -> Vec<Box<dyn AInScanner + Send>> {
+        self.stop.stop();
+        self.handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        devices.drain().filter_map(|(_, slot)| slot.dev).collect()
+    }
+}
+
+fn list_devices(devices: &Devices) -> Vec<DeviceEntry> {
+    let devices = devices.lock().unwrap_or_else(|e| e.into_inner());
+    let mut entries = devices.values().map(|slot| slot.entry.clone()).collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.address);
+    entries
+}
+
+/// One client's end of either protocol.
+trait Connection {
+    /// Waits up to [`POLL_INTERVAL`] for a command. Fails once the client has gone.
+    fn recv(&mut self) -> io::Result<Option<Result<Command, serde_json::Error>>>;
+    fn send(&mut self, msg: &Message) -> io::Result<()>;
+    fn send_block(&mut self, address: u8, block: &ScanBlock) -> io::Result<()>;
+}
+
+struct FramedConnection {
+    socket: TcpStream,
+    // commands are read on their own thread so a half-received frame never blocks streaming
+    commands: mpsc::Receiver<Vec<u8>>,
+}
+
+impl FramedConnection {
+    fn new(socket: TcpStream) -> io::Result<FramedConnection> {
+        let mut reader = socket.try_clone()?;
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            // an oversized frame ends the connection
+            while let Ok((kind, payload)) = read_frame(&mut reader, MAX_COMMAND_LEN) {
+                if kind == FRAME_COMMAND && tx.send(payload).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(FramedConnection { socket, commands: rx })
+    }
+}
+
+impl Connection for FramedConnection {
+    fn recv(&mut self) -> io::Result<Option<Result<Command, serde_json::Error>>> {
+        match self.commands.recv_timeout(POLL_INTERVAL) {
+            Ok(payload) => Ok(Some(serde_json::from_slice(&payload))),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    fn send(&mut self, msg: &Message) -> io::Result<()> {
+        write_frame(&mut self.socket, FRAME_MESSAGE, &serde_json::to_vec(msg)?)
+    }
+
+    fn send_block(&mut self, address: u8, block: &ScanBlock) -> io::Result<()> {
+        write_frame(&mut self.socket, FRAME_BLOCK, &encode_block(address, block))
+    }
+}
+
+impl Drop for FramedConnection {
+    fn drop(&mut self) {
+        // ends the reader thread
+        let _ = self.socket.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+impl Connection for WebSocket<TcpStream> {
+    fn recv(&mut self) -> io::Result<Option<Result<Command, serde_json::Error>>> {
+        match self.read() {
+            Ok(tungstenite::Message::Text(text)) => Ok(Some(serde_json::from_str(&text))),
+            Ok(tungstenite::Message::Close(_)) => Err(io::ErrorKind::ConnectionAborted.into()),
+            // pings are answered by tungstenite on the next read or write
+            Ok(_) => Ok(None),
+            Err(tungstenite::Error::Io(err)) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+            Err(tungstenite::Error::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn send(&mut self, msg: &Message) -> io::Result<()> {
+        let text = serde_json::to_string(msg)?;
+        WebSocket::send(self, tungstenite::Message::text(text)).map_err(io::Error::other)
+    }
+
+    fn send_block(&mut self, address: u8, block: &ScanBlock) -> io::Result<()> {
+        let timestamp = block.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        let data = (0..block.channel_count).map(|i| block.channel(i).collect()).collect();
+        Connection::send(self, &Message::Block { address, first_sample: block.first_sample, timestamp, data })
+    }
+}
+
+fn accept_connection(socket: TcpStream, devices: &Devices, stop: &StopHandle) {
+    // a read timeout keeps an idle client from holding up stop
+    if socket.set_nonblocking(false).is_err() || socket.set_nodelay(true).is_err() || socket.set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+
+    // WebSocket clients open with an HTTP request; framed clients with a command frame
+    let mut start = [0u8; 4];
+    let websocket = loop {
+        if stop.is_stopped() {
+            return;
+        }
+        match socket.peek(&mut start) {
+            Ok(0) => return,
+            Ok(4) => break &start == b"GET ",
+            // wait for the rest of the first four bytes
+            Ok(_) => std::thread::sleep(POLL_INTERVAL),
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    };
+
+    if websocket {
+        let mut handshake = tungstenite::accept(socket);
+        let ws = loop {
+            match handshake {
+                Ok(ws) => break ws,
+                Err(HandshakeError::Interrupted(mid)) if !stop.is_stopped() => handshake = mid.handshake(),
+                Err(_) => return,
+            }
+        };
+        serve(ws, devices, stop);
+    } else if socket.set_read_timeout(None).is_ok() {
+        if let Ok(conn) = FramedConnection::new(socket) {
+            serve(conn, devices, stop);
+        }
+    }
+}
+
+type Scan = (u8, ScanStream<Box<dyn AInScanner + Send>>);
+
+fn serve<C: Connection>(mut conn: C, devices: &Devices, stop: &StopHandle) {
+    let mut scan: Option<Scan> = None;
+    let _ = serve_until_closed(&mut conn, devices, stop, &mut scan);
+    if let Some(scan) = scan {
+        let reason = finish_scan(devices, scan);
+        let _ = conn.send(&reason);
+    }
+}
+
+fn serve_until_closed<C: Connection>(conn: &mut C, devices: &Devices, stop: &StopHandle, scan: &mut Option<Scan>) -> io::Result<()> {
+    while !stop.is_stopped() {
+        if let Some((address, stream)) = scan.as_ref() {
+            let address = *address;
+            let ended = loop {
+                match stream.try_recv() {
+                    Ok(block) => conn.send_block(address, &block)?,
+                    Err(mpsc::TryRecvError::Empty) => break false,
+                    Err(mpsc::TryRecvError::Disconnected) => break true,
+                }
+            };
+            if ended {
+                let msg = finish_scan(devices, scan.take().expect("checked above"));
+                conn.send(&msg)?;
+            }
+        }
+
+        let reply = match conn.recv()? {
+            None => continue,
+            Some(Err(err)) => Message::Error { message: format!("invalid command: {}", err) },
+            Some(Ok(command)) => handle(command, devices, scan),
+        };
+        conn.send(&reply)?;
+    }
+    Ok(())
+}
+
+fn handle(command: Command, devices: &Devices, scan: &mut Option<Scan>) -> Message {
+    let error = |message: String| Message::Error { message };
+
+    match command {
+        Command::List => Message::Devices { devices: list_devices(devices) },
+        Command::Info { address } => match list_devices(devices).into_iter().find(|entry| entry.address == address) {
+            Some(device) => Message::Device { device },
+            None => error(format!("no device at address {}", address)),
+        },
+        Command::Start {
+            address,
+            channel_mask,
+            sample_rate_per_channel,
+            samples_per_block,
+            scale_data,
+            calibrate_data,
+            external_clock,
+            external_trigger,
+        } => {
+            if scan.is_some() {
+                return error("this connection is already scanning; stop first".to_string());
+            }
+            let dev = {
+                let mut devices = devices.lock().unwrap_or_else(|e| e.into_inner());
+                let Some(slot) = devices.get_mut(&address) else {
+                    return error(format!("no device at address {}", address));
+                };
+                let Some(dev) = slot.dev.take() else {
+                    return error(format!("device {} is already scanning", address));
+                };
+                slot.entry.scanning = true;
+                dev
+            };
+
+            let opts = ScanOptions { channel_mask, sample_rate_per_channel, scale_data, calibrate_data, external_clock, external_trigger };
+            match scan_blocks(dev, opts, samples_per_block) {
+                Ok(stream) => {
+                    let info = stream.info().clone();
+                    *scan = Some((address, stream));
+                    Message::Started { info }
+                }
+                Err((dev, err)) => {
+                    return_device(devices, address, dev);
+                    error(err.to_string())
+                }
+            }
+        }
+        Command::Stop => match scan.take() {
+            Some(scan) => finish_scan(devices, scan),
+            None => error("this connection is not scanning".to_string()),
+        },
+    }
+}
+
+/// Stops a scan, gives the device back and reports why the scan ended.
+fn finish_scan(devices: &Devices, (address, stream): Scan) -> Message {
+    let (dev, end) = stream.stop();
+    return_device(devices, address, dev);
+    Message::Stopped { address, reason: format!("{:?}", end) }
+}
+
+fn return_device(devices: &Devices, address: u8, dev: Box<dyn AInScanner + Send>) {
+    let mut devices = devices.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(slot) = devices.get_mut(&address) {
+        slot.entry.scanning = false;
+        slot.dev = Some(dev);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::sim::SimulatedHat;
+
+    /// A server with a simulated MCC 118 at address 0 whose channel `n` reads `n`.
+    fn server() -> ServerHandle {
+        let mut server = Server::new();
+        server.add_device(SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, _t: f64| ch as f64).unwrap()).unwrap();
+        server.bind("127.0.0.1:0").unwrap()
+    }
+
+    fn connect(server: &ServerHandle) -> TcpStream {
+        let socket = TcpStream::connect(server.local_addr()).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        socket
+    }
+
+    fn send(socket: &mut TcpStream, command: Value) {
+        write_frame(socket, FRAME_COMMAND, command.to_string().as_bytes()).unwrap();
+    }
+
+    /// The next message, skipping blocks.
+    fn message(socket: &mut TcpStream) -> Value {
+        loop {
+            let (kind, payload) = read_frame(socket, 1 << 20).unwrap();
+            if kind == FRAME_MESSAGE {
+                return serde_json::from_slice(&payload).unwrap();
+            }
+        }
+    }
+
+    fn start(samples_per_block: usize) -> Value {
+        json!({"command": "start", "address": 0, "channel_mask": 3, "sample_rate_per_channel": 1000.0, "samples_per_block": samples_per_block})
+    }
+
+    #[test]
+    fn framed_scan() {
+        let server = server();
+        let mut socket = connect(&server);
+
+        send(&mut socket, json!({"command": "list"}));
+        let devices = message(&mut socket);
+        assert_eq!(devices["type"], "devices");
+        assert_eq!(devices["devices"][0]["address"], 0);
+        assert_eq!(devices["devices"][0]["scanning"], false);
+
+        send(&mut socket, start(50));
+        let started = message(&mut socket);
+        assert_eq!(started["type"], "started", "{}", started);
+        assert_eq!(started["info"]["channels"], json!([0, 1]));
+        assert!(server.devices()[0].scanning);
+
+        // a second client can't take the device
+        let mut other = connect(&server);
+        send(&mut other, start(50));
+        assert_eq!(message(&mut other)["type"], "error");
+
+        let mut next_sample = 0;
+        for _ in 0..3 {
+            let (kind, payload) = read_frame(&mut socket, 1 << 20).unwrap();
+            assert_eq!(kind, FRAME_BLOCK);
+            assert_eq!(payload[0], 0);
+            assert_eq!(u64::from_le_bytes(payload[1..9].try_into().unwrap()), next_sample);
+            assert_eq!(payload[21], 2);
+            let samples_per_channel = u32::from_le_bytes(payload[22..26].try_into().unwrap()) as usize;
+            assert!((1..=50).contains(&samples_per_channel));
+            assert_eq!(payload.len(), 26 + samples_per_channel * 2 * 8);
+            let data = payload[26..].chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect::<Vec<_>>();
+            assert!(data.chunks_exact(2).all(|frame| frame == [0.0, 1.0]));
+            next_sample += samples_per_channel as u64;
+        }
+
+        send(&mut socket, json!({"command": "stop"}));
+        let stopped = message(&mut socket);
+        assert_eq!(stopped, json!({"type": "stopped", "address": 0, "reason": "Stopped"}));
+        assert!(!server.devices()[0].scanning);
+
+        // the device is free again
+        send(&mut other, start(50));
+        assert_eq!(message(&mut other)["type"], "started");
+        drop(other);
+        drop(socket);
+        assert_eq!(server.stop().len(), 1);
+    }
+
+    #[test]
+    fn failed_starts_keep_the_device() {
+        let server = server();
+        let mut socket = connect(&server);
+
+        // the MCC 118 tops out at 100 kS/s
+        send(&mut socket, json!({"command": "start", "address": 0, "channel_mask": 1, "sample_rate_per_channel": 1.0e6}));
+        assert_eq!(message(&mut socket)["type"], "error");
+        send(&mut socket, start(0));
+        assert_eq!(message(&mut socket)["type"], "error");
+        send(&mut socket, json!({"command": "start", "address": 5, "channel_mask": 1, "sample_rate_per_channel": 1000.0}));
+        assert_eq!(message(&mut socket)["type"], "error");
+        send(&mut socket, json!({"command": "stop"}));
+        assert_eq!(message(&mut socket)["type"], "error");
+        write_frame(&mut socket, FRAME_COMMAND, b"{not json").unwrap();
+        assert_eq!(message(&mut socket)["type"], "error");
+
+        assert_eq!(server.devices().len(), 1);
+        assert!(!server.devices()[0].scanning);
+        send(&mut socket, start(50));
+        assert_eq!(message(&mut socket)["type"], "started");
+        drop(socket);
+        assert_eq!(server.stop().len(), 1);
+    }
+
+    #[test]
+    fn websocket_scan() {
+        let server = server();
+        let (mut ws, _) = tungstenite::client(format!("ws://{}/", server.local_addr()), connect(&server)).unwrap();
+        let next = |ws: &mut WebSocket<TcpStream>| loop {
+            if let tungstenite::Message::Text(text) = ws.read().unwrap() {
+                return serde_json::from_str::<Value>(&text).unwrap();
+            }
+        };
+
+        ws.send(tungstenite::Message::text(start(20).to_string())).unwrap();
+        assert_eq!(next(&mut ws)["type"], "started");
+        let block = next(&mut ws);
+        assert_eq!(block["type"], "block");
+        assert_eq!(block["first_sample"], 0);
+        let data = block["data"].as_array().unwrap();
+        assert_eq!(data.len(), 2);
+        assert!(data[1].as_array().unwrap().iter().all(|x| x == 1.0));
+
+        ws.send(tungstenite::Message::text(json!({"command": "stop"}).to_string())).unwrap();
+        let stopped = loop {
+            let msg = next(&mut ws);
+            if msg["type"] != "block" {
+                break msg;
+            }
+        };
+        assert_eq!(stopped["type"], "stopped");
+        ws.close(None).unwrap();
+        server.stop();
+    }
+
+    #[test]
+    fn stop_does_not_wait_for_idle_clients() {
+        let server = server();
+        // one client that never sends anything, one that stops partway through a WebSocket request
+        let _idle = connect(&server);
+        let mut partial = connect(&server);
+        partial.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+        assert_eq!(server.stop().len(), 1);
+        assert!(started.elapsed() < Duration::from_secs(1), "stop took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let mut header = vec![FRAME_COMMAND];
+        header.extend_from_slice(&(MAX_COMMAND_LEN as u32 + 1).to_le_bytes());
+        let err = read_frame(&mut Cursor::new(&header), MAX_COMMAND_LEN).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // the server hangs up instead of waiting for, or allocating, the payload
+        let server = server();
+        let mut socket = connect(&server);
+        socket.write_all(&header).unwrap();
+        let mut buf = [0u8; 1];
+        assert!(matches!(socket.read(&mut buf), Ok(0) | Err(_)));
+        assert_eq!(server.stop().len(), 1);
+    }
+}
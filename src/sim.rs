@@ -0,0 +1,680 @@
+//! A simulated board for developing and testing without hardware.
+//!
+//! [`SimulatedHat`] implements the same traits as the real wrappers, so it can be handed to
+//! [`crate::scan_channels`], [`crate::stream::scan_blocks`] or anything else that takes a device.
+//! Samples come from a [`SignalSource`]: a closure of time, or a [`Replay`] of recorded data.
+//! Simulated MCC 152s also have analog outputs and digital I/O, with the input pin levels set
+//! through [`DioInputs`].
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::core::{mcc172_actual_rate, AIn, AInScanner, AOut, Capabilities, Dio, DioConfigItem, ErrorCode, Hat, HatId, ScanOptions, ScanStatus};
+
+/// Produces the simulated input signal.
+pub trait SignalSource: Send {
+    /// The value of physical `channel` at `t` seconds after the source started, or `None` once the
+    /// source has run out of data.
+    fn sample(&mut self, channel: u8, t: f64) -> Option<f64>;
+}
+
+impl<F: FnMut(u8, f64) -> f64 + Send> SignalSource for F {
+    fn sample(&mut self, channel: u8, t: f64) -> Option<f64> {
+        Some(self(channel, t))
+    }
+}
+
+/// Plays back recorded data, one track per physical channel. Channels without a track read 0.
+pub struct Replay {
+    tracks: Vec<Vec<f64>>,
+    sample_rate: f64,
+    looping: bool,
+}
+
+impl Replay {
+    /// `tracks[n]` is played on channel `n` at `sample_rate`. Scans at a different rate pick the
+    /// nearest recorded sample.
+    pub fn new(tracks: Vec<Vec<f64>>, sample_rate: f64) -> Replay {
+        Replay { tracks, sample_rate, looping: false }
+    }
+
+    /// Starts over at the beginning instead of ending when the data runs out.
+    pub fn looping(mut self, looping: bool) -> Replay {
+        self.looping = looping;
+        self
+    }
+
+    /// Replays a WAV file, e.g. one written by [`crate::wav::WavWriter`]. `full_scale` is the value
+    /// that a full-scale WAV sample represents.
+    pub fn from_wav(path: impl AsRef<std::path::Path>, full_scale: f64) -> std::io::Result<Replay> {
+        let wav = crate::wav::read_wav(path)?;
+        let tracks = wav.channels.into_iter().map(|ch| ch.into_iter().map(|v| v * full_scale).collect()).collect();
+        Ok(Replay::new(tracks, wav.sample_rate as f64))
+    }
+
+    fn len(&self) -> usize {
+        self.tracks.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+impl SignalSource for Replay {
+    fn sample(&mut self, channel: u8, t: f64) -> Option<f64> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        let mut index = (t * self.sample_rate).round() as usize;
+        if index >= len {
+            if !self.looping {
+                return None;
+            }
+            index %= len;
+        }
+        let track = match self.tracks.get(channel as usize) {
+            Some(track) => track,
+            None => return Some(0.0),
+        };
+        Some(track.get(index).copied().unwrap_or(0.0))
+    }
+}
+
+struct SimScan {
+    channels: Vec<u8>,
+    sample_rate: f64,
+    start: Instant,
+    /// 0 for continuous scans.
+    samples_per_channel: u32,
+    /// Samples per channel generated so far.
+    generated: u64,
+    /// Samples per channel handed out by `a_in_scan_read`.
+    read: u64,
+    /// Generated samples not yet read, interleaved.
+    pending: Vec<f64>,
+    buffer_size: u32,
+    source_ended: bool,
+    stopped: bool,
+    overrun: bool,
+}
+
+impl SimScan {
+    fn running(&self) -> bool {
+        let complete = self.samples_per_channel != 0 && self.generated >= self.samples_per_channel as u64;
+        !(self.stopped || self.overrun || self.source_ended || complete)
+    }
+
+    // generates every sample that the hardware would have acquired by now
+    fn catch_up(&mut self, source: &mut dyn SignalSource) {
+        if !self.running() {
+            return;
+        }
+
+        let mut due = (self.start.elapsed().as_secs_f64() * self.sample_rate) as u64;
+        if self.samples_per_channel != 0 {
+            due = due.min(self.samples_per_channel as u64);
+        }
+        // the real library loses data once the buffer is full; only generate up to the overrun
+        let capacity = self.read + self.buffer_size as u64;
+        if due > capacity {
+            self.overrun = true;
+            due = capacity;
+        }
+
+        'samples: while self.generated < due {
+            let t = self.generated as f64 / self.sample_rate;
+            let mut frame = Vec::with_capacity(self.channels.len());
+            for &ch in &self.channels {
+                match source.sample(ch, t) {
+                    Some(value) => frame.push(value),
+                    None => {
+                        self.source_ended = true;
+                        break 'samples;
+                    }
+                }
+            }
+            self.pending.extend(frame);
+            self.generated += 1;
+        }
+    }
+
+    fn status(&self) -> ScanStatus {
+        let mut status = ScanStatus::TRIGGERED;
+        if self.running() {
+            status |= ScanStatus::RUNNING;
+        }
+        if self.overrun {
+            status |= ScanStatus::BUFFER_OVERRUN;
+        }
+        status
+    }
+
+    fn available(&self) -> u64 {
+        self.generated - self.read
+    }
+}
+
+/// The levels applied to a simulated board's digital I/O pins from outside, one bit per pin.
+/// Pins configured as outputs ignore them. Clones share the same levels, so a test can keep one
+/// to drive the inputs after handing the board off. All pins start high, as if pulled up.
+#[derive(Clone, Debug)]
+pub struct DioInputs(Arc<AtomicU8>);
+
+impl DioInputs {
+    pub fn set_bit(&self, bit: u8, value: bool) {
+        if value {
+            self.0.fetch_or(1 << bit, Ordering::Relaxed);
+        } else {
+            self.0.fetch_and(!(1 << bit), Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_port(&self, value: u8) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn port(&self) -> u8 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for DioInputs {
+    fn default() -> Self {
+        DioInputs(Arc::new(AtomicU8::new(0xff)))
+    }
+}
+
+struct SimDio {
+    // indexed by `DioConfigItem as usize`
+    config: [u8; 7],
+    outputs: u8,
+    inputs: DioInputs,
+    /// Pin levels at the last input read, for the interrupt status.
+    last_read: u8,
+}
+
+impl SimDio {
+    fn new(inputs: DioInputs) -> SimDio {
+        let mut dio = SimDio { config: [0; 7], outputs: 0, inputs, last_read: 0 };
+        dio.reset();
+        dio.last_read = dio.levels();
+        dio
+    }
+
+    // the library's power-on defaults: all inputs, pulled up, interrupts masked
+    fn reset(&mut self) {
+        self.config = [0; 7];
+        for item in [DioConfigItem::Direction, DioConfigItem::PullConfig, DioConfigItem::PullEnable, DioConfigItem::IntMask] {
+            self.config[item as usize] = 0xff;
+        }
+        self.outputs = 0xff;
+    }
+
+    fn levels(&self) -> u8 {
+        let direction = self.config[DioConfigItem::Direction as usize];
+        let levels = (self.inputs.port() & direction) | (self.outputs & !direction);
+        levels ^ self.config[DioConfigItem::InputInvert as usize]
+    }
+
+    fn read(&mut self) -> u8 {
+        let levels = self.levels();
+        self.last_read = levels;
+        levels
+    }
+
+    fn int_status(&self) -> u8 {
+        (self.levels() ^ self.last_read) & !self.config[DioConfigItem::IntMask as usize]
+    }
+}
+
+/// A board that exists only in software. It reports itself as the board type given to
+/// [`SimulatedHat::new`] and is limited by that type's [`Capabilities`].
+///
+/// Scans generate samples in real time from the wall clock, so a simulated scan runs at the same
+/// pace as a real one and overruns the same way if it isn't read quickly enough. Samples are
+/// always returned as given by the source; the scaling and calibration options are ignored.
+pub struct SimulatedHat {
+    address: u8,
+    id: HatId,
+    serial: String,
+    source: Box<dyn SignalSource>,
+    created: Instant,
+    scan: Option<SimScan>,
+    analog_outputs: Vec<f64>,
+    dio: SimDio,
+}
+
+impl SimulatedHat {
+    /// Fails with [`ErrorCode::InvalidDevice`] for [`HatId::ANY`] and bootloader IDs.
+    pub fn new(id: HatId, address: u8, source: impl SignalSource + 'static) -> Result<SimulatedHat, ErrorCode> {
+        let caps = Capabilities::of(id).ok_or(ErrorCode::InvalidDevice)?;
+
+        Ok(SimulatedHat {
+            address,
+            id,
+            serial: format!("SIM{:05}", address),
+            source: Box::new(source),
+            created: Instant::now(),
+            scan: None,
+            analog_outputs: vec![0.0; caps.ao_channels as usize],
+            dio: SimDio::new(DioInputs::default()),
+        })
+    }
+
+    pub fn with_serial(mut self, serial: &str) -> SimulatedHat {
+        self.serial = serial.to_string();
+        self
+    }
+
+    /// A handle for setting the levels on the digital I/O pins.
+    pub fn dio_inputs(&self) -> DioInputs {
+        self.dio.inputs.clone()
+    }
+
+    /// The last value written to each analog output, in volts.
+    pub fn analog_outputs(&self) -> &[f64] {
+        &self.analog_outputs
+    }
+
+    fn scan_mut(&mut self) -> Result<&mut SimScan, ErrorCode> {
+        let scan = self.scan.as_mut().ok_or(ErrorCode::ResourceUnavail)?;
+        scan.catch_up(self.source.as_mut());
+        Ok(scan)
+    }
+}
+
+impl Hat for SimulatedHat {
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn id(&self) -> HatId {
+        self.id
+    }
+
+    fn serial(&self) -> Result<String, ErrorCode> {
+        Ok(self.serial.clone())
+    }
+
+    fn blink_led(&mut self, _count: u8) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+impl AIn for SimulatedHat {
+    /// Fails with [`ErrorCode::ResourceUnavail`] once the source has run out of data.
+    fn a_in_read(&mut self, channel: u8, _options: ScanOptions) -> Result<f64, ErrorCode> {
+        if channel >= self.capabilities().ai_channels {
+            return Err(ErrorCode::BadParameter);
+        }
+        let t = self.created.elapsed().as_secs_f64();
+        self.source.sample(channel, t).ok_or(ErrorCode::ResourceUnavail)
+    }
+}
+
+impl AOut for SimulatedHat {
+    /// Fails with [`ErrorCode::InvalidDevice`] on board types without analog outputs, and with
+    /// [`ErrorCode::BadParameter`] for values outside the output range.
+    fn a_out_write(&mut self, channel: u8, options: ScanOptions, value: f64) -> Result<(), ErrorCode> {
+        let caps = self.capabilities();
+        let range = caps.ao_range.ok_or(ErrorCode::InvalidDevice)?;
+        if channel >= caps.ao_channels {
+            return Err(ErrorCode::BadParameter);
+        }
+
+        let volts = if options.contains(ScanOptions::NOSCALEDATA) {
+            let max_code = ((1u32 << caps.ao_resolution_bits) - 1) as f64;
+            if !(0.0..=max_code).contains(&value) {
+                return Err(ErrorCode::BadParameter);
+            }
+            range.min + value.round() / max_code * (range.max - range.min)
+        } else {
+            if !(range.min..=range.max).contains(&value) {
+                return Err(ErrorCode::BadParameter);
+            }
+            value
+        };
+        self.analog_outputs[channel as usize] = volts;
+        Ok(())
+    }
+
+    fn a_out_write_all(&mut self, options: ScanOptions, values: &[f64]) -> Result<(), ErrorCode> {
+        if values.len() != self.analog_outputs.len() {
+            return Err(ErrorCode::BadParameter);
+        }
+        // validate everything before changing anything, as the outputs update together
+        let before = self.analog_outputs.clone();
+        for (channel, &value) in values.iter().enumerate() {
+            if let Err(err) = self.a_out_write(channel as u8, options, value) {
+                self.analog_outputs = before;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SimulatedHat {
+    fn dio_bit(&self, channel: u8) -> Result<u8, ErrorCode> {
+        let dio_channels = self.capabilities().dio_channels;
+        if dio_channels == 0 {
+            return Err(ErrorCode::InvalidDevice);
+        }
+        if channel >= dio_channels {
+            return Err(ErrorCode::BadParameter);
+        }
+        Ok(1 << channel)
+    }
+
+    fn dio_port(&self) -> Result<&SimDio, ErrorCode> {
+        if self.capabilities().dio_channels == 0 {
+            return Err(ErrorCode::InvalidDevice);
+        }
+        Ok(&self.dio)
+    }
+}
+
+/// Every method fails with [`ErrorCode::InvalidDevice`] on board types without digital I/O.
+impl Dio for SimulatedHat {
+    fn dio_reset(&mut self) -> Result<(), ErrorCode> {
+        self.dio_port()?;
+        self.dio.reset();
+        Ok(())
+    }
+
+    fn dio_input_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode> {
+        let bit = self.dio_bit(channel)?;
+        Ok(self.dio.read() & bit != 0)
+    }
+
+    fn dio_input_read_port(&mut self) -> Result<u8, ErrorCode> {
+        self.dio_port()?;
+        Ok(self.dio.read())
+    }
+
+    fn dio_output_write_bit(&mut self, channel: u8, value: bool) -> Result<(), ErrorCode> {
+        let bit = self.dio_bit(channel)?;
+        if value {
+            self.dio.outputs |= bit;
+        } else {
+            self.dio.outputs &= !bit;
+        }
+        Ok(())
+    }
+
+    fn dio_output_write_port(&mut self, value: u8) -> Result<(), ErrorCode> {
+        self.dio_port()?;
+        self.dio.outputs = value;
+        Ok(())
+    }
+
+    fn dio_output_read_bit(&self, channel: u8) -> Result<bool, ErrorCode> {
+        let bit = self.dio_bit(channel)?;
+        Ok(self.dio.outputs & bit != 0)
+    }
+
+    fn dio_output_read_port(&self) -> Result<u8, ErrorCode> {
+        Ok(self.dio_port()?.outputs)
+    }
+
+    fn dio_int_status_read_bit(&mut self, channel: u8) -> Result<bool, ErrorCode> {
+        let bit = self.dio_bit(channel)?;
+        Ok(self.dio.int_status() & bit != 0)
+    }
+
+    fn dio_int_status_read_port(&mut self) -> Result<u8, ErrorCode> {
+        Ok(self.dio_port()?.int_status())
+    }
+
+    fn dio_config_write_bit(&mut self, channel: u8, item: DioConfigItem, value: u8) -> Result<(), ErrorCode> {
+        let bit = self.dio_bit(channel)?;
+        // the output type can only be set for the whole port
+        if item == DioConfigItem::OutputType {
+            return Err(ErrorCode::BadParameter);
+        }
+        let config = &mut self.dio.config[item as usize];
+        if value != 0 {
+            *config |= bit;
+        } else {
+            *config &= !bit;
+        }
+        Ok(())
+    }
+
+    fn dio_config_write_port(&mut self, item: DioConfigItem, value: u8) -> Result<(), ErrorCode> {
+        self.dio_port()?;
+        self.dio.config[item as usize] = value;
+        Ok(())
+    }
+
+    fn dio_config_read_bit(&self, channel: u8, item: DioConfigItem) -> Result<u8, ErrorCode> {
+        let bit = self.dio_bit(channel)?;
+        Ok((self.dio.config[item as usize] & bit != 0) as u8)
+    }
+
+    fn dio_config_read_port(&self, item: DioConfigItem) -> Result<u8, ErrorCode> {
+        Ok(self.dio_port()?.config[item as usize])
+    }
+}
+
+impl AInScanner for SimulatedHat {
+    fn a_in_scan_actual_rate(&self, channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+        if channel_count == 0 || channel_count > self.capabilities().ai_channels {
+            return Err(ErrorCode::BadParameter);
+        }
+        match self.id {
+            HatId::Mcc172 => mcc172_actual_rate(sample_rate_per_channel),
+            _ => Ok(sample_rate_per_channel.min(self.capabilities().max_scan_rate(channel_count))),
+        }
+    }
+
+    fn a_in_scan_start(&mut self, channel_mask: u8, samples_per_channel: u32, sample_rate_per_channel: f64, options: ScanOptions) -> Result<(), ErrorCode> {
+        if self.scan.is_some() {
+            return Err(ErrorCode::Busy);
+        }
+        let external_clock = options.contains(ScanOptions::EXTCLOCK);
+        let external_trigger = options.contains(ScanOptions::EXTTRIGGER);
+        self.capabilities()
+            .validate_scan(channel_mask, sample_rate_per_channel, external_clock, external_trigger)
+            .map_err(|_| ErrorCode::BadParameter)?;
+
+        let channels = (0..8).filter(|ch| channel_mask & (1 << ch) != 0).collect::<Vec<u8>>();
+        let sample_rate = self.a_in_scan_actual_rate(channels.len() as u8, sample_rate_per_channel)?;
+
+        // like the library: one second of data for continuous scans (at least 1000 samples per channel)
+        let buffer_size = if samples_per_channel == 0 || options.contains(ScanOptions::CONTINUOUS) {
+            (sample_rate as u32).max(1000).max(samples_per_channel)
+        } else {
+            samples_per_channel
+        };
+
+        self.scan = Some(SimScan {
+            channels,
+            sample_rate,
+            start: Instant::now(),
+            samples_per_channel: if options.contains(ScanOptions::CONTINUOUS) { 0 } else { samples_per_channel },
+            generated: 0,
+            read: 0,
+            pending: Vec::new(),
+            buffer_size,
+            source_ended: false,
+            stopped: false,
+            overrun: false,
+        });
+        Ok(())
+    }
+
+    fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode> {
+        let scan = self.scan.as_ref().ok_or(ErrorCode::ResourceUnavail)?;
+        Ok(scan.buffer_size * scan.channels.len() as u32)
+    }
+
+    fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode> {
+        // status is read-only here, so samples acquired since the last read aren't generated yet
+        let scan = self.scan.as_ref().ok_or(ErrorCode::ResourceUnavail)?;
+        let mut due = (scan.start.elapsed().as_secs_f64() * scan.sample_rate) as u64;
+        if scan.samples_per_channel != 0 {
+            due = due.min(scan.samples_per_channel as u64);
+        }
+        let available = if scan.running() { due.max(scan.generated) - scan.read } else { scan.available() };
+        Ok((scan.status(), available.min(u32::MAX as u64) as u32))
+    }
+
+    fn a_in_scan_read(&mut self, samples_per_channel: i32, timeout_s: f64, buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode> {
+        let deadline = if timeout_s < 0.0 { None } else { Some(Instant::now() + Duration::from_secs_f64(timeout_s)) };
+
+        let scan = self.scan_mut()?;
+        let channel_count = scan.channels.len();
+        let capacity = buffer.len() / channel_count;
+
+        // -1 reads everything available, or for finite scans waits for the rest of the scan
+        let wanted = if samples_per_channel < 0 {
+            let all = if scan.samples_per_channel != 0 { scan.samples_per_channel as u64 - scan.read } else { scan.available() };
+            all.min(capacity as u64)
+        } else if samples_per_channel as usize > capacity {
+            return Err(ErrorCode::BadParameter);
+        } else {
+            samples_per_channel as u64
+        };
+
+        let mut timed_out = false;
+        loop {
+            let scan = self.scan_mut()?;
+            if scan.available() >= wanted || !scan.running() {
+                break;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out = true;
+                break;
+            }
+            let missing = (wanted - scan.available()) as f64 / scan.sample_rate;
+            std::thread::sleep(Duration::from_secs_f64(missing.clamp(0.0005, 0.01)));
+        }
+
+        if timed_out && timeout_s > 0.0 {
+            return Err(ErrorCode::Timeout);
+        }
+
+        let scan = self.scan_mut()?;
+        let count = scan.available().min(wanted) as usize;
+        let values = count * channel_count;
+        buffer[..values].copy_from_slice(&scan.pending[..values]);
+        scan.pending.drain(..values);
+        scan.read += count as u64;
+        Ok((scan.status(), count as u32))
+    }
+
+    fn a_in_scan_channel_count(&self) -> u8 {
+        self.scan.as_ref().map(|scan| scan.channels.len() as u8).unwrap_or(0)
+    }
+
+    fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode> {
+        if let Some(scan) = self.scan.as_mut() {
+            scan.catch_up(self.source.as_mut());
+            scan.stopped = true;
+        }
+        Ok(())
+    }
+
+    fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode> {
+        self.scan = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::stream::{ScanBlock, StreamInfo};
+    use crate::wav::{WavOptions, WavWriter};
+
+    /// Samples of channel 0 of `source` at `rate`, from sample 0.
+    fn samples(source: &mut dyn SignalSource, rate: f64, count: usize) -> Vec<Option<f64>> {
+        (0..count).map(|i| source.sample(0, i as f64 / rate)).collect()
+    }
+
+    #[test]
+    fn finite_scans_deliver_every_sample() {
+        let mut dev = SimulatedHat::new(HatId::Mcc118, 0, |ch: u8, t: f64| ch as f64 * 10.0 + (t * 1000.0).round()).unwrap();
+        dev.a_in_scan_start(0b11, 20, 1000.0, ScanOptions::DEFAULT).unwrap();
+        assert_eq!(dev.a_in_scan_start(0b1, 20, 1000.0, ScanOptions::DEFAULT), Err(ErrorCode::Busy));
+        assert_eq!(dev.a_in_scan_buffer_size(), Ok(40));
+
+        let mut buffer = [0.0; 40];
+        let (status, read) = dev.a_in_scan_read(-1, 5.0, &mut buffer).unwrap();
+        assert_eq!(read, 20);
+        assert!(!status.contains(ScanStatus::RUNNING));
+        let expected: Vec<f64> = (0..20).flat_map(|i| [i as f64, 10.0 + i as f64]).collect();
+        assert_eq!(buffer[..], expected[..]);
+        // asking for more than fits is rejected rather than truncated
+        assert!(matches!(dev.a_in_scan_read(21, 0.0, &mut buffer), Err(ErrorCode::BadParameter)));
+
+        dev.a_in_scan_cleanup().unwrap();
+        assert!(matches!(dev.a_in_scan_read(1, 0.0, &mut buffer), Err(ErrorCode::ResourceUnavail)));
+    }
+
+    #[test]
+    fn slow_readers_overrun() {
+        let mut dev = SimulatedHat::new(HatId::Mcc118, 0, |_, _| 1.0).unwrap();
+        // continuous scans buffer one second, here 1000 samples
+        dev.a_in_scan_start(0b1, 0, 1000.0, ScanOptions::CONTINUOUS).unwrap();
+        assert_eq!(dev.a_in_scan_buffer_size(), Ok(1000));
+        std::thread::sleep(Duration::from_millis(1100));
+
+        let (status, available) = dev.a_in_scan_status().unwrap();
+        assert!(available >= 1000);
+        let mut buffer = vec![0.0; 2000];
+        let (status_read, read) = dev.a_in_scan_read(-1, 0.0, &mut buffer).unwrap();
+        assert!(status_read.contains(ScanStatus::BUFFER_OVERRUN), "status {:#x} then {:#x}", status.bits(), status_read.bits());
+        assert!(!status_read.contains(ScanStatus::RUNNING));
+        // what was buffered before the overrun is still there
+        assert_eq!(read, 1000);
+    }
+
+    #[test]
+    fn replays_end_or_loop() {
+        let tracks = vec![vec![1.0, 2.0, 3.0], vec![-1.0]];
+        let mut once = Replay::new(tracks.clone(), 100.0);
+        assert_eq!(samples(&mut once, 100.0, 4), [Some(1.0), Some(2.0), Some(3.0), None]);
+        // the longest track sets the length; shorter tracks and missing channels read 0
+        assert_eq!(once.sample(1, 0.02), Some(0.0));
+        assert_eq!(once.sample(5, 0.0), Some(0.0));
+
+        let mut looping = Replay::new(tracks, 100.0).looping(true);
+        assert_eq!(samples(&mut looping, 100.0, 7), [Some(1.0), Some(2.0), Some(3.0), Some(1.0), Some(2.0), Some(3.0), Some(1.0)]);
+        // other rates pick the nearest recorded sample
+        assert_eq!(samples(&mut looping, 50.0, 3), [Some(1.0), Some(3.0), Some(2.0)]);
+
+        assert_eq!(Replay::new(Vec::new(), 100.0).looping(true).sample(0, 0.0), None);
+    }
+
+    #[test]
+    fn scans_of_a_replay_finish_with_the_data() {
+        let mut dev = SimulatedHat::new(HatId::Mcc172, 0, Replay::new(vec![vec![0.5; 100]], 51200.0)).unwrap();
+        dev.a_in_scan_start(0b1, 0, 51200.0, ScanOptions::CONTINUOUS).unwrap();
+        let mut buffer = vec![0.0; 1000];
+        let (status, read) = dev.a_in_scan_read(200, 1.0, &mut buffer).unwrap();
+        assert_eq!(read, 100);
+        assert!(!status.contains(ScanStatus::RUNNING));
+        assert!(buffer[..100].iter().all(|&v| v == 0.5));
+    }
+
+    #[test]
+    fn replays_read_wav_files() {
+        let info = StreamInfo::test(HatId::Mcc172, &[0, 1], 8000.0);
+        let path = std::env::temp_dir().join(format!("daqhats-replay-{}.wav", std::process::id()));
+        let opts = WavOptions { full_scale: Some(2.0), ..WavOptions::default() };
+        let mut writer = WavWriter::create(&path, &info, &opts).unwrap();
+        writer.write_block(&ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 2, data: vec![1.0, -2.0, 0.5, 0.25] }).unwrap();
+        writer.finish().unwrap();
+
+        let mut replay = Replay::from_wav(&path, 2.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.sample(0, 0.0), Some(1.0));
+        assert_eq!(replay.sample(1, 0.0), Some(-2.0));
+        assert_eq!(replay.sample(0, 1.0 / 8000.0), Some(0.5));
+        assert_eq!(replay.sample(1, 1.0 / 8000.0), Some(0.25));
+        assert_eq!(replay.sample(0, 2.0 / 8000.0), None);
+    }
+}
@@ -0,0 +1,378 @@
+//! Spectral analysis of captured blocks: amplitude spectra, Welch PSD, RMS, crest factor and peak
+//! frequency.
+//!
+//! Everything is computed against the stream's actual scan rate, and the results serialize with
+//! serde so summaries can be sent on instead of raw waveforms.
+
+use std::time::SystemTime;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+
+use crate::filter::Window;
+use crate::stream::{ScanBlock, StreamInfo};
+
+/// A single-sided amplitude spectrum. A sine of amplitude `A` at a bin's frequency shows up as `A`
+/// in that bin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Spectrum {
+    pub sample_rate: f64,
+    /// Width of each bin in Hz.
+    pub resolution_hz: f64,
+    pub window: Window,
+    /// Amplitudes of bins `0..=n/2`, bin `k` being at `k * resolution_hz`.
+    pub magnitudes: Vec<f64>,
+}
+
+impl Spectrum {
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.resolution_hz
+    }
+
+    /// Frequency and amplitude of the largest bin, ignoring DC.
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        peak_bin(&self.magnitudes).map(|bin| (self.frequency(bin), self.magnitudes[bin]))
+    }
+}
+
+/// Windowed FFT amplitude spectrum of `samples`. The spectrum of no samples has no bins.
+pub fn amplitude_spectrum(samples: &[f64], sample_rate: f64, window: Window) -> Spectrum {
+    let n = samples.len();
+    if n == 0 {
+        return Spectrum { sample_rate, resolution_hz: 0.0, window, magnitudes: Vec::new() };
+    }
+    let weights = window_weights(window, n);
+    let gain: f64 = weights.iter().sum();
+
+    let spectrum = fft(samples.iter().zip(&weights).map(|(x, w)| x * w));
+    let magnitudes = spectrum[..=n / 2]
+        .iter()
+        .enumerate()
+        .map(|(k, c)| {
+            // single-sided: everything but DC and Nyquist appears twice in the full spectrum
+            let scale = if k == 0 || 2 * k == n { 1.0 } else { 2.0 };
+            if gain == 0.0 { 0.0 } else { scale * c.norm() / gain }
+        })
+        .collect();
+
+    Spectrum { sample_rate, resolution_hz: resolution(sample_rate, n), window, magnitudes }
+}
+
+/// A single-sided power spectral density estimated with Welch's method.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Psd {
+    pub sample_rate: f64,
+    pub resolution_hz: f64,
+    pub window: Window,
+    pub segment_len: usize,
+    /// Number of segments averaged.
+    pub segments: usize,
+    /// Density of bins `0..=segment_len/2` in units²/Hz.
+    pub density: Vec<f64>,
+}
+
+impl Psd {
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.resolution_hz
+    }
+
+    /// Frequency and density of the largest bin, ignoring DC.
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        peak_bin(&self.density).map(|bin| (self.frequency(bin), self.density[bin]))
+    }
+
+    /// RMS of the signal content between `low_hz` and `high_hz`, inclusive of the bins at the edges.
+    pub fn band_rms(&self, low_hz: f64, high_hz: f64) -> f64 {
+        let power: f64 = self
+            .density
+            .iter()
+            .enumerate()
+            .filter(|&(bin, _)| (low_hz..=high_hz).contains(&self.frequency(bin)))
+            .map(|(_, density)| density * self.resolution_hz)
+            .sum();
+        power.sqrt()
+    }
+
+    /// RMS of everything but DC.
+    pub fn ac_rms(&self) -> f64 {
+        self.band_rms(self.resolution_hz / 2.0, f64::INFINITY)
+    }
+}
+
+/// Welch PSD: `samples` is split into segments of `segment_len` overlapping by `overlap` (a
+/// fraction below 1), each segment has its mean removed and is windowed, and the periodograms are
+/// averaged. Returns `None` if there are fewer samples than one segment or `sample_rate` isn't a
+/// positive number, e.g. for streams of on-demand reads.
+pub fn welch_psd(samples: &[f64], sample_rate: f64, segment_len: usize, overlap: f64, window: Window) -> Option<Psd> {
+    if segment_len < 2 || samples.len() < segment_len || !(sample_rate.is_finite() && sample_rate > 0.0) {
+        return None;
+    }
+    let step = ((segment_len as f64 * (1.0 - overlap.clamp(0.0, 0.95))).round() as usize).max(1);
+    let weights = window_weights(window, segment_len);
+    let power_gain: f64 = weights.iter().map(|w| w * w).sum();
+
+    let mut fft_plan = FftPlanner::new();
+    let plan = fft_plan.plan_fft_forward(segment_len);
+    let mut density = vec![0.0; segment_len / 2 + 1];
+    let mut segments = 0;
+    let mut buf = vec![Complex::new(0.0, 0.0); segment_len];
+
+    for start in (0..=samples.len() - segment_len).step_by(step) {
+        let segment = &samples[start..start + segment_len];
+        let mean = segment.iter().sum::<f64>() / segment_len as f64;
+        for ((c, x), w) in buf.iter_mut().zip(segment).zip(&weights) {
+            *c = Complex::new((x - mean) * w, 0.0);
+        }
+        plan.process(&mut buf);
+
+        for (k, d) in density.iter_mut().enumerate() {
+            let scale = if k == 0 || 2 * k == segment_len { 1.0 } else { 2.0 };
+            *d += scale * buf[k].norm_sqr() / (sample_rate * power_gain);
+        }
+        segments += 1;
+    }
+    density.iter_mut().for_each(|d| *d /= segments as f64);
+
+    Some(Psd { sample_rate, resolution_hz: resolution(sample_rate, segment_len), window, segment_len, segments, density })
+}
+
+/// Root mean square, including any DC offset.
+pub fn rms(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|x| x * x).sum::<f64>() / samples.len() as f64).sqrt()
+}
+
+/// Peak absolute value divided by RMS; about 1.414 for a sine.
+pub fn crest_factor(samples: &[f64]) -> f64 {
+    let rms = rms(samples);
+    if rms == 0.0 {
+        return 0.0;
+    }
+    samples.iter().fold(0.0f64, |peak, x| peak.max(x.abs())) / rms
+}
+
+/// What to compute in [`analyze`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisConfig {
+    pub window: Window,
+    /// Welch segment length; shorter segments average more but resolve frequency more coarsely.
+    pub segment_len: usize,
+    pub overlap: f64,
+    /// `(low_hz, high_hz)` bands to report the RMS of.
+    pub bands: Vec<(f64, f64)>,
+    /// Include the full PSD in the result rather than just the derived values.
+    pub include_psd: bool,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig { window: Window::Hann, segment_len: 1024, overlap: 0.5, bands: Vec::new(), include_psd: false }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BandRms {
+    pub low_hz: f64,
+    pub high_hz: f64,
+    pub rms: f64,
+}
+
+/// Analysis results for one channel of a block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelAnalysis {
+    pub channel: u8,
+    pub units: String,
+    pub samples: usize,
+    pub mean: f64,
+    /// Time-domain RMS including DC.
+    pub rms: f64,
+    /// RMS with DC removed, from the PSD.
+    pub ac_rms: Option<f64>,
+    pub peak: f64,
+    pub crest_factor: f64,
+    pub peak_frequency_hz: Option<f64>,
+    pub bands: Vec<BandRms>,
+    pub psd: Option<Psd>,
+}
+
+/// Analysis results for every channel of a block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockAnalysis {
+    pub serial: String,
+    pub address: u8,
+    pub timestamp: SystemTime,
+    pub sample_rate: f64,
+    pub channels: Vec<ChannelAnalysis>,
+}
+
+/// Analyzes each channel of `block`. Frequency-domain values are `None`/empty if the block has
+/// fewer samples per channel than `config.segment_len` or the stream has no fixed sample rate.
+pub fn analyze(info: &StreamInfo, block: &ScanBlock, config: &AnalysisConfig) -> BlockAnalysis {
+    let channels = info
+        .channels
+        .iter()
+        .zip(&info.units)
+        .enumerate()
+        .map(|(index, (&channel, units))| {
+            let samples = block.channel(index).collect::<Vec<_>>();
+            let psd = welch_psd(&samples, info.sample_rate, config.segment_len, config.overlap, config.window);
+            let bands = match &psd {
+                Some(psd) => config.bands.iter().map(|&(low_hz, high_hz)| BandRms { low_hz, high_hz, rms: psd.band_rms(low_hz, high_hz) }).collect(),
+                None => Vec::new(),
+            };
+
+            ChannelAnalysis {
+                channel,
+                units: units.clone(),
+                samples: samples.len(),
+                mean: if samples.is_empty() { 0.0 } else { samples.iter().sum::<f64>() / samples.len() as f64 },
+                rms: rms(&samples),
+                ac_rms: psd.as_ref().map(Psd::ac_rms),
+                peak: samples.iter().fold(0.0f64, |peak, x| peak.max(x.abs())),
+                crest_factor: crest_factor(&samples),
+                peak_frequency_hz: psd.as_ref().and_then(Psd::peak).map(|(freq, _)| freq),
+                bands,
+                psd: if config.include_psd { psd } else { None },
+            }
+        })
+        .collect();
+
+    BlockAnalysis {
+        serial: info.serial.clone(),
+        address: info.address,
+        timestamp: block.timestamp,
+        sample_rate: info.sample_rate,
+        channels,
+    }
+}
+
+fn fft(samples: impl Iterator<Item = f64>) -> Vec<Complex<f64>> {
+    let mut buf = samples.map(|x| Complex::new(x, 0.0)).collect::<Vec<_>>();
+    if !buf.is_empty() {
+        FftPlanner::new().plan_fft_forward(buf.len()).process(&mut buf);
+    }
+    buf
+}
+
+fn window_weights(window: Window, len: usize) -> Vec<f64> {
+    // periodic windows, as is usual for spectral analysis
+    (0..len).map(|n| window.coefficient(n, len + 1)).collect()
+}
+
+fn resolution(sample_rate: f64, len: usize) -> f64 {
+    if len == 0 {
+        0.0
+    } else {
+        sample_rate / len as f64
+    }
+}
+
+fn peak_bin(values: &[f64]) -> Option<usize> {
+    values
+        .iter()
+        .enumerate()
+        .skip(1)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(bin, _)| bin)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::core::HatId;
+
+    fn sine(amplitude: f64, freq_hz: f64, sample_rate: f64, len: usize) -> Vec<f64> {
+        (0..len).map(|i| amplitude * (2.0 * PI * freq_hz * i as f64 / sample_rate).sin()).collect()
+    }
+
+    /// Deterministic uniform noise in -1..1.
+    fn noise(len: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sine_amplitude_at_a_bin_centre() {
+        let mut samples = sine(2.5, 50.0, 1000.0, 1000);
+        samples.iter_mut().for_each(|x| *x += 0.75);
+        for window in [Window::Rectangular, Window::Hann, Window::Hamming, Window::Blackman] {
+            let spectrum = amplitude_spectrum(&samples, 1000.0, window);
+            assert_eq!(spectrum.magnitudes.len(), 501);
+            assert_eq!(spectrum.resolution_hz, 1.0);
+            assert!((spectrum.magnitudes[50] - 2.5).abs() < 1e-9, "{:?}: {}", window, spectrum.magnitudes[50]);
+            assert!((spectrum.magnitudes[0] - 0.75).abs() < 1e-9, "{:?}: {}", window, spectrum.magnitudes[0]);
+            let (freq, amplitude) = spectrum.peak().unwrap();
+            assert_eq!(freq, 50.0);
+            assert!((amplitude - 2.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn empty_spectrum() {
+        let spectrum = amplitude_spectrum(&[], 1000.0, Window::Hann);
+        assert!(spectrum.magnitudes.is_empty());
+        assert_eq!(spectrum.peak(), None);
+    }
+
+    #[test]
+    fn welch_satisfies_parseval() {
+        let samples = noise(4096);
+        let (segment_len, step) = (256, 128);
+        let psd = welch_psd(&samples, 2000.0, segment_len, 0.5, Window::Hann).unwrap();
+        assert_eq!(psd.segments, (4096 - segment_len) / step + 1);
+
+        // the PSD integrates to the mean windowed power of the detrended segments
+        let weights = window_weights(Window::Hann, segment_len);
+        let power_gain: f64 = weights.iter().map(|w| w * w).sum();
+        let expected = samples
+            .windows(segment_len)
+            .step_by(step)
+            .map(|segment| {
+                let mean = segment.iter().sum::<f64>() / segment_len as f64;
+                segment.iter().zip(&weights).map(|(x, w)| ((x - mean) * w).powi(2)).sum::<f64>() / power_gain
+            })
+            .sum::<f64>()
+            / psd.segments as f64;
+        let total: f64 = psd.density.iter().map(|d| d * psd.resolution_hz).sum();
+        assert!((total - expected).abs() < 1e-12 * expected.max(1.0), "{} != {}", total, expected);
+
+        // and for a sine, to its mean square
+        let psd = welch_psd(&sine(3.0, 125.0, 2000.0, 4096), 2000.0, segment_len, 0.5, Window::Hann).unwrap();
+        assert!((psd.ac_rms() - 3.0 / 2f64.sqrt()).abs() < 1e-9);
+        assert!((psd.band_rms(100.0, 150.0) - 3.0 / 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(psd.peak().unwrap().0, 125.0);
+    }
+
+    #[test]
+    fn welch_needs_a_sample_rate_and_a_segment() {
+        let samples = noise(512);
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(welch_psd(&samples, rate, 256, 0.5, Window::Hann), None, "{}", rate);
+        }
+        assert_eq!(welch_psd(&samples, 1000.0, 1024, 0.5, Window::Hann), None);
+        assert_eq!(welch_psd(&samples, 1000.0, 1, 0.5, Window::Hann), None);
+    }
+
+    #[test]
+    fn analyze_polled_streams() {
+        let info = StreamInfo { address: 1, serial: "X".to_string(), units: vec!["°C".to_string()], ..StreamInfo::test(HatId::Mcc134, &[2], 0.0) };
+        let block = ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 1, data: vec![-2.0, 2.0, -2.0, 2.0] };
+        let config = AnalysisConfig { segment_len: 2, bands: vec![(0.0, 10.0)], ..AnalysisConfig::default() };
+        let analysis = analyze(&info, &block, &config);
+        let channel = &analysis.channels[0];
+        assert_eq!((channel.mean, channel.rms, channel.peak, channel.crest_factor), (0.0, 2.0, 2.0, 1.0));
+        assert_eq!((channel.ac_rms, channel.peak_frequency_hz), (None, None));
+        assert!(channel.bands.is_empty());
+    }
+}
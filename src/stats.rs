@@ -0,0 +1,334 @@
+//! Running per-channel statistics, and summaries of a stream at a fixed interval.
+
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::ErrorCode;
+use crate::stream::{ScanBlock, StreamInfo};
+
+/// Count, min, max, mean, variance and RMS of a series, updated one value at a time with Welford's
+/// algorithm so long runs don't lose precision.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    // sum of squared differences from the mean
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for RunningStats {
+    fn default() -> Self {
+        RunningStats { count: 0, mean: 0.0, m2: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY }
+    }
+}
+
+impl RunningStats {
+    pub fn new() -> RunningStats {
+        RunningStats::default()
+    }
+
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    /// Combines with statistics of another part of the same series.
+    pub fn merge(&mut self, other: &RunningStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// `None` until a value has been pushed, as are the other statistics.
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Population variance.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    pub fn rms(&self) -> Option<f64> {
+        self.variance().map(|var| (var + self.mean * self.mean).sqrt())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSummary {
+    pub channel: u8,
+    pub units: String,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub rms: f64,
+}
+
+/// Statistics of every channel over one interval.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub serial: String,
+    pub address: u8,
+    /// Start of the interval.
+    pub start: SystemTime,
+    /// End of the interval; the next summary starts here.
+    pub end: SystemTime,
+    /// Channels without samples in the interval are left out.
+    pub channels: Vec<ChannelSummary>,
+}
+
+/// Accumulates a stream's samples and emits a [`Summary`] for each `interval` of sample time.
+pub struct Summarizer {
+    info: StreamInfo,
+    interval: Duration,
+    stats: Vec<RunningStats>,
+    window_start: Option<SystemTime>,
+}
+
+impl Summarizer {
+    pub fn new(info: &StreamInfo, interval: Duration) -> Summarizer {
+        assert!(!interval.is_zero(), "the summary interval must not be zero");
+        Summarizer { info: info.clone(), interval, stats: vec![RunningStats::new(); info.channel_count()], window_start: None }
+    }
+
+    /// The statistics of the current, unfinished interval.
+    pub fn current(&self) -> &[RunningStats] {
+        &self.stats
+    }
+
+    /// Adds a block and returns the summaries of the intervals it completed. Fails with
+    /// [`ErrorCode::BadParameter`] if the block doesn't have the stream's channel count.
+    pub fn process(&mut self, block: &ScanBlock) -> Result<Vec<Summary>, ErrorCode> {
+        if block.channel_count != self.stats.len() {
+            return Err(ErrorCode::BadParameter);
+        }
+        let mut summaries = Vec::new();
+
+        for (i, frame) in block.frames().enumerate() {
+            let t = block.sample_time(i, self.info.sample_rate);
+            let start = *self.window_start.get_or_insert(t);
+            if t >= start + self.interval {
+                let end = start + self.interval;
+                summaries.extend(self.summary(start, end));
+                // skip over intervals without any samples, e.g. after a pause in polling
+                let behind = t.duration_since(end).unwrap_or_default().as_secs_f64();
+                let skipped = (behind / self.interval.as_secs_f64()).floor() as u32;
+                self.window_start = Some(end + self.interval * skipped);
+                self.stats.iter_mut().for_each(|stats| *stats = RunningStats::new());
+            }
+
+            for (stats, &x) in self.stats.iter_mut().zip(frame) {
+                stats.push(x);
+            }
+        }
+
+        Ok(summaries)
+    }
+
+    /// Summarizes and resets the current, partial interval, e.g. when the stream stops.
+    pub fn flush(&mut self) -> Option<Summary> {
+        let start = self.window_start.take()?;
+        let summary = self.summary(start, start + self.interval);
+        self.stats.iter_mut().for_each(|stats| *stats = RunningStats::new());
+        summary
+    }
+
+    fn summary(&self, start: SystemTime, end: SystemTime) -> Option<Summary> {
+        let channels = self
+            .info
+            .channels
+            .iter()
+            .zip(&self.info.units)
+            .zip(&self.stats)
+            .filter(|(_, stats)| stats.count() > 0)
+            .map(|((&channel, units), stats)| ChannelSummary {
+                channel,
+                units: units.clone(),
+                count: stats.count(),
+                min: stats.min().unwrap_or_default(),
+                max: stats.max().unwrap_or_default(),
+                mean: stats.mean().unwrap_or_default(),
+                std_dev: stats.std_dev().unwrap_or_default(),
+                rms: stats.rms().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+
+        if channels.is_empty() {
+            return None;
+        }
+        Some(Summary { serial: self.info.serial.clone(), address: self.info.address, start, end, channels })
+    }
+}
+
+/// Writes summaries as CSV, one row per interval with `chN_min`, `chN_max`, `chN_mean`,
+/// `chN_std_dev`, `chN_rms` and `chN_count` columns for each channel.
+pub struct SummaryWriter<W: Write> {
+    inner: W,
+    channels: Vec<u8>,
+}
+
+impl<W: Write> SummaryWriter<W> {
+    pub fn new(mut inner: W, info: &StreamInfo) -> io::Result<Self> {
+        writeln!(inner, "# serial {} address {} units {}", info.serial, info.address, info.units.join(","))?;
+        let mut header = String::from("start_unix_s,end_unix_s");
+        for ch in &info.channels {
+            for stat in ["min", "max", "mean", "std_dev", "rms", "count"] {
+                header.push_str(&format!(",ch{}_{}", ch, stat));
+            }
+        }
+        writeln!(inner, "{}", header)?;
+        Ok(SummaryWriter { inner, channels: info.channels.clone() })
+    }
+
+    /// Channels missing from the summary are left empty.
+    pub fn write(&mut self, summary: &Summary) -> io::Result<()> {
+        let mut row = format!("{:.6},{:.6}", unix_seconds(summary.start), unix_seconds(summary.end));
+        for ch in &self.channels {
+            match summary.channels.iter().find(|s| s.channel == *ch) {
+                Some(s) => row.push_str(&format!(",{},{},{},{},{},{}", s.min, s.max, s.mean, s.std_dev, s.rms, s.count)),
+                None => row.push_str(",,,,,,"),
+            }
+        }
+        writeln!(self.inner, "{}", row)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+fn unix_seconds(t: SystemTime) -> f64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::HatId;
+
+    fn two_pass(values: &[f64]) -> (f64, f64) {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        (mean, variance)
+    }
+
+    fn series() -> Vec<f64> {
+        // a large offset with a small spread, where a naive sum of squares loses most digits
+        (0..10_000).map(|i| 1.0e6 + ((i * 7919) % 101) as f64 * 1.0e-3).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn welford_matches_two_pass() {
+        let values = series();
+        let mut stats = RunningStats::new();
+        values.iter().for_each(|&x| stats.push(x));
+
+        let (mean, variance) = two_pass(&values);
+        assert_eq!(stats.count(), 10_000);
+        assert_close(stats.mean().unwrap(), mean, 1e-8);
+        assert_close(stats.variance().unwrap(), variance, 1e-9 * variance);
+        assert_close(stats.std_dev().unwrap(), variance.sqrt(), 1e-9);
+        assert_close(stats.rms().unwrap(), (values.iter().map(|x| x * x).sum::<f64>() / values.len() as f64).sqrt(), 1e-6);
+        assert_eq!(stats.min(), Some(1.0e6));
+        assert_eq!(stats.max(), Some(1.0e6 + 0.1));
+    }
+
+    #[test]
+    fn merge_matches_one_pass() {
+        let values = series();
+        let mut whole = RunningStats::new();
+        values.iter().for_each(|&x| whole.push(x));
+
+        let mut merged = RunningStats::new();
+        for chunk in values.chunks(777) {
+            let mut part = RunningStats::new();
+            chunk.iter().for_each(|&x| part.push(x));
+            merged.merge(&part);
+        }
+        merged.merge(&RunningStats::new());
+        assert_eq!(merged.count(), whole.count());
+        assert_close(merged.mean().unwrap(), whole.mean().unwrap(), 1e-8);
+        assert_close(merged.variance().unwrap(), whole.variance().unwrap(), 1e-9 * whole.variance().unwrap());
+        assert_eq!((merged.min(), merged.max()), (whole.min(), whole.max()));
+    }
+
+    #[test]
+    fn empty_stats() {
+        let stats = RunningStats::new();
+        assert_eq!((stats.min(), stats.max(), stats.mean(), stats.variance(), stats.rms()), (None, None, None, None, None));
+
+        let mut single = RunningStats::new();
+        single.push(-3.0);
+        assert_eq!((single.mean(), single.variance(), single.rms()), (Some(-3.0), Some(0.0), Some(3.0)));
+    }
+
+    #[test]
+    fn summarizes_each_interval() {
+        let info = StreamInfo { address: 2, serial: "S".to_string(), ..StreamInfo::test(HatId::Mcc118, &[0, 3], 10.0) };
+        let mut summarizer = Summarizer::new(&info, Duration::from_secs(1));
+        // 2.5 s of samples in blocks of 7 frames, channel 3 = 2 × channel 0
+        let mut summaries = Vec::new();
+        for first in (0..25).step_by(7) {
+            let data = (first..(first + 7).min(25)).flat_map(|i| [i as f64, 2.0 * i as f64]).collect();
+            let timestamp = UNIX_EPOCH + Duration::from_millis(100 * first);
+            summaries.extend(summarizer.process(&ScanBlock { first_sample: first, timestamp, channel_count: 2, data }).unwrap());
+        }
+        summaries.extend(summarizer.flush());
+
+        assert_eq!(summaries.len(), 3);
+        let starts = summaries.iter().map(|s| s.start.duration_since(UNIX_EPOCH).unwrap().as_secs()).collect::<Vec<_>>();
+        assert_eq!(starts, [0, 1, 2]);
+        let second = &summaries[1].channels;
+        assert_eq!((second[0].count, second[0].min, second[0].max, second[0].mean), (10, 10.0, 19.0, 14.5));
+        assert_eq!((second[1].channel, second[1].mean), (3, 29.0));
+        assert_eq!(summaries[2].channels[0].count, 5);
+        assert_eq!(summarizer.flush(), None);
+
+        // a block from another stream is refused rather than mixed into the statistics
+        let block = ScanBlock { first_sample: 25, timestamp: UNIX_EPOCH, channel_count: 3, data: vec![1.0; 3] };
+        assert_eq!(summarizer.process(&block), Err(ErrorCode::BadParameter));
+        assert_eq!(summarizer.current()[0].count(), 0);
+    }
+}
@@ -0,0 +1,427 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{AInScanner, ErrorCode, Hat, HatId, ScanStatus};
+use crate::{Error, ScanOptions};
+
+/// Describes where the samples of a stream come from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StreamInfo {
+    pub id: HatId,
+    pub address: u8,
+    pub serial: String,
+    /// Physical channel numbers, in the order the samples are interleaved in each block.
+    pub channels: Vec<u8>,
+    /// Unit of each channel's samples, e.g. "V", "°C", or "code" for unscaled data.
+    pub units: Vec<String>,
+    /// Actual per-channel sample rate in S/s, or 0 for streams of on-demand reads.
+    pub sample_rate: f64,
+    pub scale_data: bool,
+    pub calibrate_data: bool,
+    /// The board's factory calibration date as "YYYY-MM-DD", if it has one.
+    pub calibration_date: Option<String>,
+    /// When the stream was started.
+    pub start_time: SystemTime,
+}
+
+impl StreamInfo {
+    /// Info for a stream of on-demand reads (e.g. `a_in_read` or `t_in_read` polls) of `channels`,
+    /// all in the same unit.
+    pub fn polled(dev: &dyn Hat, channels: &[u8], unit: &str) -> Result<StreamInfo, ErrorCode> {
+        Ok(StreamInfo {
+            id: dev.id(),
+            address: dev.address(),
+            serial: dev.serial()?,
+            channels: channels.to_vec(),
+            units: vec![unit.to_string(); channels.len()],
+            sample_rate: 0.0,
+            scale_data: true,
+            calibrate_data: true,
+            calibration_date: dev.calibration_date()?,
+            start_time: SystemTime::now(),
+        })
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+}
+
+#[cfg(test)]
+impl StreamInfo {
+    /// A scaled, calibrated stream of `channels` in volts from address 0, started at the epoch.
+    pub(crate) fn test(id: HatId, channels: &[u8], sample_rate: f64) -> StreamInfo {
+        StreamInfo {
+            id,
+            address: 0,
+            serial: String::new(),
+            channels: channels.to_vec(),
+            units: vec!["V".to_string(); channels.len()],
+            sample_rate,
+            scale_data: true,
+            calibrate_data: true,
+            calibration_date: None,
+            start_time: std::time::UNIX_EPOCH,
+        }
+    }
+}
+
+/// A block of interleaved samples from a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanBlock {
+    /// Per-channel index of the first sample in the block, counted from the start of the stream.
+    pub first_sample: u64,
+    /// Time of the first sample in the block.
+    pub timestamp: SystemTime,
+    pub channel_count: usize,
+    /// Samples interleaved by channel: `[ch_a[0], ch_b[0], ch_a[1], ch_b[1], ...]`.
+    pub data: Vec<f64>,
+}
+
+impl ScanBlock {
+    /// A block holding one sample per channel, e.g. from a round of polled reads.
+    pub fn from_frame(first_sample: u64, timestamp: SystemTime, values: Vec<f64>) -> ScanBlock {
+        ScanBlock { first_sample, timestamp, channel_count: values.len(), data: values }
+    }
+
+    pub fn samples_per_channel(&self) -> usize {
+        if self.channel_count == 0 {
+            return 0;
+        }
+        self.data.len() / self.channel_count
+    }
+
+    /// The samples of one channel, by position in the stream's channel list.
+    pub fn channel(&self, index: usize) -> impl Iterator<Item = f64> + '_ {
+        self.data.iter().skip(index).step_by(self.channel_count.max(1)).copied()
+    }
+
+    /// One slice of `channel_count` samples per sample time.
+    pub fn frames(&self) -> std::slice::ChunksExact<'_, f64> {
+        self.data.chunks_exact(self.channel_count.max(1))
+    }
+
+    /// Time of the `frame`th sample of the block at the given per-channel rate. For streams
+    /// without a fixed rate every sample in the block shares the block's timestamp.
+    pub fn sample_time(&self, frame: usize, sample_rate: f64) -> SystemTime {
+        if sample_rate > 0.0 {
+            self.timestamp + Duration::from_secs_f64(frame as f64 / sample_rate)
+        } else {
+            self.timestamp
+        }
+    }
+}
+
+/// Why a scan stream stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScanEnd {
+    /// [`ScanStream::stop`] was called or the stream was dropped.
+    Stopped,
+    HardwareOverrun,
+    BufferOverrun,
+    /// The device reported that the scan is no longer running.
+    Finished,
+    /// Reading from, stopping or cleaning up the scan failed.
+    Error(ErrorCode),
+}
+
+/// Stops a [`ScanStream`] (or a [`crate::control::ControlHandle`]) from another thread.
+#[derive(Clone, Debug)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub(crate) fn new() -> StopHandle {
+        StopHandle(Arc::new(AtomicBool::new(false)))
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A running continuous scan delivering [`ScanBlock`]s. See [`scan_blocks`].
+///
+/// Dropping the stream stops the scan and cleans up the device in the background; call
+/// [`ScanStream::stop`] to get the device back.
+pub struct ScanStream<T> {
+    info: StreamInfo,
+    blocks: mpsc::Receiver<ScanBlock>,
+    stop: StopHandle,
+    /// Only `None` once joined.
+    handle: Option<JoinHandle<(T, ScanEnd)>>,
+}
+
+impl<T> ScanStream<T> {
+    pub fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    /// Waits for the next block. Returns `None` once the scan has ended and all blocks are consumed.
+    pub fn recv(&self) -> Option<ScanBlock> {
+        self.blocks.recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ScanBlock, mpsc::RecvTimeoutError> {
+        self.blocks.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<ScanBlock, mpsc::TryRecvError> {
+        self.blocks.try_recv()
+    }
+
+    /// Blocks until the scan ends.
+    pub fn iter(&self) -> mpsc::Iter<'_, ScanBlock> {
+        self.blocks.iter()
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Stops the scan and returns the device along with why the scan ended. Blocks that were
+    /// not yet received are discarded.
+    pub fn stop(self) -> (T, ScanEnd) {
+        self.stop.stop();
+        self.join()
+    }
+
+    /// Waits for the scan to end on its own (or via a [`StopHandle`]) and returns the device.
+    pub fn join(mut self) -> (T, ScanEnd) {
+        // closes the channel
+        self.blocks = mpsc::channel().1;
+        let handle = self.handle.take().expect("the stream is only joined once");
+        handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+impl<T> Drop for ScanStream<T> {
+    fn drop(&mut self) {
+        // a scan waiting for a trigger or data never finds out that the receiver is gone
+        self.stop.stop();
+    }
+}
+
+/// Checks the request, collects the stream info and starts the scan.
+fn start_scan<T: AInScanner>(dev: &mut T, opts: &ScanOptions, samples_per_block: usize) -> Result<StreamInfo, Error> {
+    opts.validate_device(dev)?;
+    if samples_per_block == 0 {
+        return Err(ErrorCode::BadParameter.into());
+    }
+
+    let n_ch = opts.channel_count();
+    let sample_rate = if opts.external_clock {
+        opts.sample_rate_per_channel
+    } else {
+        dev.a_in_scan_actual_rate(n_ch as u8, opts.sample_rate_per_channel)?
+    };
+    let unit = if opts.scale_data { "V" } else { "code" };
+    let info = StreamInfo {
+        id: dev.id(),
+        address: dev.address(),
+        serial: dev.serial()?,
+        channels: opts.channels(),
+        units: vec![unit.to_string(); n_ch],
+        sample_rate,
+        scale_data: opts.scale_data,
+        calibrate_data: opts.calibrate_data,
+        calibration_date: dev.calibration_date()?,
+        start_time: SystemTime::now(),
+    };
+
+    dev.a_in_scan_start(opts.channel_mask, 0, opts.sample_rate_per_channel, opts.low_level_options())?;
+    Ok(info)
+}
+
+/// Starts a continuous scan and streams the data in blocks of up to `samples_per_block` samples
+/// per channel. Unlike [`crate::scan_channels`], blocks keep the channels interleaved and carry
+/// timestamps, and the stream records why it ended. If the scan can't be started, the device is
+/// returned along with the error so it can be retried or used elsewhere.
+pub fn scan_blocks<T: AInScanner + Send + 'static>(mut dev: T, opts: ScanOptions, samples_per_block: usize) -> Result<ScanStream<T>, (T, Error)> {
+    let info = match start_scan(&mut dev, &opts, samples_per_block) {
+        Ok(info) => info,
+        Err(err) => return Err((dev, err)),
+    };
+    let n_ch = opts.channel_count();
+    let sample_rate = info.sample_rate;
+
+    let (tx, rx) = mpsc::channel();
+    let stop = StopHandle::new();
+    let thread_stop = stop.clone();
+
+    // poll at around twice the block rate so the library buffer never gets close to full
+    let poll_interval = if sample_rate > 0.0 {
+        Duration::from_secs_f64((samples_per_block as f64 / sample_rate / 2.0).clamp(0.001, 0.05))
+    } else {
+        Duration::from_millis(10)
+    };
+
+    let handle = std::thread::spawn(move || {
+        let mut read_buf = vec![0.0; samples_per_block * n_ch];
+        let mut first_sample = 0u64;
+        // time of sample 0, estimated when the first data arrives so triggered scans line up
+        let mut origin: Option<SystemTime> = None;
+
+        let mut end = loop {
+            if thread_stop.is_stopped() {
+                break ScanEnd::Stopped;
+            }
+
+            // a zero timeout returns whatever is already buffered, up to one block
+            let (status, samples_read) = match dev.a_in_scan_read(samples_per_block as i32, 0.0, &mut read_buf) {
+                Ok(res) => res,
+                Err(err) => break ScanEnd::Error(err),
+            };
+            if status.contains(ScanStatus::HW_OVERRUN) {
+                break ScanEnd::HardwareOverrun;
+            }
+            if status.contains(ScanStatus::BUFFER_OVERRUN) {
+                break ScanEnd::BufferOverrun;
+            }
+            if samples_read == 0 {
+                if !status.contains(ScanStatus::RUNNING) {
+                    break ScanEnd::Finished;
+                }
+                std::thread::sleep(poll_interval);
+                continue;
+            }
+
+            let samples_read = samples_read as usize;
+            let timestamp = if sample_rate > 0.0 {
+                let origin = *origin.get_or_insert_with(|| {
+                    SystemTime::now() - Duration::from_secs_f64(samples_read as f64 / sample_rate)
+                });
+                origin + Duration::from_secs_f64(first_sample as f64 / sample_rate)
+            } else {
+                SystemTime::now()
+            };
+
+            let block = ScanBlock {
+                first_sample,
+                timestamp,
+                channel_count: n_ch,
+                data: read_buf[..samples_read * n_ch].to_vec(),
+            };
+            first_sample += samples_read as u64;
+
+            if tx.send(block).is_err() {
+                // receiver was dropped
+                break ScanEnd::Stopped;
+            }
+        };
+
+        if let Err(err) = dev.a_in_scan_stop() {
+            if end == ScanEnd::Stopped {
+                end = ScanEnd::Error(err);
+            }
+        }
+        if let Err(err) = dev.a_in_scan_cleanup() {
+            if end == ScanEnd::Stopped {
+                end = ScanEnd::Error(err);
+            }
+        }
+
+        (dev, end)
+    });
+
+    Ok(ScanStream { info, blocks: rx, stop, handle: Some(handle) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimulatedHat;
+
+    /// A board whose scan never delivers data, like one waiting for a trigger, and that reports
+    /// when the scan is cleaned up.
+    struct Waiting(Arc<AtomicBool>);
+
+    impl Hat for Waiting {
+        fn address(&self) -> u8 {
+            0
+        }
+
+        fn id(&self) -> HatId {
+            HatId::Mcc118
+        }
+
+        fn serial(&self) -> Result<String, ErrorCode> {
+            Ok(String::new())
+        }
+    }
+
+    impl AInScanner for Waiting {
+        fn a_in_scan_actual_rate(&self, _channel_count: u8, sample_rate_per_channel: f64) -> Result<f64, ErrorCode> {
+            Ok(sample_rate_per_channel)
+        }
+
+        fn a_in_scan_start(&mut self, _channel_mask: u8, _samples_per_channel: u32, _sample_rate_per_channel: f64, _options: crate::core::ScanOptions) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn a_in_scan_buffer_size(&self) -> Result<u32, ErrorCode> {
+            Ok(1000)
+        }
+
+        fn a_in_scan_status(&self) -> Result<(ScanStatus, u32), ErrorCode> {
+            Ok((ScanStatus::RUNNING, 0))
+        }
+
+        fn a_in_scan_read(&mut self, _samples_per_channel: i32, _timeout_s: f64, _buffer: &mut [f64]) -> Result<(ScanStatus, u32), ErrorCode> {
+            Ok((ScanStatus::RUNNING, 0))
+        }
+
+        fn a_in_scan_channel_count(&self) -> u8 {
+            1
+        }
+
+        fn a_in_scan_stop(&mut self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn a_in_scan_cleanup(&mut self) -> Result<(), ErrorCode> {
+            self.0.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn opts(channel_mask: u8) -> ScanOptions {
+        ScanOptions { channel_mask, sample_rate_per_channel: 1000.0, scale_data: true, calibrate_data: true, external_clock: false, external_trigger: false }
+    }
+
+    #[test]
+    fn dropping_a_waiting_stream_ends_the_scan() {
+        let cleaned_up = Arc::new(AtomicBool::new(false));
+        let stream = scan_blocks(Waiting(cleaned_up.clone()), opts(1), 100).map_err(|(_, err)| err).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!cleaned_up.load(Ordering::Relaxed));
+
+        drop(stream);
+        let started = std::time::Instant::now();
+        while !cleaned_up.load(Ordering::Relaxed) {
+            assert!(started.elapsed() < Duration::from_secs(1), "the scan is still running");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn stop_returns_the_device() {
+        let dev = SimulatedHat::new(HatId::Mcc118, 4, |ch: u8, _t: f64| ch as f64).unwrap();
+        let stream = scan_blocks(dev, opts(0b110), 50).map_err(|(_, err)| err).unwrap();
+        assert_eq!(stream.info().channels, [1, 2]);
+        let block = stream.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(block.channel_count, 2);
+        assert!(block.channel(1).all(|v| v == 2.0));
+
+        let (dev, end) = stream.stop();
+        assert_eq!(end, ScanEnd::Stopped);
+        assert_eq!(dev.address(), 4);
+    }
+}
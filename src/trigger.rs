@@ -0,0 +1,331 @@
+//! Software triggers evaluated on streamed data, for capturing transients without wiring a
+//! hardware trigger.
+//!
+//! A [`Trigger`] watches one channel of a stream and, each time its condition fires, emits a
+//! fixed-length [`TriggerRecord`] holding every channel from `pre_trigger` samples before the
+//! trigger to `post_trigger` samples after it. Pre-trigger samples come from a ring buffer, so the
+//! trigger only arms once that buffer has filled.
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::core::ErrorCode;
+use crate::stream::{ScanBlock, StreamInfo};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TriggerCondition {
+    /// The signal rises to `level`. After firing, it must fall below `level - hysteresis` before it
+    /// can fire again, so noise around the level doesn't retrigger.
+    Rising { level: f64, hysteresis: f64 },
+    /// The signal falls to `level`, re-arming above `level + hysteresis`.
+    Falling { level: f64, hysteresis: f64 },
+    /// The signal moves from outside to inside `low..=high`.
+    WindowEnter { low: f64, high: f64 },
+    /// The signal moves from inside to outside `low..=high`.
+    WindowExit { low: f64, high: f64 },
+    /// The slope between consecutive samples reaches `rate`, in units per second (per sample for
+    /// streams without a fixed rate). A negative `rate` fires on falling slopes at least that steep.
+    Slope { rate: f64 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TriggerConfig {
+    /// Physical channel the condition is evaluated on. It must be part of the stream.
+    pub channel: u8,
+    pub condition: TriggerCondition,
+    /// Samples per channel recorded before the trigger sample.
+    pub pre_trigger: usize,
+    /// Samples per channel recorded from the trigger sample on, including it. At least 1.
+    pub post_trigger: usize,
+    /// Keep triggering after the first record. Triggers that fire while a record is being
+    /// captured are ignored.
+    pub rearm: bool,
+}
+
+/// One triggered capture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriggerRecord {
+    /// Stream sample index at which the condition fired.
+    pub trigger_sample: u64,
+    pub trigger_time: SystemTime,
+    /// `pre_trigger + post_trigger` samples per channel, starting `pre_trigger` samples before the
+    /// trigger.
+    pub block: ScanBlock,
+}
+
+struct Capture {
+    record: TriggerRecord,
+    remaining: usize,
+}
+
+pub struct Trigger {
+    config: TriggerConfig,
+    channel_index: usize,
+    channel_count: usize,
+    sample_rate: f64,
+    detector: Detector,
+    history: VecDeque<f64>,
+    capture: Option<Capture>,
+    done: bool,
+}
+
+impl Trigger {
+    /// Fails with [`ErrorCode::BadParameter`] if the channel isn't part of the stream or
+    /// `post_trigger` is 0.
+    pub fn new(info: &StreamInfo, config: TriggerConfig) -> Result<Trigger, ErrorCode> {
+        let channel_index = info.channels.iter().position(|&ch| ch == config.channel).ok_or(ErrorCode::BadParameter)?;
+        if config.post_trigger == 0 {
+            return Err(ErrorCode::BadParameter);
+        }
+
+        Ok(Trigger {
+            config,
+            channel_index,
+            channel_count: info.channel_count(),
+            sample_rate: info.sample_rate,
+            detector: Detector::new(config.condition),
+            history: VecDeque::with_capacity(config.pre_trigger * info.channel_count()),
+            capture: None,
+            done: false,
+        })
+    }
+
+    pub fn config(&self) -> &TriggerConfig {
+        &self.config
+    }
+
+    /// Whether the trigger can currently fire: the pre-trigger buffer is full, no record is being
+    /// captured, and it hasn't already fired if `rearm` is off.
+    pub fn is_armed(&self) -> bool {
+        !self.done && self.capture.is_none() && self.history.len() == self.config.pre_trigger * self.channel_count
+    }
+
+    /// Feeds the next block of the stream and returns the records completed by it. Fails with
+    /// [`ErrorCode::BadParameter`] if the block doesn't have the stream's channel count.
+    pub fn process(&mut self, block: &ScanBlock) -> Result<Vec<TriggerRecord>, ErrorCode> {
+        if block.channel_count != self.channel_count {
+            return Err(ErrorCode::BadParameter);
+        }
+        let mut records = Vec::new();
+
+        for (i, frame) in block.frames().enumerate() {
+            let fired = self.detector.update(frame[self.channel_index], self.sample_rate);
+
+            if let Some(capture) = &mut self.capture {
+                capture.record.block.data.extend_from_slice(frame);
+                capture.remaining -= 1;
+            } else if fired && self.is_armed() {
+                let trigger_sample = block.first_sample + i as u64;
+                let pre = self.config.pre_trigger as u64;
+                let mut data = Vec::with_capacity((self.config.pre_trigger + self.config.post_trigger) * self.channel_count);
+                data.extend(self.history.iter().copied());
+                data.extend_from_slice(frame);
+
+                let trigger_time = block.sample_time(i, self.sample_rate);
+                let start_time = if self.sample_rate > 0.0 {
+                    trigger_time - std::time::Duration::from_secs_f64(pre as f64 / self.sample_rate)
+                } else {
+                    trigger_time
+                };
+                self.capture = Some(Capture {
+                    record: TriggerRecord {
+                        trigger_sample,
+                        trigger_time,
+                        block: ScanBlock {
+                            first_sample: trigger_sample - pre,
+                            timestamp: start_time,
+                            channel_count: self.channel_count,
+                            data,
+                        },
+                    },
+                    remaining: self.config.post_trigger - 1,
+                });
+            }
+
+            if self.capture.as_ref().is_some_and(|capture| capture.remaining == 0) {
+                records.push(self.capture.take().unwrap().record);
+                self.done = !self.config.rearm;
+            }
+
+            if self.config.pre_trigger > 0 {
+                if self.history.len() == self.config.pre_trigger * self.channel_count {
+                    self.history.drain(..self.channel_count);
+                }
+                self.history.extend(frame.iter().copied());
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Forgets all history and any record in progress, and re-arms.
+    pub fn reset(&mut self) {
+        self.detector = Detector::new(self.config.condition);
+        self.history.clear();
+        self.capture = None;
+        self.done = false;
+    }
+}
+
+// edge detection for a trigger condition; `armed` means the condition was false (or, with
+// hysteresis, past the re-arm level) since the last time it fired
+struct Detector {
+    condition: TriggerCondition,
+    armed: bool,
+    previous: Option<f64>,
+}
+
+impl Detector {
+    fn new(condition: TriggerCondition) -> Detector {
+        Detector { condition, armed: false, previous: None }
+    }
+
+    fn update(&mut self, value: f64, sample_rate: f64) -> bool {
+        let previous = self.previous.replace(value);
+
+        let (active, rearm) = match self.condition {
+            TriggerCondition::Rising { level, hysteresis } => (value >= level, value < level - hysteresis.abs()),
+            TriggerCondition::Falling { level, hysteresis } => (value <= level, value > level + hysteresis.abs()),
+            TriggerCondition::WindowEnter { low, high } => {
+                let inside = (low..=high).contains(&value);
+                (inside, !inside)
+            }
+            TriggerCondition::WindowExit { low, high } => {
+                let inside = (low..=high).contains(&value);
+                (!inside, inside)
+            }
+            TriggerCondition::Slope { rate } => {
+                let previous = match previous {
+                    Some(previous) => previous,
+                    None => return false,
+                };
+                let slope = if sample_rate > 0.0 { (value - previous) * sample_rate } else { value - previous };
+                let steep = if rate >= 0.0 { slope >= rate } else { slope <= rate };
+                (steep, !steep)
+            }
+        };
+
+        if self.armed && active {
+            self.armed = false;
+            return true;
+        }
+        if rearm {
+            self.armed = true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::core::HatId;
+
+    fn info(channels: &[u8]) -> StreamInfo {
+        StreamInfo::test(HatId::Mcc118, channels, 100.0)
+    }
+
+    fn config(condition: TriggerCondition, pre_trigger: usize, post_trigger: usize, rearm: bool) -> TriggerConfig {
+        TriggerConfig { channel: 5, condition, pre_trigger, post_trigger, rearm }
+    }
+
+    /// Two channels, 5 and 2, where channel 5 carries `signal` and channel 2 its sample index.
+    fn blocks(signal: &[f64], block_len: usize) -> Vec<ScanBlock> {
+        signal
+            .chunks(block_len)
+            .enumerate()
+            .map(|(b, chunk)| {
+                let first_sample = (b * block_len) as u64;
+                let data = chunk.iter().enumerate().flat_map(|(i, &v)| [v, (first_sample + i as u64) as f64]).collect();
+                ScanBlock { first_sample, timestamp: UNIX_EPOCH + Duration::from_secs_f64(first_sample as f64 / 100.0), channel_count: 2, data }
+            })
+            .collect()
+    }
+
+    fn run(trigger: &mut Trigger, blocks: &[ScanBlock]) -> Vec<TriggerRecord> {
+        blocks.iter().flat_map(|block| trigger.process(block).unwrap()).collect()
+    }
+
+    #[test]
+    fn records_the_pre_trigger_ring() {
+        let mut signal = vec![0.0; 20];
+        signal[12] = 1.0;
+        signal[13] = 1.0;
+        let rising = TriggerCondition::Rising { level: 0.5, hysteresis: 0.1 };
+        let mut trigger = Trigger::new(&info(&[5, 2]), config(rising, 4, 3, false)).unwrap();
+
+        // uneven blocks so the ring is filled and read across block boundaries
+        let records = run(&mut trigger, &blocks(&signal, 5));
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.trigger_sample, 12);
+        assert_eq!(record.trigger_time, UNIX_EPOCH + Duration::from_millis(120));
+        assert_eq!(record.block.first_sample, 8);
+        assert_eq!(record.block.timestamp, UNIX_EPOCH + Duration::from_millis(80));
+        assert_eq!(record.block.channel(0).collect::<Vec<_>>(), [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(record.block.channel(1).collect::<Vec<_>>(), [8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0]);
+    }
+
+    #[test]
+    fn arms_only_once_the_ring_is_full() {
+        let signal = [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        let rising = TriggerCondition::Rising { level: 0.5, hysteresis: 0.0 };
+        let mut trigger = Trigger::new(&info(&[5, 2]), config(rising, 4, 1, false)).unwrap();
+        assert!(!trigger.is_armed());
+
+        let records = run(&mut trigger, &blocks(&signal, 3));
+        assert_eq!(records.iter().map(|r| r.trigger_sample).collect::<Vec<_>>(), [6]);
+        assert!(!trigger.is_armed());
+
+        trigger.reset();
+        assert!(!trigger.is_armed());
+    }
+
+    #[test]
+    fn hysteresis_and_rearm() {
+        // noise around the level only counts once the signal drops below 0.8
+        let signal = [0.0, 1.0, 0.95, 1.0, 0.9, 1.0, 0.5, 1.0, 0.0, 0.0, 1.0];
+        let rising = TriggerCondition::Rising { level: 1.0, hysteresis: 0.2 };
+        let mut trigger = Trigger::new(&info(&[5, 2]), config(rising, 0, 1, true)).unwrap();
+        let records = run(&mut trigger, &blocks(&signal, 4));
+        assert_eq!(records.iter().map(|r| r.trigger_sample).collect::<Vec<_>>(), [1, 7, 10]);
+
+        // while a record is captured further triggers are ignored
+        let mut trigger = Trigger::new(&info(&[5, 2]), config(rising, 0, 8, true)).unwrap();
+        let records = run(&mut trigger, &blocks(&signal, 4));
+        assert_eq!(records.iter().map(|r| r.trigger_sample).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn window_and_slope_conditions() {
+        let signal = [0.0, 0.5, 0.6, 2.0, 0.4, 0.0];
+        let enter = TriggerCondition::WindowEnter { low: 0.3, high: 0.7 };
+        let exit = TriggerCondition::WindowExit { low: 0.3, high: 0.7 };
+        // 100 S/s, so a step of 1.4 is 140 units/s
+        let slope_up = TriggerCondition::Slope { rate: 100.0 };
+        let slope_down = TriggerCondition::Slope { rate: -100.0 };
+        for (condition, expected) in [(enter, vec![1, 4]), (exit, vec![3, 5]), (slope_up, vec![3]), (slope_down, vec![4])] {
+            let mut trigger = Trigger::new(&info(&[5, 2]), config(condition, 0, 1, true)).unwrap();
+            let records = run(&mut trigger, &blocks(&signal, 6));
+            assert_eq!(records.iter().map(|r| r.trigger_sample).collect::<Vec<_>>(), expected, "{:?}", condition);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_configs() {
+        let rising = TriggerCondition::Rising { level: 0.0, hysteresis: 0.0 };
+        assert_eq!(Trigger::new(&info(&[1, 2]), config(rising, 0, 1, false)).err(), Some(ErrorCode::BadParameter));
+        assert_eq!(Trigger::new(&info(&[5]), config(rising, 0, 0, false)).err(), Some(ErrorCode::BadParameter));
+    }
+
+    #[test]
+    fn rejects_blocks_with_other_channels() {
+        let rising = TriggerCondition::Rising { level: 0.5, hysteresis: 0.0 };
+        let mut trigger = Trigger::new(&info(&[5, 2]), config(rising, 0, 1, false)).unwrap();
+        let block = ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: 1, data: vec![1.0] };
+        assert_eq!(trigger.process(&block), Err(ErrorCode::BadParameter));
+        assert_eq!(run(&mut trigger, &blocks(&[0.0, 1.0], 2)).len(), 1);
+    }
+}
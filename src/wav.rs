@@ -0,0 +1,513 @@
+//! WAV export and import, for opening captures in audio tools.
+//!
+//! Each scanned channel becomes one WAV channel. Samples are normalized so that `full_scale` (by
+//! default the top of the board's input range, e.g. 5 V on the MCC 172) maps to a full-scale
+//! sample. The board, channels, units, exact scan rate, full scale and sensor sensitivities are
+//! stored as text in a `LIST`/`INFO` chunk, which audio tools show as the file's comment.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::core::Capabilities;
+use crate::stream::{ScanBlock, ScanStream, StreamInfo};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+const PCM24_MAX: f64 = 8_388_607.0;
+
+/// The tail shared by the subformat GUIDs of the extensible format, after the format tag.
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+/// The number of speaker positions a channel mask can assign.
+const SPEAKER_POSITIONS: usize = 18;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WavFormat {
+    Float32,
+    Pcm16,
+    Pcm24,
+}
+
+impl WavFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            WavFormat::Float32 => 4,
+            WavFormat::Pcm16 => 2,
+            WavFormat::Pcm24 => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WavOptions {
+    pub format: WavFormat,
+    /// The value written as a full-scale sample. `None` uses the top of the board's input range.
+    pub full_scale: Option<f64>,
+    /// Sensor sensitivity of each channel in mV per unit (see [`crate::core::Iepe`]), recorded in
+    /// the comment. Empty if not applicable.
+    pub sensitivities: Vec<f64>,
+}
+
+impl Default for WavOptions {
+    fn default() -> Self {
+        WavOptions { format: WavFormat::Float32, full_scale: None, sensitivities: Vec::new() }
+    }
+}
+
+/// Streams scan blocks into a WAV file. The chunk sizes are filled in by [`WavWriter::finish`], so
+/// a file that isn't finished has an empty data chunk as far as most tools are concerned.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    format: WavFormat,
+    full_scale: f64,
+    channel_count: usize,
+    frames: u64,
+    riff_start: u64,
+    fact_pos: Option<u64>,
+    data_pos: u64,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, info: &StreamInfo, opts: &WavOptions) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), info, opts)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes the headers. The WAV sample rate is the stream's actual scan rate rounded to a whole
+    /// number; the exact rate is kept in the comment.
+    pub fn new(mut inner: W, info: &StreamInfo, opts: &WavOptions) -> io::Result<Self> {
+        let channel_count = info.channel_count();
+        if channel_count == 0 || channel_count > u16::MAX as usize {
+            return Err(invalid_input("a WAV file needs at least one channel"));
+        }
+        let sample_rate = info.sample_rate.round();
+        if !(1.0..=u32::MAX as f64).contains(&sample_rate) {
+            return Err(invalid_input(format!("sample rate {} S/s can't be stored in a WAV file", info.sample_rate)));
+        }
+        let full_scale = opts.full_scale.unwrap_or_else(|| {
+            Capabilities::of(info.id)
+                .and_then(|caps| caps.ai_ranges.first())
+                .map(|range| range.max)
+                .unwrap_or(1.0)
+        });
+        if !(full_scale.is_finite() && full_scale > 0.0) {
+            return Err(invalid_input("full scale must be a positive number"));
+        }
+
+        let riff_start = inner.stream_position()?;
+        inner.write_all(b"RIFF\0\0\0\0WAVE")?;
+
+        let block_align = opts.format.bytes_per_sample() * channel_count as u16;
+        let (tag, bits) = match opts.format {
+            WavFormat::Float32 => (FORMAT_IEEE_FLOAT, 32u16),
+            WavFormat::Pcm16 => (FORMAT_PCM, 16u16),
+            WavFormat::Pcm24 => (FORMAT_PCM, 24u16),
+        };
+        // PCM wider than 16 bits and anything with more than 2 channels must use the extensible
+        // format, or some readers guess the sample layout or speaker assignment wrong
+        let extensible = (tag == FORMAT_PCM && bits > 16) || channel_count > 2;
+        let mut fmt = Vec::with_capacity(40);
+        fmt.extend_from_slice(&(if extensible { FORMAT_EXTENSIBLE } else { tag }).to_le_bytes());
+        fmt.extend_from_slice(&(channel_count as u16).to_le_bytes());
+        fmt.extend_from_slice(&(sample_rate as u32).to_le_bytes());
+        fmt.extend_from_slice(&(sample_rate as u32 * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        if extensible {
+            // channels past the last speaker position are left unassigned
+            let channel_mask = (1u32 << channel_count.min(SPEAKER_POSITIONS)) - 1;
+            fmt.extend_from_slice(&22u16.to_le_bytes());
+            fmt.extend_from_slice(&bits.to_le_bytes());
+            fmt.extend_from_slice(&channel_mask.to_le_bytes());
+            fmt.extend_from_slice(&tag.to_le_bytes());
+            fmt.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+        } else if tag != FORMAT_PCM {
+            fmt.extend_from_slice(&0u16.to_le_bytes());
+        }
+        write_chunk(&mut inner, b"fmt ", &fmt)?;
+
+        // non-PCM formats need a fact chunk with the frame count
+        let fact_pos = if tag != FORMAT_PCM {
+            let pos = inner.stream_position()? + 8;
+            write_chunk(&mut inner, b"fact", &[0; 4])?;
+            Some(pos)
+        } else {
+            None
+        };
+
+        let mut list = b"INFO".to_vec();
+        let product = Capabilities::of(info.id).map(|caps| caps.product_name.to_string()).unwrap_or_else(|| format!("{:?}", info.id));
+        info_entry(&mut list, b"INAM", &format!("{} address {} serial {}", product, info.address, info.serial));
+        info_entry(&mut list, b"ICMT", &comment(info, full_scale, &opts.sensitivities));
+        info_entry(&mut list, b"ISFT", concat!("daqhats-rs ", env!("CARGO_PKG_VERSION")));
+        write_chunk(&mut inner, b"LIST", &list)?;
+
+        inner.write_all(b"data\0\0\0\0")?;
+        let data_pos = inner.stream_position()?;
+
+        Ok(WavWriter {
+            inner,
+            format: opts.format,
+            full_scale,
+            channel_count,
+            frames: 0,
+            riff_start,
+            fact_pos,
+            data_pos,
+        })
+    }
+
+    pub fn write_block(&mut self, block: &ScanBlock) -> io::Result<()> {
+        if block.channel_count != self.channel_count {
+            return Err(invalid_input(format!(
+                "block has {} channels, the WAV file has {}",
+                block.channel_count, self.channel_count
+            )));
+        }
+
+        let mut bytes = Vec::with_capacity(block.data.len() * self.format.bytes_per_sample() as usize);
+        for value in &block.data {
+            let normalized = value / self.full_scale;
+            match self.format {
+                WavFormat::Float32 => bytes.extend_from_slice(&(normalized as f32).to_le_bytes()),
+                WavFormat::Pcm16 => {
+                    let code = (normalized.clamp(-1.0, 1.0) * i16::MAX as f64).round() as i16;
+                    bytes.extend_from_slice(&code.to_le_bytes());
+                }
+                WavFormat::Pcm24 => {
+                    let code = (normalized.clamp(-1.0, 1.0) * PCM24_MAX).round() as i32;
+                    bytes.extend_from_slice(&code.to_le_bytes()[..3]);
+                }
+            }
+        }
+        self.inner.write_all(&bytes)?;
+        self.frames += block.samples_per_channel() as u64;
+        Ok(())
+    }
+
+    /// Writes blocks until the stream ends.
+    pub fn record<T>(&mut self, stream: &ScanStream<T>) -> io::Result<()> {
+        for block in stream.iter() {
+            self.write_block(&block)?;
+        }
+        Ok(())
+    }
+
+    /// Fills in the chunk sizes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_len = self.frames * self.format.bytes_per_sample() as u64 * self.channel_count as u64;
+        if data_len > u32::MAX as u64 - (self.data_pos - self.riff_start) {
+            return Err(io::Error::other("capture is too long for a WAV file"));
+        }
+        if data_len % 2 == 1 {
+            self.inner.write_all(&[0])?;
+        }
+        let end = self.inner.stream_position()?;
+
+        self.inner.seek(SeekFrom::Start(self.riff_start + 4))?;
+        self.inner.write_all(&((end - self.riff_start - 8) as u32).to_le_bytes())?;
+        if let Some(pos) = self.fact_pos {
+            self.inner.seek(SeekFrom::Start(pos))?;
+            self.inner.write_all(&(self.frames.min(u32::MAX as u64) as u32).to_le_bytes())?;
+        }
+        self.inner.seek(SeekFrom::Start(self.data_pos - 4))?;
+        self.inner.write_all(&(data_len as u32).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// The contents of a WAV file.
+#[derive(Clone, Debug)]
+pub struct Wav {
+    pub sample_rate: u32,
+    /// One array per channel, normalized so that full scale is ±1.
+    pub channels: Vec<Vec<f64>>,
+    /// The `ICMT` entry of the `LIST`/`INFO` chunk, if any.
+    pub comment: Option<String>,
+}
+
+/// Reads an integer PCM (8 to 32 bit) or floating point WAV file.
+pub fn read_wav(path: impl AsRef<Path>) -> io::Result<Wav> {
+    parse_wav(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn parse_wav<R: Read>(r: &mut R) -> io::Result<Wav> {
+    let mut riff = [0u8; 12];
+    r.read_exact(&mut riff)?;
+    if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+        return Err(invalid_data("not a WAV file"));
+    }
+
+    let mut fmt: Option<(u16, u16, u32, u16)> = None;
+    let mut comment = None;
+    loop {
+        let mut header = [0u8; 8];
+        r.read_exact(&mut header)?;
+        let id = &header[..4];
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+
+        if id == b"data" {
+            let (tag, channel_count, sample_rate, bits) = fmt.ok_or_else(|| invalid_data("data chunk before fmt chunk"))?;
+            // streams that were never finished have a zero length; read to the end instead
+            let data = if len == 0 {
+                let mut data = Vec::new();
+                r.read_to_end(&mut data)?;
+                data
+            } else {
+                read_chunk(r, len)?
+            };
+            let channels = decode(&data, tag, channel_count as usize, bits)?;
+            return Ok(Wav { sample_rate, channels, comment });
+        }
+
+        let mut body = read_chunk(r, len + len % 2)?;
+        body.truncate(len);
+        match id {
+            b"fmt " => {
+                if body.len() < 16 {
+                    return Err(invalid_data("fmt chunk too short"));
+                }
+                let mut tag = u16::from_le_bytes([body[0], body[1]]);
+                let channel_count = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                // the extensible format keeps the real format tag at the start of the subformat GUID
+                if tag == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    tag = u16::from_le_bytes([body[24], body[25]]);
+                }
+                if channel_count == 0 {
+                    return Err(invalid_data("WAV file has no channels"));
+                }
+                fmt = Some((tag, channel_count, sample_rate, bits));
+            }
+            b"LIST" if body.starts_with(b"INFO") => comment = find_info_entry(&body[4..], b"ICMT"),
+            _ => {}
+        }
+    }
+}
+
+/// Reads `len` bytes, allocating only as much as is actually there, so a corrupt chunk length
+/// can't force a huge allocation.
+fn read_chunk<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    r.take(len as u64).read_to_end(&mut body)?;
+    if body.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(body)
+}
+
+fn decode(data: &[u8], tag: u16, channel_count: usize, bits: u16) -> io::Result<Vec<Vec<f64>>> {
+    let size = bits as usize / 8;
+    let convert: fn(&[u8]) -> f64 = match (tag, bits) {
+        (FORMAT_PCM, 8) => |b| (b[0] as f64 - 128.0) / 127.0,
+        (FORMAT_PCM, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f64 / i16::MAX as f64,
+        (FORMAT_PCM, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f64 / PCM24_MAX,
+        (FORMAT_PCM, 32) => |b| i32::from_le_bytes(b.try_into().unwrap()) as f64 / i32::MAX as f64,
+        (FORMAT_IEEE_FLOAT, 32) => |b| f32::from_le_bytes(b.try_into().unwrap()) as f64,
+        (FORMAT_IEEE_FLOAT, 64) => |b| f64::from_le_bytes(b.try_into().unwrap()),
+        _ => return Err(invalid_data(format!("unsupported WAV format {} with {} bits", tag, bits))),
+    };
+
+    let frames = data.len() / (size * channel_count);
+    let mut channels = vec![Vec::with_capacity(frames); channel_count];
+    for (i, sample) in data[..frames * size * channel_count].chunks_exact(size).enumerate() {
+        channels[i % channel_count].push(convert(sample));
+    }
+    Ok(channels)
+}
+
+fn comment(info: &StreamInfo, full_scale: f64, sensitivities: &[f64]) -> String {
+    let channels = info.channels.iter().map(|ch| ch.to_string()).collect::<Vec<_>>().join(",");
+    let mut text = format!(
+        "channels {}; units {}; sample_rate {} S/s; full_scale {}; scale_data {}; calibrate_data {}",
+        channels,
+        info.units.join(","),
+        info.sample_rate,
+        full_scale,
+        info.scale_data,
+        info.calibrate_data
+    );
+    if !sensitivities.is_empty() {
+        let sens = sensitivities.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(",");
+        text.push_str(&format!("; sensitivity_mv_per_unit {}", sens));
+    }
+    text
+}
+
+fn write_chunk<W: Write>(w: &mut W, id: &[u8; 4], body: &[u8]) -> io::Result<()> {
+    w.write_all(id)?;
+    w.write_all(&(body.len() as u32).to_le_bytes())?;
+    w.write_all(body)?;
+    if body.len() % 2 == 1 {
+        w.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+fn info_entry(list: &mut Vec<u8>, id: &[u8; 4], text: &str) {
+    let mut body = text.as_bytes().to_vec();
+    body.push(0);
+    list.extend_from_slice(id);
+    list.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    list.extend_from_slice(&body);
+    if body.len() % 2 == 1 {
+        list.push(0);
+    }
+}
+
+fn find_info_entry(mut entries: &[u8], wanted: &[u8; 4]) -> Option<String> {
+    while entries.len() >= 8 {
+        let len = u32::from_le_bytes(entries[4..8].try_into().unwrap()) as usize;
+        let body = entries.get(8..8 + len)?;
+        if &entries[..4] == wanted {
+            return Some(crate::core::string_from_c_buf(body));
+        }
+        entries = entries.get(8 + len + len % 2..)?;
+    }
+    None
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn invalid_input(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::core::HatId;
+
+    fn info(channel_count: u8) -> StreamInfo {
+        let channels: Vec<u8> = (0..channel_count).collect();
+        StreamInfo { serial: "01234567".to_string(), ..StreamInfo::test(HatId::Mcc172, &channels, 51200.0) }
+    }
+
+    /// Writes `data` as one block and returns the file.
+    fn write(info: &StreamInfo, format: WavFormat, full_scale: Option<f64>, data: Vec<f64>) -> Vec<u8> {
+        let opts = WavOptions { format, full_scale, sensitivities: Vec::new() };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), info, &opts).unwrap();
+        writer.write_block(&ScanBlock { first_sample: 0, timestamp: UNIX_EPOCH, channel_count: info.channel_count(), data }).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// The body of the fmt chunk, which the writer puts first.
+    fn fmt_chunk(bytes: &[u8]) -> &[u8] {
+        assert_eq!(&bytes[12..16], b"fmt ");
+        let len = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        &bytes[20..20 + len]
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() <= tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn float32_round_trip() {
+        let bytes = write(&info(2), WavFormat::Float32, Some(2.0), vec![1.0, -0.5, 3.0, 0.25]);
+        let fmt = fmt_chunk(&bytes);
+        assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), FORMAT_IEEE_FLOAT);
+        assert_eq!(u32::from_le_bytes(fmt[4..8].try_into().unwrap()), 51200);
+
+        let wav = parse_wav(&mut &bytes[..]).unwrap();
+        assert_eq!(wav.sample_rate, 51200);
+        // floats aren't clipped, so values past full scale survive
+        assert_eq!(wav.channels, vec![vec![0.5, 1.5], vec![-0.25, 0.125]]);
+        let comment = wav.comment.unwrap();
+        assert!(comment.contains("sample_rate 51200 S/s; full_scale 2;"), "{}", comment);
+    }
+
+    #[test]
+    fn pcm16_scales_and_clips() {
+        let bytes = write(&info(2), WavFormat::Pcm16, Some(10.0), vec![5.0, -2.5, 12.0, -20.0, 0.0, 10.0]);
+        let fmt = fmt_chunk(&bytes);
+        assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), FORMAT_PCM);
+        assert_eq!(u16::from_le_bytes([fmt[14], fmt[15]]), 16);
+
+        let wav = parse_wav(&mut &bytes[..]).unwrap();
+        let lsb = 1.0 / i16::MAX as f64;
+        assert_close(&wav.channels[0], &[0.5, 1.0, 0.0], lsb);
+        assert_close(&wav.channels[1], &[-0.25, -1.0, 1.0], lsb);
+    }
+
+    #[test]
+    fn pcm24_is_extensible() {
+        // the MCC 172's input range is ±5 V
+        let bytes = write(&info(1), WavFormat::Pcm24, None, vec![2.5, -5.0, 7.5, -1.0e-3, 0.0]);
+        let fmt = fmt_chunk(&bytes);
+        assert_eq!(fmt.len(), 40);
+        assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), FORMAT_EXTENSIBLE);
+        assert_eq!(u16::from_le_bytes([fmt[12], fmt[13]]), 3);
+        assert_eq!(u16::from_le_bytes([fmt[16], fmt[17]]), 22);
+        assert_eq!(u16::from_le_bytes([fmt[18], fmt[19]]), 24);
+        assert_eq!(u32::from_le_bytes(fmt[20..24].try_into().unwrap()), 0b1);
+        assert_eq!(u16::from_le_bytes([fmt[24], fmt[25]]), FORMAT_PCM);
+        assert_eq!(fmt[26..], SUBFORMAT_GUID_TAIL);
+
+        let wav = parse_wav(&mut &bytes[..]).unwrap();
+        assert_close(&wav.channels[0], &[0.5, -1.0, 1.0, -2.0e-4, 0.0], 1.0 / PCM24_MAX);
+        // odd data lengths are padded
+        assert_eq!(bytes.len() % 2, 0);
+    }
+
+    #[test]
+    fn many_channels_are_extensible() {
+        let bytes = write(&info(4), WavFormat::Float32, Some(1.0), vec![0.1, 0.2, 0.3, 0.4]);
+        let fmt = fmt_chunk(&bytes);
+        assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), FORMAT_EXTENSIBLE);
+        assert_eq!(u32::from_le_bytes(fmt[20..24].try_into().unwrap()), 0b1111);
+        assert_eq!(u16::from_le_bytes([fmt[24], fmt[25]]), FORMAT_IEEE_FLOAT);
+
+        let wav = parse_wav(&mut &bytes[..]).unwrap();
+        let channels = wav.channels.iter().map(|ch| ch[0]).collect::<Vec<_>>();
+        assert_close(&channels, &[0.1, 0.2, 0.3, 0.4], 1.0e-7);
+    }
+
+    #[test]
+    fn reads_unfinished_files() {
+        let opts = WavOptions { format: WavFormat::Pcm16, full_scale: Some(1.0), sensitivities: Vec::new() };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), &info(1), &opts).unwrap();
+        writer.write_block(&ScanBlock::from_frame(0, UNIX_EPOCH, vec![-0.5])).unwrap();
+        let bytes = writer.inner.into_inner();
+
+        let wav = parse_wav(&mut &bytes[..]).unwrap();
+        assert_close(&wav.channels[0], &[-0.5], 1.0 / i16::MAX as f64);
+    }
+
+    #[test]
+    fn corrupt_chunk_lengths_fail_without_allocating() {
+        let mut bytes = write(&info(1), WavFormat::Float32, Some(1.0), vec![0.5, 0.25]);
+        // the data chunk comes last; the comment mentions "scale_data"
+        let data = bytes.windows(4).rposition(|id| id == b"data").unwrap();
+
+        // a data chunk claiming nearly 4 GiB in a file of under 100 bytes
+        let mut huge_data = bytes.clone();
+        huge_data[data + 4..data + 8].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        assert_eq!(parse_wav(&mut &huge_data[..]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        bytes[16..20].copy_from_slice(&0xffff_ff00u32.to_le_bytes());
+        assert_eq!(parse_wav(&mut &bytes[..]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let opts = WavOptions { full_scale: Some(0.0), ..WavOptions::default() };
+        assert_eq!(WavWriter::new(Cursor::new(Vec::new()), &info(1), &opts).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(WavWriter::new(Cursor::new(Vec::new()), &info(0), &WavOptions::default()).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_wav(&mut &b"RIFX\0\0\0\0WAVE"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
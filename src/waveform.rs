@@ -0,0 +1,608 @@
+//! Software-timed waveform and sequence generation on analog outputs, e.g. actuator setpoint
+//! profiles on an MCC 152, which has no hardware output pacing.
+//!
+//! A [`Sequence`] is a list of [`Segment`]s (point lists, holds, ramps, sines and timed profiles
+//! loaded from CSV) with one value per output channel per update. A [`Generator`] plays it on
+//! a board at a fixed update rate from its own thread, updating both MCC 152 outputs together
+//! with `a_out_write_all` when it drives both, and records how closely the updates kept to
+//! their schedule in [`Timing`].
+
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::core::{AOut, CapabilityError, ErrorCode, ScanOptions};
+use crate::stats::RunningStats;
+use crate::stream::StopHandle;
+use crate::Error;
+
+/// Part of a [`Sequence`]. Every value list has one entry per output channel of the generator.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    /// One update per point.
+    Points(Vec<Vec<f64>>),
+    /// Holds the values for the duration.
+    Hold { values: Vec<f64>, duration: Duration },
+    /// Moves linearly from `from`, reaching `to` on the last update.
+    Ramp { from: Vec<f64>, to: Vec<f64>, duration: Duration },
+    /// `offset + amplitude * sin(2π * frequency * t + phase)`, with the phase in degrees.
+    Sine { frequency: f64, amplitude: Vec<f64>, offset: Vec<f64>, phase_deg: Vec<f64>, duration: Duration },
+    /// Breakpoints of `(seconds from the start of the segment, values)`, interpolated linearly
+    /// between them. Equal times make a step. The segment ends at the last breakpoint.
+    Profile(Vec<(f64, Vec<f64>)>),
+}
+
+impl Segment {
+    /// Reads a profile from a CSV file with a row per breakpoint: the time in seconds followed
+    /// by a value per channel. A header row, blank lines and lines starting with `#` are skipped.
+    pub fn load_profile(path: impl AsRef<Path>) -> io::Result<Segment> {
+        let reader = BufReader::new(std::fs::File::open(path)?);
+        let mut breakpoints = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split(',').map(|field| field.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>();
+            match fields {
+                Ok(fields) if fields.len() >= 2 => breakpoints.push((fields[0], fields[1..].to_vec())),
+                Err(_) if breakpoints.is_empty() => continue,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: expected a time and at least one value", index + 1))),
+            }
+        }
+        let segment = Segment::Profile(breakpoints);
+        segment.check(None).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))?;
+        Ok(segment)
+    }
+
+    /// The number of updates at `rate` updates per second.
+    pub fn frames(&self, rate: f64) -> usize {
+        let updates = |duration: &Duration| ((duration.as_secs_f64() * rate).round() as usize).max(1);
+        match self {
+            Segment::Points(points) => points.len(),
+            Segment::Hold { duration, .. } | Segment::Ramp { duration, .. } | Segment::Sine { duration, .. } => updates(duration),
+            Segment::Profile(breakpoints) => breakpoints.last().map_or(0, |(end, _)| (end * rate).floor() as usize + 1),
+        }
+    }
+
+    /// Writes the values of update `index` at `rate` updates per second to `out`.
+    fn frame(&self, index: usize, rate: f64, out: &mut [f64]) {
+        match self {
+            Segment::Points(points) => out.copy_from_slice(&points[index]),
+            Segment::Hold { values, .. } => out.copy_from_slice(values),
+            Segment::Ramp { from, to, .. } => {
+                let frames = self.frames(rate);
+                let fraction = if frames > 1 { index as f64 / (frames - 1) as f64 } else { 1.0 };
+                for (ch, value) in out.iter_mut().enumerate() {
+                    *value = from[ch] + (to[ch] - from[ch]) * fraction;
+                }
+            }
+            Segment::Sine { frequency, amplitude, offset, phase_deg, .. } => {
+                let angle = 2.0 * std::f64::consts::PI * frequency * index as f64 / rate;
+                for (ch, value) in out.iter_mut().enumerate() {
+                    let phase = phase_deg.get(ch).copied().unwrap_or(0.0).to_radians();
+                    *value = offset[ch] + amplitude[ch] * (angle + phase).sin();
+                }
+            }
+            Segment::Profile(breakpoints) => {
+                let t = index as f64 / rate;
+                // the first breakpoint after `t`; equal times resolve to the later one
+                let next = breakpoints.partition_point(|(time, _)| *time <= t);
+                if next == 0 {
+                    out.copy_from_slice(&breakpoints[0].1);
+                } else if next == breakpoints.len() {
+                    out.copy_from_slice(&breakpoints[next - 1].1);
+                } else {
+                    let ((t0, v0), (t1, v1)) = (&breakpoints[next - 1], &breakpoints[next]);
+                    let fraction = (t - t0) / (t1 - t0);
+                    for (ch, value) in out.iter_mut().enumerate() {
+                        *value = v0[ch] + (v1[ch] - v0[ch]) * fraction;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The lowest and highest value the segment produces on channel `ch`.
+    fn bounds(&self, ch: usize) -> (f64, f64) {
+        let span = |values: &mut dyn Iterator<Item = f64>| values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        match self {
+            Segment::Points(points) => span(&mut points.iter().map(|point| point[ch])),
+            Segment::Hold { values, .. } => (values[ch], values[ch]),
+            Segment::Ramp { from, to, .. } => (from[ch].min(to[ch]), from[ch].max(to[ch])),
+            Segment::Sine { amplitude, offset, .. } => (offset[ch] - amplitude[ch].abs(), offset[ch] + amplitude[ch].abs()),
+            Segment::Profile(breakpoints) => span(&mut breakpoints.iter().map(|(_, values)| values[ch])),
+        }
+    }
+
+    /// Checks that the segment is well formed with `width` values per update, or a consistent
+    /// number of values if `width` is `None`.
+    fn check(&self, width: Option<usize>) -> Result<(), String> {
+        let lists: Vec<&Vec<f64>> = match self {
+            Segment::Points(points) => points.iter().collect(),
+            Segment::Hold { values, .. } => vec![values],
+            Segment::Ramp { from, to, .. } => vec![from, to],
+            Segment::Sine { frequency, amplitude, offset, phase_deg, .. } => {
+                if !frequency.is_finite() {
+                    return Err(format!("sine frequency {} is not a number", frequency));
+                }
+                if !phase_deg.is_empty() && phase_deg.len() != amplitude.len() {
+                    return Err("sine phases must be given for every channel or none".into());
+                }
+                vec![amplitude, offset]
+            }
+            Segment::Profile(breakpoints) => {
+                if breakpoints.iter().any(|(time, _)| !time.is_finite() || *time < 0.0) {
+                    return Err("profile times must be non-negative numbers".into());
+                }
+                if breakpoints.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+                    return Err("profile times must be in ascending order".into());
+                }
+                breakpoints.iter().map(|(_, values)| values).collect()
+            }
+        };
+        if lists.is_empty() {
+            return Err("segment has no values".into());
+        }
+        let width = width.unwrap_or(lists[0].len());
+        if let Some(list) = lists.iter().find(|list| list.len() != width) {
+            return Err(format!("segment has {} value(s) per update where {} are needed", list.len(), width));
+        }
+        if lists.iter().flat_map(|list| list.iter()).any(|v| !v.is_finite()) {
+            return Err("segment values must be finite".into());
+        }
+        Ok(())
+    }
+}
+
+/// The segments a [`Generator`] plays, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sequence {
+    pub segments: Vec<Segment>,
+}
+
+impl Sequence {
+    pub fn new() -> Sequence {
+        Sequence::default()
+    }
+
+    pub fn then(mut self, segment: Segment) -> Sequence {
+        self.segments.push(segment);
+        self
+    }
+
+    pub fn points(self, points: Vec<Vec<f64>>) -> Sequence {
+        self.then(Segment::Points(points))
+    }
+
+    pub fn hold(self, values: &[f64], duration: Duration) -> Sequence {
+        self.then(Segment::Hold { values: values.to_vec(), duration })
+    }
+
+    /// Holds each set of values in turn for `dwell`.
+    pub fn steps(self, levels: &[Vec<f64>], dwell: Duration) -> Sequence {
+        levels.iter().fold(self, |seq, values| seq.hold(values, dwell))
+    }
+
+    pub fn ramp(self, from: &[f64], to: &[f64], duration: Duration) -> Sequence {
+        self.then(Segment::Ramp { from: from.to_vec(), to: to.to_vec(), duration })
+    }
+
+    /// A sine starting at zero phase on every channel.
+    pub fn sine(self, frequency: f64, amplitude: &[f64], offset: &[f64], duration: Duration) -> Sequence {
+        self.then(Segment::Sine { frequency, amplitude: amplitude.to_vec(), offset: offset.to_vec(), phase_deg: Vec::new(), duration })
+    }
+
+    /// Appends a profile read with [`Segment::load_profile`].
+    pub fn profile_csv(self, path: impl AsRef<Path>) -> io::Result<Sequence> {
+        Ok(self.then(Segment::load_profile(path)?))
+    }
+
+    /// The number of updates in one pass at `rate` updates per second.
+    pub fn frames(&self, rate: f64) -> usize {
+        self.segments.iter().map(|segment| segment.frames(rate)).sum()
+    }
+}
+
+/// How closely a [`Generator`]'s updates kept to their schedule.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Timing {
+    pub updates: u64,
+    /// Updates left out because the generator fell more than a period behind. The values
+    /// resume from where the schedule is, so the sequence keeps its duration.
+    pub skipped: u64,
+    /// Seconds between the scheduled and actual start of each update.
+    pub lateness: RunningStats,
+    /// Seconds between the starts of consecutive updates.
+    pub interval: RunningStats,
+}
+
+impl Timing {
+    /// The standard deviation of the update interval, in seconds.
+    pub fn jitter(&self) -> Option<f64> {
+        self.interval.std_dev()
+    }
+}
+
+/// Plays a [`Sequence`] on one or both outputs of a board. See [`Generator::start`].
+pub struct Generator<O> {
+    dev: O,
+    channels: Vec<u8>,
+    sequence: Sequence,
+    rate: f64,
+    /// `None` to repeat until stopped.
+    loops: Option<u32>,
+    safe_values: Option<Vec<f64>>,
+}
+
+impl<O: AOut> Generator<O> {
+    /// Plays `sequence` on `channels` at `rate` updates per second, with the sequence's values
+    /// in the order of `channels`. Fails if a channel doesn't exist, the sequence is empty or
+    /// doesn't have a value per channel, or it goes outside the board's output range.
+    pub fn new(dev: O, channels: &[u8], sequence: Sequence, rate: f64) -> Result<Generator<O>, Error> {
+        let caps = dev.capabilities();
+        if caps.ao_channels == 0 {
+            return Err(ErrorCode::InvalidDevice.into());
+        }
+        if channels.is_empty() {
+            return Err(CapabilityError::NoChannels.into());
+        }
+        if let Some(&channel) = channels.iter().find(|&&ch| ch >= caps.ao_channels) {
+            return Err(CapabilityError::ChannelOutOfRange { product: caps.product_name, channel, num_channels: caps.ao_channels }.into());
+        }
+        if (1..channels.len()).any(|i| channels[..i].contains(&channels[i])) {
+            return Err(ErrorCode::BadParameter.into());
+        }
+        if rate.is_nan() || rate <= 0.0 {
+            return Err(CapabilityError::InvalidRate { requested: rate }.into());
+        }
+        if sequence.segments.iter().any(|segment| segment.check(Some(channels.len())).is_err()) || sequence.frames(rate) == 0 {
+            return Err(ErrorCode::BadParameter.into());
+        }
+        if let Some(range) = caps.ao_range {
+            for (ch, &channel) in channels.iter().enumerate() {
+                for segment in &sequence.segments {
+                    let (lo, hi) = segment.bounds(ch);
+                    if let Some(value) = [lo, hi].into_iter().find(|v| *v < range.min || *v > range.max) {
+                        return Err(CapabilityError::OutputOutOfRange { product: caps.product_name, channel, value, min: range.min, max: range.max }.into());
+                    }
+                }
+            }
+        }
+        Ok(Generator { dev, channels: channels.to_vec(), sequence, rate, loops: Some(1), safe_values: None })
+    }
+
+    /// Plays the sequence `loops` times. Once by default.
+    pub fn with_loops(mut self, loops: u32) -> Self {
+        self.loops = Some(loops);
+        self
+    }
+
+    /// Repeats the sequence until stopped.
+    pub fn forever(mut self) -> Self {
+        self.loops = None;
+        self
+    }
+
+    /// Values written when the generator stops early or after an error, one per channel, e.g. a
+    /// safe actuator position. Without them the outputs keep their last values. Fails if there
+    /// isn't a value per channel or one is outside the board's output range.
+    pub fn with_safe_output(mut self, values: &[f64]) -> Result<Self, Error> {
+        if values.len() != self.channels.len() {
+            return Err(ErrorCode::BadParameter.into());
+        }
+        let caps = self.dev.capabilities();
+        if let Some(range) = caps.ao_range {
+            if let Some((&value, &channel)) = values.iter().zip(&self.channels).find(|(v, _)| **v < range.min || **v > range.max) {
+                return Err(CapabilityError::OutputOutOfRange { product: caps.product_name, channel, value, min: range.min, max: range.max }.into());
+            }
+        }
+        self.safe_values = Some(values.to_vec());
+        Ok(self)
+    }
+
+    /// The length of one pass through the sequence.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.sequence.frames(self.rate) as f64 / self.rate)
+    }
+
+    pub fn into_device(self) -> O {
+        self.dev
+    }
+
+    fn write(&mut self, values: &[f64]) -> Result<(), ErrorCode> {
+        // every output of the board: one synchronized update
+        if self.channels.len() as u8 == self.dev.capabilities().ao_channels {
+            let mut all = vec![0.0; self.channels.len()];
+            for (value, &channel) in values.iter().zip(&self.channels) {
+                all[channel as usize] = *value;
+            }
+            return self.dev.a_out_write_all(ScanOptions::DEFAULT, &all);
+        }
+        for (value, &channel) in values.iter().zip(&self.channels) {
+            self.dev.a_out_write(channel, ScanOptions::DEFAULT, *value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: AOut + Send + 'static> Generator<O> {
+    /// Starts playing on its own thread. Each update is scheduled from the start time rather
+    /// than the previous update, so timing errors don't accumulate; if the generator falls more
+    /// than a period behind, it skips ahead to the scheduled update. The last update of the
+    /// last loop is always written.
+    pub fn start(mut self) -> GeneratorHandle<O> {
+        let timing = Arc::new(Mutex::new(Timing::default()));
+        let stop = StopHandle::new();
+        let (thread_timing, thread_stop) = (timing.clone(), stop.clone());
+
+        let handle = std::thread::spawn(move || {
+            let frames = self.sequence.frames(self.rate) as u64;
+            let total = self.loops.map(|loops| loops as u64 * frames);
+            // where each segment starts within a pass
+            let starts = self.sequence.segments.iter().scan(0, |start, segment| {
+                let this = *start;
+                *start += segment.frames(self.rate) as u64;
+                Some(this)
+            }).collect::<Vec<_>>();
+
+            let mut values = vec![0.0; self.channels.len()];
+            let start = Instant::now();
+            let mut last: Option<Instant> = None;
+            let mut update = 0u64;
+            let mut result = loop {
+                if thread_stop.is_stopped() {
+                    break Ok(());
+                }
+                if total.is_some_and(|total| update >= total) {
+                    break Ok(());
+                }
+
+                let due = start + Duration::from_secs_f64(update as f64 / self.rate);
+                let mut now = Instant::now();
+                // wake up often enough to stop promptly, even at slow update rates
+                while now < due && !thread_stop.is_stopped() {
+                    std::thread::sleep((due - now).min(POLL_STOP_INTERVAL));
+                    now = Instant::now();
+                }
+                if thread_stop.is_stopped() {
+                    break Ok(());
+                }
+                let mut skipped = (now.saturating_duration_since(due).as_secs_f64() * self.rate) as u64;
+                if let Some(total) = total {
+                    skipped = skipped.min(total - 1 - update);
+                }
+                update += skipped;
+                let due = start + Duration::from_secs_f64(update as f64 / self.rate);
+
+                let index = update % frames;
+                let segment = starts.partition_point(|&start| start <= index) - 1;
+                self.sequence.segments[segment].frame((index - starts[segment]) as usize, self.rate, &mut values);
+                if let Err(err) = self.write(&values) {
+                    break Err(err);
+                }
+
+                let mut timing = thread_timing.lock().unwrap_or_else(|e| e.into_inner());
+                timing.updates += 1;
+                timing.skipped += skipped;
+                timing.lateness.push(now.saturating_duration_since(due).as_secs_f64());
+                if let Some(last) = last {
+                    timing.interval.push((now - last).as_secs_f64());
+                }
+                last = Some(now);
+                update += 1;
+            };
+
+            if result.is_err() || thread_stop.is_stopped() {
+                if let Err(err) = self.write_safe() {
+                    result = result.and(Err(err));
+                }
+            }
+            (self.dev, result)
+        });
+
+        GeneratorHandle { timing, stop, handle }
+    }
+
+    fn write_safe(&mut self) -> Result<(), ErrorCode> {
+        match self.safe_values.take() {
+            Some(values) => self.write(&values),
+            None => Ok(()),
+        }
+    }
+}
+
+const POLL_STOP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A generator playing on its own thread. See [`Generator::start`].
+pub struct GeneratorHandle<O> {
+    timing: Arc<Mutex<Timing>>,
+    stop: StopHandle,
+    handle: JoinHandle<(O, Result<(), ErrorCode>)>,
+}
+
+impl<O> GeneratorHandle<O> {
+    /// The timing so far.
+    pub fn timing(&self) -> Timing {
+        *self.timing.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Whether all loops have been played, or playing ended early.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops playing, writes the safe output values if any and returns the device, the timing
+    /// and the error that ended playing, if any.
+    pub fn stop(self) -> (O, Timing, Result<(), ErrorCode>) {
+        self.stop.stop();
+        self.join()
+    }
+
+    /// Waits for all loops to be played. Never returns for a generator that repeats forever
+    /// unless it is stopped through a [`StopHandle`] or fails.
+    pub fn join(self) -> (O, Timing, Result<(), ErrorCode>) {
+        let (dev, result) = self.handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+        let timing = *self.timing.lock().unwrap_or_else(|e| e.into_inner());
+        (dev, timing, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Hat, HatId};
+    use crate::sim::SimulatedHat;
+
+    /// Each write with the values written: `None` for `a_out_write_all`, otherwise the channel.
+    type WriteLog = Arc<Mutex<Vec<(Option<u8>, Vec<f64>)>>>;
+
+    /// A simulated MCC 152 that records its writes.
+    struct Logged(SimulatedHat, WriteLog);
+
+    impl Hat for Logged {
+        fn address(&self) -> u8 {
+            self.0.address()
+        }
+
+        fn id(&self) -> HatId {
+            self.0.id()
+        }
+
+        fn serial(&self) -> Result<String, ErrorCode> {
+            self.0.serial()
+        }
+    }
+
+    impl AOut for Logged {
+        fn a_out_write(&mut self, channel: u8, options: ScanOptions, value: f64) -> Result<(), ErrorCode> {
+            self.1.lock().unwrap().push((Some(channel), vec![value]));
+            self.0.a_out_write(channel, options, value)
+        }
+
+        fn a_out_write_all(&mut self, options: ScanOptions, values: &[f64]) -> Result<(), ErrorCode> {
+            self.1.lock().unwrap().push((None, values.to_vec()));
+            self.0.a_out_write_all(options, values)
+        }
+    }
+
+    fn logged() -> (Logged, WriteLog) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        (Logged(SimulatedHat::new(HatId::Mcc152, 0, |_, _| 0.0).unwrap(), log.clone()), log)
+    }
+
+    fn profile_file(name: &str, text: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("daqhats-profile-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn every_update_of_every_loop_is_written() {
+        let (dev, log) = logged();
+        let sequence = Sequence::new().points(vec![vec![1.0], vec![2.0]]).ramp(&[0.0], &[3.0], Duration::from_millis(40));
+        let generator = Generator::new(dev, &[1], sequence, 100.0).unwrap().with_loops(3);
+        assert_eq!(generator.duration(), Duration::from_millis(60));
+
+        let (dev, timing, result) = generator.start().join();
+        result.unwrap();
+        assert_eq!(timing.updates + timing.skipped, 18);
+        assert_eq!(timing.interval.count() + 1, timing.updates);
+        assert_eq!(dev.0.analog_outputs()[1], 3.0);
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len() as u64, timing.updates);
+        assert!(log.iter().all(|(channel, _)| *channel == Some(1)));
+        if timing.skipped == 0 {
+            let values: Vec<f64> = log.iter().map(|(_, values)| values[0]).collect();
+            assert_eq!(values[..6], [1.0, 2.0, 0.0, 1.0, 2.0, 3.0]);
+        }
+    }
+
+    #[test]
+    fn both_outputs_are_updated_together() {
+        let (dev, log) = logged();
+        let sequence = Sequence::new().hold(&[1.0, 4.0], Duration::from_millis(20));
+        let (dev, _, result) = Generator::new(dev, &[1, 0], sequence, 100.0).unwrap().start().join();
+        result.unwrap();
+        assert_eq!(dev.0.analog_outputs(), [4.0, 1.0]);
+
+        let log = log.lock().unwrap();
+        assert!(!log.is_empty());
+        // values are in the order of `channels`, written in channel order
+        assert!(log.iter().all(|entry| *entry == (None, vec![4.0, 1.0])));
+    }
+
+    #[test]
+    fn stop_is_prompt_at_slow_rates_and_writes_safe_values() {
+        let (dev, log) = logged();
+        let sequence = Sequence::new().points(vec![vec![2.0], vec![3.0]]);
+        let handle = Generator::new(dev, &[0], sequence, 0.1).unwrap().forever().with_safe_output(&[0.5]).unwrap().start();
+        std::thread::sleep(Duration::from_millis(50));
+        let started = Instant::now();
+        let (dev, timing, result) = handle.stop();
+        assert!(started.elapsed() < Duration::from_secs(1), "stopping took {:?}", started.elapsed());
+        result.unwrap();
+        assert_eq!(timing.updates, 1);
+        assert_eq!(dev.0.analog_outputs()[0], 0.5);
+        assert_eq!(*log.lock().unwrap(), [(Some(0), vec![2.0]), (Some(0), vec![0.5])]);
+    }
+
+    #[test]
+    fn new_rejects_bad_settings() {
+        let hold = Sequence::new().hold(&[1.0], Duration::from_millis(10));
+        assert!(Generator::new(logged().0, &[], hold.clone(), 10.0).is_err());
+        assert!(Generator::new(logged().0, &[2], hold.clone(), 10.0).is_err());
+        assert!(Generator::new(logged().0, &[0, 0], Sequence::new().hold(&[1.0, 1.0], Duration::from_millis(10)), 10.0).is_err());
+        assert!(Generator::new(logged().0, &[0], hold.clone(), 0.0).is_err());
+        assert!(Generator::new(logged().0, &[0, 1], hold, 10.0).is_err());
+        assert!(Generator::new(logged().0, &[0], Sequence::new(), 10.0).is_err());
+        assert!(Generator::new(logged().0, &[0], Sequence::new().hold(&[6.0], Duration::from_millis(10)), 10.0).is_err());
+    }
+
+    #[test]
+    fn safe_output_needs_a_value_per_channel_within_range() {
+        let both = || Generator::new(logged().0, &[1, 0], Sequence::new().hold(&[1.0, 1.0], Duration::from_millis(10)), 10.0).unwrap();
+        assert!(matches!(both().with_safe_output(&[0.5]), Err(Error::Device(ErrorCode::BadParameter))));
+        assert!(matches!(both().with_safe_output(&[0.5, 0.5, 0.5]), Err(Error::Device(ErrorCode::BadParameter))));
+        let err = both().with_safe_output(&[0.5, 5.5]).err().unwrap();
+        assert!(matches!(err, Error::Capability(CapabilityError::OutputOutOfRange { channel: 0, value, .. }) if value == 5.5));
+        assert!(both().with_safe_output(&[0.0, 5.0]).is_ok());
+    }
+
+    #[test]
+    fn profiles_are_read_from_csv() {
+        let path = profile_file("ok", "time,a,b\n# warm up\n0, 0.0, 1\n\n1.5,2,3\n1.5,4,5\n2,4,5\n");
+        let segment = Segment::load_profile(&path).unwrap();
+        assert_eq!(segment, Segment::Profile(vec![(0.0, vec![0.0, 1.0]), (1.5, vec![2.0, 3.0]), (1.5, vec![4.0, 5.0]), (2.0, vec![4.0, 5.0])]));
+        std::fs::remove_file(&path).unwrap();
+
+        for (name, text) in [("short", "0,1\n1\n"), ("text", "0,1\nx,2\n"), ("order", "1,1\n0,2\n"), ("width", "0,1\n1,2,3\n"), ("empty", "time,a\n")] {
+            let path = profile_file(name, text);
+            assert_eq!(Segment::load_profile(&path).unwrap_err().kind(), io::ErrorKind::InvalidData, "{}", name);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn profiles_interpolate_and_step() {
+        let segment = Segment::Profile(vec![(0.0, vec![0.0]), (1.0, vec![2.0]), (1.0, vec![5.0]), (2.0, vec![5.0])]);
+        assert_eq!(segment.frames(4.0), 9);
+        let values: Vec<f64> = (0..9)
+            .map(|index| {
+                let mut out = [0.0];
+                segment.frame(index, 4.0, &mut out);
+                out[0]
+            })
+            .collect();
+        // the step takes effect at its time
+        assert_eq!(values, [0.0, 0.5, 1.0, 1.5, 5.0, 5.0, 5.0, 5.0, 5.0]);
+    }
+}